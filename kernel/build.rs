@@ -1,6 +1,9 @@
 fn main() {
-    // Tell cargo to pass the linker script to the linker..
-    println!("cargo:rustc-link-arg=-Tlinker.ld");
+    let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+
+    // Tell cargo to pass the linker script of the target architecture to the linker when
+    // linking the kernel, but not the library's host unit tests..
+    println!("cargo:rustc-link-arg-bins=-Tlinker-{arch}.ld");
     // ..and to re-run if it changes.
-    println!("cargo:rerun-if-changed=linker.ld");
+    println!("cargo:rerun-if-changed=linker-{arch}.ld");
 }
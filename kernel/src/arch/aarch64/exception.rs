@@ -0,0 +1,150 @@
+//! The EL1 exception vector table.
+//!
+//! Nothing on aarch64 routes interrupts or handles faults yet, so every exception is
+//! reported along with the registers it interrupted, and halts the CPU.
+
+use core::arch::{asm, global_asm};
+use core::fmt;
+use core::ptr::addr_of;
+
+use crate::{hcf, kprintln};
+
+const KINDS: [&str; 4] = ["synchronous", "IRQ", "FIQ", "SError"];
+
+const ORIGINS: [&str; 4] = [
+    "current EL with SP_EL0",
+    "current EL with SP_ELx",
+    "lower EL in AArch64",
+    "lower EL in AArch32",
+];
+
+/// The general purpose registers saved by the vector entries.
+#[repr(C)]
+pub struct ExceptionFrame {
+    /// `x0` to `x30`.
+    pub x: [u64; 31],
+    /// The index of the vector table entry that was taken.
+    pub entry: u64,
+}
+
+impl fmt::Debug for ExceptionFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, registers) in self.x.chunks(4).enumerate() {
+            if row != 0 {
+                writeln!(f)?;
+            }
+            for (column, value) in registers.iter().enumerate() {
+                if column != 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "x{:<2}={:#018x}", row * 4 + column, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Each of the 16 entries gets 128 bytes, which is too little to save every register, so
+// they save x0 and x1, note which entry was taken and continue in a common path.
+global_asm!(
+    ".macro vector_entry index",
+    ".balign 128",
+    "sub sp, sp, #(32 * 8)",
+    "stp x0, x1, [sp]",
+    "mov x0, #\\index",
+    "b __exception_common",
+    ".endm",
+    "",
+    ".section .text",
+    ".balign 2048",
+    ".global __exception_vectors",
+    "__exception_vectors:",
+    "vector_entry 0",
+    "vector_entry 1",
+    "vector_entry 2",
+    "vector_entry 3",
+    "vector_entry 4",
+    "vector_entry 5",
+    "vector_entry 6",
+    "vector_entry 7",
+    "vector_entry 8",
+    "vector_entry 9",
+    "vector_entry 10",
+    "vector_entry 11",
+    "vector_entry 12",
+    "vector_entry 13",
+    "vector_entry 14",
+    "vector_entry 15",
+    "",
+    "__exception_common:",
+    "stp x2, x3, [sp, #(2 * 8)]",
+    "stp x4, x5, [sp, #(4 * 8)]",
+    "stp x6, x7, [sp, #(6 * 8)]",
+    "stp x8, x9, [sp, #(8 * 8)]",
+    "stp x10, x11, [sp, #(10 * 8)]",
+    "stp x12, x13, [sp, #(12 * 8)]",
+    "stp x14, x15, [sp, #(14 * 8)]",
+    "stp x16, x17, [sp, #(16 * 8)]",
+    "stp x18, x19, [sp, #(18 * 8)]",
+    "stp x20, x21, [sp, #(20 * 8)]",
+    "stp x22, x23, [sp, #(22 * 8)]",
+    "stp x24, x25, [sp, #(24 * 8)]",
+    "stp x26, x27, [sp, #(26 * 8)]",
+    "stp x28, x29, [sp, #(28 * 8)]",
+    "stp x30, x0, [sp, #(30 * 8)]",
+    "mov x0, sp",
+    "bl {handler}",
+    handler = sym handle_exception,
+);
+
+extern "C" {
+    static __exception_vectors: u8;
+}
+
+/// Points `VBAR_EL1` at the vector table.
+pub(super) fn init() {
+    unsafe {
+        asm!(
+            "msr vbar_el1, {}",
+            "isb",
+            in(reg) addr_of!(__exception_vectors),
+            options(nostack, preserves_flags),
+        )
+    };
+}
+
+macro_rules! read_sysreg {
+    ($name:literal) => {{
+        let value: u64;
+        unsafe {
+            asm!(
+                concat!("mrs {}, ", $name),
+                out(reg) value,
+                options(nomem, nostack, preserves_flags),
+            )
+        };
+        value
+    }};
+}
+
+extern "C" fn handle_exception(frame: &ExceptionFrame) -> ! {
+    let entry = frame.entry as usize;
+    let esr = read_sysreg!("esr_el1");
+
+    kprintln!(
+        "EXCEPTION: {} from {} on CPU {}",
+        KINDS[entry % 4],
+        ORIGINS[entry / 4 % 4],
+        super::cpu_id()
+    );
+    kprintln!(
+        "esr={:#018x} (class {:#04x}) elr={:#018x} far={:#018x} spsr={:#018x}",
+        esr,
+        esr >> 26 & 0x3f,
+        read_sysreg!("elr_el1"),
+        read_sysreg!("far_el1"),
+        read_sysreg!("spsr_el1")
+    );
+    kprintln!("{:?}", frame);
+    hcf();
+}
@@ -0,0 +1,142 @@
+//! aarch64 support.
+//!
+//! Limine enters the kernel at EL1 with the MMU on and the HHDM mapped, but hands over
+//! no description of the machine other than the device tree. The UART and the PSCI
+//! conduit are both looked up there.
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+
+use super::Arch;
+use crate::boot::dtb::LimineDtbResponseExt;
+use crate::boot::fdt::Fdt;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::DTB;
+
+pub mod exception;
+pub mod pl011;
+pub mod psci;
+
+#[cfg(not(all(feature = "dtb", feature = "hhdm")))]
+compile_error!("aarch64 needs the `dtb` and `hhdm` features to find and map its UART");
+
+pub struct Aarch64;
+
+impl Arch for Aarch64 {
+    fn init() {
+        init();
+    }
+
+    fn enable_interrupts() {
+        enable_interrupts();
+    }
+
+    fn disable_interrupts() {
+        disable_interrupts();
+    }
+
+    fn wait_for_interrupt() {
+        wait_for_interrupt();
+    }
+
+    fn cpu_id() -> u32 {
+        cpu_id()
+    }
+
+    fn timestamp() -> u64 {
+        virtual_count()
+    }
+
+    fn write_log(args: fmt::Arguments) {
+        // Output is dropped until the UART has been found in the device tree.
+        if let Some(uart) = pl011::UART.lock().as_mut() {
+            uart.write_fmt(args).ok();
+        }
+    }
+
+    unsafe fn set_per_cpu_base(addr: u64) {
+        set_tpidr(addr);
+    }
+
+    fn per_cpu_base() -> u64 {
+        let base: u64;
+        unsafe {
+            asm!("mrs {}, tpidr_el1", out(reg) base, options(nomem, nostack, preserves_flags))
+        };
+        base
+    }
+}
+
+/// Installs the exception vectors and brings up serial output on the boot CPU.
+/// Interrupts stay masked until [`enable_interrupts`].
+pub fn init() {
+    exception::init();
+    if let Some(fdt) = device_tree() {
+        pl011::init(&fdt);
+        psci::init(&fdt);
+        log_memory(&fdt);
+    }
+}
+
+/// Logs the RAM the device tree describes.
+fn log_memory(fdt: &Fdt) {
+    for (base, size) in fdt.memory_regions() {
+        crate::kprintln!("memory: {:#x}..{:#x}", base, base + size);
+    }
+}
+
+/// Returns the device tree the bootloader passed, if any.
+pub fn device_tree() -> Option<Fdt<'static>> {
+    Fdt::new(DTB.response()?.dtb_bytes()?).ok()
+}
+
+/// Unmasks IRQs and FIQs.
+pub fn enable_interrupts() {
+    unsafe { asm!("msr daifclr, #0b0011", options(nomem, nostack)) };
+}
+
+/// Masks IRQs and FIQs.
+pub fn disable_interrupts() {
+    unsafe { asm!("msr daifset, #0b0011", options(nomem, nostack)) };
+}
+
+pub fn wait_for_interrupt() {
+    unsafe { asm!("wfi", options(nomem, nostack)) };
+}
+
+/// Reads the multiprocessor affinity register of the calling CPU.
+pub fn mpidr() -> u64 {
+    let mpidr: u64;
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr, options(nomem, nostack, preserves_flags)) };
+    mpidr
+}
+
+/// Returns the calling CPU's affinity levels 0 and 1, which tell the cores of all
+/// clusters apart on the machines we run on.
+pub fn cpu_id() -> u32 {
+    (mpidr() & 0xffff) as u32
+}
+
+/// Reads the generic timer's virtual count.
+pub fn virtual_count() -> u64 {
+    let count: u64;
+    unsafe {
+        asm!(
+            "isb",
+            "mrs {}, cntvct_el0",
+            out(reg) count,
+            options(nomem, nostack, preserves_flags),
+        )
+    };
+    count
+}
+
+/// Sets the EL1 software thread ID register.
+///
+/// ## Safety
+///
+/// Code reading `TPIDR_EL1`, like [`crate::smp::read_per_cpu`], expects it to point at
+/// whatever it was set up for.
+pub unsafe fn set_tpidr(addr: u64) {
+    asm!("msr tpidr_el1, {}", in(reg) addr, options(nomem, nostack, preserves_flags));
+}
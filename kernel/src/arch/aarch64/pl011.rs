@@ -0,0 +1,108 @@
+//! Driver for the ARM PL011 UART, which carries the kernel log on aarch64.
+
+use core::fmt;
+use core::hint::spin_loop;
+
+use spin::Mutex;
+
+use crate::boot::fdt::{Fdt, Node};
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+
+const COMPATIBLE: &str = "arm,pl011";
+
+/// Data register.
+const DR: usize = 0x00;
+/// Flag register.
+const FR: usize = 0x18;
+/// Line control register.
+const LCR_H: usize = 0x2c;
+/// Control register.
+const CR: usize = 0x30;
+
+const FR_TXFF: u32 = 1 << 5;
+const LCR_H_FEN: u32 = 1 << 4;
+const LCR_H_WLEN_8: u32 = 0b11 << 5;
+const CR_UARTEN: u32 = 1 << 0;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+/// The UART found in the device tree, if any. Until [`init`] finds one, the kernel log
+/// goes nowhere.
+pub static UART: Mutex<Option<Pl011>> = Mutex::new(None);
+
+pub struct Pl011 {
+    base: *mut u8,
+}
+
+// The registers are only ever accessed through a `&mut Pl011`.
+unsafe impl Send for Pl011 {}
+
+impl Pl011 {
+    /// ## Safety
+    ///
+    /// `base` must be the mapped register block of a PL011.
+    pub const unsafe fn new(base: *mut u8) -> Self {
+        Self { base }
+    }
+
+    fn read(&self, register: usize) -> u32 {
+        unsafe { (self.base.add(register) as *const u32).read_volatile() }
+    }
+
+    fn write(&mut self, register: usize, value: u32) {
+        unsafe { (self.base.add(register) as *mut u32).write_volatile(value) }
+    }
+
+    /// Sets up 8N1 with FIFOs. The baud rate is left as the firmware configured it.
+    pub fn init(&mut self) {
+        self.write(CR, 0);
+        self.write(LCR_H, LCR_H_WLEN_8 | LCR_H_FEN);
+        self.write(CR, CR_UARTEN | CR_TXE | CR_RXE);
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.read(FR) & FR_TXFF != 0 {
+            spin_loop();
+        }
+        self.write(DR, byte as u32);
+    }
+}
+
+impl fmt::Write for Pl011 {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+/// Finds the console UART, preferring the one `/chosen/stdout-path` names.
+fn find(fdt: &Fdt<'static>) -> Option<Node<'static>> {
+    let stdout = fdt
+        .find_node("/chosen")
+        .and_then(|chosen| chosen.property_str("stdout-path"))
+        // The path may be followed by options such as `:115200n8`.
+        .and_then(|path| fdt.find_node(path.split(':').next()?))
+        .filter(|node| node.is_compatible(COMPATIBLE));
+
+    stdout.or_else(|| fdt.find_compatible(COMPATIBLE))
+}
+
+/// Looks up the UART in the device tree and starts logging to it.
+pub(super) fn init(fdt: &Fdt<'static>) {
+    let Some((address, _)) = find(fdt).and_then(|node| node.reg()) else {
+        return;
+    };
+    let Some(hhdm) = HHDM.response() else {
+        return;
+    };
+
+    let mut uart = unsafe { Pl011::new((hhdm.offset + address) as *mut u8) };
+    uart.init();
+    *UART.lock() = Some(uart);
+}
@@ -0,0 +1,113 @@
+//! Power State Coordination Interface calls, for starting CPUs and powering off.
+//!
+//! Depending on the machine, the firmware implementing PSCI is reached through either
+//! `hvc` or `smc`. The device tree's `/psci` node says which; without one, `hvc` is
+//! assumed, which is what QEMU's virt machine uses.
+
+use core::arch::asm;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::boot::fdt::Fdt;
+
+const PSCI_VERSION: u32 = 0x8400_0000;
+const CPU_ON: u32 = 0xc400_0003;
+const SYSTEM_OFF: u32 = 0x8400_0008;
+const SYSTEM_RESET: u32 = 0x8400_0009;
+
+static USE_SMC: AtomicBool = AtomicBool::new(false);
+
+/// A negative PSCI return code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PsciError(pub i32);
+
+impl fmt::Display for PsciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.0 {
+            -1 => "not supported",
+            -2 => "invalid parameters",
+            -3 => "denied",
+            -4 => "already on",
+            -5 => "on pending",
+            -6 => "internal failure",
+            -7 => "not present",
+            -8 => "disabled",
+            -9 => "invalid address",
+            _ => return write!(f, "PSCI error {}", self.0),
+        };
+        f.write_str(name)
+    }
+}
+
+/// Picks the conduit named by the `/psci` node's `method` property.
+pub(super) fn init(fdt: &Fdt) {
+    let method = fdt
+        .find_node("/psci")
+        .and_then(|psci| psci.property_str("method"));
+    USE_SMC.store(method == Some("smc"), Ordering::Relaxed);
+}
+
+fn call(function: u32, arg1: u64, arg2: u64, arg3: u64) -> i64 {
+    let result: i64;
+    unsafe {
+        if USE_SMC.load(Ordering::Relaxed) {
+            asm!(
+                "smc #0",
+                inout("x0") function as u64 => result,
+                inout("x1") arg1 => _,
+                inout("x2") arg2 => _,
+                inout("x3") arg3 => _,
+                options(nostack),
+            );
+        } else {
+            asm!(
+                "hvc #0",
+                inout("x0") function as u64 => result,
+                inout("x1") arg1 => _,
+                inout("x2") arg2 => _,
+                inout("x3") arg3 => _,
+                options(nostack),
+            );
+        }
+    }
+    result
+}
+
+fn check(result: i64) -> Result<(), PsciError> {
+    match result as i32 {
+        0 => Ok(()),
+        error => Err(PsciError(error)),
+    }
+}
+
+/// Returns the major and minor version of the firmware's PSCI implementation.
+pub fn version() -> (u16, u16) {
+    let version = call(PSCI_VERSION, 0, 0, 0) as u32;
+    ((version >> 16) as u16, version as u16)
+}
+
+/// Powers on the CPU with affinity `mpidr`.
+///
+/// The CPU starts at EL1 with the MMU off, so `entry` is a physical address. `context`
+/// is passed in `x0`.
+///
+/// Limine already starts every CPU it lists in the SMP response and parks it until its
+/// `goto_address` is written, so this is only needed for CPUs that were powered off
+/// again, or that Limine didn't start.
+///
+/// ## Safety
+///
+/// `entry` must be code that can run with translation disabled.
+pub unsafe fn cpu_on(mpidr: u64, entry: u64, context: u64) -> Result<(), PsciError> {
+    check(call(CPU_ON, mpidr, entry, context))
+}
+
+pub fn system_off() -> ! {
+    call(SYSTEM_OFF, 0, 0, 0);
+    crate::hcf();
+}
+
+pub fn system_reset() -> ! {
+    call(SYSTEM_RESET, 0, 0, 0);
+    crate::hcf();
+}
@@ -0,0 +1,111 @@
+//! Architecture-specific code.
+//!
+//! Every architecture module implements [`Arch`] on a marker type, and [`Current`] names
+//! the one for the target. The functions here forward to it, which is all the rest of
+//! the kernel uses, so portable code never needs to know what it runs on.
+
+use core::fmt;
+
+#[cfg(target_arch = "aarch64")]
+pub mod aarch64;
+#[cfg(target_arch = "riscv64")]
+pub mod riscv64;
+#[cfg(target_arch = "x86_64")]
+pub mod x86_64;
+
+#[cfg(target_arch = "aarch64")]
+pub type Current = aarch64::Aarch64;
+#[cfg(target_arch = "riscv64")]
+pub type Current = riscv64::Riscv64;
+#[cfg(target_arch = "x86_64")]
+pub type Current = x86_64::X86_64;
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+compile_error!("unsupported target architecture");
+
+/// The hooks an architecture provides to the portable parts of the kernel.
+pub trait Arch {
+    /// Sets up exception handling, the timer and early output on the boot CPU.
+    /// Interrupts stay disabled until [`enable_interrupts`](Arch::enable_interrupts).
+    fn init();
+
+    fn enable_interrupts();
+
+    fn disable_interrupts();
+
+    /// Sleeps until the next interrupt (`hlt` or `wfi`).
+    fn wait_for_interrupt();
+
+    /// Returns an ID of the calling CPU that is unique in the system.
+    fn cpu_id() -> u32;
+
+    /// Returns a free-running, monotonically increasing counter.
+    fn timestamp() -> u64;
+
+    /// Writes to the early output path, which works before any console is up.
+    fn write_log(args: fmt::Arguments);
+
+    /// Points the calling CPU's per-CPU base register at `addr`.
+    ///
+    /// ## Safety
+    ///
+    /// Code reading per-CPU data, like [`crate::smp::read_per_cpu`], expects the register
+    /// to point at whatever it was set up for.
+    unsafe fn set_per_cpu_base(addr: u64);
+
+    /// Returns the calling CPU's per-CPU base.
+    ///
+    /// Where the base register can't be read back cheaply, the first word of the per-CPU
+    /// area is loaded instead, which [`crate::smp::PerCpuData`] keeps pointing at itself.
+    fn per_cpu_base() -> u64;
+}
+
+pub fn init() {
+    Current::init();
+}
+
+pub fn enable_interrupts() {
+    Current::enable_interrupts();
+}
+
+pub fn disable_interrupts() {
+    Current::disable_interrupts();
+}
+
+/// Sleeps until the next interrupt (`hlt` or `wfi`).
+pub fn wait_for_interrupt() {
+    Current::wait_for_interrupt();
+}
+
+/// Returns an ID of the calling CPU that is unique in the system.
+pub fn cpu_id() -> u32 {
+    Current::cpu_id()
+}
+
+/// Returns a free-running, monotonically increasing counter (the TSC, the generic
+/// timer's virtual count or the `time` CSR).
+pub fn timestamp() -> u64 {
+    Current::timestamp()
+}
+
+pub(crate) fn write_log(args: fmt::Arguments) {
+    Current::write_log(args);
+}
+
+/// Points the calling CPU's per-CPU base register (`fs` base, `TPIDR_EL1` or `tp`) at
+/// `addr`.
+///
+/// ## Safety
+///
+/// See [`Arch::set_per_cpu_base`].
+pub unsafe fn set_per_cpu_base(addr: u64) {
+    Current::set_per_cpu_base(addr);
+}
+
+pub(crate) fn per_cpu_base() -> u64 {
+    Current::per_cpu_base()
+}
@@ -0,0 +1,114 @@
+//! riscv64 support.
+//!
+//! Limine enters the kernel in S-mode with paging on, on top of an SBI implementation
+//! such as OpenSBI. Early output goes through the SBI console, which needs no device
+//! discovery at all.
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+
+use super::Arch;
+
+pub mod sbi;
+pub mod trap;
+
+/// `sstatus.SIE`: supervisor interrupts are enabled.
+const SSTATUS_SIE: u64 = 1 << 1;
+
+pub struct Riscv64;
+
+impl Arch for Riscv64 {
+    fn init() {
+        init();
+    }
+
+    fn enable_interrupts() {
+        unsafe { asm!("csrs sstatus, {}", in(reg) SSTATUS_SIE, options(nomem, nostack)) };
+    }
+
+    fn disable_interrupts() {
+        unsafe { asm!("csrc sstatus, {}", in(reg) SSTATUS_SIE, options(nomem, nostack)) };
+    }
+
+    fn wait_for_interrupt() {
+        unsafe { asm!("wfi", options(nomem, nostack)) };
+    }
+
+    fn cpu_id() -> u32 {
+        hart_id() as u32
+    }
+
+    fn timestamp() -> u64 {
+        let time: u64;
+        unsafe { asm!("rdtime {}", out(reg) time, options(nomem, nostack)) };
+        time
+    }
+
+    fn write_log(args: fmt::Arguments) {
+        sbi::Console.write_fmt(args).ok();
+    }
+
+    unsafe fn set_per_cpu_base(addr: u64) {
+        asm!("mv tp, {}", in(reg) addr, options(nomem, nostack, preserves_flags));
+    }
+
+    fn per_cpu_base() -> u64 {
+        let base: u64;
+        unsafe { asm!("mv {}, tp", out(reg) base, options(nomem, nostack, preserves_flags)) };
+        base
+    }
+}
+
+/// Installs the trap vector and records the boot hart's ID.
+pub fn init() {
+    trap::init();
+
+    #[cfg(feature = "smp")]
+    if let Some(smp) = crate::boot::requests::SMP.get_response() {
+        unsafe { set_hart_id(smp.bsp_hartid) };
+    }
+
+    #[cfg(feature = "dtb")]
+    log_device_tree();
+}
+
+/// Logs the UART and the RAM the device tree describes. Output keeps going through the
+/// SBI console either way.
+#[cfg(feature = "dtb")]
+fn log_device_tree() {
+    use crate::boot::dtb::LimineDtbResponseExt;
+    use crate::boot::fdt::Fdt;
+    use crate::boot::request::LimineRequest;
+    use crate::boot::requests::DTB;
+
+    let Some(Ok(fdt)) = DTB.response().and_then(|dtb| dtb.dtb_bytes()).map(Fdt::new) else {
+        return;
+    };
+
+    if let Some((base, _)) = fdt.find_compatible("ns16550a").and_then(|uart| uart.reg()) {
+        crate::kprintln!("uart: ns16550a at {:#x}", base);
+    }
+    for (base, size) in fdt.memory_regions() {
+        crate::kprintln!("memory: {:#x}..{:#x}", base, base + size);
+    }
+}
+
+/// Returns the ID of the calling hart, as recorded by [`set_hart_id`].
+///
+/// S-mode can't read `mhartid`, so the ID is kept in `sscratch`, which the trap entry
+/// doesn't need as long as traps only come from S-mode.
+pub fn hart_id() -> u64 {
+    let hart_id: u64;
+    unsafe { asm!("csrr {}, sscratch", out(reg) hart_id, options(nomem, nostack)) };
+    hart_id
+}
+
+/// Records the calling hart's ID for [`hart_id`].
+///
+/// ## Safety
+///
+/// `hart_id` must be the ID of the calling hart, as another hart started from the SMP
+/// response finds it in its [`SmpInfo`](crate::boot::smp::SmpInfo).
+pub unsafe fn set_hart_id(hart_id: u64) {
+    asm!("csrw sscratch, {}", in(reg) hart_id, options(nomem, nostack));
+}
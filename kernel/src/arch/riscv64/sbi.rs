@@ -0,0 +1,46 @@
+//! Calls into the Supervisor Binary Interface.
+
+use core::arch::asm;
+use core::fmt;
+
+/// The legacy console putchar extension, which every SBI implementation keeps around.
+const LEGACY_CONSOLE_PUTCHAR: u64 = 0x01;
+const SYSTEM_RESET: u64 = 0x5352_5354;
+
+const RESET_TYPE_SHUTDOWN: u64 = 0;
+
+fn call(extension: u64, function: u64, arg0: u64, arg1: u64) -> (i64, u64) {
+    let (error, value): (i64, u64);
+    unsafe {
+        asm!(
+            "ecall",
+            inout("a0") arg0 => error,
+            inout("a1") arg1 => value,
+            in("a6") function,
+            in("a7") extension,
+            options(nostack),
+        )
+    };
+    (error, value)
+}
+
+pub fn console_putchar(byte: u8) {
+    call(LEGACY_CONSOLE_PUTCHAR, 0, byte as u64, 0);
+}
+
+pub fn shutdown() -> ! {
+    call(SYSTEM_RESET, 0, RESET_TYPE_SHUTDOWN, 0);
+    crate::hcf();
+}
+
+/// The SBI console as a [`fmt::Write`] sink.
+pub struct Console;
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            console_putchar(byte);
+        }
+        Ok(())
+    }
+}
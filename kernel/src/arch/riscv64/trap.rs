@@ -0,0 +1,153 @@
+//! The supervisor trap vector.
+//!
+//! Nothing on riscv64 handles interrupts or faults yet, so every trap is reported along
+//! with the registers it interrupted, and halts the hart.
+
+use core::arch::{asm, global_asm};
+use core::fmt;
+
+use crate::{hcf, kprintln};
+
+/// `scause` bit set for interrupts, as opposed to exceptions.
+const SCAUSE_INTERRUPT: u64 = 1 << 63;
+
+const EXCEPTION_NAMES: [&str; 16] = [
+    "instruction address misaligned",
+    "instruction access fault",
+    "illegal instruction",
+    "breakpoint",
+    "load address misaligned",
+    "load access fault",
+    "store address misaligned",
+    "store access fault",
+    "environment call from U-mode",
+    "environment call from S-mode",
+    "reserved",
+    "reserved",
+    "instruction page fault",
+    "load page fault",
+    "reserved",
+    "store page fault",
+];
+
+/// The general purpose registers saved by the trap entry. `x[0]` is always zero and
+/// `x[2]` holds `sp` from before the trap.
+#[repr(C)]
+pub struct TrapFrame {
+    pub x: [u64; 32],
+}
+
+impl fmt::Debug for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row, registers) in self.x.chunks(4).enumerate() {
+            if row != 0 {
+                writeln!(f)?;
+            }
+            for (column, value) in registers.iter().enumerate() {
+                if column != 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "x{:<2}={:#018x}", row * 4 + column, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+global_asm!(
+    ".section .text",
+    ".balign 4",
+    ".global __trap_entry",
+    "__trap_entry:",
+    "addi sp, sp, -(32 * 8)",
+    "sd zero, 0(sp)",
+    "sd x1, 1 * 8(sp)",
+    "sd x3, 3 * 8(sp)",
+    "sd x4, 4 * 8(sp)",
+    "sd x5, 5 * 8(sp)",
+    "sd x6, 6 * 8(sp)",
+    "sd x7, 7 * 8(sp)",
+    "sd x8, 8 * 8(sp)",
+    "sd x9, 9 * 8(sp)",
+    "sd x10, 10 * 8(sp)",
+    "sd x11, 11 * 8(sp)",
+    "sd x12, 12 * 8(sp)",
+    "sd x13, 13 * 8(sp)",
+    "sd x14, 14 * 8(sp)",
+    "sd x15, 15 * 8(sp)",
+    "sd x16, 16 * 8(sp)",
+    "sd x17, 17 * 8(sp)",
+    "sd x18, 18 * 8(sp)",
+    "sd x19, 19 * 8(sp)",
+    "sd x20, 20 * 8(sp)",
+    "sd x21, 21 * 8(sp)",
+    "sd x22, 22 * 8(sp)",
+    "sd x23, 23 * 8(sp)",
+    "sd x24, 24 * 8(sp)",
+    "sd x25, 25 * 8(sp)",
+    "sd x26, 26 * 8(sp)",
+    "sd x27, 27 * 8(sp)",
+    "sd x28, 28 * 8(sp)",
+    "sd x29, 29 * 8(sp)",
+    "sd x30, 30 * 8(sp)",
+    "sd x31, 31 * 8(sp)",
+    // Record the stack pointer from before the trap.
+    "addi t0, sp, 32 * 8",
+    "sd t0, 2 * 8(sp)",
+    "mv a0, sp",
+    "call {handler}",
+    handler = sym handle_trap,
+);
+
+extern "C" {
+    fn __trap_entry();
+}
+
+/// Points `stvec` at the trap entry, in direct mode.
+pub(super) fn init() {
+    unsafe { asm!("csrw stvec, {}", in(reg) __trap_entry as usize, options(nomem, nostack)) };
+}
+
+macro_rules! read_csr {
+    ($name:literal) => {{
+        let value: u64;
+        unsafe { asm!(concat!("csrr {}, ", $name), out(reg) value, options(nomem, nostack)) };
+        value
+    }};
+}
+
+fn cause_name(scause: u64) -> &'static str {
+    let code = scause & !SCAUSE_INTERRUPT;
+    if scause & SCAUSE_INTERRUPT != 0 {
+        match code {
+            1 => "supervisor software interrupt",
+            5 => "supervisor timer interrupt",
+            9 => "supervisor external interrupt",
+            _ => "interrupt",
+        }
+    } else {
+        EXCEPTION_NAMES
+            .get(code as usize)
+            .copied()
+            .unwrap_or("reserved")
+    }
+}
+
+extern "C" fn handle_trap(frame: &TrapFrame) -> ! {
+    let scause = read_csr!("scause");
+
+    kprintln!(
+        "TRAP: {} (scause {:#x}) on hart {}",
+        cause_name(scause),
+        scause,
+        super::hart_id()
+    );
+    kprintln!(
+        "sepc={:#018x} stval={:#018x} sstatus={:#018x}",
+        read_csr!("sepc"),
+        read_csr!("stval"),
+        read_csr!("sstatus")
+    );
+    kprintln!("{:?}", frame);
+    hcf();
+}
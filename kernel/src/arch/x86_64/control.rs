@@ -0,0 +1,57 @@
+use core::arch::asm;
+
+/// CR0 bit making read-only pages read-only for the kernel as well.
+pub const CR0_WP: u64 = 1 << 16;
+
+/// CR4 bit set while 5-level paging is active.
+pub const CR4_LA57: u64 = 1 << 12;
+/// CR4 bit enabling supervisor mode execution prevention.
+pub const CR4_SMEP: u64 = 1 << 20;
+/// CR4 bit enabling supervisor mode access prevention.
+pub const CR4_SMAP: u64 = 1 << 21;
+
+#[inline]
+pub fn cr0() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// ## Safety
+///
+/// Changing CR0 changes fundamental protection and caching behaviour.
+#[inline]
+pub unsafe fn set_cr0(value: u64) {
+    asm!("mov cr0, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Returns the faulting address of the last page fault.
+#[inline]
+pub fn cr2() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// Returns the physical address of the active top-level page table (plus flags).
+#[inline]
+pub fn cr3() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+#[inline]
+pub fn cr4() -> u64 {
+    let value: u64;
+    unsafe { asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// ## Safety
+///
+/// Changing CR4 changes fundamental paging and protection behaviour.
+#[inline]
+pub unsafe fn set_cr4(value: u64) {
+    asm!("mov cr4, {}", in(reg) value, options(nostack, preserves_flags));
+}
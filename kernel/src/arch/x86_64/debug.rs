@@ -0,0 +1,254 @@
+//! Breakpoint and debug exception handling, and hardware breakpoints.
+//!
+//! `int3` and the debug registers trap into [`handle_breakpoint`] and [`handle_debug`],
+//! which report the trap and resume execution unless [`set_continue_on_trap`] turned
+//! that off. Once the GDB stub is enabled, traps are handed to it instead.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::gdb;
+use super::trap::TrapFrame;
+use crate::{hcf, kprintln};
+
+/// Number of hardware breakpoint slots (DR0 to DR3).
+pub const SLOT_COUNT: usize = 4;
+
+/// DR6 bit set when the trap was caused by single-stepping.
+const DR6_SINGLE_STEP: u64 = 1 << 14;
+/// The value of DR6 with no debug condition recorded.
+const DR6_CLEAR: u64 = 0xffff_0ff0;
+
+pub(super) const RFLAGS_TRAP: u64 = 1 << 8;
+const RFLAGS_RESUME: u64 = 1 << 16;
+
+static CONTINUE_ON_TRAP: AtomicBool = AtomicBool::new(true);
+static HITS: [AtomicU64; SLOT_COUNT] = [const { AtomicU64::new(0) }; SLOT_COUNT];
+
+/// The size of the memory range watched by a data breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakpointLength {
+    One,
+    Two,
+    Four,
+    Eight,
+}
+
+impl BreakpointLength {
+    fn bytes(self) -> u64 {
+        match self {
+            Self::One => 1,
+            Self::Two => 2,
+            Self::Four => 4,
+            Self::Eight => 8,
+        }
+    }
+
+    /// The DR7 LEN field encoding.
+    fn encoding(self) -> u64 {
+        match self {
+            Self::One => 0b00,
+            Self::Two => 0b01,
+            Self::Four => 0b11,
+            Self::Eight => 0b10,
+        }
+    }
+}
+
+/// What a hardware breakpoint triggers on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakpointKind {
+    /// Execution of the instruction at the address.
+    Execute,
+    /// Writes to the range.
+    Write(BreakpointLength),
+    /// Reads or writes to the range.
+    Access(BreakpointLength),
+}
+
+impl BreakpointKind {
+    /// The DR7 R/W and LEN fields for this kind.
+    fn condition(self) -> (u64, u64) {
+        match self {
+            Self::Execute => (0b00, 0b00),
+            Self::Write(len) => (0b01, len.encoding()),
+            Self::Access(len) => (0b11, len.encoding()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugError {
+    /// All four breakpoint slots are in use.
+    NoFreeSlot,
+    /// The address is not aligned to the breakpoint length.
+    Misaligned,
+}
+
+/// A hardware breakpoint occupying one of the debug register slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HwBreakpoint {
+    slot: usize,
+}
+
+impl HwBreakpoint {
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// How many times this slot has triggered since boot.
+    pub fn hits(&self) -> u64 {
+        HITS[self.slot].load(Ordering::Relaxed)
+    }
+}
+
+fn read_dr6() -> u64 {
+    let value;
+    unsafe { asm!("mov {}, dr6", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+fn write_dr6(value: u64) {
+    unsafe { asm!("mov dr6, {}", in(reg) value, options(nomem, nostack, preserves_flags)) };
+}
+
+fn read_dr7() -> u64 {
+    let value;
+    unsafe { asm!("mov {}, dr7", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+fn write_dr7(value: u64) {
+    unsafe { asm!("mov dr7, {}", in(reg) value, options(nomem, nostack, preserves_flags)) };
+}
+
+fn write_address(slot: usize, addr: u64) {
+    unsafe {
+        match slot {
+            0 => asm!("mov dr0, {}", in(reg) addr, options(nomem, nostack, preserves_flags)),
+            1 => asm!("mov dr1, {}", in(reg) addr, options(nomem, nostack, preserves_flags)),
+            2 => asm!("mov dr2, {}", in(reg) addr, options(nomem, nostack, preserves_flags)),
+            3 => asm!("mov dr3, {}", in(reg) addr, options(nomem, nostack, preserves_flags)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The DR7 local enable bit of `slot`.
+fn enable_bit(slot: usize) -> u64 {
+    1 << (slot * 2)
+}
+
+/// Programs a free debug register slot with a breakpoint at `addr`.
+pub fn set_hw_breakpoint(addr: u64, kind: BreakpointKind) -> Result<HwBreakpoint, DebugError> {
+    let (condition, len) = kind.condition();
+    if let BreakpointKind::Write(length) | BreakpointKind::Access(length) = kind {
+        if !addr.is_multiple_of(length.bytes()) {
+            return Err(DebugError::Misaligned);
+        }
+    }
+
+    let dr7 = read_dr7();
+    let slot = (0..SLOT_COUNT)
+        .find(|&slot| dr7 & enable_bit(slot) == 0)
+        .ok_or(DebugError::NoFreeSlot)?;
+
+    let field_shift = 16 + slot * 4;
+    let dr7 = dr7 & !(0b1111 << field_shift) | (len << 2 | condition) << field_shift;
+
+    write_address(slot, addr);
+    write_dr7(dr7 | enable_bit(slot));
+
+    Ok(HwBreakpoint { slot })
+}
+
+/// Disables a breakpoint and frees its slot.
+pub fn clear_hw_breakpoint(breakpoint: HwBreakpoint) {
+    let slot = breakpoint.slot;
+    write_dr7(read_dr7() & !enable_bit(slot) & !(0b1111 << (16 + slot * 4)));
+    write_address(slot, 0);
+}
+
+/// Whether the trap handlers resume execution (the default) or halt the machine.
+pub fn set_continue_on_trap(resume: bool) {
+    CONTINUE_ON_TRAP.store(resume, Ordering::Relaxed);
+}
+
+fn dump(frame: &TrapFrame) {
+    kprintln!("{:?}", frame);
+    kprintln!("dr6={:#x} dr7={:#x}", read_dr6(), read_dr7());
+}
+
+fn finish_trap() {
+    if !CONTINUE_ON_TRAP.load(Ordering::Relaxed) {
+        hcf();
+    }
+}
+
+pub(super) fn handle_breakpoint(frame: &mut TrapFrame) {
+    if gdb::is_enabled() {
+        gdb::handle_trap(frame);
+        return;
+    }
+
+    // #BP is a trap, so RIP already points past the one-byte `int3`.
+    kprintln!("breakpoint at {:#018x}", frame.rip - 1);
+    dump(frame);
+    finish_trap();
+}
+
+pub(super) fn handle_debug(frame: &mut TrapFrame) {
+    let dr6 = read_dr6();
+    // DR6 is sticky, so clear it for the next debug exception.
+    write_dr6(DR6_CLEAR);
+
+    for (slot, hits) in HITS.iter().enumerate() {
+        if dr6 & (1 << slot) != 0 {
+            hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Instruction breakpoints are faults; without RF the instruction would trap again
+    // as soon as we return to it.
+    frame.rflags |= RFLAGS_RESUME;
+
+    if gdb::is_enabled() {
+        gdb::handle_trap(frame);
+        return;
+    }
+
+    if dr6 & DR6_SINGLE_STEP != 0 {
+        kprintln!("single step at {:#018x}", frame.rip);
+        dump(frame);
+        // Only step a single instruction.
+        frame.rflags &= !RFLAGS_TRAP;
+    }
+
+    for slot in 0..SLOT_COUNT {
+        if dr6 & (1 << slot) != 0 {
+            kprintln!("hardware breakpoint {} hit at {:#018x}", slot, frame.rip);
+            dump(frame);
+        }
+    }
+
+    finish_trap();
+}
+
+/// Sets a write watchpoint on a static, writes to it and checks that the debug exception
+/// handler saw the write.
+pub fn self_test() -> bool {
+    static WATCHED: AtomicU64 = AtomicU64::new(0);
+
+    let addr = WATCHED.as_ptr() as u64;
+    let Ok(breakpoint) = set_hw_breakpoint(addr, BreakpointKind::Write(BreakpointLength::Eight))
+    else {
+        return false;
+    };
+
+    let hits = breakpoint.hits();
+    WATCHED.store(1, Ordering::SeqCst);
+    let fired = breakpoint.hits() == hits + 1;
+
+    clear_hw_breakpoint(breakpoint);
+    fired
+}
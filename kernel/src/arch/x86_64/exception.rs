@@ -0,0 +1,377 @@
+//! Reporting of CPU exceptions nothing handled.
+//!
+//! [`fault`] prints an [`ExceptionReport`]: the exception and its decoded error code, the
+//! CPU it happened on, every register and a frame pointer backtrace.
+
+use core::fmt;
+
+use super::idt::PAGE_FAULT;
+use super::trap::TrapFrame;
+use super::{control, cpu_id, extable};
+use crate::{hcf, kprintln};
+
+const EXCEPTION_NAMES: [&str; 32] = [
+    "divide error",
+    "debug",
+    "non-maskable interrupt",
+    "breakpoint",
+    "overflow",
+    "bound range exceeded",
+    "invalid opcode",
+    "device not available",
+    "double fault",
+    "coprocessor segment overrun",
+    "invalid TSS",
+    "segment not present",
+    "stack segment fault",
+    "general protection fault",
+    "page fault",
+    "reserved",
+    "x87 floating point exception",
+    "alignment check",
+    "machine check",
+    "SIMD floating point exception",
+    "virtualization exception",
+    "control protection exception",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "reserved",
+    "hypervisor injection exception",
+    "VMM communication exception",
+    "security exception",
+    "reserved",
+];
+
+/// How many return addresses a backtrace follows at most.
+const MAX_BACKTRACE_DEPTH: usize = 16;
+
+pub fn exception_name(vector: u8) -> &'static str {
+    EXCEPTION_NAMES
+        .get(vector as usize)
+        .copied()
+        .unwrap_or("interrupt")
+}
+
+/// Returns whether the CPU pushes an error code for `vector`.
+pub fn has_error_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+/// The control registers at the time of an exception.
+#[derive(Clone, Copy, Debug)]
+pub struct ControlRegisters {
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+}
+
+impl ControlRegisters {
+    pub fn read() -> Self {
+        Self {
+            cr0: control::cr0(),
+            cr2: control::cr2(),
+            cr3: control::cr3(),
+            cr4: control::cr4(),
+        }
+    }
+}
+
+/// A decoded exception error code.
+struct ErrorCode {
+    vector: u8,
+    code: u64,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.code)?;
+
+        if self.vector == PAGE_FAULT {
+            let code = self.code;
+            let flag = |bit: u32, set: &'static str, clear: &'static str| {
+                if code & (1 << bit) != 0 {
+                    set
+                } else {
+                    clear
+                }
+            };
+            write!(
+                f,
+                " ({}, {}, {}",
+                flag(0, "protection violation", "not present"),
+                flag(1, "write", "read"),
+                flag(2, "user", "supervisor"),
+            )?;
+            for (bit, name) in [
+                (3, "reserved bit set"),
+                (4, "instruction fetch"),
+                (5, "protection key"),
+                (6, "shadow stack"),
+            ] {
+                if code & (1 << bit) != 0 {
+                    write!(f, ", {}", name)?;
+                }
+            }
+            write!(f, ")")
+        } else if matches!(self.vector, 10..=13) && self.code != 0 {
+            // These error codes name the segment selector that caused the fault.
+            let table = match self.code >> 1 & 0b11 {
+                0b00 => "GDT",
+                0b10 => "LDT",
+                _ => "IDT",
+            };
+            write!(f, " ({} index {:#x}", table, self.code >> 3)?;
+            if self.code & 1 != 0 {
+                write!(f, ", external")?;
+            }
+            write!(f, ")")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Follows the saved frame pointer chain starting at `rbp`, printing `rip` first.
+pub struct Backtrace {
+    pub rip: u64,
+    pub rbp: u64,
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "backtrace:\n  #0  {:#018x}", self.rip)?;
+        write_symbol(f, self.rip)?;
+
+        let mut rbp = self.rbp;
+        for depth in 1..MAX_BACKTRACE_DEPTH {
+            if rbp == 0 || !rbp.is_multiple_of(8) {
+                break;
+            }
+            // Each frame starts with the caller's frame pointer, followed by the return address.
+            let (Some(next), Some(return_address)) =
+                (extable::read_u64(rbp), extable::read_u64(rbp + 8))
+            else {
+                break;
+            };
+            if return_address == 0 {
+                break;
+            }
+            write!(f, "\n  #{:<2} {:#018x}", depth, return_address)?;
+            write_symbol(f, return_address)?;
+
+            // The stack grows down, so callers' frames always live at higher addresses.
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+        Ok(())
+    }
+}
+
+/// Names the kernel function containing `addr`, if the kernel file has symbols.
+#[allow(unused_variables)]
+fn write_symbol(f: &mut fmt::Formatter<'_>, addr: u64) -> fmt::Result {
+    #[cfg(feature = "kernel-file")]
+    if let Some((symbol, offset)) = crate::elf::lookup_kernel(addr) {
+        write!(f, " {}+{:#x}", symbol.name, offset)?;
+    }
+    Ok(())
+}
+
+/// Everything known about an exception, formatted as a compact register table.
+pub struct ExceptionReport<'a> {
+    pub frame: &'a TrapFrame,
+    pub control: &'a ControlRegisters,
+}
+
+impl fmt::Display for ExceptionReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vector = self.frame.vector as u8;
+        writeln!(
+            f,
+            "EXCEPTION: {} (vector {}) on CPU {}",
+            exception_name(vector),
+            vector,
+            cpu_id()
+        )?;
+        if has_error_code(vector) {
+            let error_code = ErrorCode {
+                vector,
+                code: self.frame.error_code,
+            };
+            writeln!(f, "error code: {}", error_code)?;
+        }
+        writeln!(f, "{:?}", self.frame)?;
+        writeln!(
+            f,
+            "cr0={:#018x} cr2={:#018x} cr3={:#018x} cr4={:#018x}",
+            self.control.cr0, self.control.cr2, self.control.cr3, self.control.cr4
+        )?;
+        write!(
+            f,
+            "{}",
+            Backtrace {
+                rip: self.frame.rip,
+                rbp: self.frame.rbp,
+            }
+        )
+    }
+}
+
+/// Common path for exceptions nothing can recover from.
+///
+/// Faults raised by user mode code only terminate the user task, everything else
+/// halts the machine.
+pub(super) fn fault(frame: &TrapFrame, control: &ControlRegisters) -> ! {
+    #[cfg(feature = "usermode")]
+    if frame.is_user() {
+        crate::usermode::terminate(frame, control);
+    }
+
+    kprintln!("{}", ExceptionReport { frame, control });
+    hcf();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_code(vector: u8, code: u64) -> String {
+        ErrorCode { vector, code }.to_string()
+    }
+
+    #[test]
+    fn page_fault_error_code() {
+        assert_eq!(
+            error_code(PAGE_FAULT, 0),
+            "0x0 (not present, read, supervisor)"
+        );
+        assert_eq!(
+            error_code(PAGE_FAULT, 0b1_0111),
+            "0x17 (protection violation, write, user, instruction fetch)"
+        );
+        assert_eq!(
+            error_code(PAGE_FAULT, 0b110_1000),
+            "0x68 (not present, read, supervisor, reserved bit set, protection key, shadow stack)"
+        );
+    }
+
+    #[test]
+    fn selector_error_code() {
+        assert_eq!(error_code(13, 0), "0x0");
+        assert_eq!(error_code(13, 0x1c), "0x1c (LDT index 0x3)");
+        assert_eq!(error_code(11, 0x11), "0x11 (GDT index 0x2, external)");
+        assert_eq!(error_code(10, 0x2a), "0x2a (IDT index 0x5)");
+        // Other vectors only print the raw code.
+        assert_eq!(error_code(17, 0x1c), "0x1c");
+    }
+
+    #[test]
+    fn backtrace_follows_frame_pointers() {
+        // Three frames of a saved frame pointer and a return address, the last one the
+        // end of the chain.
+        let mut stack = [0u64; 6];
+        let base = stack.as_ptr() as u64;
+        stack[..4].copy_from_slice(&[base + 16, 0x1111, base + 32, 0x2222]);
+        let backtrace = Backtrace {
+            rip: 0xffff_ffff_8000_1000,
+            rbp: base,
+        };
+        assert_eq!(
+            backtrace.to_string(),
+            "backtrace:\n  #0  0xffffffff80001000\n  #1  0x0000000000001111\n  #2  0x0000000000002222"
+        );
+
+        // A frame pointer that doesn't go up the stack ends the walk after its frame.
+        stack[..2].copy_from_slice(&[base, 0x1111]);
+        assert_eq!(
+            backtrace.to_string(),
+            "backtrace:\n  #0  0xffffffff80001000\n  #1  0x0000000000001111"
+        );
+    }
+
+    /// A frame with every register set to a different value and no frame pointer.
+    fn frame(vector: u64, error_code: u64) -> TrapFrame {
+        TrapFrame {
+            r15: 15,
+            r14: 14,
+            r13: 13,
+            r12: 12,
+            r11: 11,
+            r10: 10,
+            r9: 9,
+            r8: 8,
+            rbp: 0,
+            rdi: 7,
+            rsi: 6,
+            rdx: 4,
+            rcx: 3,
+            rbx: 2,
+            rax: 1,
+            vector,
+            error_code,
+            rip: 0xffff_ffff_8000_2345,
+            cs: 0x28,
+            rflags: 0x246,
+            rsp: 0xffff_8000_0001_ff00,
+            ss: 0x30,
+        }
+    }
+
+    const CONTROL: ControlRegisters = ControlRegisters {
+        cr0: 0x8001_0033,
+        cr2: 0xdead_b000,
+        cr3: 0x10_0000,
+        cr4: 0x6a0,
+    };
+
+    const REGISTERS: &str = "\
+rip=0xffffffff80002345 rsp=0xffff80000001ff00 rflags=0x246 cs=0x28 ss=0x30
+rax=0x0000000000000001 rbx=0x0000000000000002 rcx=0x0000000000000003 rdx=0x0000000000000004
+rsi=0x0000000000000006 rdi=0x0000000000000007 rbp=0x0000000000000000 r8 =0x0000000000000008
+r9 =0x0000000000000009 r10=0x000000000000000a r11=0x000000000000000b r12=0x000000000000000c
+r13=0x000000000000000d r14=0x000000000000000e r15=0x000000000000000f
+cr0=0x0000000080010033 cr2=0x00000000deadb000 cr3=0x0000000000100000 cr4=0x00000000000006a0
+backtrace:
+  #0  0xffffffff80002345";
+
+    #[test]
+    fn report_with_error_code() {
+        let frame = frame(PAGE_FAULT as u64, 0b10);
+        let report = ExceptionReport {
+            frame: &frame,
+            control: &CONTROL,
+        };
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "EXCEPTION: page fault (vector 14) on CPU {}\n\
+                 error code: 0x2 (not present, write, supervisor)\n{}",
+                cpu_id(),
+                REGISTERS
+            )
+        );
+    }
+
+    #[test]
+    fn report_without_error_code() {
+        let frame = frame(6, 0);
+        let report = ExceptionReport {
+            frame: &frame,
+            control: &CONTROL,
+        };
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "EXCEPTION: invalid opcode (vector 6) on CPU {}\n{}",
+                cpu_id(),
+                REGISTERS
+            )
+        );
+    }
+}
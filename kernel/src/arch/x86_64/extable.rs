@@ -0,0 +1,130 @@
+//! The exception table: instructions that are allowed to fault.
+//!
+//! Each entry in `.ex_table` pairs the address of an instruction that may fault with the
+//! address to resume at if it does. The page fault and general protection handlers look
+//! the faulting RIP up here before treating the fault as fatal. Both addresses are stored
+//! relative to the entry so the table needs no relocations.
+
+use core::arch::global_asm;
+
+use super::control::{self, CR0_WP};
+
+#[repr(C)]
+struct ExceptionTableEntry {
+    instruction: i32,
+    fixup: i32,
+}
+
+impl ExceptionTableEntry {
+    fn instruction(&self) -> u64 {
+        (&self.instruction as *const i32 as u64).wrapping_add_signed(self.instruction as i64)
+    }
+
+    fn fixup(&self) -> u64 {
+        (&self.fixup as *const i32 as u64).wrapping_add_signed(self.fixup as i64)
+    }
+}
+
+extern "C" {
+    static __ex_table_start: ExceptionTableEntry;
+    static __ex_table_end: ExceptionTableEntry;
+
+    fn __extable_read_byte(addr: u64) -> u64;
+    fn __extable_write_byte(addr: u64, value: u8) -> u64;
+    fn __extable_copy(dst: *mut u8, src: *const u8, len: usize) -> usize;
+}
+
+// The probes return their result in rax: the byte read (or zero for a successful write),
+// or `u64::MAX` if the access faulted. The copy returns how many bytes it didn't copy,
+// which `rep movsb` keeps in rcx as it goes, so a fault in the middle leaves the count of
+// the bytes that are left.
+global_asm!(
+    ".pushsection .text.extable, \"ax\"",
+    ".global __extable_read_byte",
+    "__extable_read_byte:",
+    "xor eax, eax",
+    "1: mov al, [rdi]",
+    "ret",
+    "2: mov rax, -1",
+    "ret",
+    ".global __extable_write_byte",
+    "__extable_write_byte:",
+    "xor eax, eax",
+    "3: mov [rdi], sil",
+    "ret",
+    "4: mov rax, -1",
+    "ret",
+    ".global __extable_copy",
+    "__extable_copy:",
+    "mov rcx, rdx",
+    "5: rep movsb",
+    "xor eax, eax",
+    "ret",
+    "6: mov rax, rcx",
+    "ret",
+    ".popsection",
+    ".pushsection .ex_table, \"a\"",
+    ".balign 4",
+    ".long 1b - ., 2b - .",
+    ".long 3b - ., 4b - .",
+    ".long 5b - ., 6b - .",
+    ".popsection",
+);
+
+/// Returns where to resume if the instruction at `rip` is allowed to fault.
+pub fn search(rip: u64) -> Option<u64> {
+    let start = &raw const __ex_table_start;
+    let end = &raw const __ex_table_end;
+    let len = unsafe { end.offset_from(start) } as usize;
+    let table = unsafe { core::slice::from_raw_parts(start, len) };
+
+    table
+        .iter()
+        .find(|entry| entry.instruction() == rip)
+        .map(ExceptionTableEntry::fixup)
+}
+
+/// Reads a byte from an arbitrary address, returning `None` if the access faults.
+pub fn read_byte(addr: u64) -> Option<u8> {
+    let value = unsafe { __extable_read_byte(addr) };
+    (value != u64::MAX).then_some(value as u8)
+}
+
+/// Reads a little-endian `u64` from an arbitrary, possibly unaligned address.
+pub fn read_u64(addr: u64) -> Option<u64> {
+    let mut bytes = [0; 8];
+    for (offset, byte) in bytes.iter_mut().enumerate() {
+        *byte = read_byte(addr.wrapping_add(offset as u64))?;
+    }
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// Copies `len` bytes from `src` to `dst`, stopping at the first access that faults.
+/// Returns how many bytes were copied before it, as the error.
+///
+/// ## Safety
+///
+/// `dst` must not overlap `src`, and writing to it must not break anything the kernel
+/// relies on.
+pub unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) -> Result<(), usize> {
+    match __extable_copy(dst, src, len) {
+        0 => Ok(()),
+        left => Err(len - left),
+    }
+}
+
+/// Writes a byte to an arbitrary address, returning whether the write succeeded.
+///
+/// Write protection is lifted for the duration of the write so read-only kernel pages,
+/// such as the kernel's own text, can be patched.
+///
+/// ## Safety
+///
+/// Writing to arbitrary memory can corrupt anything.
+pub unsafe fn write_byte(addr: u64, value: u8) -> bool {
+    let cr0 = control::cr0();
+    control::set_cr0(cr0 & !CR0_WP);
+    let result = __extable_write_byte(addr, value);
+    control::set_cr0(cr0);
+    result != u64::MAX
+}
@@ -0,0 +1,472 @@
+//! A GDB remote serial protocol stub.
+//!
+//! The stub talks to GDB over [`COM2`] and takes over breakpoint and debug traps once it
+//! has been enabled with [`enable`] or an explicit [`breakpoint`] call. With QEMU:
+//!
+//! ```text
+//! qemu-system-x86_64 ... -serial stdio -serial tcp::1234,server,nowait
+//! gdb kernel.elf -ex 'target remote :1234'
+//! ```
+//!
+//! Register and memory access, continuing, single-stepping and software breakpoints are
+//! supported, which covers GDB's basic workflow. Memory is accessed through the exception
+//! table so bad addresses produce an error reply instead of a fault. The serial port is
+//! polled, so the kernel can only be interrupted by a breakpoint, not by Ctrl-C.
+
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use super::debug::RFLAGS_TRAP;
+use super::extable;
+use super::idt::BREAKPOINT;
+use super::trap::TrapFrame;
+use crate::serial::{SerialPort, COM2};
+
+/// The largest packet the stub accepts, advertised to GDB through `qSupported`.
+const PACKET_SIZE: usize = 1024;
+/// How many software breakpoints can be inserted at once.
+const MAX_BREAKPOINTS: usize = 32;
+
+const INT3: u8 = 0xcc;
+const SIGTRAP: u8 = 5;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy)]
+struct SoftwareBreakpoint {
+    addr: u64,
+    original: u8,
+}
+
+static BREAKPOINTS: Mutex<[Option<SoftwareBreakpoint>; MAX_BREAKPOINTS]> =
+    Mutex::new([None; MAX_BREAKPOINTS]);
+
+/// Routes breakpoint and debug traps to GDB from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Release);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Enables the stub and stops in it, waiting for GDB to connect.
+pub fn breakpoint() {
+    enable();
+    unsafe { asm!("int3") };
+}
+
+/// A packet payload being assembled.
+struct Packet {
+    data: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Packet {
+    const fn new() -> Self {
+        Self {
+            data: [0; PACKET_SIZE],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Appends `bytes` hex encoded. Anything beyond the packet size is dropped.
+    fn push_hex(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.len + 2 > PACKET_SIZE {
+                return;
+            }
+            self.data[self.len] = hex_digit(byte >> 4);
+            self.data[self.len + 1] = hex_digit(byte);
+            self.len += 2;
+        }
+    }
+}
+
+impl fmt::Write for Packet {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > PACKET_SIZE {
+            return Err(fmt::Error);
+        }
+        self.data[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// What to do after handling a packet.
+enum Action {
+    Reply,
+    Resume,
+    ReplyAndResume,
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[(value & 0xf) as usize]
+}
+
+fn from_hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
+
+/// Parses a big-endian hex number such as an address or length.
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |value, &digit| {
+        value
+            .checked_mul(16)?
+            .checked_add(from_hex_digit(digit)? as u64)
+    })
+}
+
+/// Decodes hex encoded bytes into `out`, returning how many were written.
+fn decode_hex(digits: &[u8], out: &mut [u8]) -> Option<usize> {
+    if !digits.len().is_multiple_of(2) || digits.len() / 2 > out.len() {
+        return None;
+    }
+    for (pair, byte) in digits.chunks_exact(2).zip(out.iter_mut()) {
+        *byte = from_hex_digit(pair[0])? << 4 | from_hex_digit(pair[1])?;
+    }
+    Some(digits.len() / 2)
+}
+
+/// Parses `addr,length`.
+fn parse_range(args: &[u8]) -> Option<(u64, usize)> {
+    let comma = args.iter().position(|&byte| byte == b',')?;
+    let addr = parse_hex(&args[..comma])?;
+    let len = parse_hex(&args[comma + 1..])?;
+    Some((addr, len as usize))
+}
+
+/// Waits for a packet with a valid checksum, acknowledging it, and returns its length.
+fn receive(port: &mut SerialPort, buffer: &mut [u8; PACKET_SIZE]) -> usize {
+    loop {
+        while port.read_byte_blocking() != b'$' {}
+
+        let mut len = 0;
+        let mut checksum = 0u8;
+        let mut overflow = false;
+        loop {
+            let byte = port.read_byte_blocking();
+            if byte == b'#' {
+                break;
+            }
+            checksum = checksum.wrapping_add(byte);
+            match buffer.get_mut(len) {
+                Some(slot) => {
+                    *slot = byte;
+                    len += 1;
+                }
+                None => overflow = true,
+            }
+        }
+
+        let high = from_hex_digit(port.read_byte_blocking());
+        let low = from_hex_digit(port.read_byte_blocking());
+        let expected = high.zip(low).map(|(high, low)| high << 4 | low);
+
+        if !overflow && expected == Some(checksum) {
+            port.write_byte(b'+');
+            return len;
+        }
+        port.write_byte(b'-');
+    }
+}
+
+/// Sends a packet, retransmitting it until GDB acknowledges it.
+fn send(port: &mut SerialPort, data: &[u8]) {
+    let checksum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+
+    loop {
+        port.write_byte(b'$');
+        for &byte in data {
+            port.write_byte(byte);
+        }
+        port.write_byte(b'#');
+        port.write_byte(hex_digit(checksum >> 4));
+        port.write_byte(hex_digit(checksum));
+
+        loop {
+            match port.read_byte_blocking() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Talks to GDB until it continues or steps the interrupted code.
+pub(super) fn handle_trap(frame: &mut TrapFrame) {
+    let mut port = COM2.lock();
+    let mut reply = Packet::new();
+
+    stop(frame, &mut reply);
+    send(&mut port, reply.as_bytes());
+
+    let mut buffer = [0; PACKET_SIZE];
+    loop {
+        let len = receive(&mut port, &mut buffer);
+        reply.clear();
+
+        match handle_packet(frame, &buffer[..len], &mut reply) {
+            Action::Reply => send(&mut port, reply.as_bytes()),
+            Action::Resume => return,
+            Action::ReplyAndResume => {
+                send(&mut port, reply.as_bytes());
+                return;
+            }
+        }
+    }
+}
+
+/// Builds the stop reply for a new trap.
+///
+/// When an inserted breakpoint was hit, RIP is moved back onto the `int3` and the stop is
+/// reported as `swbreak`, which tells GDB the adjustment has been made.
+fn stop(frame: &mut TrapFrame, reply: &mut Packet) {
+    let addr = frame.rip.wrapping_sub(1);
+    let inserted = BREAKPOINTS
+        .lock()
+        .iter()
+        .flatten()
+        .any(|breakpoint| breakpoint.addr == addr);
+
+    if frame.vector == BREAKPOINT as u64 && inserted {
+        frame.rip = addr;
+        let _ = write!(reply, "T{:02x}swbreak:;", SIGTRAP);
+    } else {
+        let _ = write!(reply, "S{:02x}", SIGTRAP);
+    }
+}
+
+fn handle_packet(frame: &mut TrapFrame, packet: &[u8], reply: &mut Packet) -> Action {
+    let Some((&command, args)) = packet.split_first() else {
+        return Action::Reply;
+    };
+
+    match command {
+        b'?' => {
+            let _ = write!(reply, "S{:02x}", SIGTRAP);
+        }
+        b'g' => read_registers(frame, reply),
+        b'G' => reply_result(reply, write_registers(frame, args)),
+        b'm' => read_memory(args, reply),
+        b'M' => reply_result(reply, write_memory(args)),
+        b'c' | b's' => {
+            if !args.is_empty() {
+                match parse_hex(args) {
+                    Some(addr) => frame.rip = addr,
+                    None => {
+                        reply_result(reply, None);
+                        return Action::Reply;
+                    }
+                }
+            }
+            if command == b's' {
+                frame.rflags |= RFLAGS_TRAP;
+            } else {
+                frame.rflags &= !RFLAGS_TRAP;
+            }
+            return Action::Resume;
+        }
+        // Only software breakpoints are supported, other kinds get the empty reply.
+        b'Z' | b'z' => {
+            if let Some(args) = args.strip_prefix(b"0,") {
+                let addr = parse_range(args).map(|(addr, _kind)| addr);
+                let result = match command {
+                    b'Z' => addr.and_then(insert_breakpoint),
+                    _ => addr.and_then(remove_breakpoint),
+                };
+                reply_result(reply, result);
+            }
+        }
+        b'q' => {
+            if packet.starts_with(b"qSupported") {
+                let _ = write!(reply, "PacketSize={:x};swbreak+", PACKET_SIZE);
+            } else if packet.starts_with(b"qAttached") {
+                let _ = reply.write_str("1");
+            }
+        }
+        b'H' => reply_result(reply, Some(())),
+        b'D' => {
+            detach(frame);
+            reply_result(reply, Some(()));
+            return Action::ReplyAndResume;
+        }
+        b'k' => {
+            detach(frame);
+            return Action::Resume;
+        }
+        _ => {}
+    }
+
+    Action::Reply
+}
+
+/// Replies `OK` on success and with an `EFAULT` error otherwise.
+fn reply_result(reply: &mut Packet, result: Option<()>) {
+    let _ = reply.write_str(match result {
+        Some(()) => "OK",
+        None => "E0e",
+    });
+}
+
+fn data_segments() -> [u64; 4] {
+    let (ds, es, fs, gs): (u64, u64, u64, u64);
+    unsafe {
+        asm!(
+            "mov {}, ds",
+            "mov {}, es",
+            "mov {}, fs",
+            "mov {}, gs",
+            out(reg) ds,
+            out(reg) es,
+            out(reg) fs,
+            out(reg) gs,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    [ds, es, fs, gs]
+}
+
+fn registers_mut(frame: &mut TrapFrame) -> [&mut u64; 17] {
+    [
+        &mut frame.rax,
+        &mut frame.rbx,
+        &mut frame.rcx,
+        &mut frame.rdx,
+        &mut frame.rsi,
+        &mut frame.rdi,
+        &mut frame.rbp,
+        &mut frame.rsp,
+        &mut frame.r8,
+        &mut frame.r9,
+        &mut frame.r10,
+        &mut frame.r11,
+        &mut frame.r12,
+        &mut frame.r13,
+        &mut frame.r14,
+        &mut frame.r15,
+        &mut frame.rip,
+    ]
+}
+
+/// Sends the registers in GDB's x86-64 order: the 64-bit general purpose registers and
+/// RIP, then eflags and the segment registers as 32-bit values.
+fn read_registers(frame: &mut TrapFrame, reply: &mut Packet) {
+    let (rflags, cs, ss) = (frame.rflags, frame.cs, frame.ss);
+
+    for register in registers_mut(frame) {
+        reply.push_hex(&register.to_le_bytes());
+    }
+    let [ds, es, fs, gs] = data_segments();
+    for register in [rflags, cs, ss, ds, es, fs, gs] {
+        reply.push_hex(&(register as u32).to_le_bytes());
+    }
+}
+
+/// Updates the general purpose registers, RIP and RFLAGS. Segment registers can't be
+/// changed and any registers past them (FPU and SSE state) are ignored.
+fn write_registers(frame: &mut TrapFrame, args: &[u8]) -> Option<()> {
+    let mut bytes = [0; PACKET_SIZE / 2];
+    let len = decode_hex(args, &mut bytes)?;
+    if len < 17 * 8 + 4 {
+        return None;
+    }
+
+    for (register, value) in registers_mut(frame).into_iter().zip(bytes.chunks_exact(8)) {
+        *register = u64::from_le_bytes(value.try_into().unwrap());
+    }
+    let rflags = u32::from_le_bytes(bytes[17 * 8..17 * 8 + 4].try_into().unwrap());
+    frame.rflags = rflags as u64;
+
+    Some(())
+}
+
+fn read_memory(args: &[u8], reply: &mut Packet) {
+    let Some((addr, len)) = parse_range(args) else {
+        reply_result(reply, None);
+        return;
+    };
+
+    for offset in 0..len.min(PACKET_SIZE / 2) as u64 {
+        match extable::read_byte(addr.wrapping_add(offset)) {
+            Some(byte) => reply.push_hex(&[byte]),
+            // A short read is fine as long as something was read.
+            None if offset > 0 => return,
+            None => return reply_result(reply, None),
+        }
+    }
+}
+
+fn write_memory(args: &[u8]) -> Option<()> {
+    let colon = args.iter().position(|&byte| byte == b':')?;
+    let (addr, len) = parse_range(&args[..colon])?;
+
+    let mut bytes = [0; PACKET_SIZE / 2];
+    if decode_hex(&args[colon + 1..], &mut bytes)? != len {
+        return None;
+    }
+
+    for (offset, &byte) in bytes[..len].iter().enumerate() {
+        if !unsafe { extable::write_byte(addr.wrapping_add(offset as u64), byte) } {
+            return None;
+        }
+    }
+    Some(())
+}
+
+fn insert_breakpoint(addr: u64) -> Option<()> {
+    let mut breakpoints = BREAKPOINTS.lock();
+    if breakpoints
+        .iter()
+        .flatten()
+        .any(|breakpoint| breakpoint.addr == addr)
+    {
+        return Some(());
+    }
+
+    let slot = breakpoints.iter_mut().find(|slot| slot.is_none())?;
+    let original = extable::read_byte(addr)?;
+    if !unsafe { extable::write_byte(addr, INT3) } {
+        return None;
+    }
+    *slot = Some(SoftwareBreakpoint { addr, original });
+    Some(())
+}
+
+fn remove_breakpoint(addr: u64) -> Option<()> {
+    let mut breakpoints = BREAKPOINTS.lock();
+    let slot = breakpoints
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|breakpoint| breakpoint.addr == addr))?;
+
+    let breakpoint = slot.take()?;
+    unsafe { extable::write_byte(breakpoint.addr, breakpoint.original) }.then_some(())
+}
+
+/// Removes every inserted breakpoint and stops routing traps to GDB.
+fn detach(frame: &mut TrapFrame) {
+    for slot in BREAKPOINTS.lock().iter_mut() {
+        if let Some(breakpoint) = slot.take() {
+            unsafe { extable::write_byte(breakpoint.addr, breakpoint.original) };
+        }
+    }
+    frame.rflags &= !RFLAGS_TRAP;
+    ENABLED.store(false, Ordering::Release);
+}
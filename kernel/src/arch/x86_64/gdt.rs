@@ -0,0 +1,131 @@
+//! The global descriptor table and task state segment.
+//!
+//! The layout is dictated by `syscall`/`sysret`: the kernel data segment must directly
+//! follow the kernel code segment, and the user code segment must directly follow the
+//! user data segment.
+
+use core::arch::asm;
+use core::mem::size_of;
+
+use spin::Lazy;
+
+use super::tables::Gdtr;
+
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+pub const USER_DATA_SELECTOR: u16 = 0x18 | 3;
+pub const USER_CODE_SELECTOR: u16 = 0x20 | 3;
+pub const TSS_SELECTOR: u16 = 0x28;
+
+const KERNEL_CODE: u64 = 0x00af_9a00_0000_ffff;
+const KERNEL_DATA: u64 = 0x00cf_9200_0000_ffff;
+const USER_DATA: u64 = 0x00cf_f200_0000_ffff;
+const USER_CODE: u64 = 0x00af_fa00_0000_ffff;
+
+/// Size of the stack the CPU switches to when entering ring 0 from ring 3.
+const PRIVILEGE_STACK_SIZE: usize = 16 * 1024;
+
+/// IST slot used by the NMI handler. IST indices in IDT entries are 1-based.
+pub const NMI_IST_INDEX: u8 = 1;
+const NMI_STACK_SIZE: usize = 8 * 1024;
+
+#[repr(C, align(16))]
+struct Stack<const N: usize>([u8; N]);
+
+static mut PRIVILEGE_STACK: Stack<PRIVILEGE_STACK_SIZE> = Stack([0; PRIVILEGE_STACK_SIZE]);
+static mut NMI_STACK: Stack<NMI_STACK_SIZE> = Stack([0; NMI_STACK_SIZE]);
+
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved_1: u32,
+    /// The stack pointers loaded when switching to a more privileged ring.
+    pub privilege_stack_table: [u64; 3],
+    reserved_2: u64,
+    /// The stack pointers used by interrupt gates with a non-zero IST index.
+    pub interrupt_stack_table: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    const fn new() -> Self {
+        Self {
+            reserved_1: 0,
+            privilege_stack_table: [0; 3],
+            reserved_2: 0,
+            interrupt_stack_table: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            // No I/O permission bitmap.
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
+    let mut tss = TaskStateSegment::new();
+    tss.privilege_stack_table[0] = stack_top(&raw const PRIVILEGE_STACK);
+    tss.interrupt_stack_table[NMI_IST_INDEX as usize - 1] = stack_top(&raw const NMI_STACK);
+    tss
+});
+
+static GDT: Lazy<[u64; 7]> = Lazy::new(|| {
+    let (tss_low, tss_high) = tss_descriptor(&TSS);
+    [
+        0,
+        KERNEL_CODE,
+        KERNEL_DATA,
+        USER_DATA,
+        USER_CODE,
+        tss_low,
+        tss_high,
+    ]
+});
+
+fn stack_top<const N: usize>(stack: *const Stack<N>) -> u64 {
+    stack as u64 + N as u64
+}
+
+/// Builds the 16-byte system descriptor for an available 64-bit TSS.
+fn tss_descriptor(tss: &'static TaskStateSegment) -> (u64, u64) {
+    let base = tss as *const _ as u64;
+    let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+
+    let low = (limit & 0xffff)
+        | (base & 0xff_ffff) << 16
+        | 0x89 << 40
+        | (limit >> 16 & 0xf) << 48
+        | (base >> 24 & 0xff) << 56;
+
+    (low, base >> 32)
+}
+
+/// Loads the GDT, reloads every segment register and loads the task register.
+pub fn init() {
+    let pointer = Gdtr::new(&*GDT);
+
+    unsafe {
+        asm!(
+            "lgdt [{pointer}]",
+            // Reload CS with a far return.
+            "push {code}",
+            "lea {tmp}, [rip + 2f]",
+            "push {tmp}",
+            "retfq",
+            "2:",
+            "mov ds, {data:x}",
+            "mov es, {data:x}",
+            "mov ss, {data:x}",
+            "xor {tmp:e}, {tmp:e}",
+            "mov fs, {tmp:x}",
+            "mov gs, {tmp:x}",
+            "ltr {tss:x}",
+            pointer = in(reg) &pointer,
+            code = in(reg) KERNEL_CODE_SELECTOR as u64,
+            data = in(reg) KERNEL_DATA_SELECTOR as u64,
+            tss = in(reg) TSS_SELECTOR as u64,
+            tmp = out(reg) _,
+        );
+    }
+}
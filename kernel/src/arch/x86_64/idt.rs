@@ -0,0 +1,117 @@
+//! The interrupt descriptor table.
+
+use core::fmt;
+
+use spin::Lazy;
+
+use super::gdt::{KERNEL_CODE_SELECTOR, NMI_IST_INDEX};
+use super::tables::{self, Idtr};
+use super::{nmi, pic, trap};
+
+pub const DIVIDE_ERROR: u8 = 0;
+pub const DEBUG: u8 = 1;
+pub const NMI: u8 = 2;
+pub const BREAKPOINT: u8 = 3;
+pub const INVALID_OPCODE: u8 = 6;
+pub const DOUBLE_FAULT: u8 = 8;
+pub const GENERAL_PROTECTION: u8 = 13;
+pub const PAGE_FAULT: u8 = 14;
+pub const TIMER: u8 = pic::IRQ_BASE + pic::TIMER_IRQ;
+pub const KEYBOARD: u8 = pic::IRQ_BASE + pic::KEYBOARD_IRQ;
+pub const COM1: u8 = pic::IRQ_BASE + pic::COM1_IRQ;
+pub const SPURIOUS_IRQ: u8 = pic::IRQ_BASE + pic::SPURIOUS_IRQ;
+/// The vectors handed out for message signalled interrupts, see `interrupts`.
+pub const FIRST_DYNAMIC: u8 = 48;
+pub const LAST_DYNAMIC: u8 = 79;
+/// Where the local APIC sends interrupts it had to drop.
+pub const LAPIC_SPURIOUS: u8 = 0xff;
+
+/// The frame the CPU pushes when delivering an interrupt.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct InterruptStackFrame {
+    pub instruction_pointer: u64,
+    pub code_segment: u64,
+    pub cpu_flags: u64,
+    pub stack_pointer: u64,
+    pub stack_segment: u64,
+}
+
+impl InterruptStackFrame {
+    /// Returns whether the interrupted code was running in ring 3.
+    pub fn is_user(&self) -> bool {
+        self.code_segment & 3 == 3
+    }
+}
+
+impl fmt::Debug for InterruptStackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterruptStackFrame")
+            .field("rip", &format_args!("{:#018x}", self.instruction_pointer))
+            .field("cs", &format_args!("{:#x}", self.code_segment))
+            .field("rflags", &format_args!("{:#x}", self.cpu_flags))
+            .field("rsp", &format_args!("{:#018x}", self.stack_pointer))
+            .field("ss", &format_args!("{:#x}", self.stack_segment))
+            .finish()
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IdtEntry {
+    offset_low: u16,
+    selector: u16,
+    ist: u8,
+    attributes: u8,
+    offset_mid: u16,
+    offset_high: u32,
+    reserved: u32,
+}
+
+impl IdtEntry {
+    const MISSING: Self = Self {
+        offset_low: 0,
+        selector: 0,
+        ist: 0,
+        attributes: 0,
+        offset_mid: 0,
+        offset_high: 0,
+        reserved: 0,
+    };
+
+    /// A present, ring 0 interrupt gate.
+    fn new(handler: usize) -> Self {
+        let handler = handler as u64;
+        Self {
+            offset_low: handler as u16,
+            selector: KERNEL_CODE_SELECTOR,
+            ist: 0,
+            attributes: 0x8e,
+            offset_mid: (handler >> 16) as u16,
+            offset_high: (handler >> 32) as u32,
+            reserved: 0,
+        }
+    }
+
+    /// Makes the CPU switch to the given interrupt stack table slot on entry.
+    fn with_ist(mut self, index: u8) -> Self {
+        self.ist = index;
+        self
+    }
+}
+
+type Handler = extern "x86-interrupt" fn(InterruptStackFrame);
+
+static IDT: Lazy<[IdtEntry; 256]> = Lazy::new(|| {
+    let mut idt = [IdtEntry::MISSING; 256];
+    for &(vector, entry) in trap::ENTRIES {
+        idt[vector as usize] = IdtEntry::new(entry as usize);
+    }
+    idt[NMI as usize] = IdtEntry::new(nmi::handler as Handler as usize).with_ist(NMI_IST_INDEX);
+    idt
+});
+
+/// Loads the IDT.
+pub fn init() {
+    unsafe { tables::load_idt(&Idtr::new(&*IDT)) };
+}
@@ -0,0 +1,61 @@
+//! Vectors handed out at run time to devices with message signalled interrupts.
+//!
+//! The vectors from [`FIRST_DYNAMIC`] to [`LAST_DYNAMIC`] have entry stubs that call
+//! whatever handler [`alloc_vector`] installed, then acknowledge the interrupt at the
+//! local APIC.
+
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::idt::{FIRST_DYNAMIC, LAST_DYNAMIC};
+use super::lapic;
+
+const COUNT: usize = (LAST_DYNAMIC - FIRST_DYNAMIC) as usize + 1;
+
+/// The handler of each dynamic vector as a function pointer, or zero if it is free.
+static HANDLERS: [AtomicUsize; COUNT] = [const { AtomicUsize::new(0) }; COUNT];
+
+/// Every dynamic vector is taken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoFreeVector;
+
+impl fmt::Display for NoFreeVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "all {} interrupt vectors are taken", COUNT)
+    }
+}
+
+/// Takes a free vector and makes `handler` run for it. The handler runs with interrupts
+/// disabled and doesn't need to acknowledge the interrupt.
+pub fn alloc_vector(handler: fn()) -> Result<u8, NoFreeVector> {
+    HANDLERS
+        .iter()
+        .position(|slot| {
+            slot.compare_exchange(0, handler as usize, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        })
+        .map(|index| FIRST_DYNAMIC + index as u8)
+        .ok_or(NoFreeVector)
+}
+
+/// Gives `vector` back. The device using it must have stopped raising it.
+///
+/// ## Panics
+///
+/// Panics if `vector` isn't a dynamic one.
+pub fn free_vector(vector: u8) {
+    assert!((FIRST_DYNAMIC..=LAST_DYNAMIC).contains(&vector));
+    HANDLERS[(vector - FIRST_DYNAMIC) as usize].store(0, Ordering::Release);
+}
+
+pub(crate) fn dispatch(vector: u8) {
+    let handler = HANDLERS[(vector - FIRST_DYNAMIC) as usize].load(Ordering::Acquire);
+    if handler != 0 {
+        // SAFETY: Nonzero slots only ever hold a `fn()` stored by `alloc_vector`.
+        let handler = unsafe { core::mem::transmute::<usize, fn()>(handler) };
+        handler();
+    } else {
+        crate::kwarn!("interrupt on unallocated vector {}", vector);
+    }
+    lapic::end_of_interrupt();
+}
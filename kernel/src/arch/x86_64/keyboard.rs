@@ -0,0 +1,130 @@
+//! PS/2 keyboard input through IRQ 1.
+//!
+//! The controller translates to scancode set 1 by default, where a key's release code is
+//! its press code with bit 7 set, and keys added after the original keyboard are
+//! prefixed with `0xe0`. The interrupt handler only decodes and queues key presses;
+//! [`take_event`] hands them out to code that isn't in interrupt context.
+
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+
+use super::pic::{self, KEYBOARD_IRQ};
+use super::port::inb;
+
+const DATA: u16 = 0x60;
+const STATUS: u16 = 0x64;
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+
+const EXTENDED_PREFIX: u8 = 0xe0;
+const RELEASE: u8 = 1 << 7;
+const LEFT_SHIFT: u8 = 0x2a;
+const RIGHT_SHIFT: u8 = 0x36;
+/// Page up and down, after the extended prefix.
+const PAGE_UP: u8 = 0x49;
+const PAGE_DOWN: u8 = 0x51;
+
+/// How many key presses are queued until they are taken.
+pub const EVENT_QUEUE_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    PageUp,
+    PageDown,
+    /// Any other key, by its press code and whether it had the extended prefix.
+    Other {
+        scancode: u8,
+        extended: bool,
+    },
+}
+
+/// A key press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    /// Whether either shift key was held.
+    pub shift: bool,
+}
+
+impl KeyEvent {
+    fn encode(scancode: u8, extended: bool, shift: bool) -> u16 {
+        scancode as u16 | (extended as u16) << 8 | (shift as u16) << 9
+    }
+
+    fn decode(raw: u16) -> Self {
+        let scancode = raw as u8;
+        let extended = raw & 1 << 8 != 0;
+        let key = match (extended, scancode) {
+            (true, PAGE_UP) => Key::PageUp,
+            (true, PAGE_DOWN) => Key::PageDown,
+            _ => Key::Other { scancode, extended },
+        };
+        Self {
+            key,
+            shift: raw & 1 << 9 != 0,
+        }
+    }
+}
+
+/// Presses, written only by the interrupt handler and read only by [`take_event`]. One
+/// slot stays empty to tell a full queue from an empty one.
+static EVENTS: [AtomicU16; EVENT_QUEUE_SIZE] = [const { AtomicU16::new(0) }; EVENT_QUEUE_SIZE];
+static EVENTS_HEAD: AtomicUsize = AtomicUsize::new(0);
+static EVENTS_TAIL: AtomicUsize = AtomicUsize::new(0);
+
+/// Decoder state, only touched by the interrupt handler.
+static EXTENDED: AtomicBool = AtomicBool::new(false);
+static LEFT_SHIFT_HELD: AtomicBool = AtomicBool::new(false);
+static RIGHT_SHIFT_HELD: AtomicBool = AtomicBool::new(false);
+
+/// Drops whatever the controller has buffered and lets the keyboard interrupt through.
+pub fn init() {
+    unsafe {
+        while inb(STATUS) & STATUS_OUTPUT_FULL != 0 {
+            inb(DATA);
+        }
+    }
+    pic::unmask(KEYBOARD_IRQ);
+}
+
+/// Returns the oldest key press that wasn't taken yet. Presses while the queue is full
+/// are dropped.
+pub fn take_event() -> Option<KeyEvent> {
+    let tail = EVENTS_TAIL.load(Ordering::Relaxed);
+    if tail == EVENTS_HEAD.load(Ordering::Acquire) {
+        return None;
+    }
+    let raw = EVENTS[tail].load(Ordering::Relaxed);
+    EVENTS_TAIL.store((tail + 1) % EVENT_QUEUE_SIZE, Ordering::Release);
+    Some(KeyEvent::decode(raw))
+}
+
+pub(super) fn handle_interrupt() {
+    let byte = unsafe { inb(DATA) };
+    pic::end_of_interrupt(KEYBOARD_IRQ);
+
+    if byte == EXTENDED_PREFIX {
+        EXTENDED.store(true, Ordering::Relaxed);
+        return;
+    }
+    let extended = EXTENDED.swap(false, Ordering::Relaxed);
+    let released = byte & RELEASE != 0;
+    let scancode = byte & !RELEASE;
+
+    match (extended, scancode) {
+        (false, LEFT_SHIFT) => LEFT_SHIFT_HELD.store(!released, Ordering::Relaxed),
+        (false, RIGHT_SHIFT) => RIGHT_SHIFT_HELD.store(!released, Ordering::Relaxed),
+        _ if released => {}
+        _ => {
+            let shift =
+                LEFT_SHIFT_HELD.load(Ordering::Relaxed) || RIGHT_SHIFT_HELD.load(Ordering::Relaxed);
+            let head = EVENTS_HEAD.load(Ordering::Relaxed);
+            let next = (head + 1) % EVENT_QUEUE_SIZE;
+            if next != EVENTS_TAIL.load(Ordering::Acquire) {
+                EVENTS[head].store(
+                    KeyEvent::encode(scancode, extended, shift),
+                    Ordering::Relaxed,
+                );
+                EVENTS_HEAD.store(next, Ordering::Release);
+            }
+        }
+    }
+}
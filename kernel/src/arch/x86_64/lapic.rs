@@ -0,0 +1,117 @@
+//! The local APIC of the calling CPU, as far as message signalled interrupts need it.
+//!
+//! The legacy PIC keeps delivering the timer and other ISA interrupts through the local
+//! APIC's LINT0 pin. MSIs bypass both and go straight to a local APIC, which then has to
+//! be told about the end of each one instead of the PIC.
+
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::idt::LAPIC_SPURIOUS;
+use super::msr;
+use crate::boot::request::LimineRequest;
+
+const IA32_APIC_BASE: u32 = 0x1b;
+/// The APIC base MSR bit enabling the local APIC in hardware.
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS: u64 = 0x000f_ffff_ffff_f000;
+
+const ID: usize = 0x20;
+const END_OF_INTERRUPT: usize = 0xb0;
+const SPURIOUS_VECTOR: usize = 0xf0;
+const LVT_LINT0: usize = 0x350;
+const LVT_LINT1: usize = 0x360;
+
+/// The spurious vector register bit enabling the local APIC in software.
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+/// LINT0 passing the PIC's interrupts through, as firmware sets it up.
+const LINT_EXTINT: u32 = 0b111 << 8;
+/// LINT1 delivering NMIs.
+const LINT_NMI: u32 = 0b100 << 8;
+
+/// The HHDM address of the registers, or zero before [`init`].
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+/// Why the local APIC can't be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LapicError {
+    /// The APIC is disabled in the APIC base MSR, which can't be undone until reset.
+    Disabled,
+    /// The registers aren't reachable without the HHDM.
+    NoHhdm,
+}
+
+impl fmt::Display for LapicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Disabled => "the local APIC is disabled",
+            Self::NoHhdm => "no HHDM to reach the local APIC",
+        })
+    }
+}
+
+fn read(register: usize) -> u32 {
+    let base = BASE.load(Ordering::Relaxed) as usize;
+    unsafe { ptr::read_volatile((base + register) as *const u32) }
+}
+
+fn write(register: usize, value: u32) {
+    let base = BASE.load(Ordering::Relaxed) as usize;
+    unsafe { ptr::write_volatile((base + register) as *mut u32, value) };
+}
+
+/// Enables the calling CPU's local APIC in software, with [`LAPIC_SPURIOUS`] as its
+/// spurious vector.
+///
+/// If firmware left it disabled, its LVT entries were masked while it was, so LINT0 and
+/// LINT1 are set up again the way firmware does for the PIC: passing through external
+/// interrupts and NMIs.
+pub fn init() -> Result<(), LapicError> {
+    let hhdm = crate::boot::requests::HHDM
+        .response()
+        .ok_or(LapicError::NoHhdm)?;
+    let apic_base = unsafe { msr::rdmsr(IA32_APIC_BASE) };
+    if apic_base & APIC_BASE_ENABLE == 0 {
+        return Err(LapicError::Disabled);
+    }
+    // Before base revision 3 the HHDM covers the first 4 GiB, where the registers are.
+    BASE.store(
+        (apic_base & APIC_BASE_ADDRESS) + hhdm.offset,
+        Ordering::Relaxed,
+    );
+
+    let spurious = read(SPURIOUS_VECTOR);
+    write(
+        SPURIOUS_VECTOR,
+        (spurious & !0xff) | SOFTWARE_ENABLE | LAPIC_SPURIOUS as u32,
+    );
+    if spurious & SOFTWARE_ENABLE == 0 {
+        write(LVT_LINT0, LINT_EXTINT);
+        write(LVT_LINT1, LINT_NMI);
+    }
+    Ok(())
+}
+
+/// Whether [`init`] succeeded.
+pub fn is_initialized() -> bool {
+    BASE.load(Ordering::Relaxed) != 0
+}
+
+/// The local APIC ID, which MSIs address the CPU by.
+///
+/// ## Panics
+///
+/// Panics if [`init`] didn't succeed.
+pub fn id() -> u8 {
+    assert!(is_initialized(), "the local APIC is not initialized");
+    (read(ID) >> 24) as u8
+}
+
+/// Signals the end of the interrupt being handled. Spurious interrupts must not be
+/// acknowledged.
+pub fn end_of_interrupt() {
+    if is_initialized() {
+        write(END_OF_INTERRUPT, 0);
+    }
+}
@@ -0,0 +1,221 @@
+use core::arch::asm;
+use core::arch::x86_64::{__cpuid, __cpuid_count, _rdtsc};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::Arch;
+use crate::serial::COM1;
+
+pub mod control;
+pub mod debug;
+pub mod exception;
+pub mod extable;
+pub mod gdb;
+pub mod gdt;
+pub mod idt;
+#[cfg(feature = "msi")]
+pub mod interrupts;
+pub mod keyboard;
+#[cfg(feature = "msi")]
+pub mod lapic;
+pub mod msr;
+pub mod nmi;
+pub mod paging;
+pub mod pic;
+pub mod pit;
+pub mod port;
+#[cfg(feature = "smp")]
+pub mod smp;
+#[cfg(feature = "usermode")]
+pub mod syscall;
+pub mod tables;
+pub mod trap;
+
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub struct X86_64;
+
+impl Arch for X86_64 {
+    fn init() {
+        init();
+    }
+
+    fn enable_interrupts() {
+        enable_interrupts();
+    }
+
+    fn disable_interrupts() {
+        disable_interrupts();
+    }
+
+    fn wait_for_interrupt() {
+        halt();
+    }
+
+    fn cpu_id() -> u32 {
+        cpu_id()
+    }
+
+    fn timestamp() -> u64 {
+        rdtsc()
+    }
+
+    fn write_log(args: fmt::Arguments) {
+        // Writing to the serial port cannot fail.
+        COM1.lock().write_fmt(args).ok();
+    }
+
+    unsafe fn set_per_cpu_base(addr: u64) {
+        set_fsbase(addr);
+    }
+
+    fn per_cpu_base() -> u64 {
+        let base: u64;
+        unsafe {
+            asm!("mov {}, fs:[0]", out(reg) base, options(readonly, nostack, preserves_flags))
+        };
+        base
+    }
+}
+
+/// Sets up the descriptor tables and CPU protection features of the calling CPU, and the
+/// timer interrupt. Interrupts stay disabled until [`enable_interrupts`].
+pub fn init() {
+    gdt::init();
+    idt::init();
+    enable_protections();
+    #[cfg(feature = "usermode")]
+    syscall::init();
+    pic::init();
+    pit::init();
+    #[cfg(feature = "msi")]
+    if let Err(err) = lapic::init() {
+        crate::kwarn!("no message signalled interrupts: {}", err);
+    }
+    #[cfg(feature = "paging-mode")]
+    check_paging_mode();
+}
+
+/// Reports when the bootloader set up another paging mode than the one requested, or
+/// one that disagrees with the depth [`paging::Mapper`] walks.
+#[cfg(feature = "paging-mode")]
+fn check_paging_mode() {
+    use crate::boot::requests::PAGING_MODE;
+
+    let Some(response) = PAGING_MODE.get_response() else {
+        return;
+    };
+    if !response.got_requested(&PAGING_MODE) {
+        crate::kwarn!("the bootloader fell back to paging mode {}", response.mode);
+    }
+    let levels = if response.is_five_level() { 5 } else { 4 };
+    if levels != paging::levels() {
+        crate::kwarn!(
+            "the bootloader reported {}-level paging, the CPU runs {}-level paging",
+            levels,
+            paging::levels()
+        );
+    }
+}
+
+pub fn enable_interrupts() {
+    unsafe { asm!("sti", options(nomem, nostack)) };
+}
+
+pub fn disable_interrupts() {
+    unsafe { asm!("cli", options(nomem, nostack)) };
+}
+
+pub fn halt() {
+    unsafe { asm!("hlt", options(nomem, nostack)) };
+}
+
+/// Resets the machine by pulsing the reset line through the keyboard controller, or if
+/// that does nothing, by triple faulting.
+pub fn reboot() -> ! {
+    const KEYBOARD_COMMAND: u16 = 0x64;
+    const PULSE_RESET: u8 = 0xfe;
+
+    disable_interrupts();
+    unsafe {
+        port::outb(KEYBOARD_COMMAND, PULSE_RESET);
+        for _ in 0..1000 {
+            port::inb(0x80);
+        }
+
+        // With an empty IDT the breakpoint can't be delivered, and neither can the
+        // double fault that follows.
+        tables::load_idt(&tables::Idtr { limit: 0, base: 0 });
+        asm!("int3", options(nomem, nostack));
+    }
+    loop {
+        halt();
+    }
+}
+
+/// Enables SMEP and SMAP when the CPU supports them.
+fn enable_protections() {
+    let features = __cpuid_count(7, 0).ebx;
+    let mut cr4 = control::cr4();
+
+    if features & (1 << 7) != 0 {
+        cr4 |= control::CR4_SMEP;
+    }
+    if features & (1 << 20) != 0 {
+        cr4 |= control::CR4_SMAP;
+        SMAP_ENABLED.store(true, Ordering::Relaxed);
+    }
+
+    unsafe { control::set_cr4(cr4) };
+}
+
+/// Returns the initial APIC ID of the calling CPU.
+pub fn cpu_id() -> u32 {
+    __cpuid(1).ebx >> 24
+}
+
+/// Whether maskable interrupts are enabled on the calling CPU.
+pub fn interrupts_enabled() -> bool {
+    let rflags: u64;
+    unsafe { asm!("pushfq", "pop {}", out(reg) rflags, options(preserves_flags)) };
+    rflags & (1 << 9) != 0
+}
+
+/// Reads the time stamp counter.
+pub fn rdtsc() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Sets the base address `fs`-relative accesses are made against.
+///
+/// ## Safety
+///
+/// Code using `fs`-relative addressing, like [`crate::smp::read_per_cpu`], expects the base
+/// to point at whatever it was set up for.
+pub unsafe fn set_fsbase(addr: u64) {
+    msr::wrmsr(msr::IA32_FS_BASE, addr);
+}
+
+/// Sets the base address `gs`-relative accesses are made against.
+///
+/// ## Safety
+///
+/// Same as [`set_fsbase`], for `gs`.
+pub unsafe fn set_gsbase(addr: u64) {
+    msr::wrmsr(msr::IA32_GS_BASE, addr);
+}
+
+/// Runs `f` with supervisor access to user pages temporarily allowed.
+pub fn with_user_access<R>(f: impl FnOnce() -> R) -> R {
+    let smap = SMAP_ENABLED.load(Ordering::Relaxed);
+
+    if smap {
+        unsafe { asm!("stac", options(nomem, nostack)) };
+    }
+    let result = f();
+    if smap {
+        unsafe { asm!("clac", options(nomem, nostack)) };
+    }
+
+    result
+}
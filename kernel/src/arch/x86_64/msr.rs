@@ -0,0 +1,42 @@
+use core::arch::asm;
+
+pub const IA32_EFER: u32 = 0xc000_0080;
+pub const IA32_STAR: u32 = 0xc000_0081;
+pub const IA32_LSTAR: u32 = 0xc000_0082;
+pub const IA32_FMASK: u32 = 0xc000_0084;
+pub const IA32_FS_BASE: u32 = 0xc000_0100;
+pub const IA32_GS_BASE: u32 = 0xc000_0101;
+
+/// EFER bit enabling the `syscall`/`sysret` instructions.
+pub const EFER_SCE: u64 = 1 << 0;
+/// EFER bit enabling the no-execute page table bit.
+pub const EFER_NXE: u64 = 1 << 11;
+
+/// Reads a model-specific register.
+///
+/// ## Safety
+///
+/// The MSR must exist on this CPU, otherwise a #GP is raised.
+#[inline]
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes a model-specific register.
+///
+/// ## Safety
+///
+/// The MSR must exist on this CPU and the value must be valid for it. Many MSRs change
+/// fundamental CPU behaviour.
+#[inline]
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags),
+    );
+}
@@ -0,0 +1,50 @@
+//! Non-maskable interrupt handling.
+//!
+//! An NMI can arrive at any instruction boundary, including halfway through a stack
+//! switch, so the handler always runs on its own IST stack. It must also never take a
+//! lock another context may hold, which rules out printing: it only records the event
+//! and [`report_pending`], called from the idle loop, prints it later.
+//!
+//! ## Nested NMIs
+//!
+//! The CPU blocks further NMIs until the next `iretq`. If the handler itself raised an
+//! exception, that exception's `iretq` would unblock NMIs early and a second NMI would
+//! reuse the IST stack, overwriting the frame of the first. The handler is therefore
+//! kept fault-free: it only touches statics with atomic operations, so the only `iretq`
+//! executed while it runs is its own.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use super::idt::InterruptStackFrame;
+use crate::kprintln;
+
+static COUNT: AtomicU64 = AtomicU64::new(0);
+static LAST_RIP: AtomicU64 = AtomicU64::new(0);
+static PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Returns how many NMIs this CPU has received.
+pub fn count() -> u64 {
+    COUNT.load(Ordering::Relaxed)
+}
+
+/// Returns the instruction pointer interrupted by the most recent NMI.
+pub fn last_rip() -> u64 {
+    LAST_RIP.load(Ordering::Relaxed)
+}
+
+/// Prints NMIs received since the last call. Must not be called from NMI context.
+pub fn report_pending() {
+    if PENDING.swap(false, Ordering::Acquire) {
+        kprintln!(
+            "NMI received at RIP={:#018x} (total: {})",
+            last_rip(),
+            count()
+        );
+    }
+}
+
+pub(super) extern "x86-interrupt" fn handler(frame: InterruptStackFrame) {
+    LAST_RIP.store(frame.instruction_pointer, Ordering::Relaxed);
+    COUNT.fetch_add(1, Ordering::Relaxed);
+    PENDING.store(true, Ordering::Release);
+}
@@ -0,0 +1,176 @@
+//! Minimal page table walking and mapping through the higher half direct map.
+//!
+//! The tables are walked as deep as the paging the CPU is actually running with, four
+//! or five levels. Limine picks the mode before the kernel starts and may not give the
+//! one it was asked for, see `boot::paging_mode`.
+
+use core::arch::asm;
+
+use super::control;
+use crate::boot::PhysAddrRange;
+
+pub const PAGE_SIZE: u64 = 4096;
+
+pub const PRESENT: u64 = 1 << 0;
+pub const WRITABLE: u64 = 1 << 1;
+pub const USER: u64 = 1 << 2;
+pub const HUGE_PAGE: u64 = 1 << 7;
+pub const NO_EXECUTE: u64 = 1 << 63;
+
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+#[repr(C, align(4096))]
+pub struct PageTable(pub [u64; 512]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapError {
+    /// The virtual address is already mapped.
+    AlreadyMapped,
+    /// A huge page covers the virtual address.
+    HugePage,
+    /// No frame was available for an intermediate page table.
+    OutOfFrames,
+}
+
+/// Walks and edits the active page tables, accessing them through the HHDM.
+pub struct Mapper {
+    hhdm_offset: u64,
+    levels: u32,
+}
+
+impl Mapper {
+    /// ## Safety
+    ///
+    /// `hhdm_offset` must be the offset of the higher half direct map the bootloader set up,
+    /// and the caller must make sure no one else edits the page tables concurrently.
+    pub unsafe fn new(hhdm_offset: u64) -> Self {
+        Self {
+            hhdm_offset,
+            levels: levels(),
+        }
+    }
+
+    /// How many levels of tables the walks go through.
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    fn table(&self, phys: u64) -> *mut PageTable {
+        (phys + self.hhdm_offset) as *mut PageTable
+    }
+
+    fn index(virt: u64, level: u32) -> usize {
+        (virt >> (12 + 9 * (level - 1)) & 0x1ff) as usize
+    }
+
+    /// Translates a virtual address to the physical address it is mapped to.
+    pub fn translate(&self, virt: u64) -> Option<u64> {
+        let mut table = control::cr3() & ADDRESS_MASK;
+
+        for level in (1..=self.levels).rev() {
+            let entry = unsafe { (*self.table(table)).0[Self::index(virt, level)] };
+            if entry & PRESENT == 0 {
+                return None;
+            }
+
+            // 1 GiB and 2 MiB pages end the walk early.
+            if level == 1 || (level <= 3 && entry & HUGE_PAGE != 0) {
+                let page_mask = (1 << (12 + 9 * (level - 1))) - 1;
+                return Some((entry & ADDRESS_MASK & !page_mask) | (virt & page_mask));
+            }
+
+            table = entry & ADDRESS_MASK;
+        }
+
+        unreachable!()
+    }
+
+    /// Whether the HHDM maps all of `phys`, judging by the pages at its ends. Before base
+    /// revision 3 the HHDM covers the first 4 GiB and the memory map, but device memory
+    /// elsewhere, like 64-bit BARs, isn't necessarily mapped.
+    pub fn hhdm_covers(&self, phys: PhysAddrRange) -> bool {
+        phys.is_empty()
+            || [phys.start, phys.end - 1]
+                .iter()
+                .all(|&phys| self.translate(phys + self.hhdm_offset) == Some(phys))
+    }
+
+    /// Maps the 4 KiB page at `virt` to the frame at `phys` with `flags`.
+    ///
+    /// Missing intermediate tables are allocated from `alloc_frame`, which must return
+    /// the physical address of a zeroed, page-aligned frame. Intermediate entries are made
+    /// as permissive as the leaf needs; the leaf entry is what restricts access.
+    pub fn map(
+        &mut self,
+        virt: u64,
+        phys: u64,
+        flags: u64,
+        alloc_frame: &mut dyn FnMut() -> Option<u64>,
+    ) -> Result<(), MapError> {
+        let mut table = control::cr3() & ADDRESS_MASK;
+        let table_flags = PRESENT | WRITABLE | (flags & USER);
+
+        for level in (2..=self.levels).rev() {
+            let entry = unsafe { &mut (*self.table(table)).0[Self::index(virt, level)] };
+
+            if *entry & PRESENT == 0 {
+                let frame = alloc_frame().ok_or(MapError::OutOfFrames)?;
+                *entry = frame | table_flags;
+            } else if level <= 3 && *entry & HUGE_PAGE != 0 {
+                return Err(MapError::HugePage);
+            } else {
+                *entry |= table_flags;
+            }
+
+            table = *entry & ADDRESS_MASK;
+        }
+
+        let entry = unsafe { &mut (*self.table(table)).0[Self::index(virt, 1)] };
+        if *entry & PRESENT != 0 {
+            return Err(MapError::AlreadyMapped);
+        }
+        *entry = (phys & ADDRESS_MASK) | flags | PRESENT;
+
+        flush(virt);
+        Ok(())
+    }
+
+    /// Unmaps the 4 KiB page at `virt` and returns the frame it was mapped to, or `None`
+    /// if it isn't mapped by a 4 KiB page. Page tables left empty are kept.
+    pub fn unmap(&mut self, virt: u64) -> Option<u64> {
+        let mut table = control::cr3() & ADDRESS_MASK;
+
+        for level in (2..=self.levels).rev() {
+            let entry = unsafe { (*self.table(table)).0[Self::index(virt, level)] };
+            if entry & PRESENT == 0 || (level <= 3 && entry & HUGE_PAGE != 0) {
+                return None;
+            }
+            table = entry & ADDRESS_MASK;
+        }
+
+        let entry = unsafe { &mut (*self.table(table)).0[Self::index(virt, 1)] };
+        if *entry & PRESENT == 0 {
+            return None;
+        }
+        let phys = *entry & ADDRESS_MASK;
+        *entry = 0;
+
+        flush(virt);
+        Some(phys)
+    }
+}
+
+/// The depth of the active page tables, 5 with LA57 enabled and 4 otherwise.
+pub fn levels() -> u32 {
+    if control::cr4() & control::CR4_LA57 != 0 {
+        5
+    } else {
+        4
+    }
+}
+
+/// Invalidates the TLB entry for the page containing `virt`.
+#[inline]
+pub fn flush(virt: u64) {
+    unsafe { asm!("invlpg [{}]", in(reg) virt, options(nostack, preserves_flags)) };
+}
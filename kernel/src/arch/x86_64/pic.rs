@@ -0,0 +1,89 @@
+//! The legacy 8259 programmable interrupt controllers.
+
+use super::port::{inb, outb};
+
+const PIC1_COMMAND: u16 = 0x20;
+const PIC1_DATA: u16 = 0x21;
+const PIC2_COMMAND: u16 = 0xa0;
+const PIC2_DATA: u16 = 0xa1;
+
+const ICW1_INIT: u8 = 0x11;
+const ICW4_8086: u8 = 0x01;
+const END_OF_INTERRUPT: u8 = 0x20;
+
+/// The vector IRQ 0 is delivered on. IRQs 0 to 15 follow it, clear of the CPU exceptions.
+pub const IRQ_BASE: u8 = 32;
+/// The IRQ line of the PIT.
+pub const TIMER_IRQ: u8 = 0;
+/// The IRQ line of the PS/2 keyboard.
+pub const KEYBOARD_IRQ: u8 = 1;
+/// The IRQ line of the first serial port.
+pub const COM1_IRQ: u8 = 4;
+/// The IRQ line the primary chip reports spurious interrupts on.
+pub const SPURIOUS_IRQ: u8 = 7;
+
+/// Gives the chips a moment to process a command by writing to an unused port.
+unsafe fn io_wait() {
+    outb(0x80, 0);
+}
+
+/// Moves the IRQs to [`IRQ_BASE`] and masks everything but the timer.
+pub fn init() {
+    unsafe {
+        outb(PIC1_COMMAND, ICW1_INIT);
+        io_wait();
+        outb(PIC2_COMMAND, ICW1_INIT);
+        io_wait();
+        outb(PIC1_DATA, IRQ_BASE);
+        io_wait();
+        outb(PIC2_DATA, IRQ_BASE + 8);
+        io_wait();
+        // The secondary chip is cascaded through IRQ 2 of the primary.
+        outb(PIC1_DATA, 1 << 2);
+        io_wait();
+        outb(PIC2_DATA, 2);
+        io_wait();
+        outb(PIC1_DATA, ICW4_8086);
+        io_wait();
+        outb(PIC2_DATA, ICW4_8086);
+        io_wait();
+
+        outb(PIC1_DATA, !(1 << TIMER_IRQ));
+        outb(PIC2_DATA, 0xff);
+    }
+}
+
+/// Lets the chips deliver `irq`, which [`init`] masked.
+pub fn unmask(irq: u8) {
+    unsafe {
+        if irq < 8 {
+            outb(PIC1_DATA, inb(PIC1_DATA) & !(1 << irq));
+        } else {
+            outb(PIC2_DATA, inb(PIC2_DATA) & !(1 << (irq - 8)));
+        }
+    }
+}
+
+/// Returns whether `irq` is really being serviced, as opposed to being spurious.
+pub fn is_in_service(irq: u8) -> bool {
+    const READ_ISR: u8 = 0x0b;
+    unsafe {
+        if irq < 8 {
+            outb(PIC1_COMMAND, READ_ISR);
+            inb(PIC1_COMMAND) & 1 << irq != 0
+        } else {
+            outb(PIC2_COMMAND, READ_ISR);
+            inb(PIC2_COMMAND) & 1 << (irq - 8) != 0
+        }
+    }
+}
+
+/// Acknowledges `irq` so the chips deliver the next one.
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outb(PIC2_COMMAND, END_OF_INTERRUPT);
+        }
+        outb(PIC1_COMMAND, END_OF_INTERRUPT);
+    }
+}
@@ -0,0 +1,59 @@
+//! The 8253/8254 programmable interval timer, used as the periodic timer interrupt.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::pic::{self, TIMER_IRQ};
+use super::port::{inb, outb};
+use super::trap::TrapFrame;
+
+const CHANNEL0_DATA: u16 = 0x40;
+const MODE_COMMAND: u16 = 0x43;
+
+/// The PIT's input clock.
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+/// How often the timer interrupt fires.
+pub const TIMER_HZ: u32 = 100;
+
+/// What channel 0 counts down from in every timer period.
+pub const DIVISOR: u16 = (BASE_FREQUENCY / TIMER_HZ) as u16;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Starts channel 0 as a rate generator at [`TIMER_HZ`].
+pub fn init() {
+    unsafe {
+        // Channel 0, low byte then high byte, mode 2.
+        outb(MODE_COMMAND, 0x34);
+        outb(CHANNEL0_DATA, DIVISOR as u8);
+        outb(CHANNEL0_DATA, (DIVISOR >> 8) as u8);
+    }
+}
+
+/// Reads channel 0's current count, which runs down from [`DIVISOR`] once per timer
+/// period at [`BASE_FREQUENCY`].
+pub fn read_count() -> u16 {
+    unsafe {
+        // Latch channel 0, then read the latched count low byte first.
+        outb(MODE_COMMAND, 0x00);
+        let low = inb(CHANNEL0_DATA);
+        let high = inb(CHANNEL0_DATA);
+        u16::from_le_bytes([low, high])
+    }
+}
+
+/// The number of timer interrupts since boot.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+#[cfg_attr(not(feature = "watchdog"), allow(unused_variables))]
+pub(super) fn handle_tick(frame: &TrapFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    pic::end_of_interrupt(TIMER_IRQ);
+
+    #[cfg(feature = "tasks")]
+    crate::task::request_reschedule();
+    #[cfg(feature = "watchdog")]
+    crate::watchdog::tick(frame);
+}
@@ -0,0 +1,37 @@
+//! Helpers for the limine crate's x86_64 SMP structures.
+
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use limine::LimineSmpInfo;
+
+pub trait LimineSmpInfoExt {
+    /// Stores a pointer in `extra_argument`, for the started CPU to read back with
+    /// [`get_extra_ptr`](Self::get_extra_ptr). Store it before writing `goto_address`.
+    fn set_extra_ptr<T>(&self, ptr: *const T);
+
+    /// Reads `extra_argument` back as a pointer.
+    ///
+    /// ## Safety
+    ///
+    /// The argument must have been stored by [`set_extra_ptr`](Self::set_extra_ptr) with
+    /// the same `T`.
+    unsafe fn get_extra_ptr<T>(&self) -> *const T;
+}
+
+impl LimineSmpInfoExt for LimineSmpInfo {
+    fn set_extra_ptr<T>(&self, ptr: *const T) {
+        extra_argument(self).store(ptr as usize as u64, Ordering::Release);
+    }
+
+    unsafe fn get_extra_ptr<T>(&self) -> *const T {
+        extra_argument(self).load(Ordering::Acquire) as usize as *const T
+    }
+}
+
+/// The field is shared between CPUs, so it is only ever accessed atomically.
+fn extra_argument(info: &LimineSmpInfo) -> &AtomicU64 {
+    // SAFETY: The field is a naturally aligned `u64` in memory the bootloader handed
+    // over, and nothing accesses it non-atomically.
+    unsafe { AtomicU64::from_ptr(ptr::addr_of!(info.extra_argument).cast_mut()) }
+}
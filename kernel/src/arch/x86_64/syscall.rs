@@ -0,0 +1,75 @@
+//! `syscall` entry point.
+//!
+//! Only a single CPU ever runs user code, so the user stack pointer is stashed in a
+//! plain static and the entry stub runs on a dedicated static kernel stack.
+
+use core::arch::naked_asm;
+
+use super::gdt::{KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR};
+use super::msr::{self, EFER_SCE, IA32_EFER, IA32_FMASK, IA32_LSTAR, IA32_STAR};
+
+const SYSCALL_STACK_SIZE: usize = 16 * 1024;
+
+/// RFLAGS bits cleared on entry: TF, IF, DF, NT and AC.
+const SYSCALL_FLAGS_MASK: u64 = 1 << 8 | 1 << 9 | 1 << 10 | 1 << 14 | 1 << 18;
+
+#[repr(C, align(16))]
+struct Stack([u8; SYSCALL_STACK_SIZE]);
+
+static mut SYSCALL_STACK: Stack = Stack([0; SYSCALL_STACK_SIZE]);
+static mut USER_RSP: u64 = 0;
+
+/// Enables `syscall`/`sysret` and points the CPU at the entry stub.
+pub fn init() {
+    // `sysret` derives the user selectors from this base: SS is base + 8 and CS is base + 16.
+    let sysret_base = (KERNEL_DATA_SELECTOR | 3) as u64;
+
+    unsafe {
+        msr::wrmsr(
+            IA32_STAR,
+            sysret_base << 48 | (KERNEL_CODE_SELECTOR as u64) << 32,
+        );
+        msr::wrmsr(IA32_LSTAR, syscall_entry as *const () as u64);
+        msr::wrmsr(IA32_FMASK, SYSCALL_FLAGS_MASK);
+        msr::wrmsr(IA32_EFER, msr::rdmsr(IA32_EFER) | EFER_SCE);
+    }
+}
+
+/// The number is passed in `rax` and up to three arguments in `rdi`, `rsi` and `rdx`. The
+/// result is returned in `rax`; `rcx` and `r11` are clobbered, every other register is
+/// preserved.
+#[unsafe(naked)]
+unsafe extern "C" fn syscall_entry() {
+    naked_asm!(
+        "mov [rip + {user_rsp}], rsp",
+        "lea rsp, [rip + {stack} + {stack_size}]",
+        // User RIP and RFLAGS, needed by `sysretq`.
+        "push rcx",
+        "push r11",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push r8",
+        "push r9",
+        "push r10",
+        "mov rcx, rdx",
+        "mov rdx, rsi",
+        "mov rsi, rdi",
+        "mov rdi, rax",
+        "call {dispatch}",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop r11",
+        "pop rcx",
+        "mov rsp, [rip + {user_rsp}]",
+        "sysretq",
+        user_rsp = sym USER_RSP,
+        stack = sym SYSCALL_STACK,
+        stack_size = const SYSCALL_STACK_SIZE,
+        dispatch = sym crate::usermode::handle_syscall,
+    );
+}
@@ -0,0 +1,108 @@
+//! Descriptor tables and the pointers `lgdt` and `lidt` load them from.
+//!
+//! The tables here are plain arrays of 8-byte slots. Descriptors that are 16 bytes in
+//! long mode, like TSS descriptors and IDT gates, take two consecutive slots.
+
+use core::arch::asm;
+use core::mem::size_of;
+
+/// The operand of `lgdt`, `lidt`, `sgdt` and `sidt`: the table's limit and base. In long
+/// mode the base is 64 bits wide, making it 10 bytes rather than the 6 of protected mode.
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct DescriptorTablePointer {
+    /// The size of the table in bytes, minus one.
+    pub limit: u16,
+    pub base: u64,
+}
+
+pub type Gdtr = DescriptorTablePointer;
+pub type Idtr = DescriptorTablePointer;
+
+impl DescriptorTablePointer {
+    /// Points at `table`, whose size must fit the 16-bit limit.
+    pub fn new<T>(table: &T) -> Self {
+        Self {
+            limit: (size_of::<T>() - 1) as u16,
+            base: table as *const T as u64,
+        }
+    }
+}
+
+/// A table of `N` 8-byte descriptors, for use as a GDT, LDT or IDT.
+#[repr(C, align(16))]
+#[derive(Clone, Debug)]
+pub struct DescriptorTable<const N: usize> {
+    descriptors: [u64; N],
+}
+
+impl<const N: usize> DescriptorTable<N> {
+    /// A table of null descriptors.
+    pub const fn new() -> Self {
+        Self {
+            descriptors: [0; N],
+        }
+    }
+
+    /// Stores `desc` in slot `index`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_descriptor(&mut self, index: u16, desc: u64) {
+        self.descriptors[index as usize] = desc;
+    }
+
+    pub fn descriptor(&self, index: u16) -> Option<u64> {
+        self.descriptors.get(index as usize).copied()
+    }
+
+    pub fn pointer(&self) -> DescriptorTablePointer {
+        DescriptorTablePointer::new(&self.descriptors)
+    }
+
+    /// Makes this the calling CPU's GDT. The segment registers keep their cached
+    /// descriptors until they are reloaded.
+    ///
+    /// ## Safety
+    ///
+    /// The table must stay in place and unchanged for as long as it's loaded, and hold
+    /// valid descriptors for every selector that is or will be loaded.
+    pub unsafe fn load_gdt(&self) {
+        load_gdt(&self.pointer());
+    }
+
+    /// Makes this the calling CPU's IDT.
+    ///
+    /// ## Safety
+    ///
+    /// The table must stay in place for as long as it's loaded, and hold valid gates
+    /// for every vector that can be raised.
+    pub unsafe fn load_idt(&self) {
+        load_idt(&self.pointer());
+    }
+}
+
+impl<const N: usize> Default for DescriptorTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Loads `gdtr` into the GDT register.
+///
+/// ## Safety
+///
+/// See [`DescriptorTable::load_gdt`].
+pub unsafe fn load_gdt(gdtr: &Gdtr) {
+    asm!("lgdt [{}]", in(reg) gdtr, options(readonly, nostack, preserves_flags));
+}
+
+/// Loads `idtr` into the IDT register.
+///
+/// ## Safety
+///
+/// See [`DescriptorTable::load_idt`].
+pub unsafe fn load_idt(idtr: &Idtr) {
+    asm!("lidt [{}]", in(reg) idtr, options(readonly, nostack, preserves_flags));
+}
@@ -0,0 +1,258 @@
+//! Exception entry stubs that save the complete register state.
+//!
+//! The `x86-interrupt` ABI only exposes the frame the CPU pushes, so CPU exceptions are
+//! entered through these stubs instead, which lets handlers like the debugger inspect and
+//! change general purpose registers. They push every register into a [`TrapFrame`] and
+//! restore it (including any changes the handler made) before returning with `iretq`.
+
+use core::arch::naked_asm;
+use core::fmt;
+
+use super::exception::{self, ControlRegisters};
+use super::idt::{
+    BREAKPOINT, COM1, DEBUG, GENERAL_PROTECTION, KEYBOARD, LAPIC_SPURIOUS, PAGE_FAULT,
+    SPURIOUS_IRQ, TIMER,
+};
+use super::{debug, extable, keyboard, pic, pit};
+
+/// Every register of the interrupted context, in the order the entry stub pushes them.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TrapFrame {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+    pub vector: u64,
+    /// The error code pushed by the CPU, or zero for exceptions without one.
+    pub error_code: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+}
+
+impl TrapFrame {
+    /// Returns whether the interrupted code was running in ring 3.
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+}
+
+impl fmt::Debug for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "rip={:#018x} rsp={:#018x} rflags={:#x} cs={:#x} ss={:#x}",
+            self.rip, self.rsp, self.rflags, self.cs, self.ss
+        )?;
+        writeln!(
+            f,
+            "rax={:#018x} rbx={:#018x} rcx={:#018x} rdx={:#018x}",
+            self.rax, self.rbx, self.rcx, self.rdx
+        )?;
+        writeln!(
+            f,
+            "rsi={:#018x} rdi={:#018x} rbp={:#018x} r8 ={:#018x}",
+            self.rsi, self.rdi, self.rbp, self.r8
+        )?;
+        writeln!(
+            f,
+            "r9 ={:#018x} r10={:#018x} r11={:#018x} r12={:#018x}",
+            self.r9, self.r10, self.r11, self.r12
+        )?;
+        write!(
+            f,
+            "r13={:#018x} r14={:#018x} r15={:#018x}",
+            self.r13, self.r14, self.r15
+        )
+    }
+}
+
+/// Defines the entry stubs of the listed vectors and the [`ENTRIES`] table pointing at
+/// them. Vectors for which the CPU doesn't push an error code push a zero in its place,
+/// so every handler sees the same [`TrapFrame`] layout.
+macro_rules! trap_stubs {
+    ($($vector:literal => $name:ident $(($error_code:ident))?,)*) => {
+        $(
+            #[unsafe(naked)]
+            extern "C" fn $name() {
+                naked_asm!(
+                    trap_stubs!(@error_code $($error_code)?),
+                    "push {vector}",
+                    "jmp {common}",
+                    vector = const $vector,
+                    common = sym trap_common,
+                );
+            }
+        )*
+
+        /// Every vector handled through [`dispatch`] and its entry stub.
+        pub(super) const ENTRIES: &[(u8, extern "C" fn())] = &[$(($vector, $name)),*];
+    };
+    (@error_code) => { "push 0" };
+    (@error_code error_code) => { "" };
+}
+
+// NMIs use their own handler and stack, see `nmi`.
+trap_stubs! {
+    0 => divide_error_entry,
+    1 => debug_entry,
+    3 => breakpoint_entry,
+    4 => overflow_entry,
+    5 => bound_range_entry,
+    6 => invalid_opcode_entry,
+    7 => device_not_available_entry,
+    8 => double_fault_entry(error_code),
+    9 => coprocessor_segment_overrun_entry,
+    10 => invalid_tss_entry(error_code),
+    11 => segment_not_present_entry(error_code),
+    12 => stack_segment_fault_entry(error_code),
+    13 => general_protection_entry(error_code),
+    14 => page_fault_entry(error_code),
+    15 => reserved_15_entry,
+    16 => x87_floating_point_entry,
+    17 => alignment_check_entry(error_code),
+    18 => machine_check_entry,
+    19 => simd_floating_point_entry,
+    20 => virtualization_entry,
+    21 => control_protection_entry(error_code),
+    22 => reserved_22_entry,
+    23 => reserved_23_entry,
+    24 => reserved_24_entry,
+    25 => reserved_25_entry,
+    26 => reserved_26_entry,
+    27 => reserved_27_entry,
+    28 => hypervisor_injection_entry,
+    29 => vmm_communication_entry(error_code),
+    30 => security_exception_entry(error_code),
+    31 => reserved_31_entry,
+    // IRQs remapped by the PIC, see the IRQ vectors in `idt`.
+    32 => timer_entry,
+    33 => keyboard_entry,
+    36 => com1_entry,
+    39 => spurious_irq_entry,
+    // Message signalled interrupts, see `interrupts`.
+    48 => dynamic_48_entry,
+    49 => dynamic_49_entry,
+    50 => dynamic_50_entry,
+    51 => dynamic_51_entry,
+    52 => dynamic_52_entry,
+    53 => dynamic_53_entry,
+    54 => dynamic_54_entry,
+    55 => dynamic_55_entry,
+    56 => dynamic_56_entry,
+    57 => dynamic_57_entry,
+    58 => dynamic_58_entry,
+    59 => dynamic_59_entry,
+    60 => dynamic_60_entry,
+    61 => dynamic_61_entry,
+    62 => dynamic_62_entry,
+    63 => dynamic_63_entry,
+    64 => dynamic_64_entry,
+    65 => dynamic_65_entry,
+    66 => dynamic_66_entry,
+    67 => dynamic_67_entry,
+    68 => dynamic_68_entry,
+    69 => dynamic_69_entry,
+    70 => dynamic_70_entry,
+    71 => dynamic_71_entry,
+    72 => dynamic_72_entry,
+    73 => dynamic_73_entry,
+    74 => dynamic_74_entry,
+    75 => dynamic_75_entry,
+    76 => dynamic_76_entry,
+    77 => dynamic_77_entry,
+    78 => dynamic_78_entry,
+    79 => dynamic_79_entry,
+    255 => lapic_spurious_entry,
+}
+
+/// Saves the general purpose registers, calls [`dispatch`] and restores them.
+///
+/// The CPU aligns the stack before pushing its five qword frame, so after the error code,
+/// vector and fifteen registers the stack is 16 byte aligned again for the call.
+#[unsafe(naked)]
+extern "C" fn trap_common() {
+    naked_asm!(
+        "push rax",
+        "push rbx",
+        "push rcx",
+        "push rdx",
+        "push rsi",
+        "push rdi",
+        "push rbp",
+        "push r8",
+        "push r9",
+        "push r10",
+        "push r11",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov rdi, rsp",
+        "cld",
+        "call {dispatch}",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop r11",
+        "pop r10",
+        "pop r9",
+        "pop r8",
+        "pop rbp",
+        "pop rdi",
+        "pop rsi",
+        "pop rdx",
+        "pop rcx",
+        "pop rbx",
+        "pop rax",
+        // Drop the vector and error code.
+        "add rsp, 16",
+        "iretq",
+        dispatch = sym dispatch,
+    );
+}
+
+extern "C" fn dispatch(frame: &mut TrapFrame) {
+    // Read the control registers first, before a nested page fault can replace CR2.
+    let control = ControlRegisters::read();
+
+    match frame.vector as u8 {
+        DEBUG => debug::handle_debug(frame),
+        BREAKPOINT => debug::handle_breakpoint(frame),
+        GENERAL_PROTECTION | PAGE_FAULT => match extable::search(frame.rip) {
+            Some(fixup) => frame.rip = fixup,
+            None => exception::fault(frame, &control),
+        },
+        TIMER => pit::handle_tick(frame),
+        KEYBOARD => keyboard::handle_interrupt(),
+        COM1 => crate::serial::handle_com1_interrupt(),
+        // A spurious IRQ from the primary chip must not be acknowledged.
+        SPURIOUS_IRQ => {
+            if pic::is_in_service(pic::SPURIOUS_IRQ) {
+                pic::end_of_interrupt(pic::SPURIOUS_IRQ);
+            }
+        }
+        #[cfg(feature = "msi")]
+        vector @ super::idt::FIRST_DYNAMIC..=super::idt::LAST_DYNAMIC => {
+            super::interrupts::dispatch(vector)
+        }
+        // Spurious local APIC interrupts must not be acknowledged either.
+        LAPIC_SPURIOUS => {}
+        _ => exception::fault(frame, &control),
+    }
+}
@@ -0,0 +1,346 @@
+//! Finding ACPI tables through the RSDP response.
+//!
+//! The RSDP leads to the RSDT, or from revision 2 on to the XSDT, which lists the
+//! physical addresses of all other tables. Every table starts with the same 36 byte
+//! header holding its signature and length, and like the RSDP its bytes sum to zero.
+
+use core::{fmt, slice};
+
+use limine::LimineRsdpResponse;
+
+use super::request::LimineRequest;
+use super::requests::{HHDM, RSDP};
+use super::PhysAddr;
+
+const RSDP_SIGNATURE: &[u8] = b"RSD PTR ";
+/// The length of the ACPI 1.0 RSDP, which the first checksum covers.
+const RSDP_V1_LEN: usize = 20;
+const HEADER_LEN: usize = 36;
+
+pub const RSDT_SIGNATURE: [u8; 4] = *b"RSDT";
+pub const XSDT_SIGNATURE: [u8; 4] = *b"XSDT";
+pub const HPET_SIGNATURE: [u8; 4] = *b"HPET";
+pub const MCFG_SIGNATURE: [u8; 4] = *b"MCFG";
+
+/// Where the MCFG table's entries start, after the header and 8 reserved bytes.
+const MCFG_ENTRIES: usize = HEADER_LEN + 8;
+const MCFG_ENTRY_LEN: usize = 16;
+
+/// Why a table couldn't be found or used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The bootloader found no RSDP.
+    NoRsdp,
+    /// The RSDP or a table doesn't start with its signature.
+    BadSignature,
+    /// The bytes of the RSDP or a table don't sum to zero.
+    BadChecksum,
+    /// The RSDP or a table is too short for its fields.
+    Truncated,
+    /// The tables aren't reachable without the HHDM.
+    NoHhdm,
+    /// No table has the signature looked for.
+    NotFound,
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoRsdp => "no ACPI RSDP",
+            Self::BadSignature => "ACPI table has a bad signature",
+            Self::BadChecksum => "ACPI table has a bad checksum",
+            Self::Truncated => "ACPI table is truncated",
+            Self::NoHhdm => "no HHDM to reach the ACPI tables",
+            Self::NotFound => "no such ACPI table",
+        })
+    }
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn le32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Where the RSDP says the root table is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rsdp {
+    pub revision: u8,
+    /// The physical address of the XSDT if there is one, else of the RSDT.
+    pub root_address: PhysAddr,
+    /// Whether the root table is the XSDT, with 64-bit entries.
+    pub extended: bool,
+}
+
+impl Rsdp {
+    /// Validates an RSDP, including the extended part from revision 2 on.
+    pub fn parse(bytes: &[u8]) -> Result<Self, AcpiError> {
+        if !bytes.starts_with(RSDP_SIGNATURE) {
+            return Err(AcpiError::BadSignature);
+        }
+        let v1 = bytes.get(..RSDP_V1_LEN).ok_or(AcpiError::Truncated)?;
+        if !checksum_ok(v1) {
+            return Err(AcpiError::BadChecksum);
+        }
+        let revision = v1[15];
+
+        if revision >= 2 {
+            let len = le32(bytes, 20).ok_or(AcpiError::Truncated)? as usize;
+            let rsdp = bytes.get(..len).filter(|_| len >= 36);
+            let rsdp = rsdp.ok_or(AcpiError::Truncated)?;
+            if !checksum_ok(rsdp) {
+                return Err(AcpiError::BadChecksum);
+            }
+            let xsdt = le64(rsdp, 24).unwrap();
+            if xsdt != 0 {
+                return Ok(Self {
+                    revision,
+                    root_address: xsdt,
+                    extended: true,
+                });
+            }
+        }
+
+        Ok(Self {
+            revision,
+            root_address: le32(v1, 16).unwrap() as u64,
+            extended: false,
+        })
+    }
+}
+
+/// Checks that `bytes` start with a whole table of `signature` with a valid checksum,
+/// and returns that table.
+pub fn validate_table<'a>(bytes: &'a [u8], signature: &[u8; 4]) -> Result<&'a [u8], AcpiError> {
+    let len = le32(bytes, 4).ok_or(AcpiError::Truncated)? as usize;
+    if !bytes.starts_with(signature) {
+        return Err(AcpiError::BadSignature);
+    }
+    let table = bytes.get(..len).filter(|_| len >= HEADER_LEN);
+    let table = table.ok_or(AcpiError::Truncated)?;
+    if !checksum_ok(table) {
+        return Err(AcpiError::BadChecksum);
+    }
+    Ok(table)
+}
+
+/// What the HPET table says about the first HPET.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HpetInfo {
+    /// The physical address of the registers.
+    pub base_address: PhysAddr,
+    /// Whether the main counter is 64 bits wide rather than 32.
+    pub counter_size: bool,
+    /// Whether the HPET can take over the PIT and RTC interrupts.
+    pub legacy_replacement: bool,
+}
+
+impl HpetInfo {
+    /// Reads an HPET table, validating it first.
+    pub fn parse(bytes: &[u8]) -> Result<Self, AcpiError> {
+        let table = validate_table(bytes, &HPET_SIGNATURE)?;
+        let block_id = le32(table, 36).ok_or(AcpiError::Truncated)?;
+        // The address is the one of a generic address structure, which starts at 40.
+        let base_address = le64(table, 44).ok_or(AcpiError::Truncated)?;
+        Ok(Self {
+            base_address,
+            counter_size: block_id & (1 << 13) != 0,
+            legacy_replacement: block_id & (1 << 15) != 0,
+        })
+    }
+}
+
+/// One ECAM window from the MCFG table: the memory mapped configuration space of a range
+/// of buses on one segment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct McfgEntry {
+    /// The physical address of bus 0's configuration space, even if the window starts at
+    /// a later bus.
+    pub base_address: PhysAddr,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+/// Reads the entries of an MCFG table, validating it first.
+pub fn mcfg_entries(bytes: &[u8]) -> Result<impl Iterator<Item = McfgEntry> + '_, AcpiError> {
+    let table = validate_table(bytes, &MCFG_SIGNATURE)?;
+    let entries = table.get(MCFG_ENTRIES..).ok_or(AcpiError::Truncated)?;
+    Ok(entries.chunks_exact(MCFG_ENTRY_LEN).map(|entry| McfgEntry {
+        base_address: le64(entry, 0).unwrap(),
+        segment: u16::from_le_bytes([entry[8], entry[9]]),
+        start_bus: entry[10],
+        end_bus: entry[11],
+    }))
+}
+
+/// The tables the root table lists.
+#[derive(Clone, Copy, Debug)]
+pub struct Acpi {
+    hhdm: u64,
+    /// The root table's entries.
+    entries: &'static [u8],
+    entry_size: usize,
+}
+
+impl Acpi {
+    /// Finds the root table through the RSDP response.
+    pub fn from_response(response: &LimineRsdpResponse) -> Result<Self, AcpiError> {
+        let hhdm = HHDM.response().ok_or(AcpiError::NoHhdm)?.offset;
+        let address = response.address.as_ptr().ok_or(AcpiError::NoRsdp)? as u64;
+        // The RSDP address is an HHDM address before base revision 3 and a physical one
+        // from then on.
+        let address = if address < hhdm {
+            address + hhdm
+        } else {
+            address
+        };
+
+        // SAFETY: The firmware places the RSDP in memory the HHDM covers. The extended
+        // RSDP's length field lies within the first 36 bytes, and the revision decides
+        // whether there is more than the first 20.
+        let rsdp = unsafe {
+            let rsdp = address as *const u8;
+            let len = match *rsdp.add(15) {
+                0 | 1 => RSDP_V1_LEN,
+                _ => u32::from_le_bytes(*(rsdp.add(20) as *const [u8; 4])) as usize,
+            };
+            Rsdp::parse(slice::from_raw_parts(rsdp, len))?
+        };
+
+        let (signature, entry_size) = if rsdp.extended {
+            (XSDT_SIGNATURE, 8)
+        } else {
+            (RSDT_SIGNATURE, 4)
+        };
+        // SAFETY: The RSDP was validated, and the root table is firmware memory the
+        // HHDM covers.
+        let root = unsafe { table_at(hhdm, rsdp.root_address) };
+        let root = validate_table(root, &signature)?;
+        Ok(Self {
+            hhdm,
+            entries: &root[HEADER_LEN..],
+            entry_size,
+        })
+    }
+
+    /// The physical addresses of the tables.
+    pub fn table_addresses(&self) -> impl Iterator<Item = PhysAddr> + '_ {
+        self.entries
+            .chunks_exact(self.entry_size)
+            .map(|entry| match *entry {
+                [a, b, c, d] => u32::from_le_bytes([a, b, c, d]) as u64,
+                _ => u64::from_le_bytes(entry.try_into().unwrap()),
+            })
+    }
+
+    /// The signatures of the tables, in the order the root table lists them.
+    pub fn signatures(&self) -> impl Iterator<Item = [u8; 4]> + '_ {
+        self.table_addresses().map(|address| {
+            // SAFETY: The root table lists firmware memory the HHDM covers.
+            let table = unsafe { table_at(self.hhdm, address) };
+            table[..4].try_into().unwrap()
+        })
+    }
+
+    /// Returns the first table with `signature`, validated.
+    pub fn find_table(&self, signature: &[u8; 4]) -> Result<&'static [u8], AcpiError> {
+        let table = self
+            .table_addresses()
+            // SAFETY: The root table lists firmware memory the HHDM covers.
+            .map(|address| unsafe { table_at(self.hhdm, address) })
+            .find(|table| table.starts_with(signature))
+            .ok_or(AcpiError::NotFound)?;
+        validate_table(table, signature)
+    }
+
+    pub fn find_hpet(&self) -> Result<HpetInfo, AcpiError> {
+        HpetInfo::parse(self.find_table(&HPET_SIGNATURE)?)
+    }
+
+    pub fn find_mcfg(&self) -> Result<impl Iterator<Item = McfgEntry>, AcpiError> {
+        mcfg_entries(self.find_table(&MCFG_SIGNATURE)?)
+    }
+
+    /// The offset of the HHDM the tables are reached through.
+    pub fn hhdm_offset(&self) -> u64 {
+        self.hhdm
+    }
+}
+
+/// The table at `address`, as long as its header says.
+///
+/// ## Safety
+///
+/// `address` must be the physical address of a table header covered by the HHDM at
+/// `hhdm`, and as many bytes as the header's length must follow it.
+unsafe fn table_at(hhdm: u64, address: PhysAddr) -> &'static [u8] {
+    let header = (address + hhdm) as *const u8;
+    let len = u32::from_le_bytes(*(header.add(4) as *const [u8; 4]));
+    slice::from_raw_parts(header, (len as usize).max(HEADER_LEN))
+}
+
+/// Finds the HPET table through the built-in RSDP request.
+pub fn find_hpet() -> Result<HpetInfo, AcpiError> {
+    built_in()?.find_hpet()
+}
+
+/// Reads the tables through the built-in RSDP request.
+pub fn built_in() -> Result<Acpi, AcpiError> {
+    let response = RSDP.response().ok_or(AcpiError::NoRsdp)?;
+    Acpi::from_response(response)
+}
+
+/// Parses a synthetic HPET table laid out like QEMU's, and versions of it that are
+/// corrupted, truncated or have another signature.
+pub fn self_test() -> bool {
+    const BASE: u64 = 0xfed0_0000;
+
+    /// A 56 byte HPET table with the event timer block ID `block_id`, checksummed.
+    fn hpet_table(block_id: u32) -> [u8; 56] {
+        let mut table = [0; 56];
+        table[..4].copy_from_slice(&HPET_SIGNATURE);
+        table[4..8].copy_from_slice(&56u32.to_le_bytes());
+        table[8] = 1;
+        table[10..16].copy_from_slice(b"BOCHS ");
+        table[36..40].copy_from_slice(&block_id.to_le_bytes());
+        // A generic address in system memory, 64 bits wide.
+        table[40..44].copy_from_slice(&[0, 64, 0, 0]);
+        table[44..52].copy_from_slice(&BASE.to_le_bytes());
+        // The minimum tick.
+        table[53..55].copy_from_slice(&0x80u16.to_le_bytes());
+        table[9] = 0u8.wrapping_sub(table.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+        table
+    }
+
+    // Vendor 0x8086, 3 comparators, 64-bit counter, legacy replacement capable.
+    let qemu = hpet_table(0x8086_a201);
+    // The same without legacy replacement.
+    let no_legacy = hpet_table(0x8086_2201);
+    let mut corrupted = qemu;
+    corrupted[45] ^= 0x10;
+    let mut other = qemu;
+    other[..4].copy_from_slice(&MCFG_SIGNATURE);
+
+    HpetInfo::parse(&qemu)
+        == Ok(HpetInfo {
+            base_address: BASE,
+            counter_size: true,
+            legacy_replacement: true,
+        })
+        && HpetInfo::parse(&no_legacy)
+            .is_ok_and(|info| info.counter_size && !info.legacy_replacement)
+        && HpetInfo::parse(&corrupted) == Err(AcpiError::BadChecksum)
+        && HpetInfo::parse(&qemu[..40]) == Err(AcpiError::Truncated)
+        && HpetInfo::parse(&other) == Err(AcpiError::BadSignature)
+}
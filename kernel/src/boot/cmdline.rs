@@ -0,0 +1,31 @@
+//! The kernel command line, as passed by Limine through the kernel file request.
+//!
+//! Options are separated by whitespace and are either plain flags (`nowatchdog`) or
+//! `key=value` pairs (`watchdog_thresh=20`).
+
+use super::request::LimineRequest;
+use super::requests::KERNEL_FILE;
+
+/// Returns the whole command line, or an empty string if there is none.
+pub fn get() -> &'static str {
+    KERNEL_FILE
+        .response()
+        .and_then(|response| response.kernel_file.get())
+        .and_then(|file| file.cmdline.to_str())
+        .and_then(|cmdline| cmdline.to_str().ok())
+        .unwrap_or("")
+}
+
+pub fn options() -> impl Iterator<Item = &'static str> {
+    get().split_ascii_whitespace()
+}
+
+/// Returns whether the flag `name` was given.
+pub fn has_flag(name: &str) -> bool {
+    options().any(|option| option == name)
+}
+
+/// Returns the value of the first `key=value` option with the given key.
+pub fn value(key: &str) -> Option<&'static str> {
+    options().find_map(|option| option.strip_prefix(key)?.strip_prefix('='))
+}
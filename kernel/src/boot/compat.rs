@@ -0,0 +1,90 @@
+//! Which protocol revision introduced what.
+//!
+//! Every response carries a `revision`, and fields added in a later revision are missing
+//! (the memory after the structure is garbage) when an older bootloader answers. The
+//! request's own revision tells the bootloader which fields the kernel understands, so a
+//! request has to be created with at least the revision of the fields it reads.
+//!
+//! | Response      | Revision | Adds                                                       |
+//! |---------------|----------|------------------------------------------------------------|
+//! | framebuffer   | 1        | `mode_count` and `modes`, see [`FRAMEBUFFER_VIDEO_MODES`]  |
+//! | module        | 1        | loading of `internal_modules`, see [`MODULE_INTERNAL_MODULES`] |
+//!
+//! Independently of the responses, the base revision of the protocol changes how
+//! addresses are reported, see [`PHYSICAL_TABLE_ADDRESSES`].
+
+use core::fmt;
+use core::panic::Location;
+
+use crate::kprintln;
+
+/// The framebuffer response revision that added the list of video modes to every
+/// framebuffer.
+pub const FRAMEBUFFER_VIDEO_MODES: u64 = 1;
+
+/// The module response revision from which the bootloader honours the
+/// `internal_modules` of the request.
+pub const MODULE_INTERNAL_MODULES: u64 = 1;
+
+/// The base revision from which the RSDP, SMBIOS entry point and EFI system table
+/// addresses are physical rather than HHDM addresses.
+pub const PHYSICAL_TABLE_ADDRESSES: u64 = 3;
+
+/// Returned when a response is older than a field that was asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RevisionError {
+    pub found: u64,
+    pub required: u64,
+}
+
+impl fmt::Display for RevisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "response revision {} is too old, revision {} is required",
+            self.found, self.required
+        )
+    }
+}
+
+/// Checks that a response is at least revision `required_rev`.
+pub fn check_min_revision(response_rev: u64, required_rev: u64) -> Result<(), RevisionError> {
+    if response_rev >= required_rev {
+        return Ok(());
+    }
+    Err(RevisionError {
+        found: response_rev,
+        required: required_rev,
+    })
+}
+
+/// Like [`check_min_revision`], logging which caller needed the newer bootloader when
+/// the response is too old.
+#[track_caller]
+pub fn assert_min_revision(response_rev: u64, required_rev: u64) -> Result<(), RevisionError> {
+    let result = check_min_revision(response_rev, required_rev);
+    if let Err(err) = &result {
+        kprintln!(
+            "limine: {} at {}, update the bootloader",
+            err,
+            Location::caller()
+        );
+    }
+    result
+}
+
+/// Checks the revision gate just below, at and above the required revision.
+///
+/// Only [`check_min_revision`] is taken below it, as [`assert_min_revision`] would put a
+/// warning about the bootloader into the boot log.
+pub fn self_test() -> bool {
+    let too_old = check_min_revision(0, FRAMEBUFFER_VIDEO_MODES)
+        == Err(RevisionError {
+            found: 0,
+            required: FRAMEBUFFER_VIDEO_MODES,
+        });
+    let exact = assert_min_revision(FRAMEBUFFER_VIDEO_MODES, FRAMEBUFFER_VIDEO_MODES).is_ok();
+    let newer = assert_min_revision(FRAMEBUFFER_VIDEO_MODES + 1, FRAMEBUFFER_VIDEO_MODES).is_ok();
+
+    too_old && exact && newer
+}
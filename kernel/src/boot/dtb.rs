@@ -0,0 +1,73 @@
+//! Access to the device tree blob passed by the bootloader. See [`fdt`](super::fdt)
+//! for parsing it.
+
+use core::slice;
+
+use limine::LimineDtbResponse;
+
+pub use super::fdt::FDT_MAGIC;
+
+/// Reads the `totalsize` field of the flattened device tree header at `ptr`.
+///
+/// Returns `None` if `ptr` is null or doesn't point at a valid FDT magic.
+///
+/// ## Safety
+///
+/// If `ptr` is non-null it must be valid for reading the 8-byte start of an FDT header.
+pub unsafe fn fdt_totalsize(ptr: *const u8) -> Option<u32> {
+    if ptr.is_null() {
+        return None;
+    }
+
+    // The header fields are big-endian and the blob is only guaranteed 4-byte alignment
+    // in practice, so read the words unaligned.
+    let header = ptr as *const u32;
+    let magic = u32::from_be(header.read_unaligned());
+    if magic != FDT_MAGIC {
+        return None;
+    }
+
+    Some(u32::from_be(header.add(1).read_unaligned()))
+}
+
+pub trait LimineDtbResponseExt {
+    /// Returns the device tree blob, bounded by the `totalsize` from its header.
+    fn dtb_bytes(&self) -> Option<&[u8]>;
+}
+
+impl LimineDtbResponseExt for LimineDtbResponse {
+    fn dtb_bytes(&self) -> Option<&[u8]> {
+        let ptr = self.dtb_ptr.as_ptr()? as *const u8;
+        // SAFETY: The bootloader hands us a pointer to a device tree blob, whose header
+        // tells us exactly how many bytes it spans.
+        unsafe {
+            let size = fdt_totalsize(ptr)?;
+            Some(slice::from_raw_parts(ptr, size as usize))
+        }
+    }
+}
+
+/// Reads the size of a minimal FDT header with the right magic, a corrupted one and a
+/// null pointer, and bounds a response's blob by it.
+pub fn self_test() -> bool {
+    // The magic and a `totalsize` of 12, then the trailing bytes that aren't part of it.
+    static BLOB: [u8; 16] = [
+        0xd0, 0x0d, 0xfe, 0xed, 0, 0, 0, 12, 1, 2, 3, 4, 0xff, 0xff, 0xff, 0xff,
+    ];
+    let mut corrupted = BLOB;
+    corrupted[3] ^= 1;
+
+    let response = LimineDtbResponse {
+        revision: 0,
+        // SAFETY: `LiminePtr` is a transparent wrapper around a nullable pointer.
+        dtb_ptr: unsafe { core::mem::transmute::<*const u8, limine::LiminePtr<u8>>(BLOB.as_ptr()) },
+    };
+
+    // SAFETY: Both arrays hold a full 8-byte header start.
+    let sizes_ok = unsafe {
+        fdt_totalsize(BLOB.as_ptr()) == Some(12)
+            && fdt_totalsize(corrupted.as_ptr()).is_none()
+            && fdt_totalsize(core::ptr::null()).is_none()
+    };
+    sizes_ok && response.dtb_bytes() == Some(&BLOB[..12])
+}
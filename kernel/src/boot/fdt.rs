@@ -0,0 +1,677 @@
+//! A parser for flattened device trees, the format of the blob the DTB response points
+//! to.
+//!
+//! [`Fdt::new`] walks the whole structure block once and rejects blobs that are
+//! truncated, unbalanced or reference strings outside the strings block, so the
+//! accessors afterwards never have to. All values in the blob are big-endian.
+
+use core::fmt;
+
+/// Magic number at the start of every flattened device tree.
+pub const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// The oldest format version this parser reads. It is the first with the strings block
+/// sized in the header.
+const MIN_VERSION: u32 = 16;
+/// The newest format version blobs may claim compatibility with.
+const MAX_LAST_COMP_VERSION: u32 = 17;
+
+const HEADER_SIZE: usize = 0x28;
+
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+/// How deeply nodes can be nested, which bounds the state kept while walking the tree.
+pub const MAX_DEPTH: usize = 16;
+
+/// The `#address-cells` and `#size-cells` a node's children get if it has neither.
+const DEFAULT_CELLS: (u32, u32) = (2, 1);
+
+/// Why a blob was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FdtError {
+    /// The blob doesn't start with [`FDT_MAGIC`].
+    BadMagic,
+    /// The format version is older or newer than this parser understands.
+    UnsupportedVersion(u32),
+    /// The header or a block points past the end of the blob.
+    Truncated,
+    /// The structure block has an unknown token, unbalanced nodes or a property outside
+    /// any node.
+    BadStructure,
+    /// Nodes are nested deeper than [`MAX_DEPTH`].
+    TooDeep,
+    /// A node or property name isn't a NUL terminated UTF-8 string within its block.
+    BadString,
+}
+
+impl fmt::Display for FdtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BadMagic => f.write_str("not a flattened device tree"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported device tree version {}", version)
+            }
+            Self::Truncated => f.write_str("device tree is truncated"),
+            Self::BadStructure => f.write_str("malformed device tree structure block"),
+            Self::TooDeep => f.write_str("device tree nodes are nested too deeply"),
+            Self::BadString => f.write_str("invalid device tree string"),
+        }
+    }
+}
+
+/// Reads the big-endian word at `offset`.
+fn be32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let word = bytes.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes(word.try_into().unwrap()))
+}
+
+fn be64(bytes: &[u8], offset: usize) -> Option<u64> {
+    let word = bytes.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_be_bytes(word.try_into().unwrap()))
+}
+
+fn align4(offset: usize) -> usize {
+    offset.next_multiple_of(4)
+}
+
+/// Returns the NUL terminated string at the start of `bytes`.
+fn c_str(bytes: &[u8]) -> Option<&str> {
+    let len = bytes.iter().position(|&b| b == 0)?;
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+/// Reads a value of `cells` 32-bit cells at `offset`.
+fn read_cells(bytes: &[u8], offset: usize, cells: u32) -> Option<u64> {
+    (0..cells as usize).try_fold(0u64, |value, cell| {
+        Some(value << 32 | be32(bytes, offset + cell * 4)? as u64)
+    })
+}
+
+/// A read-only view of a flattened device tree.
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    bytes: &'a [u8],
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    /// Parses the header of the blob in `bytes` and validates its memory reservation and
+    /// structure blocks.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, FdtError> {
+        let header = |offset| be32(bytes, offset).ok_or(FdtError::Truncated);
+        if header(0x00)? != FDT_MAGIC {
+            return Err(FdtError::BadMagic);
+        }
+        let total_size = header(0x04)? as usize;
+        let bytes = bytes
+            .get(..total_size)
+            .filter(|bytes| bytes.len() >= HEADER_SIZE)
+            .ok_or(FdtError::Truncated)?;
+
+        let version = header(0x14)?;
+        if version < MIN_VERSION || header(0x18)? > MAX_LAST_COMP_VERSION {
+            return Err(FdtError::UnsupportedVersion(version));
+        }
+
+        let block = |offset_field, size_field| {
+            let offset = header(offset_field)? as usize;
+            let size = header(size_field)? as usize;
+            offset
+                .checked_add(size)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or(FdtError::Truncated)
+        };
+        let fdt = Self {
+            bytes,
+            structure: block(0x08, 0x24)?,
+            strings: block(0x0c, 0x20)?,
+        };
+
+        if fdt.memory_reservations_end().is_none() {
+            return Err(FdtError::Truncated);
+        }
+        fdt.validate_structure()?;
+        Ok(fdt)
+    }
+
+    /// Walks every token of the structure block.
+    fn validate_structure(&self) -> Result<(), FdtError> {
+        let structure = self.structure;
+        let token = |offset| be32(structure, offset).ok_or(FdtError::Truncated);
+        let mut offset = 0;
+        let mut depth = 0;
+        let mut seen_root = false;
+
+        loop {
+            match token(offset)? {
+                FDT_BEGIN_NODE => {
+                    if depth == 0 && seen_root {
+                        return Err(FdtError::BadStructure);
+                    }
+                    let name = structure.get(offset + 4..).ok_or(FdtError::Truncated)?;
+                    let name = c_str(name).ok_or(FdtError::BadString)?;
+                    offset = align4(offset + 4 + name.len() + 1);
+                    depth += 1;
+                    seen_root = true;
+                    if depth > MAX_DEPTH {
+                        return Err(FdtError::TooDeep);
+                    }
+                }
+                FDT_END_NODE => {
+                    if depth == 0 {
+                        return Err(FdtError::BadStructure);
+                    }
+                    depth -= 1;
+                    offset += 4;
+                }
+                FDT_PROP => {
+                    if depth == 0 {
+                        return Err(FdtError::BadStructure);
+                    }
+                    let len = token(offset + 4)? as usize;
+                    self.string(token(offset + 8)?).ok_or(FdtError::BadString)?;
+                    let end = (offset + 12)
+                        .checked_add(len)
+                        .filter(|&end| end <= structure.len())
+                        .ok_or(FdtError::Truncated)?;
+                    offset = align4(end);
+                }
+                FDT_NOP => offset += 4,
+                FDT_END if depth == 0 && seen_root => return Ok(()),
+                _ => return Err(FdtError::BadStructure),
+            }
+        }
+    }
+
+    /// The offset just past the terminating entry of the memory reservation block.
+    fn memory_reservations_end(&self) -> Option<usize> {
+        let mut offset = be32(self.bytes, 0x10)? as usize;
+        loop {
+            let (address, size) = (be64(self.bytes, offset)?, be64(self.bytes, offset + 8)?);
+            offset += 16;
+            if address == 0 && size == 0 {
+                return Some(offset);
+            }
+        }
+    }
+
+    /// The physical ID of the CPU the blob was handed to.
+    pub fn boot_cpuid(&self) -> u32 {
+        be32(self.bytes, 0x1c).unwrap()
+    }
+
+    /// The address and size of every range the memory reservation block lists, which
+    /// must not be used as normal memory.
+    pub fn memory_reservations(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let bytes = self.bytes;
+        let mut offset = be32(bytes, 0x10).unwrap() as usize;
+        core::iter::from_fn(move || {
+            let entry = (be64(bytes, offset)?, be64(bytes, offset + 8)?);
+            offset += 16;
+            (entry != (0, 0)).then_some(entry)
+        })
+    }
+
+    /// The root node.
+    pub fn root(&self) -> Node<'a> {
+        self.nodes().next().unwrap()
+    }
+
+    /// All nodes, depth first, starting with the root.
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes {
+            fdt: *self,
+            offset: 0,
+            depth: 0,
+            base_depth: 0,
+            cells: [DEFAULT_CELLS; MAX_DEPTH],
+        }
+    }
+
+    /// Finds the node at an absolute path such as `/psci` or `/soc/uart@10000000`.
+    /// Components without a unit address also match nodes that have one.
+    pub fn find_node(&self, path: &str) -> Option<Node<'a>> {
+        let mut node = self.root();
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = node
+                .children()
+                .find(|child| child.name_matches(component))?;
+        }
+        Some(node)
+    }
+
+    /// The base and size of the RAM the `/memory` nodes describe.
+    pub fn memory_regions(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        self.root()
+            .children()
+            .filter(|node| {
+                node.property_str("device_type") == Some("memory") || node.name_matches("memory")
+            })
+            .flat_map(|node| node.reg_entries())
+    }
+
+    /// Finds the first node whose `compatible` list contains `compatible`.
+    pub fn find_compatible(&self, compatible: &str) -> Option<Node<'a>> {
+        self.nodes().find(|node| node.is_compatible(compatible))
+    }
+
+    fn string(&self, offset: u32) -> Option<&'a str> {
+        c_str(self.strings.get(offset as usize..)?)
+    }
+}
+
+/// Iterator over the nodes of an [`Fdt`], or of a subtree.
+pub struct Nodes<'a> {
+    fdt: Fdt<'a>,
+    offset: usize,
+    depth: usize,
+    /// The depth at which the walked subtree ends.
+    base_depth: usize,
+    /// `#address-cells` and `#size-cells` of the open node at each depth.
+    cells: [(u32, u32); MAX_DEPTH],
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        let structure = self.fdt.structure;
+        loop {
+            let token = be32(structure, self.offset)?;
+            self.offset += 4;
+
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_str(structure.get(self.offset..)?)?;
+                    self.offset = align4(self.offset + name.len() + 1);
+
+                    let (address_cells, size_cells) = match self.depth {
+                        0 => DEFAULT_CELLS,
+                        depth => self.cells[depth - 1],
+                    };
+                    self.depth += 1;
+                    let node = Node {
+                        fdt: self.fdt,
+                        name,
+                        offset: self.offset,
+                        depth: self.depth,
+                        address_cells,
+                        size_cells,
+                    };
+
+                    self.cells[self.depth - 1] = node.cells();
+                    return Some(node);
+                }
+                FDT_END_NODE if self.depth == self.base_depth => break,
+                FDT_END_NODE => self.depth -= 1,
+                FDT_PROP => {
+                    let len = be32(structure, self.offset)? as usize;
+                    self.offset = align4(self.offset + 8 + len);
+                }
+                FDT_NOP => {}
+                _ => break,
+            }
+        }
+
+        self.offset = structure.len();
+        None
+    }
+}
+
+/// A device tree node and its properties.
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    fdt: Fdt<'a>,
+    name: &'a str,
+    /// Offset of the node's first property in the structure block.
+    offset: usize,
+    /// The root node has depth 1.
+    depth: usize,
+    /// Cell counts of the parent node, which define the layout of this node's `reg`.
+    address_cells: u32,
+    size_cells: u32,
+}
+
+impl<'a> Node<'a> {
+    /// The node name including its unit address, e.g. `pl011@9000000`. Empty for the root.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The unit address part of the name, e.g. `9000000` for `pl011@9000000`.
+    pub fn unit_address(&self) -> Option<&'a str> {
+        self.name.split_once('@').map(|(_, address)| address)
+    }
+
+    fn name_matches(&self, component: &str) -> bool {
+        self.name == component
+            || (!component.contains('@') && self.name.split('@').next() == Some(component))
+    }
+
+    /// The `#address-cells` and `#size-cells` this node gives its children.
+    fn cells(&self) -> (u32, u32) {
+        (
+            self.property_u32("#address-cells")
+                .unwrap_or(DEFAULT_CELLS.0),
+            self.property_u32("#size-cells").unwrap_or(DEFAULT_CELLS.1),
+        )
+    }
+
+    /// The direct children of this node.
+    pub fn children(&self) -> impl Iterator<Item = Node<'a>> + 'a {
+        let mut cells = [DEFAULT_CELLS; MAX_DEPTH];
+        cells[self.depth - 1] = self.cells();
+        let depth = self.depth;
+        Nodes {
+            fdt: self.fdt,
+            offset: self.offset,
+            depth,
+            base_depth: depth,
+            cells,
+        }
+        .filter(move |child| child.depth == depth + 1)
+    }
+
+    /// The properties of this node as name and raw value pairs.
+    pub fn properties(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let fdt = self.fdt;
+        let bytes = self.fdt.structure;
+        let mut offset = self.offset;
+
+        core::iter::from_fn(move || loop {
+            match be32(bytes, offset)? {
+                FDT_PROP => {
+                    let len = be32(bytes, offset + 4)? as usize;
+                    let name = fdt.string(be32(bytes, offset + 8)?)?;
+                    let value = bytes.get(offset + 12..offset + 12 + len)?;
+                    offset = align4(offset + 12 + len);
+                    return Some((name, value));
+                }
+                FDT_NOP => offset += 4,
+                _ => return None,
+            }
+        })
+    }
+
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.properties()
+            .find(|&(property, _)| property == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        be32(self.property(name)?, 0)
+    }
+
+    /// Reads a property holding one or two cells.
+    pub fn property_u64(&self, name: &str) -> Option<u64> {
+        let value = self.property(name)?;
+        match value.len() {
+            4 => be32(value, 0).map(u64::from),
+            _ => be64(value, 0),
+        }
+    }
+
+    /// Reads a string property, dropping the terminating NUL.
+    pub fn property_str(&self, name: &str) -> Option<&'a str> {
+        c_str(self.property(name)?)
+    }
+
+    pub fn is_compatible(&self, compatible: &str) -> bool {
+        self.property("compatible").is_some_and(|list| {
+            list.split(|&b| b == 0)
+                .any(|entry| entry == compatible.as_bytes())
+        })
+    }
+
+    /// The address and size of the node's first `reg` entry.
+    pub fn reg(&self) -> Option<(u64, u64)> {
+        self.reg_entries().next()
+    }
+
+    /// The address and size pairs of the node's `reg` property, laid out by the parent's
+    /// `#address-cells` and `#size-cells`.
+    pub fn reg_entries(&self) -> impl Iterator<Item = (u64, u64)> + 'a {
+        let reg = self.property("reg").unwrap_or(&[]);
+        let (address_cells, size_cells) = (self.address_cells, self.size_cells);
+        let entry_len = (address_cells + size_cells) as usize * 4;
+        let mut offset = 0;
+
+        core::iter::from_fn(move || {
+            if entry_len == 0 {
+                return None;
+            }
+            let address = read_cells(reg, offset, address_cells)?;
+            let size = read_cells(reg, offset + address_cells as usize * 4, size_cells)?;
+            offset += entry_len;
+            Some((address, size))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds blobs token by token, with the strings block and header filled in by
+    /// [`Builder::finish`].
+    #[derive(Default)]
+    struct Builder {
+        structure: Vec<u8>,
+        strings: Vec<u8>,
+    }
+
+    impl Builder {
+        fn token(&mut self, word: u32) -> &mut Self {
+            self.structure.extend_from_slice(&word.to_be_bytes());
+            self
+        }
+
+        fn pad(&mut self) {
+            self.structure.resize(align4(self.structure.len()), 0);
+        }
+
+        fn begin(&mut self, name: &str) -> &mut Self {
+            self.token(FDT_BEGIN_NODE);
+            self.structure.extend_from_slice(name.as_bytes());
+            self.structure.push(0);
+            self.pad();
+            self
+        }
+
+        fn end(&mut self) -> &mut Self {
+            self.token(FDT_END_NODE)
+        }
+
+        fn name_offset(&mut self, name: &str) -> u32 {
+            let offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            offset
+        }
+
+        fn prop(&mut self, name: &str, value: &[u8]) -> &mut Self {
+            let name_offset = self.name_offset(name);
+            self.token(FDT_PROP)
+                .token(value.len() as u32)
+                .token(name_offset);
+            self.structure.extend_from_slice(value);
+            self.pad();
+            self
+        }
+
+        fn prop_cells(&mut self, name: &str, cells: &[u32]) -> &mut Self {
+            let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+            self.prop(name, &value)
+        }
+
+        /// Appends `FDT_END` and lays out the header, an empty memory reservation block,
+        /// the structure block and the strings block.
+        fn finish(&mut self) -> Vec<u8> {
+            self.token(FDT_END);
+            let structure_offset = HEADER_SIZE + 16;
+            let strings_offset = structure_offset + self.structure.len();
+            let total_size = strings_offset + self.strings.len();
+            let header = [
+                FDT_MAGIC,
+                total_size as u32,
+                structure_offset as u32,
+                strings_offset as u32,
+                HEADER_SIZE as u32,
+                17,
+                16,
+                0,
+                self.strings.len() as u32,
+                self.structure.len() as u32,
+            ];
+
+            let mut blob: Vec<u8> = header.iter().flat_map(|word| word.to_be_bytes()).collect();
+            blob.resize(structure_offset, 0);
+            blob.extend_from_slice(&self.structure);
+            blob.extend_from_slice(&self.strings);
+            blob
+        }
+    }
+
+    fn minimal() -> Vec<u8> {
+        Builder::default()
+            .begin("")
+            .prop("compatible", b"linux,dummy-virt\0")
+            .end()
+            .finish()
+    }
+
+    #[test]
+    fn minimal_tree() {
+        let blob = minimal();
+        let fdt = Fdt::new(&blob).unwrap();
+        assert_eq!(fdt.root().name(), "");
+        assert!(fdt.root().is_compatible("linux,dummy-virt"));
+        assert_eq!(fdt.memory_reservations().count(), 0);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut blob = minimal();
+        blob[0] ^= 1;
+        assert_eq!(Fdt::new(&blob).err(), Some(FdtError::BadMagic));
+    }
+
+    #[test]
+    fn total_size_past_the_slice() {
+        let blob = minimal();
+        assert_eq!(
+            Fdt::new(&blob[..blob.len() - 1]).err(),
+            Some(FdtError::Truncated)
+        );
+        assert_eq!(Fdt::new(&blob[..8]).err(), Some(FdtError::Truncated));
+    }
+
+    #[test]
+    fn unbalanced_end_node() {
+        let blob = Builder::default().begin("").end().end().finish();
+        assert_eq!(Fdt::new(&blob).err(), Some(FdtError::BadStructure));
+
+        let blob = Builder::default().begin("").begin("cpus").end().finish();
+        assert_eq!(Fdt::new(&blob).err(), Some(FdtError::BadStructure));
+    }
+
+    #[test]
+    fn property_past_the_structure_block() {
+        let mut builder = Builder::default();
+        let name_offset = builder.name_offset("reg");
+        let blob = builder
+            .begin("")
+            .token(FDT_PROP)
+            .token(64)
+            .token(name_offset)
+            .end()
+            .finish();
+        assert_eq!(Fdt::new(&blob).err(), Some(FdtError::Truncated));
+    }
+
+    #[test]
+    fn name_offset_outside_the_strings_block() {
+        let mut builder = Builder::default();
+        let past_strings = builder.name_offset("reg") + 4;
+        let blob = builder
+            .begin("")
+            .token(FDT_PROP)
+            .token(0)
+            .token(past_strings)
+            .end()
+            .finish();
+        assert_eq!(Fdt::new(&blob).err(), Some(FdtError::BadString));
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let nested = |depth| {
+            let mut builder = Builder::default();
+            for _ in 0..depth {
+                builder.begin("node");
+            }
+            for _ in 0..depth {
+                builder.end();
+            }
+            builder.finish()
+        };
+        assert!(Fdt::new(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Fdt::new(&nested(MAX_DEPTH + 1)).err(),
+            Some(FdtError::TooDeep)
+        );
+    }
+
+    #[test]
+    fn reg_follows_the_parent_cells() {
+        let blob = Builder::default()
+            .begin("")
+            .prop_cells("#address-cells", &[2])
+            .prop_cells("#size-cells", &[2])
+            .begin("memory@40000000")
+            .prop("device_type", b"memory\0")
+            .prop_cells("reg", &[0, 0x4000_0000, 1, 0])
+            .end()
+            .begin("soc")
+            .prop_cells("#address-cells", &[1])
+            .prop_cells("#size-cells", &[1])
+            .begin("uart@9000000")
+            .prop_cells("reg", &[0x900_0000, 0x1000])
+            .end()
+            .begin("bus")
+            .begin("device@1")
+            // `bus` has no cell counts, so its children get the defaults of 2 and 1.
+            .prop_cells("reg", &[0, 1, 0x100])
+            .end()
+            .end()
+            .end()
+            .end()
+            .finish();
+        let fdt = Fdt::new(&blob).unwrap();
+
+        assert_eq!(
+            fdt.memory_regions().collect::<Vec<_>>(),
+            [(0x4000_0000, 1 << 32)]
+        );
+        let uart = fdt.find_node("/soc/uart").unwrap();
+        assert_eq!(uart.unit_address(), Some("9000000"));
+        assert_eq!(uart.reg(), Some((0x900_0000, 0x1000)));
+        assert_eq!(
+            fdt.find_node("/soc/bus/device@1").unwrap().reg(),
+            Some((1, 0x100))
+        );
+
+        // Walking the whole tree tracks the same cell counts as walking children.
+        let regs: Vec<_> = fdt.nodes().filter_map(|node| node.reg()).collect();
+        assert_eq!(
+            regs,
+            [(0x4000_0000, 1 << 32), (0x900_0000, 0x1000), (1, 0x100)]
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! The firmware type request, which tells whether the machine booted through BIOS or
+//! UEFI.
+
+use super::request::limine_request;
+
+pub const FIRMWARE_TYPE_X86_BIOS: u64 = 0;
+pub const FIRMWARE_TYPE_UEFI32: u64 = 1;
+pub const FIRMWARE_TYPE_UEFI64: u64 = 2;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LimineFirmwareTypeResponse {
+    pub revision: u64,
+    /// One of the `FIRMWARE_TYPE_*` constants.
+    pub firmware_type: u64,
+}
+
+impl LimineFirmwareTypeResponse {
+    /// Whether the machine booted through 32-bit or 64-bit UEFI.
+    pub fn is_uefi(&self) -> bool {
+        matches!(
+            self.firmware_type,
+            FIRMWARE_TYPE_UEFI32 | FIRMWARE_TYPE_UEFI64
+        )
+    }
+
+    pub fn is_64bit_uefi(&self) -> bool {
+        self.firmware_type == FIRMWARE_TYPE_UEFI64
+    }
+
+    /// Whether the machine booted through a legacy x86 BIOS.
+    pub fn is_bios(&self) -> bool {
+        self.firmware_type == FIRMWARE_TYPE_X86_BIOS
+    }
+}
+
+limine_request!(
+    pub struct LimineFirmwareTypeRequest: [0x8c2f75d90bef28a8, 0x7045a4688eac00c3]
+        => LimineFirmwareTypeResponse {}
+);
+
+/// Checks every firmware type constant, and an unknown value, against what each helper
+/// has to say about it.
+pub fn self_test() -> bool {
+    // (type, is_uefi, is_64bit_uefi, is_bios)
+    let cases = [
+        (FIRMWARE_TYPE_X86_BIOS, false, false, true),
+        (FIRMWARE_TYPE_UEFI32, true, false, false),
+        (FIRMWARE_TYPE_UEFI64, true, true, false),
+        (3, false, false, false),
+    ];
+    cases
+        .into_iter()
+        .all(|(firmware_type, uefi, uefi64, bios)| {
+            let response = LimineFirmwareTypeResponse {
+                revision: 0,
+                firmware_type,
+            };
+            response.is_uefi() == uefi
+                && response.is_64bit_uefi() == uefi64
+                && response.is_bios() == bios
+        })
+}
@@ -0,0 +1,461 @@
+use core::{ptr, slice};
+
+use limine::{LimineFramebuffer, LimineFramebufferRequest, LimineFramebufferResponse, NonNullPtr};
+
+use super::compat::{self, RevisionError};
+use super::info::BootInfoError;
+use super::ptr::{ArrayPtr, ArrayPtrExt};
+use super::request::LimineRequest;
+#[cfg(feature = "hhdm")]
+use super::PhysAddr;
+use crate::array_vec::ArrayVec;
+
+/// A mode the framebuffer's display supports, from revision
+/// [`FRAMEBUFFER_VIDEO_MODES`](compat::FRAMEBUFFER_VIDEO_MODES) on.
+#[repr(C)]
+#[derive(Debug)]
+pub struct LimineVideoMode {
+    pub pitch: u64,
+    pub width: u64,
+    pub height: u64,
+    pub bpp: u16,
+    pub memory_model: u8,
+    pub red_mask_size: u8,
+    pub red_mask_shift: u8,
+    pub green_mask_size: u8,
+    pub green_mask_shift: u8,
+    pub blue_mask_size: u8,
+    pub blue_mask_shift: u8,
+}
+
+/// The revision 1 layout of a framebuffer, which the limine crate stops short of.
+#[repr(C)]
+struct FramebufferV1 {
+    base: LimineFramebuffer,
+    mode_count: u64,
+    modes: *const NonNullPtr<LimineVideoMode>,
+}
+
+pub trait LimineFramebufferRequestExt {
+    /// Returns the response, for kernels that can't do without a framebuffer.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the bootloader didn't answer the request.
+    fn assert_framebuffer_available(&self) -> &LimineFramebufferResponse;
+
+    /// Returns the first framebuffer, failing if there is no response or it lists none.
+    fn try_get_primary(&self) -> Result<&LimineFramebuffer, BootInfoError>;
+}
+
+impl LimineFramebufferRequestExt for LimineFramebufferRequest {
+    fn assert_framebuffer_available(&self) -> &LimineFramebufferResponse {
+        self.response()
+            .expect("No framebuffer provided by bootloader")
+    }
+
+    fn try_get_primary(&self) -> Result<&LimineFramebuffer, BootInfoError> {
+        self.response()
+            .and_then(LimineFramebufferResponseExt::primary)
+            .ok_or(BootInfoError::NoFramebuffer)
+    }
+}
+
+pub trait LimineFramebufferResponseExt {
+    /// Builds a response listing `framebuffers`, laid out the way the bootloader would:
+    /// `framebuffers` points at an array of `framebuffer_count` pointers, each to one
+    /// [`LimineFramebuffer`]. The array is the slice itself, as a `&LimineFramebuffer`
+    /// has the layout of the crate's `NonNullPtr`.
+    ///
+    /// This is for exercising code that takes a response without a bootloader.
+    ///
+    /// ## Safety
+    ///
+    /// The framebuffers are shared, so nothing may mutate them through the response,
+    /// such as through `DerefMut` on its pointers.
+    unsafe fn from_parts(
+        revision: u64,
+        framebuffers: &'static [&'static LimineFramebuffer],
+    ) -> Self
+    where
+        Self: Sized;
+
+    /// The first framebuffer, the one the bootloader considers primary.
+    fn primary(&self) -> Option<&LimineFramebuffer>;
+
+    /// Iterates over the framebuffers with the given bits per pixel.
+    fn iter_by_bpp(&self, bpp: u16) -> impl Iterator<Item = &LimineFramebuffer>;
+
+    /// Iterates over the 32 bits per pixel framebuffers.
+    fn iter_32bpp(&self) -> impl Iterator<Item = &LimineFramebuffer> {
+        self.iter_by_bpp(32)
+    }
+
+    /// Iterates over the 24 bits per pixel framebuffers.
+    fn iter_24bpp(&self) -> impl Iterator<Item = &LimineFramebuffer> {
+        self.iter_by_bpp(24)
+    }
+
+    /// Returns the first framebuffer of exactly `width` by `height` pixels.
+    fn find(&self, width: u64, height: u64) -> Option<&LimineFramebuffer>;
+
+    /// Returns the framebuffer whose size is closest to `width` by `height` pixels, by
+    /// the sum of the differences in width and height, saturated at `u64::MAX`. Ties go
+    /// to the earlier one.
+    fn find_closest(&self, width: u64, height: u64) -> Option<&LimineFramebuffer>;
+
+    /// The sum of `width * height` over all framebuffers.
+    fn total_pixel_count(&self) -> u64;
+
+    /// The bytes of memory all framebuffers take up, `pitch * height` each.
+    fn total_byte_count(&self) -> u64;
+
+    /// The width of all framebuffers placed side by side, as [`MonitorLayout`] does by
+    /// default.
+    fn combined_width(&self) -> u64;
+
+    /// Whether `addr` lies in the memory of any framebuffer. The framebuffer addresses
+    /// are HHDM addresses and are translated back with the HHDM response, or compared as
+    /// reported if there is none.
+    #[cfg(feature = "hhdm")]
+    fn contains_address(&self, addr: PhysAddr) -> bool;
+
+    /// Returns the video modes of `framebuffer`, which must be one of this response's.
+    /// Fails if the bootloader predates them.
+    fn video_modes<'a>(
+        &'a self,
+        framebuffer: &'a LimineFramebuffer,
+    ) -> Result<impl Iterator<Item = &'a LimineVideoMode>, RevisionError>;
+}
+
+impl LimineFramebufferResponseExt for LimineFramebufferResponse {
+    unsafe fn from_parts(
+        revision: u64,
+        framebuffers: &'static [&'static LimineFramebuffer],
+    ) -> Self {
+        Self {
+            revision,
+            framebuffer_count: framebuffers.len() as u64,
+            // SAFETY: `NonNullPtr` is a transparent wrapper around a non-null pointer, as
+            // are references, and slices are never null even when empty.
+            framebuffers: unsafe {
+                core::mem::transmute::<*const &LimineFramebuffer, ArrayPtr<LimineFramebuffer>>(
+                    framebuffers.as_ptr(),
+                )
+            },
+        }
+    }
+
+    fn primary(&self) -> Option<&LimineFramebuffer> {
+        all_framebuffers(self).next()
+    }
+
+    fn iter_by_bpp(&self, bpp: u16) -> impl Iterator<Item = &LimineFramebuffer> {
+        all_framebuffers(self).filter(move |framebuffer| framebuffer.bpp == bpp)
+    }
+
+    fn find(&self, width: u64, height: u64) -> Option<&LimineFramebuffer> {
+        all_framebuffers(self)
+            .find(|framebuffer| framebuffer.width == width && framebuffer.height == height)
+    }
+
+    fn find_closest(&self, width: u64, height: u64) -> Option<&LimineFramebuffer> {
+        all_framebuffers(self).min_by_key(|framebuffer| {
+            framebuffer
+                .width
+                .abs_diff(width)
+                .saturating_add(framebuffer.height.abs_diff(height))
+        })
+    }
+
+    fn total_pixel_count(&self) -> u64 {
+        all_framebuffers(self)
+            .map(|framebuffer| framebuffer.width * framebuffer.height)
+            .sum()
+    }
+
+    fn total_byte_count(&self) -> u64 {
+        all_framebuffers(self).map(byte_len).sum()
+    }
+
+    fn combined_width(&self) -> u64 {
+        all_framebuffers(self)
+            .map(|framebuffer| framebuffer.width)
+            .sum()
+    }
+
+    #[cfg(feature = "hhdm")]
+    fn contains_address(&self, addr: PhysAddr) -> bool {
+        let hhdm = super::requests::HHDM
+            .response()
+            .map_or(0, |hhdm| hhdm.offset);
+        all_framebuffers(self).any(|framebuffer| framebuffer_contains(framebuffer, hhdm, addr))
+    }
+
+    fn video_modes<'a>(
+        &'a self,
+        framebuffer: &'a LimineFramebuffer,
+    ) -> Result<impl Iterator<Item = &'a LimineVideoMode>, RevisionError> {
+        compat::assert_min_revision(self.revision, compat::FRAMEBUFFER_VIDEO_MODES)?;
+
+        // SAFETY: From revision 1 on, every framebuffer of the response has the full
+        // layout, and the bootloader reports the modes array along with its count.
+        let modes = unsafe {
+            let framebuffer = &*(framebuffer as *const LimineFramebuffer as *const FramebufferV1);
+            if framebuffer.modes.is_null() {
+                &[]
+            } else {
+                slice::from_raw_parts(framebuffer.modes, framebuffer.mode_count as usize)
+            }
+        };
+        Ok(modes.iter().map(|mode| &**mode))
+    }
+}
+
+/// The bytes of the framebuffer's memory, padding included. Unlike
+/// [`LimineFramebuffer::size`], which multiplies the pitch by the bytes per pixel
+/// again.
+fn byte_len(framebuffer: &LimineFramebuffer) -> u64 {
+    framebuffer.pitch * framebuffer.height
+}
+
+/// Whether the physical address `addr` lies in the memory of `framebuffer`, whose
+/// address is in the HHDM at `hhdm`.
+#[cfg(feature = "hhdm")]
+fn framebuffer_contains(framebuffer: &LimineFramebuffer, hhdm: u64, addr: PhysAddr) -> bool {
+    let Some(start) = framebuffer
+        .address
+        .as_ptr()
+        .and_then(|address| (address as u64).checked_sub(hhdm))
+    else {
+        return false;
+    };
+    start
+        .checked_add(byte_len(framebuffer))
+        .is_some_and(|end| (start..end).contains(&addr))
+}
+
+fn all_framebuffers(
+    response: &LimineFramebufferResponse,
+) -> impl Iterator<Item = &LimineFramebuffer> {
+    // SAFETY: The count comes from the bootloader along with the array.
+    unsafe {
+        response
+            .framebuffers
+            .iter(response.framebuffer_count as usize)
+    }
+}
+
+/// How many framebuffers a [`MonitorLayout`] can place.
+pub const MAX_MONITORS: usize = 8;
+
+/// The positions of the framebuffers on one global desktop, in pixels.
+#[derive(Debug)]
+pub struct MonitorLayout {
+    framebuffers: ArrayVec<&'static LimineFramebuffer, MAX_MONITORS>,
+    positions: ArrayVec<(i64, i64), MAX_MONITORS>,
+}
+
+impl MonitorLayout {
+    /// Places the framebuffers left to right in the order the bootloader reported them,
+    /// top aligned. Framebuffers past the first [`MAX_MONITORS`] are left out.
+    pub fn from_response(response: &'static LimineFramebufferResponse) -> Self {
+        let mut layout = Self {
+            framebuffers: ArrayVec::new(),
+            positions: ArrayVec::new(),
+        };
+
+        let mut x = 0;
+        for framebuffer in all_framebuffers(response) {
+            if layout.framebuffers.push(framebuffer).is_err() {
+                break;
+            }
+            let _ = layout.positions.push((x, 0));
+            x += framebuffer.width as i64;
+        }
+
+        layout
+    }
+
+    pub fn framebuffers(&self) -> &[&'static LimineFramebuffer] {
+        &self.framebuffers
+    }
+
+    /// The global position of each framebuffer's top left corner.
+    pub fn positions(&self) -> &[(i64, i64)] {
+        &self.positions
+    }
+
+    /// Moves the framebuffer at `index` so its top left corner is at `(x, y)`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_position(&mut self, index: usize, x: i64, y: i64) {
+        self.positions[index] = (x, y);
+    }
+
+    /// Maps a global pixel to the index of the framebuffer showing it and the pixel
+    /// within that framebuffer. Where framebuffers overlap, the first one wins.
+    pub fn global_to_local(&self, gx: i64, gy: i64) -> Option<(usize, u64, u64)> {
+        self.framebuffers
+            .iter()
+            .zip(self.positions.iter())
+            .enumerate()
+            .find_map(|(index, (framebuffer, &(x, y)))| {
+                let local_x = u64::try_from(gx.checked_sub(x)?).ok()?;
+                let local_y = u64::try_from(gy.checked_sub(y)?).ok()?;
+                (local_x < framebuffer.width && local_y < framebuffer.height)
+                    .then_some((index, local_x, local_y))
+            })
+    }
+}
+
+/// Checks the framebuffer response helpers against made-up responses.
+pub fn self_test() -> bool {
+    #[cfg(feature = "hhdm")]
+    let contains_ok = contains_self_test();
+    #[cfg(not(feature = "hhdm"))]
+    let contains_ok = true;
+    video_modes_self_test() && from_parts_self_test() && find_self_test() && contains_ok
+}
+
+/// Lists the video modes of a revision 1 framebuffer.
+fn video_modes_self_test() -> bool {
+    use crate::gfx::{framebuffer_from_parts, FramebufferInfo};
+
+    let mode = |width, height| LimineVideoMode {
+        pitch: 4 * width,
+        width,
+        height,
+        bpp: 32,
+        memory_model: 1,
+        red_mask_size: 8,
+        red_mask_shift: 16,
+        green_mask_size: 8,
+        green_mask_shift: 8,
+        blue_mask_size: 8,
+        blue_mask_shift: 0,
+    };
+    let (small, large) = (mode(640, 480), mode(1024, 768));
+    let modes = [&small, &large];
+    let framebuffer = FramebufferV1 {
+        // SAFETY: A null address, nothing draws on it.
+        base: unsafe {
+            framebuffer_from_parts(ptr::null_mut(), FramebufferInfo::xrgb8888(1024, 768))
+        },
+        mode_count: modes.len() as u64,
+        // A `&LimineVideoMode` has the layout of a `NonNullPtr`.
+        modes: modes.as_ptr().cast(),
+    };
+    // SAFETY: Nothing mutates the framebuffers of an empty list.
+    let response = unsafe { LimineFramebufferResponse::from_parts(1, &[]) };
+
+    response.video_modes(&framebuffer.base).is_ok_and(|modes| {
+        modes
+            .map(|mode| (mode.width, mode.height))
+            .eq([(640, 480), (1024, 768)])
+    })
+}
+
+/// Walks a response of two framebuffers built with `from_parts`, through the limine
+/// crate's accessor and the helpers built on the same array.
+fn from_parts_self_test() -> bool {
+    use crate::gfx::{framebuffer_from_parts, FramebufferInfo};
+
+    // SAFETY: Null addresses, nothing draws on them.
+    static FIRST: LimineFramebuffer =
+        unsafe { framebuffer_from_parts(ptr::null_mut(), FramebufferInfo::xrgb8888(800, 600)) };
+    static SECOND: LimineFramebuffer = unsafe {
+        framebuffer_from_parts(
+            ptr::null_mut(),
+            FramebufferInfo {
+                pitch: 2 * 640,
+                bpp: 16,
+                ..FramebufferInfo::xrgb8888(640, 480)
+            },
+        )
+    };
+    static FRAMEBUFFERS: [&LimineFramebuffer; 2] = [&FIRST, &SECOND];
+
+    // SAFETY: Nothing mutates the framebuffers.
+    let response = unsafe { LimineFramebufferResponse::from_parts(2, &FRAMEBUFFERS) };
+    let empty = unsafe { LimineFramebufferResponse::from_parts(2, &[]) };
+
+    response.revision == 2
+        && response
+            .framebuffers()
+            .iter()
+            .map(|framebuffer| &**framebuffer as *const LimineFramebuffer)
+            .eq([&FIRST as *const _, &SECOND as *const _])
+        && response
+            .primary()
+            .is_some_and(|framebuffer| ptr::eq(framebuffer, &FIRST))
+        && response
+            .iter_by_bpp(16)
+            .map(|framebuffer| framebuffer.width)
+            .eq([640])
+        && response.total_pixel_count() == 800 * 600 + 640 * 480
+        && response.total_byte_count() == 4 * 800 * 600 + 2 * 640 * 480
+        && empty.framebuffers().is_empty()
+        && empty.primary().is_none()
+}
+
+/// Looks up framebuffers by exact and by closest size in a response listing three.
+fn find_self_test() -> bool {
+    use crate::gfx::{framebuffer_from_parts, FramebufferInfo};
+
+    // SAFETY: Null addresses, nothing draws on them.
+    static WIDE: LimineFramebuffer =
+        unsafe { framebuffer_from_parts(ptr::null_mut(), FramebufferInfo::xrgb8888(1920, 1080)) };
+    static HD: LimineFramebuffer =
+        unsafe { framebuffer_from_parts(ptr::null_mut(), FramebufferInfo::xrgb8888(1280, 720)) };
+    static SXGA: LimineFramebuffer =
+        unsafe { framebuffer_from_parts(ptr::null_mut(), FramebufferInfo::xrgb8888(1280, 1024)) };
+    static FRAMEBUFFERS: [&LimineFramebuffer; 3] = [&WIDE, &HD, &SXGA];
+
+    // SAFETY: Nothing mutates the framebuffers.
+    let response = unsafe { LimineFramebufferResponse::from_parts(0, &FRAMEBUFFERS) };
+    let empty = unsafe { LimineFramebufferResponse::from_parts(0, &[]) };
+    let is = |found: Option<&LimineFramebuffer>, expected| {
+        found.is_some_and(|framebuffer| ptr::eq(framebuffer, expected))
+    };
+
+    is(response.find(1280, 1024), &SXGA)
+        && response.find(800, 600).is_none()
+        && is(response.find_closest(1280, 800), &HD)
+        && is(response.find_closest(2560, 1440), &WIDE)
+        // 152 from both HD and SXGA, so the earlier one.
+        && is(response.find_closest(1280, 872), &HD)
+        && empty.find_closest(1280, 720).is_none()
+        // Differences that don't fit a `u64` when added up.
+        && is(response.find_closest(u64::MAX, u64::MAX), &WIDE)
+}
+
+/// Checks the bounds of a framebuffer's memory as seen through the HHDM, one byte past
+/// the end included.
+#[cfg(feature = "hhdm")]
+fn contains_self_test() -> bool {
+    use crate::gfx::{framebuffer_from_parts, FramebufferInfo};
+
+    const HHDM: u64 = 0xffff_8000_0000_0000;
+    const BASE: PhysAddr = 0xfd00_0000;
+    // 1024 by 768 pixels of 4 bytes.
+    const LEN: u64 = 0x30_0000;
+
+    // SAFETY: Nothing draws on the framebuffer, so its address is never used as one.
+    let framebuffer = unsafe {
+        framebuffer_from_parts(
+            (HHDM + BASE) as *mut u8,
+            FramebufferInfo::xrgb8888(1024, 768),
+        )
+    };
+    let contains = |addr| framebuffer_contains(&framebuffer, HHDM, addr);
+
+    contains(BASE)
+        && contains(BASE + LEN - 1)
+        && !contains(BASE + LEN)
+        && !contains(BASE - 1)
+        // An address below the HHDM, which can't be translated back.
+        && !framebuffer_contains(&framebuffer, HHDM + BASE + 1, BASE)
+}
@@ -0,0 +1,137 @@
+//! The IDs of the built-in requests that are compiled in.
+//!
+//! A request whose feature is disabled has no entry here, so [`ALL`] always matches
+//! the contents of `.limine_requests`. The build fails if it doesn't.
+//!
+//! The values are written out as the protocol defines them rather than taken from the
+//! request types, and [`requests`](super::requests) checks at compile time that every
+//! request carries its ID.
+
+pub use super::request::LimineRequestId;
+
+/// Builds a full request ID from the two request specific words.
+// Every user is behind a feature.
+#[allow(dead_code)]
+const fn id(id1: u64, id2: u64) -> LimineRequestId {
+    LimineRequestId::limine(id1, id2)
+}
+
+#[cfg(feature = "boot-info")]
+pub const BOOT_INFO: LimineRequestId = id(0xf55038d8e2a1202f, 0x279426fcf5f59740);
+#[cfg(feature = "framebuffer")]
+pub const FRAMEBUFFER: LimineRequestId = id(0x9d5827dcd881dd75, 0xa3148604f6fab11b);
+#[cfg(feature = "hhdm")]
+pub const HHDM: LimineRequestId = id(0x48dcf1cb8ad2b852, 0x63984e959a98244b);
+#[cfg(feature = "memory-map")]
+pub const MEMORY_MAP: LimineRequestId = id(0x67cf3d9d378a806f, 0xe304acdfc50c3c62);
+#[cfg(feature = "smp")]
+pub const SMP: LimineRequestId = id(0x95a67b819a1b857e, 0xa0b61b723b6a73e0);
+#[cfg(feature = "acpi")]
+pub const RSDP: LimineRequestId = id(0xc5e77b6b397e7b43, 0x27637845accdcf3c);
+#[cfg(feature = "smbios")]
+pub const SMBIOS: LimineRequestId = id(0x9e9046f11e095391, 0xaa4a520fefbde5ee);
+#[cfg(feature = "efi")]
+pub const EFI_SYSTEM_TABLE: LimineRequestId = id(0x5ceba5163eaaf6d6, 0x0a6981610cf65fcc);
+#[cfg(feature = "boot-time")]
+pub const BOOT_TIME: LimineRequestId = id(0x502746e184c088aa, 0xfbc5ec83e6327893);
+#[cfg(feature = "kernel-file")]
+pub const KERNEL_FILE: LimineRequestId = id(0xad97e90e83f1ed67, 0x31eb5d1c5ff23b69);
+#[cfg(feature = "kernel-address")]
+pub const KERNEL_ADDRESS: LimineRequestId = id(0x71ba76863cc55f63, 0xb2644a48c516a487);
+#[cfg(feature = "modules")]
+pub const MODULES: LimineRequestId = id(0x3e7e279702be32af, 0xca1c4f3bd1280cee);
+#[cfg(feature = "dtb")]
+pub const DTB: LimineRequestId = id(0xb40ddb48fb54bac7, 0x545081493f81ffb7);
+#[cfg(feature = "firmware-type")]
+pub const FIRMWARE_TYPE: LimineRequestId = id(0x8c2f75d90bef28a8, 0x7045a4688eac00c3);
+#[cfg(feature = "paging-mode")]
+pub const PAGING_MODE: LimineRequestId = id(0x95c1a0edab0944cb, 0xa4e5cb3842f7488a);
+#[cfg(feature = "legacy-terminal")]
+pub const TERMINAL: LimineRequestId = id(0xc8ac59310c2b0844, 0xa68d0c7265d38878);
+
+/// Every request ID compiled into the kernel.
+pub const ALL: &[LimineRequestId] = &[
+    #[cfg(feature = "boot-info")]
+    BOOT_INFO,
+    #[cfg(feature = "framebuffer")]
+    FRAMEBUFFER,
+    #[cfg(feature = "hhdm")]
+    HHDM,
+    #[cfg(feature = "memory-map")]
+    MEMORY_MAP,
+    #[cfg(feature = "smp")]
+    SMP,
+    #[cfg(feature = "acpi")]
+    RSDP,
+    #[cfg(feature = "smbios")]
+    SMBIOS,
+    #[cfg(feature = "efi")]
+    EFI_SYSTEM_TABLE,
+    #[cfg(feature = "boot-time")]
+    BOOT_TIME,
+    #[cfg(feature = "kernel-file")]
+    KERNEL_FILE,
+    #[cfg(feature = "kernel-address")]
+    KERNEL_ADDRESS,
+    #[cfg(feature = "modules")]
+    MODULES,
+    #[cfg(feature = "dtb")]
+    DTB,
+    #[cfg(feature = "firmware-type")]
+    FIRMWARE_TYPE,
+    #[cfg(feature = "paging-mode")]
+    PAGING_MODE,
+    #[cfg(feature = "legacy-terminal")]
+    TERMINAL,
+];
+
+/// Whether `id` is in [`ALL`].
+const fn is_listed(id: LimineRequestId) -> bool {
+    let mut i = 0;
+    while i < ALL.len() {
+        if ALL[i].const_eq(&id) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+// Fails the build if a request is listed with its feature disabled, or missing with it
+// enabled. The IDs are taken from the request types, which exist with every feature, or
+// written out for the ones this crate defines behind their feature.
+const _: () = {
+    macro_rules! check {
+        ($feature:literal, $id:expr) => {
+            assert!(
+                is_listed($id) == cfg!(feature = $feature),
+                concat!("ids::ALL doesn't match the `", $feature, "` feature")
+            );
+        };
+    }
+    use limine::*;
+
+    let raw = LimineRequestId::from_raw;
+    check!("boot-info", raw(LimineBootInfoRequest::ID));
+    check!("framebuffer", raw(LimineFramebufferRequest::ID));
+    check!("hhdm", raw(LimineHhdmRequest::ID));
+    check!("memory-map", raw(LimineMemmapRequest::ID));
+    check!("smp", raw(LimineSmpRequest::ID));
+    check!("acpi", raw(LimineRsdpRequest::ID));
+    check!("smbios", raw(LimineSmbiosRequest::ID));
+    check!("efi", raw(LimineEfiSystemTableRequest::ID));
+    check!("boot-time", raw(LimineBootTimeRequest::ID));
+    check!("kernel-file", raw(LimineKernelFileRequest::ID));
+    check!("kernel-address", raw(LimineKernelAddressRequest::ID));
+    check!("modules", raw(LimineModuleRequest::ID));
+    check!("dtb", raw(LimineDtbRequest::ID));
+    check!(
+        "firmware-type",
+        LimineRequestId::limine(0x8c2f75d90bef28a8, 0x7045a4688eac00c3)
+    );
+    check!(
+        "paging-mode",
+        LimineRequestId::limine(0x95c1a0edab0944cb, 0xa4e5cb3842f7488a)
+    );
+    check!("legacy-terminal", raw(LimineTerminalRequest::ID));
+};
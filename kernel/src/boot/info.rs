@@ -0,0 +1,413 @@
+//! All common responses resolved and checked in one go.
+//!
+//! ```ignore
+//! let boot = BootInfo::collect(&Requests::built_in())?;
+//! let console = BasicConsole::new(boot.framebuffer);
+//! ```
+
+use core::fmt;
+use core::marker::PhantomData;
+
+#[cfg(feature = "boot-time")]
+use limine::{LimineBootTimeRequest, LimineBootTimeResponse};
+#[cfg(feature = "framebuffer")]
+use limine::{LimineFramebuffer, LimineFramebufferRequest, LimineFramebufferResponse};
+#[cfg(feature = "hhdm")]
+use limine::{LimineHhdmRequest, LimineHhdmResponse};
+#[cfg(feature = "kernel-address")]
+use limine::{LimineKernelAddressRequest, LimineKernelAddressResponse};
+#[cfg(feature = "kernel-file")]
+use limine::{LimineKernelFileRequest, LimineKernelFileResponse};
+#[cfg(feature = "memory-map")]
+use limine::{LimineMemmapRequest, LimineMemmapResponse};
+#[cfg(feature = "modules")]
+use limine::{LimineModuleRequest, LimineModuleResponse};
+#[cfg(feature = "acpi")]
+use limine::{LimineRsdpRequest, LimineRsdpResponse};
+
+#[cfg(feature = "firmware-type")]
+use super::firmware::{LimineFirmwareTypeRequest, LimineFirmwareTypeResponse};
+#[cfg(feature = "framebuffer")]
+use super::ptr::ArrayPtrExt;
+#[allow(unused_imports)]
+use super::request::LimineRequest;
+#[allow(unused_imports)]
+use super::requests;
+
+/// The requests [`BootInfo::collect`] reads the responses of, through their
+/// [`Responses`] implementation. They are `&'static`, as only requests in statics are
+/// answered, see [`LimineRequest`].
+#[derive(Clone, Copy)]
+pub struct Requests {
+    #[cfg(feature = "framebuffer")]
+    pub framebuffer: &'static LimineFramebufferRequest,
+    #[cfg(feature = "memory-map")]
+    pub memory_map: &'static LimineMemmapRequest,
+    #[cfg(feature = "hhdm")]
+    pub hhdm: &'static LimineHhdmRequest,
+    #[cfg(feature = "kernel-file")]
+    pub kernel_file: &'static LimineKernelFileRequest,
+    #[cfg(feature = "kernel-address")]
+    pub kernel_address: &'static LimineKernelAddressRequest,
+    #[cfg(feature = "modules")]
+    pub modules: &'static LimineModuleRequest,
+    #[cfg(feature = "acpi")]
+    pub rsdp: &'static LimineRsdpRequest,
+    #[cfg(feature = "boot-time")]
+    pub boot_time: &'static LimineBootTimeRequest,
+    #[cfg(feature = "firmware-type")]
+    pub firmware_type: &'static LimineFirmwareTypeRequest,
+}
+
+impl Requests {
+    /// The statics of [`requests`](super::requests).
+    pub fn built_in() -> Self {
+        Self {
+            #[cfg(feature = "framebuffer")]
+            framebuffer: &requests::FRAMEBUFFER,
+            #[cfg(feature = "memory-map")]
+            memory_map: &requests::MEMORY_MAP,
+            #[cfg(feature = "hhdm")]
+            hhdm: &requests::HHDM,
+            #[cfg(feature = "kernel-file")]
+            kernel_file: &requests::KERNEL_FILE,
+            #[cfg(feature = "kernel-address")]
+            kernel_address: &requests::KERNEL_ADDRESS,
+            #[cfg(feature = "modules")]
+            modules: &requests::MODULES,
+            #[cfg(feature = "acpi")]
+            rsdp: &requests::RSDP,
+            #[cfg(feature = "boot-time")]
+            boot_time: &requests::BOOT_TIME,
+            #[cfg(feature = "firmware-type")]
+            firmware_type: &requests::FIRMWARE_TYPE,
+        }
+    }
+}
+
+/// Where [`BootInfo::collect`] reads the responses from, one method per field of
+/// [`Requests`]. Every method defaults to the request being unanswered, so sources other
+/// than [`Requests`], like fixed responses in tests, only implement those they answer.
+pub trait Responses<'a> {
+    #[cfg(feature = "framebuffer")]
+    fn framebuffer(&self) -> Option<&'a LimineFramebufferResponse> {
+        None
+    }
+    #[cfg(feature = "memory-map")]
+    fn memory_map(&self) -> Option<&'a LimineMemmapResponse> {
+        None
+    }
+    #[cfg(feature = "hhdm")]
+    fn hhdm(&self) -> Option<&'a LimineHhdmResponse> {
+        None
+    }
+    #[cfg(feature = "kernel-file")]
+    fn kernel_file(&self) -> Option<&'a LimineKernelFileResponse> {
+        None
+    }
+    #[cfg(feature = "kernel-address")]
+    fn kernel_address(&self) -> Option<&'a LimineKernelAddressResponse> {
+        None
+    }
+    #[cfg(feature = "modules")]
+    fn modules(&self) -> Option<&'a LimineModuleResponse> {
+        None
+    }
+    #[cfg(feature = "acpi")]
+    fn rsdp(&self) -> Option<&'a LimineRsdpResponse> {
+        None
+    }
+    #[cfg(feature = "boot-time")]
+    fn boot_time(&self) -> Option<&'a LimineBootTimeResponse> {
+        None
+    }
+    #[cfg(feature = "firmware-type")]
+    fn firmware_type(&self) -> Option<&'a LimineFirmwareTypeResponse> {
+        None
+    }
+}
+
+impl Responses<'static> for Requests {
+    #[cfg(feature = "framebuffer")]
+    fn framebuffer(&self) -> Option<&'static LimineFramebufferResponse> {
+        self.framebuffer.response()
+    }
+    #[cfg(feature = "memory-map")]
+    fn memory_map(&self) -> Option<&'static LimineMemmapResponse> {
+        self.memory_map.response()
+    }
+    #[cfg(feature = "hhdm")]
+    fn hhdm(&self) -> Option<&'static LimineHhdmResponse> {
+        self.hhdm.response()
+    }
+    #[cfg(feature = "kernel-file")]
+    fn kernel_file(&self) -> Option<&'static LimineKernelFileResponse> {
+        self.kernel_file.response()
+    }
+    #[cfg(feature = "kernel-address")]
+    fn kernel_address(&self) -> Option<&'static LimineKernelAddressResponse> {
+        self.kernel_address.response()
+    }
+    #[cfg(feature = "modules")]
+    fn modules(&self) -> Option<&'static LimineModuleResponse> {
+        self.modules.response()
+    }
+    #[cfg(feature = "acpi")]
+    fn rsdp(&self) -> Option<&'static LimineRsdpResponse> {
+        self.rsdp.response()
+    }
+    #[cfg(feature = "boot-time")]
+    fn boot_time(&self) -> Option<&'static LimineBootTimeResponse> {
+        self.boot_time.response()
+    }
+    #[cfg(feature = "firmware-type")]
+    fn firmware_type(&self) -> Option<&'static LimineFirmwareTypeResponse> {
+        self.firmware_type.response()
+    }
+}
+
+/// A mandatory response the bootloader didn't provide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootInfoError {
+    /// There is no framebuffer response, or it lists no framebuffers.
+    #[cfg(feature = "framebuffer")]
+    NoFramebuffer,
+    #[cfg(feature = "memory-map")]
+    NoMemoryMap,
+}
+
+impl fmt::Display for BootInfoError {
+    #[allow(unused_variables)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "framebuffer")]
+            Self::NoFramebuffer => f.write_str("the bootloader provided no framebuffer"),
+            #[cfg(feature = "memory-map")]
+            Self::NoMemoryMap => f.write_str("the bootloader provided no memory map"),
+        }
+    }
+}
+
+/// The resolved responses. The framebuffer and memory map are mandatory, everything else
+/// is `None` when the bootloader didn't answer. Those from [`Requests`] are
+/// `BootInfo<'static>`.
+#[derive(Clone, Copy, Debug)]
+pub struct BootInfo<'a> {
+    /// The first framebuffer.
+    #[cfg(feature = "framebuffer")]
+    pub framebuffer: &'a LimineFramebuffer,
+    #[cfg(feature = "memory-map")]
+    pub memory_map: &'a LimineMemmapResponse,
+    #[cfg(feature = "hhdm")]
+    pub hhdm: Option<&'a LimineHhdmResponse>,
+    #[cfg(feature = "kernel-file")]
+    pub kernel_file: Option<&'a LimineKernelFileResponse>,
+    #[cfg(feature = "kernel-address")]
+    pub kernel_address: Option<&'a LimineKernelAddressResponse>,
+    #[cfg(feature = "modules")]
+    pub modules: Option<&'a LimineModuleResponse>,
+    #[cfg(feature = "acpi")]
+    pub rsdp: Option<&'a LimineRsdpResponse>,
+    #[cfg(feature = "boot-time")]
+    pub boot_time: Option<&'a LimineBootTimeResponse>,
+    #[cfg(feature = "firmware-type")]
+    pub firmware_type: Option<&'a LimineFirmwareTypeResponse>,
+    /// Keeps `'a` used when no response is collected.
+    responses: PhantomData<&'a ()>,
+}
+
+impl<'a> BootInfo<'a> {
+    /// Reads `responses`, failing if a mandatory one is missing.
+    #[allow(unused_variables)]
+    pub fn collect(responses: &dyn Responses<'a>) -> Result<Self, BootInfoError> {
+        Ok(Self {
+            #[cfg(feature = "framebuffer")]
+            framebuffer: responses
+                .framebuffer()
+                // SAFETY: The count comes from the bootloader along with the array.
+                .and_then(|response| unsafe {
+                    response
+                        .framebuffers
+                        .iter(response.framebuffer_count as usize)
+                        .next()
+                })
+                .ok_or(BootInfoError::NoFramebuffer)?,
+            #[cfg(feature = "memory-map")]
+            memory_map: responses.memory_map().ok_or(BootInfoError::NoMemoryMap)?,
+            #[cfg(feature = "hhdm")]
+            hhdm: responses.hhdm(),
+            #[cfg(feature = "kernel-file")]
+            kernel_file: responses.kernel_file(),
+            #[cfg(feature = "kernel-address")]
+            kernel_address: responses.kernel_address(),
+            #[cfg(feature = "modules")]
+            modules: responses.modules(),
+            #[cfg(feature = "acpi")]
+            rsdp: responses.rsdp(),
+            #[cfg(feature = "boot-time")]
+            boot_time: responses.boot_time(),
+            #[cfg(feature = "firmware-type")]
+            firmware_type: responses.firmware_type(),
+            responses: PhantomData,
+        })
+    }
+}
+
+/// Collects from fixed responses, some answered and some not, and checks that missing
+/// optional responses come out as `None` and missing mandatory ones as errors.
+#[cfg(any(feature = "framebuffer", feature = "memory-map"))]
+#[allow(unused_variables)]
+pub fn self_test() -> bool {
+    #[cfg(feature = "framebuffer")]
+    use super::framebuffer::LimineFramebufferResponseExt;
+    #[cfg(feature = "memory-map")]
+    use super::memmap::LimineMemmapResponseExt;
+
+    /// Answers the framebuffer and memory map requests with what it holds, the HHDM and
+    /// firmware type requests always, and leaves the rest unanswered.
+    #[derive(Clone, Copy)]
+    struct Fixed<'a> {
+        #[cfg(feature = "framebuffer")]
+        framebuffer: Option<&'a LimineFramebufferResponse>,
+        #[cfg(feature = "memory-map")]
+        memory_map: Option<&'a LimineMemmapResponse>,
+        #[cfg(feature = "hhdm")]
+        hhdm: &'a LimineHhdmResponse,
+        #[cfg(feature = "firmware-type")]
+        firmware_type: &'a LimineFirmwareTypeResponse,
+    }
+
+    impl<'a> Responses<'a> for Fixed<'a> {
+        #[cfg(feature = "framebuffer")]
+        fn framebuffer(&self) -> Option<&'a LimineFramebufferResponse> {
+            self.framebuffer
+        }
+        #[cfg(feature = "memory-map")]
+        fn memory_map(&self) -> Option<&'a LimineMemmapResponse> {
+            self.memory_map
+        }
+        #[cfg(feature = "hhdm")]
+        fn hhdm(&self) -> Option<&'a LimineHhdmResponse> {
+            Some(self.hhdm)
+        }
+        #[cfg(feature = "firmware-type")]
+        fn firmware_type(&self) -> Option<&'a LimineFirmwareTypeResponse> {
+            Some(self.firmware_type)
+        }
+    }
+
+    // SAFETY: A null address, nothing draws on it.
+    #[cfg(feature = "framebuffer")]
+    static FRAMEBUFFER: LimineFramebuffer = unsafe {
+        crate::gfx::framebuffer_from_parts(
+            core::ptr::null_mut(),
+            crate::gfx::FramebufferInfo::xrgb8888(640, 480),
+        )
+    };
+    #[cfg(feature = "framebuffer")]
+    static FRAMEBUFFERS: [&LimineFramebuffer; 1] = [&FRAMEBUFFER];
+    // SAFETY: The framebuffers are statics.
+    #[cfg(feature = "framebuffer")]
+    let with_framebuffer = unsafe { LimineFramebufferResponse::from_parts(0, &FRAMEBUFFERS) };
+    // SAFETY: As above.
+    #[cfg(feature = "framebuffer")]
+    let without_framebuffers = unsafe { LimineFramebufferResponse::from_parts(0, &[]) };
+
+    // SAFETY: As above, for the entries.
+    #[cfg(feature = "memory-map")]
+    let memory_map = unsafe { LimineMemmapResponse::from_parts(0, &[]) };
+
+    #[cfg(feature = "hhdm")]
+    let hhdm = LimineHhdmResponse {
+        revision: 0,
+        offset: 0xffff_8000_0000_0000,
+    };
+    #[cfg(feature = "firmware-type")]
+    let firmware_type = LimineFirmwareTypeResponse {
+        revision: 0,
+        firmware_type: super::firmware::FIRMWARE_TYPE_UEFI64,
+    };
+
+    let answered = Fixed {
+        #[cfg(feature = "framebuffer")]
+        framebuffer: Some(&with_framebuffer),
+        #[cfg(feature = "memory-map")]
+        memory_map: Some(&memory_map),
+        #[cfg(feature = "hhdm")]
+        hhdm: &hhdm,
+        #[cfg(feature = "firmware-type")]
+        firmware_type: &firmware_type,
+    };
+
+    let Ok(info) = BootInfo::collect(&answered) else {
+        return false;
+    };
+    #[cfg(feature = "framebuffer")]
+    let framebuffer_ok = core::ptr::eq(info.framebuffer, &FRAMEBUFFER);
+    #[cfg(feature = "memory-map")]
+    let memory_map_ok = core::ptr::eq(info.memory_map, &memory_map);
+    #[cfg(feature = "hhdm")]
+    let hhdm_ok = info
+        .hhdm
+        .is_some_and(|hhdm| hhdm.offset == 0xffff_8000_0000_0000);
+    #[cfg(feature = "firmware-type")]
+    let firmware_ok = info
+        .firmware_type
+        .is_some_and(|firmware| firmware.is_64bit_uefi());
+    let absent = [
+        #[cfg(feature = "kernel-file")]
+        info.kernel_file.is_none(),
+        #[cfg(feature = "kernel-address")]
+        info.kernel_address.is_none(),
+        #[cfg(feature = "modules")]
+        info.modules.is_none(),
+        #[cfg(feature = "acpi")]
+        info.rsdp.is_none(),
+        #[cfg(feature = "boot-time")]
+        info.boot_time.is_none(),
+    ];
+    let present = [
+        #[cfg(feature = "framebuffer")]
+        framebuffer_ok,
+        #[cfg(feature = "memory-map")]
+        memory_map_ok,
+        #[cfg(feature = "hhdm")]
+        hhdm_ok,
+        #[cfg(feature = "firmware-type")]
+        firmware_ok,
+    ];
+
+    // With only one of the mandatory responses, the updates set every field.
+    #[allow(clippy::needless_update)]
+    let missing = [
+        #[cfg(feature = "framebuffer")]
+        (
+            Fixed {
+                framebuffer: None,
+                ..answered
+            },
+            BootInfoError::NoFramebuffer,
+        ),
+        #[cfg(feature = "framebuffer")]
+        (
+            Fixed {
+                framebuffer: Some(&without_framebuffers),
+                ..answered
+            },
+            BootInfoError::NoFramebuffer,
+        ),
+        #[cfg(feature = "memory-map")]
+        (
+            Fixed {
+                memory_map: None,
+                ..answered
+            },
+            BootInfoError::NoMemoryMap,
+        ),
+    ];
+    let rejected = missing
+        .iter()
+        .all(|(responses, err)| BootInfo::collect(responses).err() == Some(*err));
+
+    absent.into_iter().chain(present).all(|ok| ok) && rejected
+}
@@ -0,0 +1,560 @@
+use core::fmt;
+use core::ops::Range;
+
+use limine::{LimineMemmapEntry, LimineMemmapResponse, LimineMemoryMapEntryType};
+
+use super::ptr::{ArrayPtr, ArrayPtrExt};
+use crate::array_vec::ArrayVec;
+
+pub use super::{PhysAddr, PhysAddrRange};
+
+/// Displays a memory map entry type the way the Limine specification names it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntryType(pub LimineMemoryMapEntryType);
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.0 {
+            LimineMemoryMapEntryType::Usable => "usable",
+            LimineMemoryMapEntryType::Reserved => "reserved",
+            LimineMemoryMapEntryType::AcpiReclaimable => "ACPI reclaimable",
+            LimineMemoryMapEntryType::AcpiNvs => "ACPI NVS",
+            LimineMemoryMapEntryType::BadMemory => "bad memory",
+            LimineMemoryMapEntryType::BootloaderReclaimable => "bootloader reclaimable",
+            LimineMemoryMapEntryType::KernelAndModules => "kernel and modules",
+            LimineMemoryMapEntryType::Framebuffer => "framebuffer",
+        })
+    }
+}
+
+pub trait LimineMemmapEntryExt {
+    /// Whether the entry can be reclaimed once the kernel is done with the data the
+    /// firmware or bootloader left in it.
+    fn is_reclaimable(&self) -> bool;
+
+    /// Whether the entry can eventually be handed out as general purpose memory, either
+    /// right away or after being reclaimed.
+    fn is_available(&self) -> bool;
+}
+
+impl LimineMemmapEntryExt for LimineMemmapEntry {
+    #[inline]
+    fn is_reclaimable(&self) -> bool {
+        matches!(
+            self.typ,
+            LimineMemoryMapEntryType::AcpiReclaimable
+                | LimineMemoryMapEntryType::BootloaderReclaimable
+        )
+    }
+
+    #[inline]
+    fn is_available(&self) -> bool {
+        self.typ == LimineMemoryMapEntryType::Usable || self.is_reclaimable()
+    }
+}
+
+pub trait LimineMemmapResponseExt {
+    /// Builds a response listing `entries`, laid out the way the bootloader would, like
+    /// `LimineFramebufferResponseExt::from_parts` does for framebuffers.
+    ///
+    /// This is for exercising code that takes a memory map without a bootloader.
+    ///
+    /// ## Safety
+    ///
+    /// The entries are shared, so nothing may mutate them through the response.
+    unsafe fn from_parts(revision: u64, entries: &'static [&'static LimineMemmapEntry]) -> Self;
+
+    /// Returns up to `N` usable entries sorted by base address. Usable entries past the
+    /// first `N` in the memory map are left out.
+    fn usable_regions_sorted<const N: usize>(&self) -> ArrayVec<&LimineMemmapEntry, N>;
+
+    /// Returns the usable entry with the lowest base at or above `base`.
+    fn usable_region_above(&self, base: u64) -> Option<&LimineMemmapEntry>;
+
+    /// The lowest address at or above `base`, aligned to `align`, with `size` usable
+    /// bytes behind it in a single entry. Finds nothing if `align` is zero.
+    fn first_usable_above(&self, base: PhysAddr, size: u64, align: u64) -> Option<PhysAddr>;
+
+    /// Collects up to `N` usable entries into a [`FreeRegionList`], sorted by base
+    /// address.
+    fn as_free_region_list<const N: usize>(&self) -> FreeRegionList<N>;
+
+    /// Iterates over the entries holding the kernel image. Limine gives modules the same
+    /// type, so these include them.
+    fn kernel_regions(&self) -> impl Iterator<Item = &LimineMemmapEntry>;
+
+    /// Iterates over the entries holding modules. These are the same entries as
+    /// [`kernel_regions`](Self::kernel_regions), as Limine doesn't tell them apart.
+    fn modules_regions(&self) -> impl Iterator<Item = &LimineMemmapEntry> {
+        self.kernel_regions()
+    }
+
+    /// The total length of the [`kernel_regions`](Self::kernel_regions), modules
+    /// included.
+    fn kernel_total_size(&self) -> u64 {
+        self.kernel_regions().map(|entry| entry.len).sum()
+    }
+
+    /// Writes one line per entry, as `0x<base> - 0x<end> [<type>]` with the end exclusive.
+    fn print_map<W: fmt::Write + ?Sized>(&self, out: &mut W) -> fmt::Result;
+}
+
+impl LimineMemmapResponseExt for LimineMemmapResponse {
+    unsafe fn from_parts(revision: u64, entries: &'static [&'static LimineMemmapEntry]) -> Self {
+        Self {
+            revision,
+            entry_count: entries.len() as u64,
+            // SAFETY: `NonNullPtr` is a transparent wrapper around a non-null pointer, as
+            // are references, and slices are never null even when empty.
+            entries: unsafe {
+                core::mem::transmute::<*const &LimineMemmapEntry, ArrayPtr<LimineMemmapEntry>>(
+                    entries.as_ptr(),
+                )
+            },
+        }
+    }
+
+    fn usable_regions_sorted<const N: usize>(&self) -> ArrayVec<&LimineMemmapEntry, N> {
+        let mut regions = ArrayVec::new();
+        for entry in usable_entries(self) {
+            if regions.push(entry).is_err() {
+                break;
+            }
+        }
+
+        // Insertion sort: Limine already sorts the map, so this is usually a single pass.
+        for i in 1..regions.len() {
+            let mut j = i;
+            while j > 0 && regions[j - 1].base > regions[j].base {
+                regions.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        regions
+    }
+
+    fn usable_region_above(&self, base: u64) -> Option<&LimineMemmapEntry> {
+        usable_entries(self)
+            .filter(|entry| entry.base >= base)
+            .min_by_key(|entry| entry.base)
+    }
+
+    fn first_usable_above(&self, base: PhysAddr, size: u64, align: u64) -> Option<PhysAddr> {
+        usable_entries(self)
+            .filter_map(|entry| {
+                let start = entry.base.max(base).checked_next_multiple_of(align)?;
+                let end = start.checked_add(size)?;
+                (end <= entry.base + entry.len).then_some(start)
+            })
+            .min()
+    }
+
+    fn as_free_region_list<const N: usize>(&self) -> FreeRegionList<N> {
+        let mut list = FreeRegionList::new();
+        for entry in self.usable_regions_sorted::<N>().iter() {
+            list.free_region(entry.base..entry.base + entry.len);
+        }
+        list
+    }
+
+    fn kernel_regions(&self) -> impl Iterator<Item = &LimineMemmapEntry> {
+        entries_of_type(self, LimineMemoryMapEntryType::KernelAndModules)
+    }
+
+    fn print_map<W: fmt::Write + ?Sized>(&self, out: &mut W) -> fmt::Result {
+        // SAFETY: The count comes from the bootloader along with the array.
+        for entry in unsafe { self.entries.iter(self.entry_count as usize) } {
+            writeln!(
+                out,
+                "0x{:016x} - 0x{:016x} [{}]",
+                entry.base,
+                entry.base + entry.len,
+                EntryType(entry.typ)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn usable_entries(memmap: &LimineMemmapResponse) -> impl Iterator<Item = &LimineMemmapEntry> {
+    entries_of_type(memmap, LimineMemoryMapEntryType::Usable)
+}
+
+fn entries_of_type(
+    memmap: &LimineMemmapResponse,
+    typ: LimineMemoryMapEntryType,
+) -> impl Iterator<Item = &LimineMemmapEntry> {
+    unsafe { memmap.entries.iter(memmap.entry_count as usize) }
+        .filter(move |entry| entry.typ == typ)
+}
+
+/// Free physical memory as a sorted list of up to `N` disjoint ranges, which can be
+/// allocated from and freed back to.
+#[derive(Debug)]
+pub struct FreeRegionList<const N: usize> {
+    regions: ArrayVec<PhysAddrRange, N>,
+}
+
+impl<const N: usize> FreeRegionList<N> {
+    pub const fn new() -> Self {
+        Self {
+            regions: ArrayVec::new(),
+        }
+    }
+
+    /// The free ranges, sorted by address.
+    pub fn regions(&self) -> &[PhysAddrRange] {
+        &self.regions
+    }
+
+    /// The total number of free bytes.
+    pub fn free_bytes(&self) -> u64 {
+        self.regions
+            .iter()
+            .map(|region| region.end - region.start)
+            .sum()
+    }
+
+    /// Takes `size` bytes aligned to `align`, a power of two, from the first range they
+    /// fit in.
+    ///
+    /// A range is only split in two if there is room for the extra entry, otherwise the
+    /// next one is tried.
+    pub fn alloc_region(&mut self, size: u64, align: u64) -> Option<PhysAddrRange> {
+        self.alloc_region_below(size, align, u64::MAX)
+    }
+
+    /// Like [`alloc_region`](Self::alloc_region), but the allocated range ends at or
+    /// below `limit`.
+    pub fn alloc_region_below(
+        &mut self,
+        size: u64,
+        align: u64,
+        limit: u64,
+    ) -> Option<PhysAddrRange> {
+        if size == 0 || !align.is_power_of_two() {
+            return None;
+        }
+
+        for index in 0..self.regions.len() {
+            let region = self.regions[index].clone();
+            let Some(start) = region.start.checked_next_multiple_of(align) else {
+                continue;
+            };
+            let Some(end) = start
+                .checked_add(size)
+                .filter(|&end| end <= region.end && end <= limit)
+            else {
+                continue;
+            };
+
+            match (start > region.start, end < region.end) {
+                (true, true) => {
+                    if self.regions.insert(index + 1, end..region.end).is_err() {
+                        continue;
+                    }
+                    self.regions[index].end = start;
+                }
+                (true, false) => self.regions[index].end = start,
+                (false, true) => self.regions[index].start = end,
+                (false, false) => {
+                    self.regions.remove(index);
+                }
+            }
+            return Some(start..end);
+        }
+        None
+    }
+
+    /// Gives `range` back, merging it with adjacent free ranges. It must not overlap any
+    /// free range.
+    ///
+    /// If it can't be merged and the list is full, the range is dropped and stays in
+    /// use.
+    pub fn free_region(&mut self, range: PhysAddrRange) {
+        if range.is_empty() {
+            return;
+        }
+
+        let index = self
+            .regions
+            .iter()
+            .position(|region| region.start > range.start)
+            .unwrap_or(self.regions.len());
+        let merges_prev = index > 0 && self.regions[index - 1].end == range.start;
+        let merges_next = self
+            .regions
+            .get(index)
+            .is_some_and(|next| next.start == range.end);
+
+        match (merges_prev, merges_next) {
+            (true, true) => {
+                let next = self.regions.remove(index);
+                self.regions[index - 1].end = next.end;
+            }
+            (true, false) => self.regions[index - 1].end = range.end,
+            (false, true) => self.regions[index].start = range.start,
+            (false, false) => {
+                let _ = self.regions.insert(index, range);
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for FreeRegionList<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How many reclaimable regions a [`BootReclaimGuard`] can hold on to.
+pub const MAX_RECLAIMABLE_REGIONS: usize = 64;
+
+/// Holds back the reclaimable memory map regions until the kernel is done with them.
+///
+/// Bootloader reclaimable memory contains the Limine responses themselves, including the
+/// memory map, module list and the pointers into them, and ACPI reclaimable memory holds
+/// the ACPI tables. Handing either to a frame allocator while they are still being read
+/// lets freshly allocated frames overwrite them. The guard records the regions up front,
+/// copying them out of the memory map so it can outlive the response, and only gives them
+/// away when [`reclaim`](Self::reclaim) is called, which should be the last thing done
+/// after every module, ACPI table and other response has been copied or parsed.
+pub struct BootReclaimGuard {
+    regions: [Range<u64>; MAX_RECLAIMABLE_REGIONS],
+    len: usize,
+}
+
+impl BootReclaimGuard {
+    /// Records the reclaimable entries of the memory map. Entries beyond
+    /// [`MAX_RECLAIMABLE_REGIONS`] are left alone and never reclaimed.
+    pub fn new(memmap: &LimineMemmapResponse) -> Self {
+        let mut guard = Self {
+            regions: [const { 0..0 }; MAX_RECLAIMABLE_REGIONS],
+            len: 0,
+        };
+
+        let entries = unsafe { memmap.entries.iter(memmap.entry_count as usize) };
+        for entry in entries.filter(|entry| entry.is_reclaimable()) {
+            let Some(slot) = guard.regions.get_mut(guard.len) else {
+                break;
+            };
+            *slot = entry.base..entry.base + entry.len;
+            guard.len += 1;
+        }
+
+        guard
+    }
+
+    /// The regions that will be released by [`reclaim`](Self::reclaim).
+    pub fn reclaimable_regions(&self) -> &[Range<u64>] {
+        &self.regions[..self.len]
+    }
+
+    /// Releases every recorded region into a frame allocator through `release`.
+    ///
+    /// ## Safety
+    ///
+    /// Nothing may access bootloader or ACPI reclaimable memory anymore, including every
+    /// Limine response and all data reached through them.
+    pub unsafe fn reclaim(self, mut release: impl FnMut(Range<u64>)) {
+        // The bootloader's terminal lives in the memory about to be released.
+        #[cfg(feature = "legacy-terminal")]
+        super::terminal::invalidate();
+
+        for region in self.reclaimable_regions() {
+            release(region.clone());
+        }
+    }
+}
+
+/// Returned when a [`MemoryMap`] runs out of room for its entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryMapFull;
+
+impl fmt::Display for MemoryMapFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no room left for memory map entries")
+    }
+}
+
+/// A copy of the memory map the kernel can edit, kept in caller provided storage.
+pub struct MemoryMap<'a> {
+    entries: &'a mut [LimineMemmapEntry],
+    len: usize,
+}
+
+impl<'a> MemoryMap<'a> {
+    /// Copies the entries of `memmap` into `storage`, which should have room to spare for
+    /// the entries [`reserve`](Self::reserve) splits off.
+    pub fn new(
+        memmap: &LimineMemmapResponse,
+        storage: &'a mut [LimineMemmapEntry],
+    ) -> Result<Self, MemoryMapFull> {
+        let count = memmap.entry_count as usize;
+        if count > storage.len() {
+            return Err(MemoryMapFull);
+        }
+
+        let entries = unsafe { memmap.entries.iter(count) };
+        for (slot, entry) in storage.iter_mut().zip(entries) {
+            *slot = LimineMemmapEntry {
+                base: entry.base,
+                len: entry.len,
+                typ: entry.typ,
+            };
+        }
+
+        Ok(Self {
+            entries: storage,
+            len: count,
+        })
+    }
+
+    pub fn entries(&self) -> &[LimineMemmapEntry] {
+        &self.entries[..self.len]
+    }
+
+    /// Marks `base..base + length` reserved where it overlaps usable entries, which are
+    /// truncated, or split in two if the range lies in their middle. Other entry types
+    /// are left alone.
+    ///
+    /// Nothing is changed if there isn't room for the extra entries.
+    pub fn reserve(&mut self, base: u64, length: u64) -> Result<(), MemoryMapFull> {
+        let end = base.saturating_add(length);
+        let pieces = |entry: &LimineMemmapEntry| {
+            let entry_end = entry.base + entry.len;
+            if entry.typ != LimineMemoryMapEntryType::Usable
+                || base >= entry_end
+                || end <= entry.base
+            {
+                return None;
+            }
+            let start = base.max(entry.base);
+            let stop = end.min(entry_end);
+            Some([
+                (
+                    entry.base,
+                    start - entry.base,
+                    LimineMemoryMapEntryType::Usable,
+                ),
+                (start, stop - start, LimineMemoryMapEntryType::Reserved),
+                (stop, entry_end - stop, LimineMemoryMapEntryType::Usable),
+            ])
+        };
+        let piece_count = |pieces: &[(u64, u64, LimineMemoryMapEntryType); 3]| {
+            pieces.iter().filter(|(_, len, _)| *len != 0).count()
+        };
+
+        let needed: usize = self
+            .entries()
+            .iter()
+            .filter_map(pieces)
+            .map(|pieces| piece_count(&pieces) - 1)
+            .sum();
+        if self.len + needed > self.entries.len() {
+            return Err(MemoryMapFull);
+        }
+
+        let mut i = 0;
+        while i < self.len {
+            let Some(new) = pieces(&self.entries[i]) else {
+                i += 1;
+                continue;
+            };
+
+            // Open up room after the entry by rotating unused slots in from the end.
+            let extra = piece_count(&new) - 1;
+            self.entries[i + 1..self.len + extra].rotate_right(extra);
+            self.len += extra;
+
+            for (base, len, typ) in new.into_iter().filter(|(_, len, _)| *len != 0) {
+                self.entries[i] = LimineMemmapEntry { base, len, typ };
+                i += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs the memory map checks against synthetic memory maps.
+pub fn self_test() -> bool {
+    reclaim_guard_self_test() && reserve_self_test()
+}
+
+/// A memory map entry, for building synthetic memory maps.
+const fn entry(base: u64, len: u64, typ: LimineMemoryMapEntryType) -> LimineMemmapEntry {
+    LimineMemmapEntry { base, len, typ }
+}
+
+/// Checks that a [`BootReclaimGuard`] records both kinds of reclaimable entries and
+/// nothing else, and that an allocator only sees them once they are reclaimed.
+fn reclaim_guard_self_test() -> bool {
+    use LimineMemoryMapEntryType::*;
+
+    static USABLE: LimineMemmapEntry = entry(0x1000, 0x9000, Usable);
+    static BOOTLOADER: LimineMemmapEntry = entry(0x10_0000, 0x4000, BootloaderReclaimable);
+    static RESERVED: LimineMemmapEntry = entry(0x10_4000, 0x1000, Reserved);
+    static ACPI: LimineMemmapEntry = entry(0x20_0000, 0x2000, AcpiReclaimable);
+    static ENTRIES: [&LimineMemmapEntry; 4] = [&USABLE, &BOOTLOADER, &RESERVED, &ACPI];
+
+    // SAFETY: Nothing mutates the entries.
+    let memmap = unsafe { LimineMemmapResponse::from_parts(0, &ENTRIES) };
+    let mut frames = FreeRegionList::<4>::new();
+    let guard = BootReclaimGuard::new(&memmap);
+
+    let expected = [0x10_0000..0x10_4000, 0x20_0000..0x20_2000];
+    let recorded = guard.reclaimable_regions() == expected;
+    let held_back = frames.regions().is_empty();
+
+    // Reclaiming gives up the bootloader's terminal for good.
+    if cfg!(feature = "legacy-terminal") {
+        return recorded && held_back;
+    }
+    // SAFETY: The regions are made up, nothing is in them.
+    unsafe { guard.reclaim(|region| frames.free_region(region)) };
+
+    recorded && held_back && frames.regions() == expected
+}
+
+/// Reserves ranges at the start, in the middle and at the end of usable entries of a
+/// [`MemoryMap`], then one it has no room left for.
+fn reserve_self_test() -> bool {
+    use LimineMemoryMapEntryType::*;
+
+    static LOW: LimineMemmapEntry = entry(0x1000, 0xf000, Usable);
+    static RESERVED: LimineMemmapEntry = entry(0x1_0000, 0x1000, Reserved);
+    static HIGH: LimineMemmapEntry = entry(0x2_0000, 0x1_0000, Usable);
+    static ENTRIES: [&LimineMemmapEntry; 3] = [&LOW, &RESERVED, &HIGH];
+    const EXPECTED: [(u64, u64, LimineMemoryMapEntryType); 7] = [
+        (0x1000, 0x2000, Reserved),
+        (0x3000, 0xc000, Usable),
+        (0xf000, 0x1000, Reserved),
+        (0x1_0000, 0x1000, Reserved),
+        (0x2_0000, 0x4000, Usable),
+        (0x2_4000, 0x1000, Reserved),
+        (0x2_5000, 0xb000, Usable),
+    ];
+
+    // SAFETY: The entries are statics.
+    let memmap = unsafe { LimineMemmapResponse::from_parts(0, &ENTRIES) };
+    let mut storage = [const { entry(0, 0, Usable) }; 8];
+    let Ok(mut map) = MemoryMap::new(&memmap, &mut storage) else {
+        return false;
+    };
+    let matches = |map: &MemoryMap| {
+        map.entries()
+            .iter()
+            .map(|entry| (entry.base, entry.len, entry.typ))
+            .eq(EXPECTED)
+    };
+
+    let reserved = map.reserve(0x1000, 0x2000).is_ok()
+        && map.reserve(0x2_4000, 0x1000).is_ok()
+        // Runs on into the reserved entry, which stays as it is.
+        && map.reserve(0xf000, 0x2000).is_ok()
+        && matches(&map);
+    // Splitting `HIGH` again needs two more entries, but there is room for one.
+    reserved && map.reserve(0x2_6000, 0x1000) == Err(MemoryMapFull) && matches(&map)
+}
@@ -0,0 +1,55 @@
+//! Helpers layered on top of the Limine boot protocol structures.
+
+use core::ops::Range;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+#[cfg(feature = "acpi")]
+pub mod acpi;
+#[cfg(feature = "kernel-file")]
+pub mod cmdline;
+pub mod compat;
+#[cfg(feature = "dtb")]
+pub mod dtb;
+pub mod fdt;
+#[cfg(feature = "firmware-type")]
+pub mod firmware;
+#[cfg(feature = "framebuffer")]
+pub mod framebuffer;
+pub mod ids;
+pub mod info;
+#[cfg(feature = "memory-map")]
+pub mod memmap;
+#[cfg(feature = "modules")]
+pub mod module;
+#[cfg(feature = "paging-mode")]
+pub mod paging_mode;
+pub mod ptr;
+pub mod request;
+pub mod requests;
+#[cfg(feature = "smbios")]
+pub mod smbios;
+#[cfg(all(feature = "smp", not(target_arch = "x86_64")))]
+pub mod smp;
+#[cfg(feature = "legacy-terminal")]
+pub mod terminal;
+
+/// A physical address.
+pub type PhysAddr = u64;
+
+/// A range of physical addresses.
+pub type PhysAddrRange = Range<PhysAddr>;
+
+/// A virtual address.
+pub type VirtAddr = u64;
+
+/// Orders every read of a response after the bootloader's writes, and has to run before
+/// the kernel looks at any request.
+///
+/// The bootloader fills in the requests before jumping to the kernel, so as far as the
+/// compiler can tell, the response pointers are still the nulls they were built with.
+/// Responses are read with `read_volatile`, which keeps each read from being folded
+/// into that null, and this fence keeps them from being moved ahead of the entry point.
+#[inline(always)]
+pub fn limine_init() {
+    compiler_fence(Ordering::SeqCst);
+}
@@ -0,0 +1,211 @@
+use limine::{LimineFile, LimineModuleRequest, LimineModuleResponse};
+
+use core::{ptr, slice};
+
+use super::ptr::ArrayPtrExt;
+use super::VirtAddr;
+use crate::crypto::{self, crc32::crc32, sha256::sha256};
+
+/// Finders for the module types kernels most commonly look for.
+pub trait LimineModuleResponseExt {
+    /// Returns the first module whose path ends with `.cpio` or `.img`, which is
+    /// by convention the init ramdisk.
+    fn find_ramdisk(&self) -> Option<&LimineFile>;
+
+    /// Returns the first module whose path ends with `.dtb`.
+    fn find_dtb(&self) -> Option<&LimineFile>;
+
+    /// Returns the first module named `config` or `config.toml`.
+    fn find_config(&self) -> Option<&LimineFile>;
+
+    /// Returns the first module whose cmdline is exactly `cmdline`.
+    fn find_by_cmdline(&self, cmdline: &str) -> Option<&LimineFile>;
+
+    /// Copies the contents of the module at `index`, up to `max_len` bytes of it, to
+    /// `dest`. Returns the number of bytes copied, or `None` if there is no such module
+    /// or it has no contents.
+    ///
+    /// Debug builds check that `max_len` fits the whole module.
+    ///
+    /// ## Safety
+    ///
+    /// `dest` must be mapped and writable for `max_len` bytes and must not overlap the
+    /// module.
+    unsafe fn load_at(&self, index: usize, dest: VirtAddr, max_len: usize) -> Option<usize>;
+}
+
+impl LimineModuleResponseExt for LimineModuleResponse {
+    fn find_ramdisk(&self) -> Option<&LimineFile> {
+        find_module(self, |path| {
+            path.ends_with(b".cpio") || path.ends_with(b".img")
+        })
+    }
+
+    fn find_dtb(&self) -> Option<&LimineFile> {
+        find_module(self, |path| path.ends_with(b".dtb"))
+    }
+
+    fn find_config(&self) -> Option<&LimineFile> {
+        find_module(self, |path| {
+            let name = file_name(path);
+            name == b"config" || name == b"config.toml"
+        })
+    }
+
+    fn find_by_cmdline(&self, cmdline: &str) -> Option<&LimineFile> {
+        // SAFETY: The count comes from the bootloader along with the array.
+        unsafe { self.modules.iter(self.module_count as usize) }.find(|module| {
+            module
+                .cmdline
+                .to_str()
+                .is_some_and(|module_cmdline| module_cmdline.to_bytes() == cmdline.as_bytes())
+        })
+    }
+
+    unsafe fn load_at(&self, index: usize, dest: VirtAddr, max_len: usize) -> Option<usize> {
+        // SAFETY: The count comes from the bootloader along with the array.
+        let module = unsafe { self.modules.iter(self.module_count as usize) }.nth(index)?;
+        let data = module.data()?;
+        debug_assert!(
+            max_len >= data.len(),
+            "module {} is {} bytes but only {} fit at {:#x}",
+            index,
+            data.len(),
+            max_len,
+            dest
+        );
+
+        let len = data.len().min(max_len);
+        // SAFETY: The caller guarantees `dest` is writable for `max_len >= len` bytes
+        // and doesn't overlap the module.
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), dest as *mut u8, len) };
+        Some(len)
+    }
+}
+
+pub trait LimineFileExt {
+    /// Returns the contents of the file, or `None` if the bootloader gave no address.
+    fn data(&self) -> Option<&'static [u8]>;
+
+    /// Whether the SHA-256 of the contents is `expected`. A file without contents never
+    /// matches.
+    fn verify_sha256(&self, expected: &[u8; 32]) -> bool {
+        self.data()
+            .is_some_and(|data| crypto::constant_time_eq(&sha256(data), expected))
+    }
+
+    /// Whether the CRC-32 of the contents is `expected`. Good for catching a corrupted
+    /// or wrong file, but not a forged one.
+    fn verify_crc32(&self, expected: u32) -> bool {
+        self.data().is_some_and(|data| crc32(data) == expected)
+    }
+}
+
+impl LimineFileExt for LimineFile {
+    fn data(&self) -> Option<&'static [u8]> {
+        let base = self.base.as_ptr()?;
+        // SAFETY: Limine loads the whole file into bootloader-reclaimable memory, which
+        // stays mapped as long as the kernel doesn't reclaim it.
+        Some(unsafe { slice::from_raw_parts(base, self.length as usize) })
+    }
+}
+
+/// Builds a [`LimineModuleRequest`] for kernels that only care about modules whose
+/// cmdline starts with a given prefix.
+///
+/// The bootloader still loads every module; the filter is applied when walking the
+/// response. Keep the builder in a static next to the request so it can be used for that:
+///
+/// ```ignore
+/// static DRIVERS: LimineModuleRequestBuilder =
+///     LimineModuleRequestBuilder::with_cmdline_filter("driver:");
+///
+/// #[used]
+/// #[link_section = ".limine_requests"]
+/// static MODULES: LimineModuleRequest = DRIVERS.build();
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct LimineModuleRequestBuilder {
+    prefix: &'static str,
+}
+
+impl LimineModuleRequestBuilder {
+    pub const fn with_cmdline_filter(prefix: &'static str) -> Self {
+        Self { prefix }
+    }
+
+    pub const fn build(self) -> LimineModuleRequest {
+        LimineModuleRequest::new(0)
+    }
+
+    /// Iterates over the modules in `response` whose cmdline starts with the prefix.
+    /// Modules without a cmdline only match an empty prefix.
+    pub fn filtered_modules<'a>(
+        &'a self,
+        response: &'a LimineModuleResponse,
+    ) -> impl Iterator<Item = &'a LimineFile> {
+        // SAFETY: The count comes from the bootloader along with the array.
+        unsafe { response.modules.iter(response.module_count as usize) }.filter(|module| {
+            let cmdline = module
+                .cmdline
+                .to_str()
+                .map_or(&[][..], |cmdline| cmdline.to_bytes());
+            cmdline.starts_with(self.prefix.as_bytes())
+        })
+    }
+}
+
+/// Returns the first module whose path satisfies `predicate`.
+fn find_module(
+    response: &LimineModuleResponse,
+    predicate: impl Fn(&[u8]) -> bool,
+) -> Option<&LimineFile> {
+    // SAFETY: The count comes from the bootloader along with the array.
+    unsafe { response.modules.iter(response.module_count as usize) }.find(|module| {
+        module
+            .path
+            .to_str()
+            .is_some_and(|path| predicate(path.to_bytes()))
+    })
+}
+
+/// Returns the last component of a slash-separated path.
+fn file_name(path: &[u8]) -> &[u8] {
+    match path.iter().rposition(|&b| b == b'/') {
+        Some(index) => &path[index + 1..],
+        None => path,
+    }
+}
+
+/// Checks [`LimineFileExt::verify_crc32`] and [`LimineFileExt::verify_sha256`] against a
+/// file with the right contents, one with a byte changed and one without contents.
+pub fn self_test() -> bool {
+    static GOOD: [u8; 9] = *b"123456789";
+    static BAD: [u8; 9] = *b"123456780";
+
+    fn file(data: Option<&'static [u8]>) -> LimineFile {
+        let (base, length) = data.map_or((ptr::null(), 0), |data| (data.as_ptr(), data.len()));
+        // SAFETY: Every field is an integer or a nullable pointer, for which zero is
+        // valid, and `LiminePtr` is a transparent wrapper around one.
+        unsafe {
+            LimineFile {
+                base: core::mem::transmute::<*const u8, limine::LiminePtr<u8>>(base),
+                length: length as u64,
+                ..core::mem::zeroed()
+            }
+        }
+    }
+
+    const EXPECTED: u32 = 0xcbf4_3926;
+    const EXPECTED_SHA256: [u8; 32] = [
+        0x15, 0xe2, 0xb0, 0xd3, 0xc3, 0x38, 0x91, 0xeb, 0xb0, 0xf1, 0xef, 0x60, 0x9e, 0xc4, 0x19,
+        0x42, 0x0c, 0x20, 0xe3, 0x20, 0xce, 0x94, 0xc6, 0x5f, 0xbc, 0x8c, 0x33, 0x12, 0x44, 0x8e,
+        0xb2, 0x25,
+    ];
+    file(Some(&GOOD)).verify_crc32(EXPECTED)
+        && !file(Some(&BAD)).verify_crc32(EXPECTED)
+        && !file(None).verify_crc32(0)
+        && file(Some(&GOOD)).verify_sha256(&EXPECTED_SHA256)
+        && !file(Some(&BAD)).verify_sha256(&EXPECTED_SHA256)
+        && !file(None).verify_sha256(&EXPECTED_SHA256)
+}
@@ -0,0 +1,97 @@
+//! The paging mode request, which asks for a paging depth within a range and reports
+//! the one the bootloader set up.
+//!
+//! The bootloader falls back to another mode between `min_mode` and `max_mode` if the
+//! CPU can't do the requested one, like 5-level paging without LA57, so the response
+//! is what tells how deep the page tables are.
+
+use super::request::limine_request;
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub const PAGING_MODE_4LVL: u64 = 0;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub const PAGING_MODE_5LVL: u64 = 1;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const PAGING_MODE_DEFAULT: u64 = PAGING_MODE_4LVL;
+
+#[cfg(target_arch = "riscv64")]
+pub const PAGING_MODE_SV39: u64 = 0;
+#[cfg(target_arch = "riscv64")]
+pub const PAGING_MODE_SV48: u64 = 1;
+#[cfg(target_arch = "riscv64")]
+pub const PAGING_MODE_SV57: u64 = 2;
+#[cfg(target_arch = "riscv64")]
+const PAGING_MODE_DEFAULT: u64 = PAGING_MODE_SV48;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct LiminePagingModeResponse {
+    pub revision: u64,
+    /// One of the `PAGING_MODE_*` constants.
+    pub mode: u64,
+}
+
+impl LiminePagingModeResponse {
+    /// Whether the bootloader set up the mode `req` asked for, rather than falling back
+    /// to another one in its range.
+    pub fn got_requested(&self, req: &LiminePagingModeRequest) -> bool {
+        self.mode == req.mode
+    }
+
+    /// Whether the page tables have five levels.
+    pub fn is_five_level(&self) -> bool {
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        return self.mode == PAGING_MODE_5LVL;
+        #[cfg(target_arch = "riscv64")]
+        return self.mode == PAGING_MODE_SV57;
+    }
+}
+
+limine_request!(
+    pub struct LiminePagingModeRequest: [0x95c1a0edab0944cb, 0xa4e5cb3842f7488a]
+        => LiminePagingModeResponse {
+            /// The mode to set up if the CPU supports it.
+            pub mode: u64 = PAGING_MODE_DEFAULT,
+            /// The deepest mode to fall back to. Read from revision 1 on.
+            pub max_mode: u64 = PAGING_MODE_DEFAULT,
+            /// The shallowest mode to fall back to. Read from revision 1 on.
+            pub min_mode: u64 = PAGING_MODE_DEFAULT,
+        }
+);
+
+impl LiminePagingModeRequest {
+    /// Asks for `mode`, accepting anything from `min_mode` to `max_mode` instead.
+    #[must_use]
+    pub const fn with_modes(mut self, mode: u64, min_mode: u64, max_mode: u64) -> Self {
+        self.mode = mode;
+        self.min_mode = min_mode;
+        self.max_mode = max_mode;
+        self
+    }
+}
+
+/// Checks [`LiminePagingModeResponse::got_requested`] and
+/// [`LiminePagingModeResponse::is_five_level`] against a request for 5-level paging
+/// answered with 4-level paging, and one answered as asked.
+#[cfg(target_arch = "x86_64")]
+pub fn self_test() -> bool {
+    let request = LiminePagingModeRequest::new(1).with_modes(
+        PAGING_MODE_5LVL,
+        PAGING_MODE_4LVL,
+        PAGING_MODE_5LVL,
+    );
+
+    let fell_back = LiminePagingModeResponse {
+        revision: 0,
+        mode: PAGING_MODE_4LVL,
+    };
+    let matched = LiminePagingModeResponse {
+        revision: 0,
+        mode: PAGING_MODE_5LVL,
+    };
+
+    !fell_back.got_requested(&request)
+        && !fell_back.is_five_level()
+        && matched.got_requested(&request)
+        && matched.is_five_level()
+}
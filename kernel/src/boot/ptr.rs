@@ -0,0 +1,192 @@
+use core::ops::Deref;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use core::{fmt, ptr, slice};
+
+use limine::{LiminePtr, NonNullPtr};
+
+/// The pointer-to-array-of-pointers layout the Limine protocol uses for lists.
+pub type ArrayPtr<T> = NonNullPtr<NonNullPtr<T>>;
+
+/// Returned when a [`LiminePtr`] the bootloader was expected to fill in is null.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NullPtrError;
+
+impl fmt::Display for NullPtrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("null limine pointer")
+    }
+}
+
+static NULL_RESPONSE_HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Makes debug builds call `handler` with the type name whenever a response the kernel
+/// looks up through [`LiminePtrExt`], [`LimineRequest::response`] or a
+/// [`limine_request!`] request turns out to be null, so the message is printed before
+/// whatever the `None` leads to. Release builds never call it.
+///
+/// Calling `get_response().get()` on a request of the limine crate bypasses this, so the
+/// kernel looks its responses up with [`LimineRequest::response`].
+///
+/// [`limine_request!`]: super::request
+/// [`LimineRequest::response`]: super::request::LimineRequest::response
+pub fn set_null_response_handler(handler: fn(&str)) {
+    NULL_RESPONSE_HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+/// Calls the handler set with [`set_null_response_handler`] for `T`. Does nothing in
+/// release builds.
+#[inline]
+pub fn report_null_response<T>() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let handler = NULL_RESPONSE_HANDLER.load(Ordering::Acquire);
+    if !handler.is_null() {
+        // SAFETY: Only `fn(&str)` pointers are stored.
+        let handler: fn(&str) = unsafe { core::mem::transmute(handler) };
+        handler(core::any::type_name::<T>());
+    }
+}
+
+/// `Result` based accessors for [`LiminePtr`], so callers can use `?`, and raw pointer
+/// ones for FFI.
+pub trait LiminePtrExt<T> {
+    fn try_deref(&self) -> Result<&T, NullPtrError>;
+
+    fn try_deref_mut(&mut self) -> Result<&mut T, NullPtrError>;
+
+    /// The pointer, null where [`LiminePtr::as_ptr`] gives `None`, for interfaces that
+    /// take null as "none".
+    fn as_raw_ptr(&self) -> *const T;
+
+    /// [`as_raw_ptr`](LiminePtrExt::as_raw_ptr) for mutable access.
+    fn as_raw_mut_ptr(&mut self) -> *mut T;
+}
+
+impl<T> LiminePtrExt<T> for LiminePtr<T> {
+    #[inline]
+    fn try_deref(&self) -> Result<&T, NullPtrError> {
+        self.get().ok_or_else(|| {
+            report_null_response::<T>();
+            NullPtrError
+        })
+    }
+
+    #[inline]
+    fn try_deref_mut(&mut self) -> Result<&mut T, NullPtrError> {
+        self.get_mut().ok_or_else(|| {
+            report_null_response::<T>();
+            NullPtrError
+        })
+    }
+
+    #[inline]
+    fn as_raw_ptr(&self) -> *const T {
+        self.as_ptr().map_or(ptr::null(), |ptr| ptr.cast_const())
+    }
+
+    #[inline]
+    fn as_raw_mut_ptr(&mut self) -> *mut T {
+        self.as_ptr().unwrap_or(ptr::null_mut())
+    }
+}
+
+pub trait ArrayPtrExt<T> {
+    /// Iterates over the `len` elements the array points to.
+    ///
+    /// ## Safety
+    ///
+    /// The array must hold at least `len` valid pointers, which is the case when `len` is
+    /// the count the bootloader reported alongside it.
+    unsafe fn iter<'a>(&'a self, len: usize) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a;
+}
+
+impl<T> ArrayPtrExt<T> for ArrayPtr<T> {
+    unsafe fn iter<'a>(&'a self, len: usize) -> impl Iterator<Item = &'a T>
+    where
+        T: 'a,
+    {
+        slice::from_raw_parts(self.as_ptr(), len)
+            .iter()
+            .map(|element| &**element)
+    }
+}
+
+/// A global holding a reference to a response, set once during boot.
+///
+/// ```ignore
+/// static FRAMEBUFFER: LimineStatic<LimineFramebufferResponse> = LimineStatic::new();
+///
+/// FRAMEBUFFER.init(requests::FRAMEBUFFER.response().unwrap());
+/// let count = FRAMEBUFFER.framebuffer_count;
+/// ```
+pub struct LimineStatic<T> {
+    value: AtomicPtr<T>,
+}
+
+impl<T: 'static> LimineStatic<T> {
+    pub const fn new() -> Self {
+        Self {
+            value: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Stores `value`. Only the first call has an effect.
+    pub fn init(&self, value: &'static T) {
+        let _ = self.value.compare_exchange(
+            ptr::null_mut(),
+            value as *const T as *mut T,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        );
+    }
+
+    /// Returns the value, or `None` before [`init`](Self::init).
+    pub fn get(&self) -> Option<&'static T> {
+        // SAFETY: Only `&'static T` are ever stored.
+        unsafe { self.value.load(Ordering::Acquire).as_ref() }
+    }
+}
+
+impl<T: 'static> Default for LimineStatic<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> Deref for LimineStatic<T> {
+    type Target = T;
+
+    /// ## Panics
+    ///
+    /// Panics before [`init`](LimineStatic::init).
+    fn deref(&self) -> &T {
+        self.get().expect("LimineStatic used before init")
+    }
+}
+
+/// Iterates an [`ArrayPtr`] built over two statics, all of it and a prefix of it, and
+/// checks both the values and that each one is read through its own pointer.
+pub fn self_test() -> bool {
+    static FIRST: u64 = 0x1111;
+    static SECOND: u64 = 0x2222;
+    static ELEMENTS: [&u64; 2] = [&FIRST, &SECOND];
+
+    // SAFETY: `NonNullPtr` is a transparent wrapper around a non-null pointer, as are
+    // references.
+    let array = unsafe { core::mem::transmute::<*const &u64, ArrayPtr<u64>>(ELEMENTS.as_ptr()) };
+
+    // SAFETY: The array holds two valid pointers.
+    let mut all = unsafe { array.iter(2) };
+    let both = matches!(
+        (all.next(), all.next(), all.next()),
+        (Some(first), Some(second), None)
+            if ptr::eq(first, &FIRST) && ptr::eq(second, &SECOND) && *second == 0x2222
+    );
+    // SAFETY: As above.
+    let prefix = unsafe { array.iter(1) }.eq([&0x1111]);
+
+    both && prefix
+}
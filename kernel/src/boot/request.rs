@@ -0,0 +1,236 @@
+//! Support for defining requests the limine crate doesn't provide.
+
+use core::fmt;
+
+use super::ptr::report_null_response;
+use limine::{
+    Limine5LevelPagingRequest, LimineBootInfoRequest, LimineBootTimeRequest, LimineDtbRequest,
+    LimineEfiSystemTableRequest, LimineEntryPointRequest, LimineFramebufferRequest,
+    LimineHhdmRequest, LimineKernelAddressRequest, LimineKernelFileRequest, LimineMemmapRequest,
+    LimineModuleRequest, LimineRsdpRequest, LimineSmbiosRequest, LimineSmpRequest,
+    LimineStackSizeRequest, LimineTerminalRequest,
+};
+
+/// The first half of every request ID, `LIMINE_COMMON_MAGIC` in the protocol header.
+pub const COMMON_MAGIC: [u64; 2] = [0xc7b1dd30df4c8b88, 0x0a82e883a194f07b];
+
+/// The four words identifying a request, laid out as at the start of a request.
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct LimineRequestId([u64; 4]);
+
+impl LimineRequestId {
+    pub const fn new(a: u64, b: u64, c: u64, d: u64) -> Self {
+        Self([a, b, c, d])
+    }
+
+    /// The ID of a request of the limine crate, whose `ID` constants are plain arrays.
+    pub const fn from_raw(id: [u64; 4]) -> Self {
+        Self(id)
+    }
+
+    /// A request ID from the two request specific words after [`COMMON_MAGIC`].
+    pub const fn limine(c: u64, d: u64) -> Self {
+        Self::new(COMMON_MAGIC[0], COMMON_MAGIC[1], c, d)
+    }
+
+    pub const fn as_raw(&self) -> &[u64; 4] {
+        &self.0
+    }
+
+    /// The first two words, which are [`COMMON_MAGIC`] for every Limine request.
+    pub const fn common_prefix(&self) -> [u64; 2] {
+        [self.0[0], self.0[1]]
+    }
+
+    pub const fn is_limine_request(&self) -> bool {
+        self.0[0] == COMMON_MAGIC[0] && self.0[1] == COMMON_MAGIC[1]
+    }
+
+    /// `==` for constant expressions.
+    pub const fn const_eq(&self, other: &Self) -> bool {
+        self.0[0] == other.0[0]
+            && self.0[1] == other.0[1]
+            && self.0[2] == other.0[2]
+            && self.0[3] == other.0[3]
+    }
+}
+
+impl fmt::Display for LimineRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{:016x}-{:016x}-{:016x}-{:016x}", a, b, c, d)
+    }
+}
+
+#[doc(hidden)]
+pub(crate) mod sealed {
+    pub trait Sealed {}
+}
+
+/// A request type the bootloader answers: those of the limine crate and the ones
+/// defined with [`limine_request!`]. The trait is sealed, so code taking requests by it,
+/// like [`BootInfo::collect`](super::info::BootInfo::collect), only gets real ones.
+///
+/// Only requests in statics are ever answered, since the bootloader finds them by
+/// scanning the kernel image. Taking them as `&'static` references, like [`Requests`]
+/// does, keeps requests built on the stack out, so this doesn't compile, as
+/// `framebuffer` doesn't live long enough:
+///
+/// ```compile_fail,E0597
+/// use kernel::boot::request::LimineRequest;
+/// use limine::LimineFramebufferRequest;
+///
+/// fn is_answered<R: LimineRequest>(request: &'static R) -> bool {
+///     request.response().is_some()
+/// }
+///
+/// let framebuffer = LimineFramebufferRequest::new(0);
+/// is_answered(&framebuffer);
+/// ```
+///
+/// [`Requests`]: super::info::Requests
+pub trait LimineRequest: sealed::Sealed + Sync + 'static {
+    type Response: 'static;
+
+    /// The response, or `None` if the bootloader didn't answer, which debug builds
+    /// report through [`set_null_response_handler`].
+    ///
+    /// [`set_null_response_handler`]: super::ptr::set_null_response_handler
+    fn response(&self) -> Option<&'static Self::Response>;
+}
+
+macro_rules! impl_limine_request {
+    ($($request:ty => $response:ty),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $request {}
+
+            impl LimineRequest for $request {
+                type Response = $response;
+
+                fn response(&self) -> Option<&'static $response> {
+                    let response = self.get_response().get();
+                    if response.is_none() {
+                        report_null_response::<$response>();
+                    }
+                    response
+                }
+            }
+        )*
+    };
+}
+
+impl_limine_request!(
+    LimineBootInfoRequest => limine::LimineBootInfoResponse,
+    LimineStackSizeRequest => limine::LimineStackSizeResponse,
+    LimineHhdmRequest => limine::LimineHhdmResponse,
+    LimineFramebufferRequest => limine::LimineFramebufferResponse,
+    LimineTerminalRequest => limine::LimineTerminalResponse,
+    Limine5LevelPagingRequest => limine::Limine5LevelPagingResponse,
+    LimineSmpRequest => limine::LimineSmpResponse,
+    LimineMemmapRequest => limine::LimineMemmapResponse,
+    LimineEntryPointRequest => limine::LimineEntryPointResponse,
+    LimineKernelFileRequest => limine::LimineKernelFileResponse,
+    LimineModuleRequest => limine::LimineModuleResponse,
+    LimineRsdpRequest => limine::LimineRsdpResponse,
+    LimineSmbiosRequest => limine::LimineSmbiosResponse,
+    LimineEfiSystemTableRequest => limine::LimineEfiSystemTableResponse,
+    LimineBootTimeRequest => limine::LimineBootTimeResponse,
+    LimineKernelAddressRequest => limine::LimineKernelAddressResponse,
+    LimineDtbRequest => limine::LimineDtbResponse,
+);
+
+/// Defines a request with the same layout and API as the ones in the limine crate,
+/// except that `get_response` returns an `Option`.
+macro_rules! limine_request {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident: [$id1:expr, $id2:expr] => $response:ty {
+            $($(#[$field_meta:meta])* pub $field:ident: $field_ty:ty = $default:expr),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        #[derive(Debug)]
+        pub struct $name {
+            id: $crate::boot::request::LimineRequestId,
+            revision: u64,
+            // The bootloader writes the response behind the compiler's back.
+            response: ::core::cell::UnsafeCell<*const $response>,
+            $($(#[$field_meta])* pub $field: $field_ty,)*
+        }
+
+        // The response is only written by the bootloader, before the kernel runs.
+        unsafe impl Sync for $name {}
+
+        impl $name {
+            pub const ID: $crate::boot::request::LimineRequestId =
+                $crate::boot::request::LimineRequestId::limine($id1, $id2);
+
+            /// The request does nothing unless it is placed in a static, which the
+            /// bootloader can find.
+            #[must_use]
+            pub const fn new(revision: u64) -> Self {
+                Self {
+                    id: Self::ID,
+                    revision,
+                    response: ::core::cell::UnsafeCell::new(::core::ptr::null()),
+                    $($field: $default,)*
+                }
+            }
+
+            // The read has to stay volatile: a plain one may be folded into the null
+            // the request was built with. See `boot::limine_init`.
+            pub fn get_response(&self) -> Option<&'static $response> {
+                let response = unsafe { ::core::ptr::read_volatile(self.response.get()).as_ref() };
+                if response.is_none() {
+                    $crate::boot::ptr::report_null_response::<$response>();
+                }
+                response
+            }
+
+            /// Whether the bootloader answered the request, without looking at the
+            /// response itself.
+            pub fn is_answered(&self) -> bool {
+                !unsafe { ::core::ptr::read_volatile(self.response.get()) }.is_null()
+            }
+        }
+
+        impl $crate::boot::request::sealed::Sealed for $name {}
+
+        impl $crate::boot::request::LimineRequest for $name {
+            type Response = $response;
+
+            fn response(&self) -> Option<&'static $response> {
+                self.get_response()
+            }
+        }
+    };
+}
+
+#[allow(unused_imports)]
+pub(crate) use limine_request;
+
+/// Checks `is_answered` on a request of [`limine_request!`] before and after its
+/// response pointer is set, the way the bootloader would, and that
+/// [`LimineRequest::response`] reads the pointer again every time it changes.
+pub fn self_test() -> bool {
+    limine_request! {
+        /// Never answered for real: it is on the stack, where the bootloader doesn't
+        /// look for requests.
+        pub struct TestRequest: [0, 0] => u64 {}
+    }
+
+    static ANSWER: u64 = 42;
+    static OTHER_ANSWER: u64 = 7;
+
+    let request = TestRequest::new(0);
+    let slot = request.response.get();
+    let unanswered = !request.is_answered();
+    // SAFETY: Nothing else has a reference to the request.
+    unsafe { *slot = &ANSWER };
+    let answered = request.is_answered() && request.response() == Some(&ANSWER);
+    // SAFETY: As above.
+    unsafe { *slot = &OTHER_ANSWER };
+    unanswered && answered && request.response() == Some(&OTHER_ANSWER)
+}
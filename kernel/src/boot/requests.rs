@@ -0,0 +1,193 @@
+//! The built-in Limine requests.
+//!
+//! Each request is gated behind its own cargo feature so kernels only pay for the
+//! requests they use. The statics are placed in `.limine_requests`, which the linker
+//! script keeps together in the data segment.
+
+#[cfg(feature = "boot-info")]
+use limine::LimineBootInfoRequest;
+#[cfg(feature = "boot-time")]
+use limine::LimineBootTimeRequest;
+#[cfg(feature = "dtb")]
+use limine::LimineDtbRequest;
+#[cfg(feature = "efi")]
+use limine::LimineEfiSystemTableRequest;
+#[cfg(feature = "framebuffer")]
+use limine::LimineFramebufferRequest;
+#[cfg(feature = "hhdm")]
+use limine::LimineHhdmRequest;
+#[cfg(feature = "kernel-address")]
+use limine::LimineKernelAddressRequest;
+#[cfg(feature = "kernel-file")]
+use limine::LimineKernelFileRequest;
+#[cfg(feature = "memory-map")]
+use limine::LimineMemmapRequest;
+#[cfg(feature = "modules")]
+use limine::LimineModuleRequest;
+#[cfg(feature = "acpi")]
+use limine::LimineRsdpRequest;
+#[cfg(feature = "smbios")]
+use limine::LimineSmbiosRequest;
+#[cfg(feature = "legacy-terminal")]
+use limine::LimineTerminalRequest;
+
+#[cfg(feature = "firmware-type")]
+use super::firmware::LimineFirmwareTypeRequest;
+#[cfg(feature = "paging-mode")]
+use super::paging_mode::LiminePagingModeRequest;
+#[cfg(all(feature = "smp", target_arch = "x86_64"))]
+use limine::LimineSmpRequest;
+
+#[allow(unused_imports)]
+use super::ids::{self, LimineRequestId};
+#[cfg(all(feature = "smp", not(target_arch = "x86_64")))]
+use super::smp::SmpRequest as LimineSmpRequest;
+
+/// Fails the build if `$request` doesn't carry the ID `$id`, which would leave its
+/// response empty without any other sign. The limine crate's IDs are plain arrays, those
+/// of requests defined with [`limine_request!`](super::request) are marked `own` and
+/// already [`LimineRequestId`]s.
+// Every user is behind a feature.
+#[allow(unused_macros)]
+macro_rules! assert_id {
+    ($request:ident, $id:ident) => {
+        assert_id!(@check LimineRequestId::from_raw($request::ID), $request, $id);
+    };
+    (own $request:ident, $id:ident) => {
+        assert_id!(@check $request::ID, $request, $id);
+    };
+    (@check $actual:expr, $request:ident, $id:ident) => {
+        const _: () = assert!(
+            $actual.const_eq(&ids::$id),
+            concat!(
+                stringify!($request),
+                " doesn't carry ids::",
+                stringify!($id)
+            )
+        );
+    };
+}
+
+// `assert_id!` only catches a wrong ID if `const_eq` tells IDs apart in every word.
+const _: () = {
+    let id = LimineRequestId::limine(1, 2);
+    assert!(id.const_eq(&LimineRequestId::limine(1, 2)));
+    assert!(!id.const_eq(&LimineRequestId::limine(1, 3)));
+    assert!(!id.const_eq(&LimineRequestId::limine(3, 2)));
+    assert!(!id.const_eq(&LimineRequestId::new(0, 0, 1, 2)));
+};
+
+#[cfg(feature = "boot-info")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static BOOT_INFO: LimineBootInfoRequest = LimineBootInfoRequest::new(0);
+#[cfg(feature = "boot-info")]
+assert_id!(LimineBootInfoRequest, BOOT_INFO);
+
+#[cfg(feature = "framebuffer")]
+#[used]
+#[link_section = ".limine_requests"]
+/// Revision 1, so the bootloader lists the video modes.
+pub static FRAMEBUFFER: LimineFramebufferRequest = LimineFramebufferRequest::new(1);
+#[cfg(feature = "framebuffer")]
+assert_id!(LimineFramebufferRequest, FRAMEBUFFER);
+
+#[cfg(feature = "hhdm")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static HHDM: LimineHhdmRequest = LimineHhdmRequest::new(0);
+#[cfg(feature = "hhdm")]
+assert_id!(LimineHhdmRequest, HHDM);
+
+#[cfg(feature = "memory-map")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static MEMORY_MAP: LimineMemmapRequest = LimineMemmapRequest::new(0);
+#[cfg(feature = "memory-map")]
+assert_id!(LimineMemmapRequest, MEMORY_MAP);
+
+#[cfg(feature = "smp")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static SMP: LimineSmpRequest = LimineSmpRequest::new(0);
+#[cfg(all(feature = "smp", target_arch = "x86_64"))]
+assert_id!(LimineSmpRequest, SMP);
+#[cfg(all(feature = "smp", not(target_arch = "x86_64")))]
+assert_id!(own LimineSmpRequest, SMP);
+
+#[cfg(feature = "acpi")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static RSDP: LimineRsdpRequest = LimineRsdpRequest::new(0);
+#[cfg(feature = "acpi")]
+assert_id!(LimineRsdpRequest, RSDP);
+
+#[cfg(feature = "smbios")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static SMBIOS: LimineSmbiosRequest = LimineSmbiosRequest::new(0);
+#[cfg(feature = "smbios")]
+assert_id!(LimineSmbiosRequest, SMBIOS);
+
+#[cfg(feature = "efi")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static EFI_SYSTEM_TABLE: LimineEfiSystemTableRequest = LimineEfiSystemTableRequest::new(0);
+#[cfg(feature = "efi")]
+assert_id!(LimineEfiSystemTableRequest, EFI_SYSTEM_TABLE);
+
+#[cfg(feature = "boot-time")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static BOOT_TIME: LimineBootTimeRequest = LimineBootTimeRequest::new(0);
+#[cfg(feature = "boot-time")]
+assert_id!(LimineBootTimeRequest, BOOT_TIME);
+
+#[cfg(feature = "kernel-file")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static KERNEL_FILE: LimineKernelFileRequest = LimineKernelFileRequest::new(0);
+#[cfg(feature = "kernel-file")]
+assert_id!(LimineKernelFileRequest, KERNEL_FILE);
+
+#[cfg(feature = "kernel-address")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static KERNEL_ADDRESS: LimineKernelAddressRequest = LimineKernelAddressRequest::new(0);
+#[cfg(feature = "kernel-address")]
+assert_id!(LimineKernelAddressRequest, KERNEL_ADDRESS);
+
+#[cfg(feature = "modules")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static MODULES: LimineModuleRequest = LimineModuleRequest::new(0);
+#[cfg(feature = "modules")]
+assert_id!(LimineModuleRequest, MODULES);
+
+#[cfg(feature = "dtb")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static DTB: LimineDtbRequest = LimineDtbRequest::new(0);
+#[cfg(feature = "dtb")]
+assert_id!(LimineDtbRequest, DTB);
+
+#[cfg(feature = "firmware-type")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static FIRMWARE_TYPE: LimineFirmwareTypeRequest = LimineFirmwareTypeRequest::new(0);
+#[cfg(feature = "firmware-type")]
+assert_id!(own LimineFirmwareTypeRequest, FIRMWARE_TYPE);
+
+#[cfg(feature = "paging-mode")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static PAGING_MODE: LiminePagingModeRequest = LiminePagingModeRequest::new(0);
+#[cfg(feature = "paging-mode")]
+assert_id!(own LiminePagingModeRequest, PAGING_MODE);
+
+#[cfg(feature = "legacy-terminal")]
+#[used]
+#[link_section = ".limine_requests"]
+pub static TERMINAL: LimineTerminalRequest = LimineTerminalRequest::new(0);
+#[cfg(feature = "legacy-terminal")]
+assert_id!(LimineTerminalRequest, TERMINAL);
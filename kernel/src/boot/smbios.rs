@@ -0,0 +1,512 @@
+//! Parsing of the SMBIOS structure table the entry points of the SMBIOS response lead
+//! to.
+//!
+//! The table is a sequence of structures, each a formatted part starting with a type,
+//! length and handle, followed by a set of NUL terminated strings that ends with an
+//! extra NUL. Fields of the formatted part refer to the strings by 1-based index.
+
+use core::{fmt, slice, str};
+
+use limine::LimineSmbiosResponse;
+
+use super::request::LimineRequest;
+use super::requests::HHDM;
+
+const ENTRY_32_ANCHOR: &[u8] = b"_SM_";
+const ENTRY_32_INTERMEDIATE_ANCHOR: &[u8] = b"_DMI_";
+const ENTRY_64_ANCHOR: &[u8] = b"_SM3_";
+
+pub const TYPE_BIOS_INFO: u8 = 0;
+pub const TYPE_SYSTEM_INFO: u8 = 1;
+pub const TYPE_MEMORY_DEVICE: u8 = 17;
+pub const TYPE_END_OF_TABLE: u8 = 127;
+
+/// Why the structure table couldn't be found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmbiosError {
+    /// The bootloader found no entry point.
+    NoEntryPoint,
+    /// The entry point doesn't start with its anchor string.
+    BadAnchor,
+    /// The bytes of the entry point don't sum to zero.
+    BadChecksum,
+    /// The entry point's length is too short for its fields.
+    Truncated,
+    /// The table isn't reachable without the HHDM.
+    NoHhdm,
+}
+
+impl fmt::Display for SmbiosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoEntryPoint => "no SMBIOS entry point",
+            Self::BadAnchor => "SMBIOS entry point has a bad anchor",
+            Self::BadChecksum => "SMBIOS entry point has a bad checksum",
+            Self::Truncated => "SMBIOS entry point is truncated",
+            Self::NoHhdm => "no HHDM to reach the SMBIOS table",
+        })
+    }
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn le16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn le32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn le64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Where an entry point says the structure table is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub major: u8,
+    pub minor: u8,
+    /// The physical address of the table.
+    pub table_address: u64,
+    /// The length of the table, or for 64-bit entry points its maximum length.
+    pub table_len: u32,
+}
+
+impl EntryPoint {
+    /// Validates a 32-bit (`_SM_`) entry point, including its `_DMI_` part.
+    pub fn parse_32(bytes: &[u8]) -> Result<Self, SmbiosError> {
+        if !bytes.starts_with(ENTRY_32_ANCHOR) {
+            return Err(SmbiosError::BadAnchor);
+        }
+        let len = *bytes.get(5).ok_or(SmbiosError::Truncated)? as usize;
+        let entry = bytes.get(..len).filter(|_| len >= 0x1f);
+        let entry = entry.ok_or(SmbiosError::Truncated)?;
+        if !entry[0x10..].starts_with(ENTRY_32_INTERMEDIATE_ANCHOR) {
+            return Err(SmbiosError::BadAnchor);
+        }
+        if !checksum_ok(entry) || !checksum_ok(&entry[0x10..0x1f]) {
+            return Err(SmbiosError::BadChecksum);
+        }
+
+        Ok(Self {
+            major: entry[6],
+            minor: entry[7],
+            table_address: le32(entry, 0x18).unwrap() as u64,
+            table_len: le16(entry, 0x16).unwrap() as u32,
+        })
+    }
+
+    /// Validates a 64-bit (`_SM3_`) entry point.
+    pub fn parse_64(bytes: &[u8]) -> Result<Self, SmbiosError> {
+        if !bytes.starts_with(ENTRY_64_ANCHOR) {
+            return Err(SmbiosError::BadAnchor);
+        }
+        let len = *bytes.get(6).ok_or(SmbiosError::Truncated)? as usize;
+        let entry = bytes.get(..len).filter(|_| len >= 0x18);
+        let entry = entry.ok_or(SmbiosError::Truncated)?;
+        if !checksum_ok(entry) {
+            return Err(SmbiosError::BadChecksum);
+        }
+
+        Ok(Self {
+            major: entry[7],
+            minor: entry[8],
+            table_address: le64(entry, 0x10).unwrap(),
+            table_len: le32(entry, 0x0c).unwrap(),
+        })
+    }
+}
+
+/// The structure table.
+#[derive(Clone, Copy, Debug)]
+pub struct Smbios<'a> {
+    pub major: u8,
+    pub minor: u8,
+    table: &'a [u8],
+}
+
+impl<'a> Smbios<'a> {
+    /// Wraps the bytes of a structure table.
+    pub fn new(major: u8, minor: u8, table: &'a [u8]) -> Self {
+        Self {
+            major,
+            minor,
+            table,
+        }
+    }
+
+    /// Iterates over the structures, up to the end-of-table structure or the first one
+    /// that doesn't fit the table.
+    pub fn structures(&self) -> Structures<'a> {
+        Structures { rest: self.table }
+    }
+
+    pub fn structures_of_type(&self, typ: u8) -> impl Iterator<Item = Structure<'a>> {
+        self.structures()
+            .filter(move |structure| structure.typ == typ)
+    }
+
+    pub fn bios_info(&self) -> Option<BiosInfo<'a>> {
+        self.structures_of_type(TYPE_BIOS_INFO).next().map(BiosInfo)
+    }
+
+    pub fn system_info(&self) -> Option<SystemInfo<'a>> {
+        self.structures_of_type(TYPE_SYSTEM_INFO)
+            .next()
+            .map(SystemInfo)
+    }
+
+    pub fn memory_devices(&self) -> impl Iterator<Item = MemoryDevice<'a>> {
+        self.structures_of_type(TYPE_MEMORY_DEVICE)
+            .map(MemoryDevice)
+    }
+}
+
+impl Smbios<'static> {
+    /// Finds the structure table through the SMBIOS response, preferring the 64-bit
+    /// entry point.
+    pub fn from_response(response: &LimineSmbiosResponse) -> Result<Self, SmbiosError> {
+        let hhdm = HHDM.response().ok_or(SmbiosError::NoHhdm)?.offset;
+        // The entry points are HHDM addresses before base revision 3 and physical ones
+        // from then on.
+        let virt = |addr: u64| if addr < hhdm { addr + hhdm } else { addr };
+        // SAFETY: The firmware places the entry points in memory the HHDM covers. Both
+        // are at most 255 bytes long, as their length is a single byte.
+        let entry_bytes = |ptr: *mut u8| unsafe {
+            let entry = virt(ptr as u64) as *const u8;
+            let len = match *entry {
+                b'_' if *entry.add(3) == b'3' => *entry.add(6),
+                _ => *entry.add(5),
+            };
+            slice::from_raw_parts(entry, len as usize)
+        };
+
+        let entry = match (response.entry_64.as_ptr(), response.entry_32.as_ptr()) {
+            (Some(entry_64), _) => EntryPoint::parse_64(entry_bytes(entry_64))?,
+            (None, Some(entry_32)) => EntryPoint::parse_32(entry_bytes(entry_32))?,
+            (None, None) => return Err(SmbiosError::NoEntryPoint),
+        };
+
+        // SAFETY: The entry point was validated, and the table it describes is firmware
+        // memory the HHDM covers.
+        let table = unsafe {
+            slice::from_raw_parts(
+                virt(entry.table_address) as *const u8,
+                entry.table_len as usize,
+            )
+        };
+        Ok(Self::new(entry.major, entry.minor, table))
+    }
+}
+
+pub struct Structures<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for Structures<'a> {
+    type Item = Structure<'a>;
+
+    fn next(&mut self) -> Option<Structure<'a>> {
+        let len = *self.rest.get(1)? as usize;
+        if len < 4 || len > self.rest.len() {
+            self.rest = &[];
+            return None;
+        }
+
+        let (formatted, tail) = self.rest.split_at(len);
+        // The string set ends with a double NUL, which is all there is of an empty one.
+        let Some(strings_len) = tail.windows(2).position(|pair| pair == [0, 0]) else {
+            self.rest = &[];
+            return None;
+        };
+
+        let structure = Structure {
+            typ: formatted[0],
+            handle: le16(formatted, 2).unwrap(),
+            formatted,
+            strings: &tail[..strings_len],
+        };
+        self.rest = if structure.typ == TYPE_END_OF_TABLE {
+            &[]
+        } else {
+            &tail[strings_len + 2..]
+        };
+        Some(structure)
+    }
+}
+
+/// A structure of the table.
+#[derive(Clone, Copy, Debug)]
+pub struct Structure<'a> {
+    pub typ: u8,
+    pub handle: u16,
+    /// The formatted part, including the header.
+    pub formatted: &'a [u8],
+    /// The NUL separated strings, without the double NUL ending them.
+    strings: &'a [u8],
+}
+
+impl<'a> Structure<'a> {
+    /// Returns the string with the 1-based `index`. Index 0 means "no string", and
+    /// indices past the string set or strings that aren't UTF-8 give `None` too.
+    pub fn string(&self, index: u8) -> Option<&'a str> {
+        if index == 0 || self.strings.is_empty() {
+            return None;
+        }
+        let string = self
+            .strings
+            .split(|&byte| byte == 0)
+            .nth(index as usize - 1)?;
+        str::from_utf8(string).ok()
+    }
+
+    pub fn byte(&self, offset: usize) -> Option<u8> {
+        self.formatted.get(offset).copied()
+    }
+
+    pub fn word(&self, offset: usize) -> Option<u16> {
+        le16(self.formatted, offset)
+    }
+
+    pub fn dword(&self, offset: usize) -> Option<u32> {
+        le32(self.formatted, offset)
+    }
+
+    /// Resolves the string whose index is the byte at `offset`.
+    pub fn string_at(&self, offset: usize) -> Option<&'a str> {
+        self.string(self.byte(offset)?)
+    }
+}
+
+/// A type 0 structure.
+#[derive(Clone, Copy, Debug)]
+pub struct BiosInfo<'a>(pub Structure<'a>);
+
+impl<'a> BiosInfo<'a> {
+    pub fn vendor(&self) -> Option<&'a str> {
+        self.0.string_at(0x04)
+    }
+
+    pub fn version(&self) -> Option<&'a str> {
+        self.0.string_at(0x05)
+    }
+
+    pub fn release_date(&self) -> Option<&'a str> {
+        self.0.string_at(0x08)
+    }
+}
+
+/// A type 1 structure.
+#[derive(Clone, Copy, Debug)]
+pub struct SystemInfo<'a>(pub Structure<'a>);
+
+impl<'a> SystemInfo<'a> {
+    pub fn manufacturer(&self) -> Option<&'a str> {
+        self.0.string_at(0x04)
+    }
+
+    pub fn product(&self) -> Option<&'a str> {
+        self.0.string_at(0x05)
+    }
+
+    pub fn version(&self) -> Option<&'a str> {
+        self.0.string_at(0x06)
+    }
+
+    pub fn serial_number(&self) -> Option<&'a str> {
+        self.0.string_at(0x07)
+    }
+}
+
+/// A type 17 structure.
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryDevice<'a>(pub Structure<'a>);
+
+impl<'a> MemoryDevice<'a> {
+    /// The size in bytes. `Some(0)` means no module is installed, `None` that the size is
+    /// unknown.
+    pub fn size(&self) -> Option<u64> {
+        match self.0.word(0x0c)? {
+            0xffff => None,
+            // The real size is in the extended size field, in MiB.
+            0x7fff => Some((self.0.dword(0x1c)? & 0x7fff_ffff) as u64 * 1024 * 1024),
+            size if size & 0x8000 != 0 => Some((size & 0x7fff) as u64 * 1024),
+            size => Some(size as u64 * 1024 * 1024),
+        }
+    }
+
+    /// The maximum speed in MT/s, if known.
+    pub fn speed(&self) -> Option<u32> {
+        match self.0.word(0x15)? {
+            0 => None,
+            0xffff => self.0.dword(0x54).filter(|&speed| speed != 0),
+            speed => Some(speed as u32),
+        }
+    }
+
+    /// The slot or soldered position the device is in, e.g. `DIMM 0`.
+    pub fn locator(&self) -> Option<&'a str> {
+        self.0.string_at(0x10)
+    }
+
+    pub fn bank_locator(&self) -> Option<&'a str> {
+        self.0.string_at(0x11)
+    }
+
+    pub fn manufacturer(&self) -> Option<&'a str> {
+        self.0.string_at(0x17)
+    }
+
+    pub fn part_number(&self) -> Option<&'a str> {
+        self.0.string_at(0x1a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_point_64() {
+        let mut entry = [0u8; 0x18];
+        entry[..5].copy_from_slice(ENTRY_64_ANCHOR);
+        entry[6] = 0x18;
+        entry[7] = 3;
+        entry[0x0a] = 1;
+        entry[0x0c..0x10].copy_from_slice(&0x1000u32.to_le_bytes());
+        entry[0x10..0x18].copy_from_slice(&0xf_5a20u64.to_le_bytes());
+        entry[5] = 0u8.wrapping_sub(entry.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)));
+
+        let mut corrupted = entry;
+        corrupted[0x10] ^= 1;
+        assert_eq!(
+            EntryPoint::parse_64(&entry),
+            Ok(EntryPoint {
+                major: 3,
+                minor: 0,
+                table_address: 0xf_5a20,
+                table_len: 0x1000,
+            })
+        );
+        assert_eq!(
+            EntryPoint::parse_64(&corrupted),
+            Err(SmbiosError::BadChecksum)
+        );
+        assert_eq!(
+            EntryPoint::parse_64(&entry[..0x10]),
+            Err(SmbiosError::Truncated)
+        );
+        assert_eq!(EntryPoint::parse_32(&entry), Err(SmbiosError::BadAnchor));
+    }
+
+    /// A structure table modelled after what QEMU's SeaBIOS reports.
+    fn seabios_table() -> Vec<u8> {
+        [
+            // BIOS information: vendor 1, version 2, release date 3.
+            &[
+                0, 0x18, 0x00, 0x00, 1, 2, 0x00, 0xe8, 3, 0, 0x08, 0, 0, 0, 0, 0, 0, 0, 0, 0x1c, 0,
+                0, 0xff, 0xff,
+            ][..],
+            b"SeaBIOS\x001.16.3-debian-1.16.3-2\x0004/01/2014\0\0",
+            // System information: manufacturer 1, product 2, version 3, no serial number.
+            &[
+                1, 0x1b, 0x00, 0x01, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0x06, 0, 0,
+            ],
+            b"QEMU\0Standard PC (i440FX + PIIX, 1996)\0pc-i440fx-8.2\0\0",
+            // A 1 GiB device at 3200 MT/s: locator 1, manufacturer 2.
+            &[
+                17, 0x28, 0x00, 0x11, 0x00, 0x10, 0xfe, 0xff, 0x40, 0, 0x40, 0, 0x00, 0x04, 0x09,
+                0, 1, 0, 0x07, 0x02, 0x00, 0x80, 0x0c, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0,
+            ],
+            b"DIMM 0\0QEMU\0\0",
+            // A 32 GiB device in the extended size field, of unknown speed: locator 1 and
+            // manufacturer 5, past the only string.
+            &[
+                17, 0x28, 0x01, 0x11, 0x00, 0x10, 0xfe, 0xff, 0x40, 0, 0x40, 0, 0xff, 0x7f, 0x09,
+                0, 1, 0, 0x07, 0x02, 0x00, 0, 0, 5, 0, 0, 0, 0, 0x00, 0x80, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0,
+            ],
+            b"DIMM 1\0\0",
+            // End of table, without strings.
+            &[127, 0x04, 0x00, 0x7f],
+            b"\0\0",
+            // Past the end of the table, so never walked.
+            &[1, 0x1b, 0x01, 0x01],
+            b"\0\0",
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn walk_stops_at_end_of_table() {
+        let table = seabios_table();
+        let smbios = Smbios::new(3, 0, &table);
+        let walked: Vec<_> = smbios
+            .structures()
+            .map(|structure| (structure.typ, structure.handle))
+            .collect();
+        assert_eq!(
+            walked,
+            [
+                (0, 0),
+                (1, 0x100),
+                (17, 0x1100),
+                (17, 0x1101),
+                (127, 0x7f00)
+            ]
+        );
+        let end = smbios.structures_of_type(TYPE_END_OF_TABLE).next().unwrap();
+        assert_eq!(end.string(1), None);
+    }
+
+    #[test]
+    fn bios_and_system_info() {
+        let table = seabios_table();
+        let smbios = Smbios::new(3, 0, &table);
+
+        let bios = smbios.bios_info().unwrap();
+        assert_eq!(bios.vendor(), Some("SeaBIOS"));
+        assert_eq!(bios.version(), Some("1.16.3-debian-1.16.3-2"));
+        assert_eq!(bios.release_date(), Some("04/01/2014"));
+        assert_eq!(bios.0.string(4), None);
+
+        let system = smbios.system_info().unwrap();
+        assert_eq!(system.manufacturer(), Some("QEMU"));
+        assert_eq!(system.product(), Some("Standard PC (i440FX + PIIX, 1996)"));
+        assert_eq!(system.version(), Some("pc-i440fx-8.2"));
+        assert_eq!(system.serial_number(), None);
+    }
+
+    #[test]
+    fn memory_devices() {
+        let table = seabios_table();
+        let smbios = Smbios::new(3, 0, &table);
+        let mut devices = smbios.memory_devices();
+
+        let first = devices.next().unwrap();
+        assert_eq!(first.size(), Some(1 << 30));
+        assert_eq!(first.speed(), Some(3200));
+        assert_eq!(first.locator(), Some("DIMM 0"));
+        assert_eq!(first.bank_locator(), None);
+        assert_eq!(first.manufacturer(), Some("QEMU"));
+
+        let second = devices.next().unwrap();
+        assert_eq!(second.size(), Some(32 << 30));
+        assert_eq!(second.speed(), None);
+        assert_eq!(second.locator(), Some("DIMM 1"));
+        assert_eq!(second.manufacturer(), None);
+
+        assert!(devices.next().is_none());
+    }
+}
@@ -0,0 +1,101 @@
+//! The aarch64 and riscv64 layouts of the SMP request.
+//!
+//! The limine crate only models the x86_64 response, whose CPU entries carry LAPIC IDs.
+//! On aarch64 the bootloader reports each CPU's MPIDR and GIC CPU interface number
+//! instead, on riscv64 each hart's ID, and the flags grow to 64 bits, so the request is
+//! defined here. It shares its ID with `limine::LimineSmpRequest`.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::ptr::{ArrayPtr, ArrayPtrExt};
+use super::request::limine_request;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct SmpInfo {
+    /// ACPI processor UID as specified by the MADT.
+    #[cfg(target_arch = "aarch64")]
+    pub processor_id: u32,
+    /// GIC CPU interface number of the processor as specified by the MADT.
+    #[cfg(target_arch = "aarch64")]
+    pub gic_iface_no: u32,
+    /// MPIDR of the processor as specified by the MADT or device tree.
+    #[cfg(target_arch = "aarch64")]
+    pub mpidr: u64,
+    /// ACPI processor UID as specified by the MADT.
+    #[cfg(target_arch = "riscv64")]
+    pub processor_id: u64,
+    /// ID of the hart as specified by the MADT or device tree.
+    #[cfg(target_arch = "riscv64")]
+    pub hartid: u64,
+    reserved: u64,
+    /// Written last to make the parked CPU jump to it.
+    goto_address: AtomicU64,
+    extra_argument: AtomicU64,
+}
+
+impl SmpInfo {
+    /// Makes the parked CPU jump to `entry` on its own stack, with a pointer to this
+    /// structure as the first argument.
+    ///
+    /// This does nothing for the entry describing the bootstrap processor.
+    pub fn start(&self, entry: extern "C" fn(&'static SmpInfo) -> !, argument: u64) {
+        self.extra_argument.store(argument, Ordering::Relaxed);
+        // The release store publishes the argument to the woken CPU.
+        self.goto_address
+            .store(entry as usize as u64, Ordering::Release);
+    }
+
+    /// The argument passed to [`start`](SmpInfo::start).
+    pub fn extra_argument(&self) -> u64 {
+        self.extra_argument.load(Ordering::Acquire)
+    }
+
+    /// Stores a pointer as the extra argument, for the started CPU to read back with
+    /// [`get_extra_ptr`](Self::get_extra_ptr).
+    pub fn set_extra_ptr<T>(&self, ptr: *const T) {
+        self.extra_argument
+            .store(ptr as usize as u64, Ordering::Release);
+    }
+
+    /// Reads the extra argument back as a pointer.
+    ///
+    /// ## Safety
+    ///
+    /// The argument must have been stored by [`set_extra_ptr`](Self::set_extra_ptr) with
+    /// the same `T`.
+    pub unsafe fn get_extra_ptr<T>(&self) -> *const T {
+        self.extra_argument() as usize as *const T
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct SmpResponse {
+    pub revision: u64,
+    /// Always zero.
+    pub flags: u64,
+    /// MPIDR of the bootstrap processor.
+    #[cfg(target_arch = "aarch64")]
+    pub bsp_mpidr: u64,
+    /// Hart ID of the bootstrap processor.
+    #[cfg(target_arch = "riscv64")]
+    pub bsp_hartid: u64,
+    /// How many CPUs are present, including the bootstrap processor.
+    pub cpu_count: u64,
+    cpus: ArrayPtr<SmpInfo>,
+}
+
+impl SmpResponse {
+    pub fn cpus(&self) -> impl Iterator<Item = &SmpInfo> {
+        // SAFETY: The bootloader reports how many entries it filled in.
+        unsafe { self.cpus.iter(self.cpu_count as usize) }
+    }
+}
+
+limine_request!(
+    pub struct SmpRequest: [0x95a67b819a1b857e, 0xa0b61b723b6a73e0] => SmpResponse {
+        /// Unused outside x86_64.
+        pub flags: u64 = 0,
+    }
+);
@@ -0,0 +1,98 @@
+//! The terminal older Limine releases provide, for output before the kernel has any
+//! console of its own.
+//!
+//! The terminal's code and state live in bootloader reclaimable memory, so it must not
+//! be used once that memory is handed out. [`invalidate`] marks that point: from then on
+//! [`kprintln!`](crate::kprintln) stops mirroring to the terminal, and
+//! [`BootReclaimGuard::reclaim`](super::memmap::BootReclaimGuard::reclaim) calls it
+//! before releasing anything.
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use limine::{LimineTerminal, LimineTerminalResponse};
+use spin::Mutex;
+
+use super::ptr::ArrayPtrExt;
+use super::request::LimineRequest;
+use super::requests::TERMINAL;
+
+/// A terminal together with the response holding its `write` callback.
+pub struct Terminal {
+    response: &'static LimineTerminalResponse,
+    terminal: &'static LimineTerminal,
+}
+
+impl Terminal {
+    /// Returns the first terminal of `response`, or `None` if it has no terminals or no
+    /// `write` callback.
+    pub fn new(response: &'static LimineTerminalResponse) -> Option<Self> {
+        response.write().map(drop)?;
+        // SAFETY: The count comes from the bootloader along with the array.
+        let terminal =
+            unsafe { response.terminals.iter(response.terminal_count as usize) }.next()?;
+        Some(Self { response, terminal })
+    }
+
+    pub fn columns(&self) -> u64 {
+        self.terminal.cols
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.terminal.rows
+    }
+
+    /// Writes `s` through the bootloader's callback.
+    ///
+    /// The callback isn't reentrant, and it's only valid until bootloader reclaimable
+    /// memory is reused: prefer the global terminal behind `kprintln!`, which takes care
+    /// of both.
+    pub fn write_str(&self, s: &str) {
+        if let Some(write) = self.response.write() {
+            write(self.terminal, s);
+        }
+    }
+}
+
+// SAFETY: The responses are never written after boot, and the global terminal
+// serializes calls to the callback.
+unsafe impl Send for Terminal {}
+
+impl fmt::Write for Terminal {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Terminal::write_str(self, s);
+        Ok(())
+    }
+}
+
+static GLOBAL: Mutex<Option<Terminal>> = Mutex::new(None);
+static VALID: AtomicBool = AtomicBool::new(true);
+
+/// Makes `kprintln!` mirror its output to the bootloader's terminal, if there is one and
+/// it hasn't been invalidated yet.
+pub fn init() {
+    if !VALID.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(terminal) = TERMINAL.response().and_then(Terminal::new) {
+        *GLOBAL.lock() = Some(terminal);
+    }
+}
+
+/// Stops all use of the terminal. Must be called before bootloader reclaimable memory is
+/// reused, and can't be undone.
+pub fn invalidate() {
+    VALID.store(false, Ordering::Release);
+    // Waits for a write in progress on another CPU to finish.
+    GLOBAL.lock().take();
+}
+
+/// Writes to the global terminal, if there still is one.
+pub(crate) fn write_fmt(args: fmt::Arguments) {
+    if !VALID.load(Ordering::Acquire) {
+        return;
+    }
+    if let Some(terminal) = GLOBAL.lock().as_mut() {
+        fmt::Write::write_fmt(terminal, args).ok();
+    }
+}
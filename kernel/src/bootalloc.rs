@@ -0,0 +1,183 @@
+//! Carving physically contiguous memory out of the memory map before any allocator
+//! exists, for things like the frame allocator's own bookkeeping or AP stacks.
+//!
+//! Every range handed out is recorded, and allocators set up later have to leave the
+//! recorded ranges alone:
+//!
+//! ```ignore
+//! let bitmap = bootalloc::take(bitmap_size, 4096, None).unwrap();
+//! bootalloc::with_carved(|carved| unsafe { HEAP.init(memmap, carved) });
+//! ```
+
+use limine::LimineMemmapResponse;
+use spin::Mutex;
+
+use crate::array_vec::ArrayVec;
+use crate::boot::memmap::LimineMemmapResponseExt;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::MEMORY_MAP;
+use crate::boot::{PhysAddr, PhysAddrRange};
+
+/// How many ranges can be carved out in total.
+pub const MAX_CARVE_OUTS: usize = 32;
+
+/// How many usable memory map entries are carved from. Those past the first
+/// `MAX_USABLE_REGIONS` are left alone.
+pub const MAX_USABLE_REGIONS: usize = 64;
+
+/// The ranges carved out of the usable memory so far.
+#[derive(Debug, Default)]
+pub struct CarveOuts<const N: usize> {
+    regions: ArrayVec<PhysAddrRange, N>,
+}
+
+impl<const N: usize> CarveOuts<N> {
+    pub const fn new() -> Self {
+        Self {
+            regions: ArrayVec::new(),
+        }
+    }
+
+    pub fn regions(&self) -> &[PhysAddrRange] {
+        &self.regions
+    }
+
+    /// Carves `size` bytes aligned to `align`, a power of two, out of one of the first
+    /// [`MAX_USABLE_REGIONS`] usable entries of `memmap`, ending at or below `below` if
+    /// given. Takes the lowest address that doesn't overlap an earlier carve-out.
+    ///
+    /// Limine never reports the kernel or modules as usable, so they are avoided too.
+    pub fn take_from(
+        &mut self,
+        memmap: &LimineMemmapResponse,
+        size: u64,
+        align: u64,
+        below: Option<u64>,
+    ) -> Option<PhysAddr> {
+        if size == 0 || !align.is_power_of_two() || self.regions.is_full() {
+            return None;
+        }
+        let limit = below.unwrap_or(u64::MAX);
+
+        // The entries are sorted, so the first that fits has the lowest address.
+        let base = memmap
+            .usable_regions_sorted::<MAX_USABLE_REGIONS>()
+            .iter()
+            .find_map(|entry| {
+                let end = entry.base.saturating_add(entry.len).min(limit);
+                self.fit(entry.base, end, size, align)
+            })?;
+
+        let _ = self.regions.push(base..base + size);
+        Some(base)
+    }
+
+    /// Records `range` as taken without carving it, for memory claimed some other way
+    /// that allocators have to leave alone. Fails once `N` ranges are recorded.
+    pub fn reserve(&mut self, range: PhysAddrRange) -> bool {
+        self.regions.push(range).is_ok()
+    }
+
+    /// The lowest aligned start in `start..end` that `size` bytes fit behind without
+    /// touching a carve-out.
+    fn fit(&self, mut start: u64, end: u64, size: u64, align: u64) -> Option<u64> {
+        loop {
+            start = start.checked_next_multiple_of(align)?;
+            let stop = start.checked_add(size).filter(|&stop| stop <= end)?;
+            match self
+                .regions
+                .iter()
+                .find(|region| region.start < stop && start < region.end)
+            {
+                Some(region) => start = region.end,
+                None => return Some(start),
+            }
+        }
+    }
+}
+
+static CARVED: Mutex<CarveOuts<MAX_CARVE_OUTS>> = Mutex::new(CarveOuts::new());
+
+/// Carves `size` bytes aligned to `align` out of the boot memory map, below `below` if
+/// given. Fails without a memory map, if nothing fits, or once [`MAX_CARVE_OUTS`] ranges
+/// have been taken.
+pub fn take(size: u64, align: u64, below: Option<u64>) -> Option<PhysAddr> {
+    let memmap = MEMORY_MAP.response()?;
+    CARVED.lock().take_from(memmap, size, align, below)
+}
+
+/// Records `range` so [`take`] and the allocators consulting [`with_carved`] leave it
+/// alone. Fails once [`MAX_CARVE_OUTS`] ranges are recorded.
+pub fn reserve(range: PhysAddrRange) -> bool {
+    CARVED.lock().reserve(range)
+}
+
+/// Calls `f` with every range [`take`] handed out or [`reserve`] recorded.
+pub fn with_carved<R>(f: impl FnOnce(&[PhysAddrRange]) -> R) -> R {
+    f(CARVED.lock().regions())
+}
+
+/// Carves ranges out of a synthetic memory map with the kernel between two usable
+/// entries, checking alignment, `below`, and that earlier carve-outs, reserved ranges
+/// and the kernel are avoided. Another map lists its entries out of order and ends at
+/// the top of the address space.
+pub fn self_test() -> bool {
+    use limine::{LimineMemmapEntry, LimineMemoryMapEntryType::*};
+
+    static LOW: LimineMemmapEntry = LimineMemmapEntry {
+        base: 0x1000,
+        len: 0x7000,
+        typ: Usable,
+    };
+    static KERNEL: LimineMemmapEntry = LimineMemmapEntry {
+        base: 0x8000,
+        len: 0x8000,
+        typ: KernelAndModules,
+    };
+    static HIGH: LimineMemmapEntry = LimineMemmapEntry {
+        base: 0x1_0000,
+        len: 0x2_0000,
+        typ: Usable,
+    };
+    static ENTRIES: [&LimineMemmapEntry; 3] = [&LOW, &KERNEL, &HIGH];
+
+    // SAFETY: The entries are statics.
+    let memmap = unsafe { LimineMemmapResponse::from_parts(0, &ENTRIES) };
+    let mut carved = CarveOuts::<5>::new();
+    let carving_ok = carved.take_from(&memmap, 0x2000, 0x1000, None) == Some(0x1000)
+        && carved.take_from(&memmap, 0x2000, 0x4000, None) == Some(0x4000)
+        // The gaps left in `LOW` are too small, and it doesn't run on into the kernel.
+        && carved.take_from(&memmap, 0x4000, 0x1000, None) == Some(0x1_0000)
+        && carved.reserve(0x1_4000..0x1_8000)
+        && carved.take_from(&memmap, 0x1000, 0x1000, Some(0x1000)).is_none()
+        && carved.take_from(&memmap, 0x8000, 0x1000, Some(0x2_0000)) == Some(0x1_8000)
+        && carved.take_from(&memmap, 0x1000, 3, None).is_none()
+        && carved.take_from(&memmap, 0, 0x1000, None).is_none();
+    let full_ok = carved.take_from(&memmap, 0x1000, 0x1000, None).is_none()
+        && !carved.reserve(0x4_0000..0x4_1000);
+
+    // Its end doesn't fit in a `u64`.
+    static TOP: LimineMemmapEntry = LimineMemmapEntry {
+        base: 0xffff_ffff_fff0_0000,
+        len: 0x10_0000,
+        typ: Usable,
+    };
+    static UNSORTED: [&LimineMemmapEntry; 3] = [&TOP, &HIGH, &LOW];
+    // SAFETY: The entries are statics.
+    let unsorted = unsafe { LimineMemmapResponse::from_parts(0, &UNSORTED) };
+    let mut sorted = CarveOuts::<2>::new();
+    let unsorted_ok = sorted.take_from(&unsorted, 0x1000, 0x1000, None) == Some(0x1000)
+        && sorted.take_from(&unsorted, 0x8_0000, 0x1000, None) == Some(0xffff_ffff_fff0_0000);
+
+    carving_ok
+        && full_ok
+        && unsorted_ok
+        && *carved.regions()
+            == [
+                0x1000..0x3000,
+                0x4000..0x6000,
+                0x1_0000..0x1_4000,
+                0x1_4000..0x1_8000,
+                0x1_8000..0x2_0000,
+            ]
+}
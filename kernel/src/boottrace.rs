@@ -0,0 +1,207 @@
+//! Timing of the boot stages, to see where boot time goes.
+//!
+//! [`span`] starts timing a stage and the guard it returns ends it when dropped.
+//! Spans started while another one is open are nested in it:
+//!
+//! ```ignore
+//! let _init = boottrace::span("timers");
+//! {
+//!     let _hpet = boottrace::span("acpi_hpet");
+//!     // ...
+//! }
+//! ```
+//!
+//! The table keeps raw cycle counter values, so spans recorded before the TSC is
+//! calibrated are still converted correctly, once [`report`] prints the table.
+//!
+//! Spans are meant for the boot CPU's own boot path. The table is behind a lock, so
+//! they mustn't be started or ended in interrupt handlers.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use crate::array_vec::ArrayVec;
+use crate::kprintln;
+use crate::time::{cycles_between, tsc_now};
+
+/// How many spans are recorded. Further ones are counted and dropped.
+pub const MAX_SPANS: usize = 64;
+
+/// The width of the name column of the report, indentation included.
+const NAME_WIDTH: usize = 28;
+
+#[derive(Clone, Copy, Debug)]
+struct Record {
+    name: &'static str,
+    depth: usize,
+    start: u64,
+    /// `None` while the span is still open.
+    end: Option<u64>,
+}
+
+struct Table {
+    records: ArrayVec<Record, MAX_SPANS>,
+    /// How many spans are open right now, which is the depth of the next one.
+    open: usize,
+    dropped: usize,
+}
+
+static TABLE: Mutex<Table> = Mutex::new(Table {
+    records: ArrayVec::new(),
+    open: 0,
+    dropped: 0,
+});
+
+/// The counter frequency set by [`set_counter_hz`], or 0.
+static COUNTER_HZ: AtomicU64 = AtomicU64::new(0);
+
+/// Times a boot stage until the guard is dropped.
+#[must_use = "the span ends as soon as the guard is dropped"]
+pub struct Span {
+    /// The index of the record, or `None` if the table was full.
+    index: Option<usize>,
+}
+
+/// Starts timing the stage `name`, nested in the innermost open span.
+pub fn span(name: &'static str) -> Span {
+    let mut table = TABLE.lock();
+    let record = Record {
+        name,
+        depth: table.open,
+        start: tsc_now(),
+        end: None,
+    };
+    table.open += 1;
+    let index = table.records.len();
+    match table.records.push(record) {
+        Ok(()) => Span { index: Some(index) },
+        Err(_) => {
+            table.dropped += 1;
+            Span { index: None }
+        }
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let end = tsc_now();
+        let mut table = TABLE.lock();
+        table.open = table.open.saturating_sub(1);
+        if let Some(record) = self.index.and_then(|index| table.records.get_mut(index)) {
+            record.end = Some(end);
+        }
+    }
+}
+
+/// Sets the frequency of the counter the spans are timed with. Without it, [`report`]
+/// takes it from the TSC calibration of [`time::init`](crate::time::init) on x86_64.
+pub fn set_counter_hz(hz: u64) {
+    COUNTER_HZ.store(hz, Ordering::Relaxed);
+}
+
+fn counter_hz() -> Option<u64> {
+    match COUNTER_HZ.load(Ordering::Relaxed) {
+        0 => {}
+        hz => return Some(hz),
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        /// Timer ticks to measure over when nothing calibrated the TSC, about 50 ms.
+        const CALIBRATION_TICKS: u64 = 5;
+
+        if let Some(crate::time::DelaySource::Tsc { hz }) = crate::time::delay_source() {
+            return Some(hz);
+        }
+        crate::time::calibrate_tsc_hz(CALIBRATION_TICKS)
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    None
+}
+
+/// A number of counter cycles, shown in milliseconds once the frequency is known.
+struct Elapsed {
+    cycles: u64,
+    hz: Option<u64>,
+    /// Right-align the number for a column.
+    align: bool,
+}
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hz {
+            Some(hz) if hz > 0 => {
+                let us = (self.cycles as u128 * 1_000_000 / hz as u128) as u64;
+                let width = if self.align { 6 } else { 0 };
+                write!(
+                    f,
+                    "{:>width$}.{:03} ms",
+                    us / 1000,
+                    us % 1000,
+                    width = width
+                )
+            }
+            _ => {
+                let width = if self.align { 10 } else { 0 };
+                write!(f, "{:>width$} cycles", self.cycles, width = width)
+            }
+        }
+    }
+}
+
+/// Prints every span in the order they started, nested spans indented under the one
+/// they ran in, with their duration and share of the time since the first span
+/// started. Spans still open are timed up to now.
+pub fn report() {
+    let now = tsc_now();
+    let hz = counter_hz();
+    // A copy, so open spans keep their indices and can end while this prints.
+    let (mut records, dropped) = {
+        let table = TABLE.lock();
+        let mut records = ArrayVec::<Record, MAX_SPANS>::new();
+        for &record in table.records.iter() {
+            let _ = records.push(record);
+        }
+        (records, table.dropped)
+    };
+
+    let Some(first) = records.iter().map(|record| record.start).min() else {
+        return;
+    };
+    // A span nested in one started in the same cycle still comes after it.
+    records.sort_unstable_by_key(|record| (record.start, record.depth));
+    let total = cycles_between(first, now).max(1);
+
+    kprintln!(
+        "boot trace, {} since the first span:",
+        Elapsed {
+            cycles: total,
+            hz,
+            align: false
+        }
+    );
+    for record in records.iter() {
+        let cycles = cycles_between(record.start, record.end.unwrap_or(now));
+        let permille = (cycles as u128 * 1000 / total as u128) as u64;
+        let indent = 2 * record.depth.min(NAME_WIDTH / 2 - 1);
+        kprintln!(
+            "  {:indent$}{:<width$} {} {:>3}.{}%{}",
+            "",
+            record.name,
+            Elapsed {
+                cycles,
+                hz,
+                align: true
+            },
+            permille / 10,
+            permille % 10,
+            if record.end.is_none() { " (open)" } else { "" },
+            indent = indent,
+            width = NAME_WIDTH - indent,
+        );
+    }
+    if dropped > 0 {
+        kprintln!("  {} more spans didn't fit in the table", dropped);
+    }
+}
@@ -0,0 +1,48 @@
+//! CRC-32 as used by zlib, gzip and PNG (reflected polynomial `0xedb88320`).
+//!
+//! This catches corruption and mixed up files, not tampering.
+
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+/// The remainder of every byte value, computed at compile time.
+const TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Continues the CRC `crc` of some earlier data over `data`. Start with 0.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    !data.iter().fold(!crc, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ crc >> 8
+    })
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0, data)
+}
+
+/// Checks the CRC of a few well known strings, whole and in pieces.
+pub fn self_test() -> bool {
+    const FOX: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+    crc32(b"") == 0
+        && crc32(b"a") == 0xe8b7_be43
+        && crc32(b"123456789") == 0xcbf4_3926
+        && crc32(FOX) == 0x414f_a339
+        && crc32_update(crc32(&FOX[..10]), &FOX[10..]) == crc32(FOX)
+}
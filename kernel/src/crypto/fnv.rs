@@ -0,0 +1,18 @@
+//! 64-bit FNV-1a, a fast hash for fingerprinting data.
+//!
+//! It spreads small changes well, which makes it good for telling whether two buffers
+//! differ, but it is trivial to find collisions for on purpose.
+
+pub const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Continues the hash `hash` of some earlier data over `data`. Start with
+/// [`OFFSET_BASIS`].
+pub fn fnv1a_update(hash: u64, data: &[u8]) -> u64 {
+    data.iter()
+        .fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+}
+
+pub fn fnv1a(data: &[u8]) -> u64 {
+    fnv1a_update(OFFSET_BASIS, data)
+}
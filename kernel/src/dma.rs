@@ -0,0 +1,184 @@
+//! Physically contiguous buffers for devices, with known physical addresses.
+//!
+//! Buffers come from a pool of low memory set aside with [`reserve_pool`] or
+//! [`add_region`], and go back to it when dropped.
+//!
+//! x86_64 keeps DMA coherent with the CPU caches, but drivers should still call
+//! [`DmaBuffer::sync_for_device`] before a device reads a buffer and
+//! [`DmaBuffer::sync_for_cpu`] before reading what a device wrote, so they keep working
+//! on architectures where those have to flush or invalidate caches.
+
+use core::fmt;
+use core::sync::atomic::{fence, Ordering};
+
+use spin::Mutex;
+
+use crate::boot::memmap::FreeRegionList;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+use crate::boot::{PhysAddr, PhysAddrRange};
+use crate::bootalloc;
+use crate::frame::FRAME_SIZE;
+
+/// How many disjoint free ranges the pool keeps track of.
+pub const MAX_POOL_REGIONS: usize = 32;
+
+/// 16 MiB, the limit of ISA DMA.
+pub const ISA_LIMIT: u64 = 16 << 20;
+/// 4 GiB, the limit of devices with 32-bit addressing.
+pub const DMA32_LIMIT: u64 = 4 << 30;
+
+static POOL: Mutex<FreeRegionList<MAX_POOL_REGIONS>> = Mutex::new(FreeRegionList::new());
+
+/// Where a buffer may be placed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DmaConstraints {
+    /// The buffer has to end at or below this physical address.
+    pub limit: u64,
+    /// The minimum alignment of the buffer's physical address, a power of two. Buffers
+    /// are always at least frame aligned.
+    pub align: u64,
+    /// Align the buffer to its size rounded up to a power of two, as many devices want
+    /// for rings and descriptor tables.
+    pub align_to_size: bool,
+}
+
+impl DmaConstraints {
+    /// Anywhere in the pool.
+    pub const ANY: Self = Self {
+        limit: u64::MAX,
+        align: FRAME_SIZE,
+        align_to_size: false,
+    };
+    pub const DMA32: Self = Self {
+        limit: DMA32_LIMIT,
+        ..Self::ANY
+    };
+    pub const ISA: Self = Self {
+        limit: ISA_LIMIT,
+        ..Self::ANY
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaError {
+    /// The length is zero, or the alignment isn't a power of two.
+    InvalidRequest,
+    /// The bootloader provided no HHDM to reach the buffer through.
+    NoHhdm,
+    /// The pool has no free range satisfying the constraints.
+    NoSuitableMemory,
+}
+
+impl fmt::Display for DmaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InvalidRequest => "invalid DMA buffer request",
+            Self::NoHhdm => "no HHDM to reach DMA buffers through",
+            Self::NoSuitableMemory => "no DMA memory satisfying the constraints",
+        })
+    }
+}
+
+/// Carves `size` bytes ending at or below `limit` out of usable memory through
+/// [`bootalloc`] and adds them to the pool.
+pub fn reserve_pool(size: u64, limit: u64) -> Result<(), DmaError> {
+    let size = size.next_multiple_of(FRAME_SIZE);
+    let base = bootalloc::take(size, FRAME_SIZE, Some(limit)).ok_or(DmaError::NoSuitableMemory)?;
+    // SAFETY: The range was just carved out for good, so nothing else uses it.
+    unsafe { add_region(base..base + size) };
+    Ok(())
+}
+
+/// Adds `range` to the pool.
+///
+/// ## Safety
+///
+/// The memory must be unused, stay reserved for the pool and be covered by the HHDM.
+pub unsafe fn add_region(range: PhysAddrRange) {
+    POOL.lock().free_region(range);
+}
+
+/// A physically contiguous buffer, returned to the pool on drop.
+pub struct DmaBuffer {
+    virt: *mut u8,
+    phys: PhysAddrRange,
+    len: usize,
+}
+
+// The buffer is owned memory like a `Box<[u8]>`.
+unsafe impl Send for DmaBuffer {}
+unsafe impl Sync for DmaBuffer {}
+
+impl DmaBuffer {
+    /// Allocates a zeroed buffer of `len` bytes, rounded up to whole frames, placed as
+    /// `constraints` ask. Fails rather than placing it elsewhere if they can't be met.
+    pub fn alloc(len: usize, constraints: DmaConstraints) -> Result<Self, DmaError> {
+        if len == 0 || !constraints.align.is_power_of_two() {
+            return Err(DmaError::InvalidRequest);
+        }
+        let hhdm = HHDM.response().ok_or(DmaError::NoHhdm)?;
+
+        let size = (len as u64).next_multiple_of(FRAME_SIZE);
+        let mut align = constraints.align.max(FRAME_SIZE);
+        if constraints.align_to_size {
+            align = align.max(size.next_power_of_two());
+        }
+        let phys = POOL
+            .lock()
+            .alloc_region_below(size, align, constraints.limit)
+            .ok_or(DmaError::NoSuitableMemory)?;
+
+        let virt = (phys.start + hhdm.offset) as *mut u8;
+        // SAFETY: The range belongs to the pool, which `add_region` requires to be
+        // covered by the HHDM, and it was just taken for this buffer alone.
+        unsafe { virt.write_bytes(0, size as usize) };
+        Ok(Self { virt, phys, len })
+    }
+
+    /// The physical address of the first byte, for programming into the device.
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.phys.start
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.virt
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The buffer's contents. Don't hold on to the slice while the device writes to it.
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: The buffer owns `len` initialized bytes at `virt`.
+        unsafe { core::slice::from_raw_parts(self.virt, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: As for `as_slice`, and `&mut self` makes the access exclusive.
+        unsafe { core::slice::from_raw_parts_mut(self.virt, self.len) }
+    }
+
+    /// Makes the CPU's writes visible to the device. Just a fence on x86_64, which needs
+    /// no cache maintenance.
+    pub fn sync_for_device(&self) {
+        fence(Ordering::SeqCst);
+    }
+
+    /// Makes the device's writes visible to the CPU. Just a fence on x86_64, which needs
+    /// no cache maintenance.
+    pub fn sync_for_cpu(&self) {
+        fence(Ordering::SeqCst);
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        POOL.lock().free_region(self.phys.clone());
+    }
+}
@@ -0,0 +1,288 @@
+//! UEFI runtime services, for reading firmware variables after boot.
+//!
+//! Limine leaves the firmware's runtime services in place without calling
+//! `SetVirtualAddressMap`, so they keep running on physical addresses and need the
+//! identity map of the bootloader's page tables. Whether that still holds is something
+//! only the kernel knows, which is why every call goes through a [`RuntimeServices`]
+//! token: creating one is `unsafe` and is where the kernel vouches for it, in early boot
+//! before it touches the page tables or the firmware's memory.
+
+use core::fmt;
+use core::mem::size_of;
+use core::ptr;
+
+use spin::Mutex;
+
+use crate::arch::x86_64::paging::Mapper;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::{EFI_SYSTEM_TABLE, FIRMWARE_TYPE, HHDM};
+
+const SYSTEM_TABLE_SIGNATURE: u64 = u64::from_le_bytes(*b"IBI SYST");
+const RUNTIME_SERVICES_SIGNATURE: u64 = u64::from_le_bytes(*b"RUNTSERV");
+
+/// The vendor GUID of the variables the UEFI specification defines.
+pub const GLOBAL_VARIABLE: Guid = Guid::new(
+    0x8be4df61,
+    0x93ca,
+    0x11d2,
+    [0xaa, 0x0d, 0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c],
+);
+
+/// The longest variable name, in UCS-2 code units including the terminator, that the
+/// typed accessors encode.
+const MAX_NAME_LEN: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guid {
+    pub data1: u32,
+    pub data2: u16,
+    pub data3: u16,
+    pub data4: [u8; 8],
+}
+
+impl Guid {
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        Self {
+            data1,
+            data2,
+            data3,
+            data4,
+        }
+    }
+}
+
+/// An `EFI_STATUS` value.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status(pub usize);
+
+impl Status {
+    const ERROR_BIT: usize = 1 << (usize::BITS - 1);
+
+    pub const SUCCESS: Self = Self(0);
+    pub const BUFFER_TOO_SMALL: Self = Self(Self::ERROR_BIT | 5);
+    pub const NOT_FOUND: Self = Self(Self::ERROR_BIT | 14);
+
+    pub fn is_error(self) -> bool {
+        self.0 & Self::ERROR_BIT != 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EfiError {
+    /// The firmware type response is missing or names something other than 64-bit UEFI.
+    NotUefi64,
+    /// No EFI system table was passed, or it doesn't look like one.
+    NoSystemTable,
+    /// The runtime services table or its code isn't identity mapped.
+    NotMapped,
+    /// The name contains characters outside the basic multilingual plane or a NUL, or
+    /// doesn't fit the buffer.
+    InvalidName,
+    /// The firmware returned an error.
+    Status(Status),
+}
+
+impl fmt::Display for EfiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotUefi64 => f.write_str("not booted through 64-bit UEFI"),
+            Self::NoSystemTable => f.write_str("no EFI system table"),
+            Self::NotMapped => f.write_str("runtime services are not identity mapped"),
+            Self::InvalidName => f.write_str("variable name is not valid UCS-2"),
+            Self::Status(status) => write!(f, "EFI status {:#x}", status.0),
+        }
+    }
+}
+
+/// Encodes `name` as NUL terminated UCS-2 into `buf`, returning the used part.
+pub fn encode_ucs2<'a>(name: &str, buf: &'a mut [u16]) -> Result<&'a [u16], EfiError> {
+    let mut len = 0;
+    for c in name.chars() {
+        let unit = u16::try_from(c as u32)
+            .ok()
+            .filter(|&unit| unit != 0)
+            .ok_or(EfiError::InvalidName)?;
+        *buf.get_mut(len).ok_or(EfiError::InvalidName)? = unit;
+        len += 1;
+    }
+    *buf.get_mut(len).ok_or(EfiError::InvalidName)? = 0;
+    Ok(&buf[..=len])
+}
+
+#[repr(C)]
+struct TableHeader {
+    signature: u64,
+    revision: u32,
+    header_size: u32,
+    crc32: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+struct SystemTable {
+    header: TableHeader,
+    firmware_vendor: *const u16,
+    firmware_revision: u32,
+    console_in_handle: usize,
+    console_in: usize,
+    console_out_handle: usize,
+    console_out: usize,
+    standard_error_handle: usize,
+    standard_error: usize,
+    runtime_services: *const RuntimeServicesTable,
+    boot_services: usize,
+    number_of_table_entries: usize,
+    configuration_table: usize,
+}
+
+type GetVariableFn = unsafe extern "efiapi" fn(
+    name: *const u16,
+    vendor: *const Guid,
+    attributes: *mut u32,
+    data_size: *mut usize,
+    data: *mut u8,
+) -> Status;
+
+/// The start of `EFI_RUNTIME_SERVICES`, up to the last service used here.
+#[repr(C)]
+struct RuntimeServicesTable {
+    header: TableHeader,
+    get_time: usize,
+    set_time: usize,
+    get_wakeup_time: usize,
+    set_wakeup_time: usize,
+    set_virtual_address_map: usize,
+    convert_pointer: usize,
+    get_variable: GetVariableFn,
+}
+
+/// Runtime services aren't reentrant, so calls are serialized.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Proof that the runtime services can be called.
+pub struct RuntimeServices {
+    table: &'static RuntimeServicesTable,
+}
+
+impl RuntimeServices {
+    /// Checks that the machine booted through 64-bit UEFI and that the runtime services
+    /// table and `GetVariable` are identity mapped right now.
+    ///
+    /// ## Safety
+    ///
+    /// The identity map and the firmware's runtime memory must stay as they are for as
+    /// long as the token lives: the caller must not unmap the lower half, switch to page
+    /// tables without the identity map, or hand `EfiRuntimeServices*` memory (which
+    /// Limine reports as reserved) to an allocator.
+    pub unsafe fn acquire() -> Result<Self, EfiError> {
+        if !FIRMWARE_TYPE
+            .get_response()
+            .is_some_and(|firmware| firmware.is_64bit_uefi())
+        {
+            return Err(EfiError::NotUefi64);
+        }
+
+        let system_table = EFI_SYSTEM_TABLE
+            .response()
+            .and_then(|response| response.address.as_ptr())
+            .ok_or(EfiError::NoSystemTable)? as *const SystemTable;
+        let system_table = &*system_table;
+        if system_table.header.signature != SYSTEM_TABLE_SIGNATURE {
+            return Err(EfiError::NoSystemTable);
+        }
+
+        let table = system_table.runtime_services;
+        let hhdm = HHDM.response().ok_or(EfiError::NotMapped)?;
+        let mapper = Mapper::new(hhdm.offset);
+        let identity_mapped = |addr: u64| mapper.translate(addr) == Some(addr);
+        let table_end = table as u64 + size_of::<RuntimeServicesTable>() as u64 - 1;
+        if !identity_mapped(table as u64) || !identity_mapped(table_end) {
+            return Err(EfiError::NotMapped);
+        }
+
+        let table = &*table;
+        if table.header.signature != RUNTIME_SERVICES_SIGNATURE {
+            return Err(EfiError::NoSystemTable);
+        }
+        if !identity_mapped(table.get_variable as usize as u64) {
+            return Err(EfiError::NotMapped);
+        }
+
+        Ok(Self { table })
+    }
+
+    /// Reads the variable `name` of `vendor` into `data`, returning its attributes and
+    /// size. If `data` is too small, the error is [`Status::BUFFER_TOO_SMALL`].
+    pub fn get_variable(
+        &self,
+        name: &[u16],
+        vendor: &Guid,
+        data: &mut [u8],
+    ) -> Result<(u32, usize), EfiError> {
+        if name.last() != Some(&0) {
+            return Err(EfiError::InvalidName);
+        }
+
+        let mut attributes = 0;
+        let mut size = data.len();
+        let status = {
+            let _guard = LOCK.lock();
+            unsafe {
+                (self.table.get_variable)(
+                    name.as_ptr(),
+                    vendor,
+                    &mut attributes,
+                    &mut size,
+                    if data.is_empty() {
+                        ptr::null_mut()
+                    } else {
+                        data.as_mut_ptr()
+                    },
+                )
+            }
+        };
+
+        if status.is_error() {
+            Err(EfiError::Status(status))
+        } else {
+            Ok((attributes, size))
+        }
+    }
+
+    /// Reads a fixed-size global variable.
+    fn global<const N: usize>(&self, name: &str) -> Result<[u8; N], EfiError> {
+        let mut buf = [0; MAX_NAME_LEN];
+        let name = encode_ucs2(name, &mut buf)?;
+        let mut data = [0; N];
+        self.get_variable(name, &GLOBAL_VARIABLE, &mut data)?;
+        Ok(data)
+    }
+
+    pub fn secure_boot(&self) -> Result<SecureBoot, EfiError> {
+        match self.global::<1>("SecureBoot") {
+            Ok([0]) => Ok(SecureBoot::Disabled),
+            Ok([1]) => Ok(SecureBoot::Enabled),
+            Ok([value]) => Ok(SecureBoot::Unknown(value)),
+            Err(EfiError::Status(Status::NOT_FOUND)) => Ok(SecureBoot::Unsupported),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The `Boot####` option the firmware booted from.
+    pub fn boot_current(&self) -> Result<u16, EfiError> {
+        self.global("BootCurrent").map(u16::from_le_bytes)
+    }
+}
+
+/// The value of the `SecureBoot` variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecureBoot {
+    Disabled,
+    Enabled,
+    /// The variable holds something other than 0 or 1.
+    Unknown(u8),
+    /// The firmware doesn't implement Secure Boot and has no such variable.
+    Unsupported,
+}
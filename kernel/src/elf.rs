@@ -0,0 +1,290 @@
+//! Symbol lookup in ELF64 images, for naming addresses in backtraces.
+//!
+//! Only the section headers and the static symbol table are read, which is all a
+//! statically linked kernel has. Images without a `.symtab`, like stripped ones, parse
+//! fine and resolve nothing.
+
+use core::fmt;
+
+const MAGIC: &[u8; 4] = b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+
+const SECTION_HEADERS: usize = 0x28;
+const SECTION_HEADER_SIZE: usize = 0x3a;
+const SECTION_COUNT: usize = 0x3c;
+const HEADER_LEN: usize = 0x40;
+
+const SECTION_LEN: usize = 0x40;
+const SHT_SYMTAB: u32 = 2;
+
+const SYMBOL_LEN: usize = 24;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    /// A header or table goes past the end of the image.
+    Truncated,
+    BadMagic,
+    /// Not a little-endian 64-bit image.
+    Unsupported,
+    /// A section header or symbol entry has a size other than the ELF64 one.
+    BadEntrySize,
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Truncated => "ELF image is truncated",
+            Self::BadMagic => "not an ELF image",
+            Self::Unsupported => "not a little-endian ELF64 image",
+            Self::BadEntrySize => "ELF table entries have the wrong size",
+        })
+    }
+}
+
+fn read<const N: usize>(bytes: &[u8], offset: usize) -> Option<[u8; N]> {
+    bytes.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    read(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    read(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    read(bytes, offset).map(u64::from_le_bytes)
+}
+
+/// A function or data object from the symbol table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    /// The link-time address.
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Symbol<'_> {
+    pub fn contains(&self, addr: u64) -> bool {
+        addr.checked_sub(self.value)
+            .is_some_and(|offset| offset < self.size)
+    }
+}
+
+/// The symbol and string tables of an image.
+#[derive(Clone, Copy, Debug)]
+pub struct Symbols<'a> {
+    symtab: &'a [u8],
+    strtab: &'a [u8],
+}
+
+impl<'a> Symbols<'a> {
+    /// A table without symbols.
+    pub const EMPTY: Self = Self {
+        symtab: &[],
+        strtab: &[],
+    };
+
+    /// Finds the symbol table of `image`. An image without one gives [`EMPTY`].
+    ///
+    /// [`EMPTY`]: Symbols::EMPTY
+    pub fn parse(image: &'a [u8]) -> Result<Self, ElfError> {
+        if image.len() < HEADER_LEN {
+            return Err(ElfError::Truncated);
+        }
+        if &image[..4] != MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if image[4] != CLASS_64 || image[5] != DATA_LITTLE_ENDIAN {
+            return Err(ElfError::Unsupported);
+        }
+
+        let truncated = ElfError::Truncated;
+        let table = u64_at(image, SECTION_HEADERS).ok_or(truncated)? as usize;
+        let count = u16_at(image, SECTION_COUNT).ok_or(truncated)? as usize;
+        if table == 0 || count == 0 {
+            return Ok(Self::EMPTY);
+        }
+        if u16_at(image, SECTION_HEADER_SIZE).ok_or(truncated)? as usize != SECTION_LEN {
+            return Err(ElfError::BadEntrySize);
+        }
+
+        let section = |index: usize| -> Result<&'a [u8], ElfError> {
+            let start = index
+                .checked_mul(SECTION_LEN)
+                .and_then(|offset| offset.checked_add(table))
+                .ok_or(truncated)?;
+            image.get(start..start + SECTION_LEN).ok_or(truncated)
+        };
+        let contents = |header: &[u8]| -> Result<&'a [u8], ElfError> {
+            let offset = u64_at(header, 24).ok_or(truncated)? as usize;
+            let size = u64_at(header, 32).ok_or(truncated)? as usize;
+            image
+                .get(offset..offset.checked_add(size).ok_or(truncated)?)
+                .ok_or(truncated)
+        };
+
+        for index in 0..count {
+            let header = section(index)?;
+            if u32_at(header, 4) != Some(SHT_SYMTAB) {
+                continue;
+            }
+            if u64_at(header, 56) != Some(SYMBOL_LEN as u64) {
+                return Err(ElfError::BadEntrySize);
+            }
+            let link = u32_at(header, 40).ok_or(truncated)? as usize;
+            return Ok(Self {
+                symtab: contents(header)?,
+                strtab: contents(section(link)?)?,
+            });
+        }
+        Ok(Self::EMPTY)
+    }
+
+    /// Iterates over the named functions and data objects with a size.
+    pub fn iter(&self) -> impl Iterator<Item = Symbol<'a>> + '_ {
+        let strtab = self.strtab;
+        self.symtab
+            .chunks_exact(SYMBOL_LEN)
+            .filter_map(move |entry| {
+                let kind = entry[4] & 0xf;
+                if kind != STT_FUNC && kind != STT_OBJECT {
+                    return None;
+                }
+                let size = u64_at(entry, 16)?;
+                if size == 0 {
+                    return None;
+                }
+                let name = strtab.get(u32_at(entry, 0)? as usize..)?;
+                let name = core::ffi::CStr::from_bytes_until_nul(name)
+                    .ok()?
+                    .to_str()
+                    .ok()?;
+                Some(Symbol {
+                    name,
+                    value: u64_at(entry, 8)?,
+                    size,
+                })
+            })
+    }
+
+    /// The symbol whose range contains the link-time address `addr`.
+    pub fn lookup(&self, addr: u64) -> Option<Symbol<'a>> {
+        self.iter().find(|symbol| symbol.contains(addr))
+    }
+
+    /// The name of the symbol whose range contains the link-time address `addr`.
+    pub fn resolve(&self, addr: u64) -> Option<&'a str> {
+        self.lookup(addr).map(|symbol| symbol.name)
+    }
+}
+
+/// The kernel's own symbols, from the kernel file the bootloader loaded. Parsed on the
+/// first call; empty if there is no file or it doesn't parse.
+#[cfg(feature = "kernel-file")]
+pub fn kernel_symbols() -> &'static Symbols<'static> {
+    use crate::boot::request::LimineRequest;
+    use crate::boot::requests::KERNEL_FILE;
+
+    static SYMBOLS: spin::Once<Symbols<'static>> = spin::Once::new();
+    SYMBOLS.call_once(|| {
+        KERNEL_FILE
+            .response()
+            .and_then(|response| response.kernel_file.get())
+            .and_then(|file| {
+                let base = file.base.as_ptr()?;
+                // SAFETY: Limine loads the whole file into bootloader-reclaimable memory,
+                // which the kernel never reclaims.
+                let image = unsafe { core::slice::from_raw_parts(base, file.length as usize) };
+                Symbols::parse(image).ok()
+            })
+            .unwrap_or(Symbols::EMPTY)
+    })
+}
+
+/// The kernel symbol containing the load-time address `addr`, and how far into it the
+/// address is.
+///
+/// Without the `kernel-address` feature the kernel is taken to run where it was linked.
+#[cfg(feature = "kernel-file")]
+pub fn lookup_kernel(addr: u64) -> Option<(Symbol<'static>, u64)> {
+    #[cfg(feature = "kernel-address")]
+    let addr = crate::kaslr::runtime_to_link(addr);
+    let symbol = kernel_symbols().lookup(addr)?;
+    Some((symbol, addr - symbol.value))
+}
+
+/// Builds an image holding two functions and checks that lookups find them, miss
+/// between and past them, and that an image without sections resolves nothing.
+pub fn self_test() -> bool {
+    const STRTAB: usize = HEADER_LEN;
+    const SYMTAB: usize = 0x50;
+    const SECTIONS: usize = SYMTAB + 3 * SYMBOL_LEN;
+    const LEN: usize = SECTIONS + 3 * SECTION_LEN;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    fn symbol(image: &mut [u8], index: usize, name: u32, value: u64, size: u64) {
+        let entry = SYMTAB + index * SYMBOL_LEN;
+        put(image, entry, &name.to_le_bytes());
+        image[entry + 4] = STT_FUNC;
+        put(image, entry + 8, &value.to_le_bytes());
+        put(image, entry + 16, &size.to_le_bytes());
+    }
+    fn section(image: &mut [u8], index: usize, kind: u32, range: (usize, usize), link: u32) {
+        let header = SECTIONS + index * SECTION_LEN;
+        put(image, header + 4, &kind.to_le_bytes());
+        put(image, header + 24, &(range.0 as u64).to_le_bytes());
+        put(image, header + 32, &(range.1 as u64).to_le_bytes());
+        put(image, header + 40, &link.to_le_bytes());
+        if kind == SHT_SYMTAB {
+            put(image, header + 56, &(SYMBOL_LEN as u64).to_le_bytes());
+        }
+    }
+
+    let mut image = [0; LEN];
+    put(&mut image, 0, MAGIC);
+    image[4] = CLASS_64;
+    image[5] = DATA_LITTLE_ENDIAN;
+    put(
+        &mut image,
+        SECTION_HEADERS,
+        &(SECTIONS as u64).to_le_bytes(),
+    );
+    put(
+        &mut image,
+        SECTION_HEADER_SIZE,
+        &(SECTION_LEN as u16).to_le_bytes(),
+    );
+    put(&mut image, SECTION_COUNT, &3u16.to_le_bytes());
+
+    let names = b"\0first\0second\0";
+    put(&mut image, STRTAB, names);
+    // Entry 0 is the null symbol.
+    symbol(&mut image, 1, 1, 0x1000, 0x10);
+    symbol(&mut image, 2, 7, 0x1020, 0x08);
+    section(&mut image, 1, SHT_SYMTAB, (SYMTAB, 3 * SYMBOL_LEN), 2);
+    // String tables are type 3.
+    section(&mut image, 2, 3, (STRTAB, names.len()), 0);
+
+    let Ok(symbols) = Symbols::parse(&image) else {
+        return false;
+    };
+    let found = symbols.resolve(0x1000) == Some("first")
+        && symbols.resolve(0x100f) == Some("first")
+        && symbols.resolve(0x1010).is_none()
+        && symbols.resolve(0x1024) == Some("second")
+        && symbols.resolve(0x1028).is_none();
+
+    // Without section headers there is no symbol table.
+    put(&mut image, SECTION_COUNT, &0u16.to_le_bytes());
+    let empty = Symbols::parse(&image).is_ok_and(|symbols| symbols.resolve(0x1000).is_none());
+
+    found && empty
+}
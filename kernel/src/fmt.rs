@@ -0,0 +1,192 @@
+//! Hex dumps and hexadecimal display of values.
+//!
+//! [`hexdump`] prints the classic `hexdump -C` layout, an offset, the bytes in groups
+//! of eight and their printable ASCII:
+//!
+//! ```text
+//! 00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 00  |Hello, world!...|
+//! 00001010  00 00 00                                          |...|
+//! ```
+//!
+//! Short lines are padded so the ASCII column stays aligned.
+
+use core::fmt::{self, Write};
+
+/// How [`hexdump_with`] lays out its lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HexdumpOptions {
+    /// Bytes per line, at least one.
+    pub width: usize,
+    /// Print runs of lines identical to the one before as a single `*`, like
+    /// `hexdump -C`. The offset where the dump ends follows a run that reaches the end.
+    pub collapse_repeats: bool,
+}
+
+impl Default for HexdumpOptions {
+    fn default() -> Self {
+        Self {
+            width: 16,
+            collapse_repeats: true,
+        }
+    }
+}
+
+/// Bytes per group, separated by an extra space.
+const GROUP: usize = 8;
+
+/// Dumps `bytes` 16 to a line, labelling the first with `base`, and collapsing repeated
+/// lines.
+pub fn hexdump<W: Write + ?Sized>(out: &mut W, base: u64, bytes: &[u8]) -> fmt::Result {
+    hexdump_with(out, base, bytes, HexdumpOptions::default())
+}
+
+pub fn hexdump_with<W: Write + ?Sized>(
+    out: &mut W,
+    base: u64,
+    bytes: &[u8],
+    options: HexdumpOptions,
+) -> fmt::Result {
+    let width = options.width.max(1);
+    let mut previous: Option<&[u8]> = None;
+    let mut collapsed = false;
+
+    for (index, line) in bytes.chunks(width).enumerate() {
+        let offset = base.wrapping_add((index * width) as u64);
+        if options.collapse_repeats && line.len() == width && previous == Some(line) {
+            if !collapsed {
+                out.write_str("*\n")?;
+                collapsed = true;
+            }
+            continue;
+        }
+        previous = Some(line);
+        collapsed = false;
+
+        write!(out, "{:08x} ", offset)?;
+        for column in 0..width {
+            if column % GROUP == 0 {
+                out.write_char(' ')?;
+            }
+            match line.get(column) {
+                Some(byte) => write!(out, "{:02x} ", byte)?,
+                None => out.write_str("   ")?,
+            }
+        }
+        out.write_str(" |")?;
+        for &byte in line {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            out.write_char(c)?;
+        }
+        out.write_str("|\n")?;
+    }
+
+    if collapsed {
+        writeln!(out, "{:08x}", base.wrapping_add(bytes.len() as u64))?;
+    }
+    Ok(())
+}
+
+/// Shows integers as `0x` and all their digits, and byte slices and arrays as
+/// space-separated pairs of digits:
+///
+/// ```ignore
+/// kprintln!("{} {}", DisplayHex(0x2au16), DisplayHex(&[0xde, 0xad][..]));
+/// // 0x002a de ad
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DisplayHex<T>(pub T);
+
+macro_rules! impl_display_hex {
+    ($($ty:ty),*) => {
+        $(impl fmt::Display for DisplayHex<$ty> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{:#0width$x}", self.0, width = 2 + 2 * size_of::<$ty>())
+            }
+        })*
+    };
+}
+
+impl_display_hex!(u8, u16, u32, u64, u128, usize);
+
+impl fmt::Display for DisplayHex<&[u8]> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_char(' ')?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for DisplayHex<&[u8; N]> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DisplayHex(&self.0[..]).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(base: u64, bytes: &[u8], options: HexdumpOptions) -> String {
+        let mut out = String::new();
+        hexdump_with(&mut out, base, bytes, options).unwrap();
+        out
+    }
+
+    #[test]
+    fn short_last_line() {
+        assert_eq!(
+            dump(
+                0x1000,
+                b"Hello, world!\n\0\0\0\0\0",
+                HexdumpOptions::default()
+            ),
+            "00001000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a 00 00  |Hello, world!...|\n\
+             00001010  00 00 00                                          |...|\n"
+        );
+    }
+
+    #[test]
+    fn collapsed_run_reaching_the_end() {
+        assert_eq!(
+            dump(0, &[0; 48], HexdumpOptions::default()),
+            "00000000  00 00 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |................|\n\
+             *\n\
+             00000030\n"
+        );
+    }
+
+    #[test]
+    fn narrow_without_collapsing() {
+        let options = HexdumpOptions {
+            width: 4,
+            collapse_repeats: false,
+        };
+        assert_eq!(
+            dump(0xff, b"abcdabcdab", options),
+            "000000ff  61 62 63 64  |abcd|\n\
+             00000103  61 62 63 64  |abcd|\n\
+             00000107  61 62        |ab|\n"
+        );
+    }
+
+    #[test]
+    fn display_hex() {
+        assert_eq!(
+            format!(
+                "{} {} {}",
+                DisplayHex(0x2au16),
+                DisplayHex(&[0xdeu8, 0xad]),
+                DisplayHex(0xffu64)
+            ),
+            "0x002a de ad 0x00000000000000ff"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Handing out physical frames from the memory map.
+
+use limine::LimineMemmapResponse;
+
+use crate::boot::memmap::LimineMemmapResponseExt;
+use crate::boot::{PhysAddr, PhysAddrRange};
+
+pub const FRAME_SIZE: u64 = 4096;
+
+/// Allocates frames from the usable entries of the memory map by moving a cursor up
+/// through physical memory. Frames are never freed.
+///
+/// Frames skipped to satisfy an alignment, and the tail of an entry too short for a
+/// contiguous request, are not handed out later.
+pub struct BumpFrameAllocator<'a> {
+    memmap: &'a LimineMemmapResponse,
+    exclude: &'a [PhysAddrRange],
+    next: PhysAddr,
+}
+
+impl<'a> BumpFrameAllocator<'a> {
+    /// Allocates from the usable entries of `memmap` outside of `exclude`, normally the
+    /// [carve-outs](crate::bootalloc::with_carved):
+    ///
+    /// ```ignore
+    /// bootalloc::with_carved(|carved| {
+    ///     let mut frames = BumpFrameAllocator::new(memmap, carved);
+    ///     // ...
+    /// });
+    /// ```
+    pub fn new(memmap: &'a LimineMemmapResponse, exclude: &'a [PhysAddrRange]) -> Self {
+        Self {
+            memmap,
+            exclude,
+            next: 0,
+        }
+    }
+
+    /// Returns the physical address of a free frame.
+    pub fn alloc_frame(&mut self) -> Option<PhysAddr> {
+        self.alloc_contiguous(1, 1)
+    }
+
+    /// Returns the base of `count` physically contiguous free frames, aligned to
+    /// `align_frames` frames. A run never spans two memory map entries, even adjacent
+    /// ones.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `align_frames` isn't a power of two.
+    pub fn alloc_contiguous(&mut self, count: usize, align_frames: usize) -> Option<PhysAddr> {
+        assert!(
+            align_frames.is_power_of_two(),
+            "frame alignment {} isn't a power of two",
+            align_frames
+        );
+        let size = (count as u64).checked_mul(FRAME_SIZE)?;
+        let align = (align_frames as u64).checked_mul(FRAME_SIZE)?;
+
+        let mut above = self.next;
+        let base = loop {
+            let base = self.memmap.first_usable_above(above, size, align)?;
+            let end = base + size;
+            match self
+                .exclude
+                .iter()
+                .find(|range| range.start < end && base < range.end)
+            {
+                Some(range) => above = range.end,
+                None => break base,
+            }
+        };
+
+        self.next = base + size;
+        Some(base)
+    }
+}
+
+/// Allocates runs of frames from a synthetic memory map with a reserved gap, two
+/// adjacent usable entries, and an excluded range, including runs that don't fit.
+pub fn self_test() -> bool {
+    use limine::{LimineMemmapEntry, LimineMemoryMapEntryType::*};
+
+    static LOW: LimineMemmapEntry = LimineMemmapEntry {
+        base: FRAME_SIZE,
+        len: 3 * FRAME_SIZE,
+        typ: Usable,
+    };
+    static GAP: LimineMemmapEntry = LimineMemmapEntry {
+        base: 4 * FRAME_SIZE,
+        len: 0xfc * FRAME_SIZE,
+        typ: Reserved,
+    };
+    static HIGH: LimineMemmapEntry = LimineMemmapEntry {
+        base: 0x100 * FRAME_SIZE,
+        len: 8 * FRAME_SIZE,
+        typ: Usable,
+    };
+    static ADJACENT: LimineMemmapEntry = LimineMemmapEntry {
+        base: 0x108 * FRAME_SIZE,
+        len: 4 * FRAME_SIZE,
+        typ: Usable,
+    };
+    static ENTRIES: [&LimineMemmapEntry; 4] = [&LOW, &GAP, &HIGH, &ADJACENT];
+
+    // SAFETY: The entries are statics.
+    let memmap = unsafe { LimineMemmapResponse::from_parts(0, &ENTRIES) };
+    let excluded = 0x100 * FRAME_SIZE..0x102 * FRAME_SIZE;
+
+    // 15 usable frames, but no 12 of them in one entry.
+    let too_long = BumpFrameAllocator::new(&memmap, &[]).alloc_contiguous(12, 1);
+
+    let mut frames = BumpFrameAllocator::new(&memmap, core::slice::from_ref(&excluded));
+    too_long.is_none()
+        && frames.alloc_contiguous(2, 1) == Some(FRAME_SIZE)
+        // Past the rest of `LOW` and the excluded range, to the next multiple of 4.
+        && frames.alloc_contiguous(4, 4) == Some(0x104 * FRAME_SIZE)
+        // Only 4 frames are left, in `ADJACENT`, and a failure doesn't use them up.
+        && frames.alloc_contiguous(5, 1).is_none()
+        && frames.alloc_contiguous(4, 1) == Some(0x108 * FRAME_SIZE)
+        && frames.alloc_frame().is_none()
+}
@@ -0,0 +1,341 @@
+//! A text console that needs nothing but a framebuffer.
+
+use core::cell::Cell;
+use core::fmt;
+
+use super::font::Font;
+use super::surface::Surface;
+use super::theme::Theme;
+use super::FramebufferColor;
+
+/// Renders text onto a [`Surface`], usually a `&LimineFramebuffer`.
+///
+/// Text wraps at the right edge and the console scrolls up by one line once the
+/// bottom is reached.
+///
+/// Changing a color only affects text written afterwards, and lines scrolled in take the
+/// background current at that time; [`clear`](Self::clear) repaints the whole surface.
+///
+/// The cursor is drawn by inverting its cell between the foreground and background
+/// colors, see [`draw_cursor`](Self::draw_cursor). Writing and clearing erase it first.
+pub struct BasicConsole<S: Surface> {
+    surface: S,
+    font: Font,
+    columns: u64,
+    rows: u64,
+    column: u64,
+    row: u64,
+    foreground: FramebufferColor,
+    background: FramebufferColor,
+    palette: [FramebufferColor; 16],
+    /// Where the cursor is drawn, and the bits it flipped there.
+    cursor: Cell<Option<DrawnCursor>>,
+}
+
+#[derive(Clone, Copy)]
+struct DrawnCursor {
+    x: u64,
+    y: u64,
+    mask: u32,
+}
+
+impl<S: Surface> BasicConsole<S> {
+    /// Creates a white on black console covering the whole surface, using the
+    /// [boot font](Font::boot_font).
+    pub fn new(surface: S) -> Self {
+        Self::with_colors(surface, FramebufferColor::WHITE, FramebufferColor::BLACK)
+    }
+
+    pub fn with_colors(
+        surface: S,
+        foreground: FramebufferColor,
+        background: FramebufferColor,
+    ) -> Self {
+        Self::with_font_and_colors(surface, Font::boot_font(), foreground, background)
+    }
+
+    /// Like [`with_colors`](Self::with_colors) with `font` instead of the boot font, which
+    /// is looked up on the command line and parsed from a module on every call.
+    pub fn with_font_and_colors(
+        surface: S,
+        font: Font,
+        foreground: FramebufferColor,
+        background: FramebufferColor,
+    ) -> Self {
+        Self {
+            palette: Theme::DARK.palette,
+            columns: surface.width() / font.width(),
+            rows: surface.height() / font.height(),
+            surface,
+            font,
+            column: 0,
+            row: 0,
+            foreground,
+            background,
+            cursor: Cell::new(None),
+        }
+    }
+
+    /// Creates a console using the colors of `theme`.
+    pub fn with_theme(surface: S, theme: Theme) -> Self {
+        let mut console = Self::with_colors(surface, theme.foreground, theme.background);
+        console.palette = theme.palette;
+        console
+    }
+
+    pub fn set_foreground(&mut self, color: FramebufferColor) {
+        self.foreground = color;
+    }
+
+    pub fn set_background(&mut self, color: FramebufferColor) {
+        self.background = color;
+    }
+
+    /// Switches the text color to the ANSI color `index` of the theme. Indices of 16 and
+    /// above are ignored.
+    pub fn set_ansi_foreground(&mut self, index: u8) {
+        if let Some(&color) = self.palette.get(index as usize) {
+            self.foreground = color;
+        }
+    }
+
+    /// Switches the background color to the ANSI color `index` of the theme. Indices of
+    /// 16 and above are ignored.
+    pub fn set_ansi_background(&mut self, index: u8) {
+        if let Some(&color) = self.palette.get(index as usize) {
+            self.background = color;
+        }
+    }
+
+    /// Switches to `font`, which changes the number of rows and columns. The cursor
+    /// moves home; the text already on the surface stays as it is.
+    pub fn with_font(mut self, font: Font) -> Self {
+        self.draw_cursor(false);
+        self.columns = self.surface.width() / font.width();
+        self.rows = self.surface.height() / font.height();
+        self.column = 0;
+        self.row = 0;
+        self.font = font;
+        self
+    }
+
+    pub fn columns(&self) -> u64 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Fills the surface with the background color and moves the cursor home.
+    pub fn clear(&mut self) {
+        self.cursor.set(None);
+        let surface = &self.surface;
+        surface.fill_rect(0, 0, surface.width(), surface.height(), self.background);
+        self.column = 0;
+        self.row = 0;
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+    }
+
+    pub fn write_char(&mut self, c: char) {
+        if self.columns == 0 || self.rows == 0 {
+            return;
+        }
+        self.draw_cursor(false);
+
+        match c {
+            '\n' => self.newline(),
+            '\r' => self.column = 0,
+            c => {
+                if self.column >= self.columns {
+                    self.newline();
+                }
+                self.draw_glyph(
+                    c,
+                    self.column * self.font.width(),
+                    self.row * self.font.height(),
+                );
+                self.column += 1;
+            }
+        }
+    }
+
+    /// Shows or hides the cursor in the cell the next character goes to. Pixels in the
+    /// foreground color turn into the background color and the other way around, so
+    /// hiding it gives back exactly what was there without saving the cell.
+    ///
+    /// The cursor is hidden where it was drawn, even if the colors changed since.
+    pub fn draw_cursor(&self, visible: bool) {
+        if self.cursor.get().is_some() == visible {
+            return;
+        }
+        let cursor = match self.cursor.take() {
+            Some(cursor) => cursor,
+            None if self.columns == 0 || self.rows == 0 => return,
+            None => DrawnCursor {
+                // Past the last column until the next character wraps.
+                x: self.column.min(self.columns - 1) * self.font.width(),
+                y: self.row * self.font.height(),
+                mask: self.surface.encode(self.foreground) ^ self.surface.encode(self.background),
+            },
+        };
+        self.invert_cell(&cursor);
+        if visible {
+            self.cursor.set(Some(cursor));
+        }
+    }
+
+    /// Flips the cursor between shown and hidden, for blinking it.
+    pub fn tick_cursor(&mut self) {
+        self.draw_cursor(self.cursor.get().is_none());
+    }
+
+    pub fn is_cursor_visible(&self) -> bool {
+        self.cursor.get().is_some()
+    }
+
+    fn invert_cell(&self, cursor: &DrawnCursor) {
+        let surface = &self.surface;
+        for dy in 0..self.font.height() {
+            for dx in 0..self.font.width() {
+                let (x, y) = (cursor.x + dx, cursor.y + dy);
+                if let Some(raw) = surface.get_raw_pixel(x, y) {
+                    surface.put_raw_pixel(x, y, raw ^ cursor.mask);
+                }
+            }
+        }
+    }
+
+    fn draw_glyph(&self, c: char, x: u64, y: u64) {
+        let surface = &self.surface;
+        let foreground = surface.encode(self.foreground);
+        let background = surface.encode(self.background);
+
+        let glyph = self.font.glyph(c);
+        for dy in 0..self.font.height() {
+            for dx in 0..self.font.width() {
+                let raw = if glyph.pixel(dx, dy) {
+                    foreground
+                } else {
+                    background
+                };
+                surface.put_raw_pixel(x + dx, y + dy, raw);
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// Moves every text line up by one and clears the last one.
+    fn scroll(&mut self) {
+        let surface = &self.surface;
+        let line_height = self.font.height();
+        surface.scroll_up(line_height);
+
+        let last_line = (self.rows - 1) * line_height;
+        surface.fill_rect(0, last_line, surface.width(), line_height, self.background);
+    }
+}
+
+impl<S: Surface> fmt::Write for BasicConsole<S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        BasicConsole::write_str(self, s);
+        Ok(())
+    }
+}
+
+/// Checks that an `A` written after a space lands in the second cell with the bits of
+/// its glyph, and that hiding the cursor gives back the cell it was drawn over and
+/// writing with the cursor shown draws the same as writing without one.
+pub fn self_test() -> bool {
+    glyph_self_test() && cursor_self_test()
+}
+
+fn glyph_self_test() -> bool {
+    use super::surface::RawSurface;
+    use super::FramebufferInfo;
+
+    const WIDTH: usize = 16;
+    const HEIGHT: usize = 8;
+    /// The rows of the `A` of `font8x8`, the leftmost pixel in the lowest bit.
+    const A: [u8; 8] = [0x0c, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x00];
+
+    let info = FramebufferInfo::xrgb8888(WIDTH as u64, HEIGHT as u64);
+    let mut pixels = [0u32; WIDTH * HEIGHT];
+    // SAFETY: The array holds the `pitch * height` bytes `info` describes and outlives
+    // the console.
+    let surface = unsafe { RawSurface::new(pixels.as_mut_ptr().cast(), info) };
+    let mut console = BasicConsole::with_font_and_colors(
+        surface,
+        Font::EMBEDDED,
+        FramebufferColor::WHITE,
+        FramebufferColor::BLACK,
+    );
+    console.clear();
+    console.write_str(" A");
+
+    let (on, off) = (
+        info.encode(FramebufferColor::WHITE),
+        info.encode(FramebufferColor::BLACK),
+    );
+    pixels.chunks_exact(WIDTH).zip(A).all(|(row, bits)| {
+        let (space, glyph) = row.split_at(WIDTH / 2);
+        space.iter().all(|&pixel| pixel == off)
+            && glyph
+                .iter()
+                .enumerate()
+                .all(|(x, &pixel)| pixel == if bits >> x & 1 != 0 { on } else { off })
+    })
+}
+
+fn cursor_self_test() -> bool {
+    use super::surface::RawSurface;
+    use super::FramebufferInfo;
+
+    const WIDTH: usize = 32;
+    const HEIGHT: usize = 32;
+    let info = FramebufferInfo::xrgb8888(WIDTH as u64, HEIGHT as u64);
+
+    let mut pixels = [0u32; WIDTH * HEIGHT];
+    let mut expected = [0u32; WIDTH * HEIGHT];
+    // SAFETY: Both buffers hold the `pitch * height` bytes `info` describes and outlive
+    // the consoles.
+    let (surface, plain) = unsafe {
+        (
+            RawSurface::new(pixels.as_mut_ptr().cast(), info),
+            RawSurface::new(expected.as_mut_ptr().cast(), info),
+        )
+    };
+    let mut console = BasicConsole::new(surface).with_font(Font::EMBEDDED);
+    let mut reference = BasicConsole::new(plain).with_font(Font::EMBEDDED);
+    console.clear();
+    reference.clear();
+
+    // The cursor goes over a glyph, so both colors get flipped.
+    console.write_str("A\r");
+    reference.write_str("A\r");
+    let before = pixels;
+    console.draw_cursor(true);
+    let drawn = pixels != before && console.is_cursor_visible();
+    console.tick_cursor();
+    let restored = pixels == before && !console.is_cursor_visible();
+
+    console.draw_cursor(true);
+    console.write_str("Bc");
+    reference.write_str("Bc");
+    let rewritten = pixels == expected && !console.is_cursor_visible();
+
+    drawn && restored && rewritten
+}
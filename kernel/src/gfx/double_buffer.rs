@@ -0,0 +1,65 @@
+//! Flicker-free rendering through an off-screen back buffer.
+
+use core::ptr;
+
+use limine::LimineFramebuffer;
+
+use super::LimineFramebufferExt;
+
+/// Pairs a framebuffer with a back buffer of the same dimensions.
+///
+/// Drawing happens in the back buffer, which has no padding between scanlines, and
+/// [`swap`](Self::swap) copies it to the framebuffer in one go, row by row so the
+/// framebuffer's pitch is respected.
+pub struct DoubleBuffer<'a> {
+    front: &'a mut LimineFramebuffer,
+    back: &'static mut [u8],
+    row_size: usize,
+}
+
+impl<'a> DoubleBuffer<'a> {
+    /// ## Panics
+    ///
+    /// Panics if `back` is smaller than `width * height` pixels of the framebuffer.
+    pub fn new(fb: &'a mut LimineFramebuffer, back: &'static mut [u8]) -> Self {
+        let row_size = fb.width as usize * fb.bytes_per_pixel();
+        let size = row_size * fb.height as usize;
+        assert!(
+            back.len() >= size,
+            "back buffer holds {} bytes but the framebuffer needs {}",
+            back.len(),
+            size
+        );
+
+        Self {
+            front: fb,
+            back: &mut back[..size],
+            row_size,
+        }
+    }
+
+    /// The back buffer, one tightly packed scanline of `width` pixels after another.
+    pub fn back_mut(&mut self) -> &mut [u8] {
+        self.back
+    }
+
+    /// The number of bytes per scanline in the back buffer.
+    pub fn back_pitch(&self) -> usize {
+        self.row_size
+    }
+
+    /// Copies the back buffer to the framebuffer.
+    pub fn swap(&mut self) {
+        let Some(front) = self.front.address.as_ptr() else {
+            return;
+        };
+        if self.row_size == 0 {
+            return;
+        }
+        let pitch = self.front.pitch as usize;
+
+        for (y, row) in self.back.chunks_exact(self.row_size).enumerate() {
+            unsafe { ptr::copy_nonoverlapping(row.as_ptr(), front.add(y * pitch), row.len()) };
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Console fonts.
+//!
+//! [`Font`] describes any bitmap font the console can draw with: the embedded 8x8 font
+//! or a PSF font parsed by [`psf`](super::psf). The embedded glyphs are eight rows of
+//! eight pixels, one byte per row with the least significant bit being the leftmost
+//! pixel. They are from the public domain `font8x8` set.
+
+pub const GLYPH_WIDTH: u64 = 8;
+pub const GLYPH_HEIGHT: u64 = 8;
+
+const FIRST: char = ' ';
+const LAST: char = '~';
+
+/// Returns the embedded glyph for `c`, or the glyph for `?` if the font doesn't cover it.
+pub fn glyph(c: char) -> &'static [u8; 8] {
+    if (FIRST..=LAST).contains(&c) {
+        &FONT8X8[c as usize - FIRST as usize]
+    } else {
+        &FONT8X8['?' as usize - FIRST as usize]
+    }
+}
+
+/// How code points map to glyph indices.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Mapping {
+    /// The embedded font, which starts at [`FIRST`].
+    Embedded,
+    /// Glyph `n` is code point `n`.
+    Identity,
+    /// A PSF1 unicode table: little-endian UCS-2 entries.
+    Psf1(&'static [u8]),
+    /// A PSF2 unicode table: UTF-8 entries.
+    Psf2(&'static [u8]),
+}
+
+/// A bitmap font of fixed-size glyphs.
+#[derive(Clone, Copy, Debug)]
+pub struct Font {
+    pub(super) width: u64,
+    pub(super) height: u64,
+    pub(super) glyph_count: usize,
+    /// `glyph_count` glyphs of `height` rows each, every row padded to whole bytes.
+    pub(super) glyphs: &'static [u8],
+    pub(super) mapping: Mapping,
+}
+
+impl Font {
+    /// The embedded 8x8 font, covering printable ASCII.
+    pub const EMBEDDED: Self = Self {
+        width: GLYPH_WIDTH,
+        height: GLYPH_HEIGHT,
+        glyph_count: FONT8X8.len(),
+        glyphs: FONT8X8.as_flattened(),
+        mapping: Mapping::Embedded,
+    };
+
+    /// The font given with `font=<tag>` on the command line, found as the module with
+    /// that cmdline, or [`EMBEDDED`](Self::EMBEDDED) if there is none or it can't be
+    /// parsed.
+    pub fn boot_font() -> Self {
+        #[cfg(all(feature = "kernel-file", feature = "modules"))]
+        if let Some(font) = super::psf::load_from_cmdline() {
+            return font;
+        }
+        Self::EMBEDDED
+    }
+
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn bytes_per_row(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    /// Returns the glyph for `c`, falling back to the glyph for the replacement character
+    /// U+FFFD, then to the one for `?` and then to the font's first glyph.
+    pub fn glyph(&self, c: char) -> Glyph {
+        let index = self
+            .index(c)
+            .or_else(|| self.index(char::REPLACEMENT_CHARACTER))
+            .or_else(|| self.index('?'))
+            .unwrap_or(0);
+        let size = self.bytes_per_row() * self.height as usize;
+
+        Glyph {
+            rows: &self.glyphs[index * size..(index + 1) * size],
+            bytes_per_row: self.bytes_per_row(),
+            lsb_first: matches!(self.mapping, Mapping::Embedded),
+        }
+    }
+
+    fn index(&self, c: char) -> Option<usize> {
+        let index = match self.mapping {
+            Mapping::Embedded => (FIRST..=LAST)
+                .contains(&c)
+                .then(|| c as usize - FIRST as usize),
+            Mapping::Identity => Some(c as usize),
+            Mapping::Psf1(table) => psf1_lookup(table, c),
+            Mapping::Psf2(table) => psf2_lookup(table, c),
+        };
+        index.filter(|&index| index < self.glyph_count)
+    }
+}
+
+/// Finds the glyph whose PSF1 table entry lists `c`. Each glyph's entry is a list of
+/// code points ending in `0xffff`, where `0xfffe` starts combining sequences, which
+/// are skipped.
+fn psf1_lookup(table: &[u8], c: char) -> Option<usize> {
+    let c = u16::try_from(c as u32).ok()?;
+    let mut glyph = 0;
+    let mut in_sequence = false;
+
+    for unit in table.chunks_exact(2) {
+        match u16::from_le_bytes([unit[0], unit[1]]) {
+            0xffff => {
+                glyph += 1;
+                in_sequence = false;
+            }
+            0xfffe => in_sequence = true,
+            unit if unit == c && !in_sequence => return Some(glyph),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Finds the glyph whose PSF2 table entry lists `c`. Each glyph's entry is UTF-8 text
+/// ending in `0xff`, where `0xfe` starts combining sequences, which are skipped.
+fn psf2_lookup(table: &[u8], c: char) -> Option<usize> {
+    let mut encoded = [0; 4];
+    let encoded = c.encode_utf8(&mut encoded).as_bytes();
+
+    for (glyph, entry) in table.split(|&b| b == 0xff).enumerate() {
+        let single = entry.split(|&b| b == 0xfe).next().unwrap_or(&[]);
+        // Every code point's encoding starts with a byte that isn't a continuation
+        // byte, so matching at those positions can't produce false positives.
+        let mut offset = 0;
+        while offset < single.len() {
+            if single[offset..].starts_with(encoded) {
+                return Some(glyph);
+            }
+            offset += 1;
+            while single.get(offset).is_some_and(|&b| b & 0xc0 == 0x80) {
+                offset += 1;
+            }
+        }
+    }
+    None
+}
+
+/// One glyph of a [`Font`].
+#[derive(Clone, Copy, Debug)]
+pub struct Glyph {
+    rows: &'static [u8],
+    bytes_per_row: usize,
+    /// Whether the leftmost pixel is the least significant bit of a byte, rather than
+    /// the most significant one as in PSF fonts.
+    lsb_first: bool,
+}
+
+impl Glyph {
+    /// Whether the pixel at `(x, y)` within the glyph is set.
+    pub fn pixel(&self, x: u64, y: u64) -> bool {
+        let byte = self.rows[y as usize * self.bytes_per_row + x as usize / 8];
+        let bit = if self.lsb_first { x % 8 } else { 7 - x % 8 };
+        byte >> bit & 1 != 0
+    }
+}
+
+#[rustfmt::skip]
+static FONT8X8: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x18, 0x3c, 0x3c, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7f, 0x36, 0x7f, 0x36, 0x36, 0x00], // '#'
+    [0x0c, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x0c, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0c, 0x66, 0x63, 0x00], // '%'
+    [0x1c, 0x36, 0x1c, 0x6e, 0x3b, 0x33, 0x6e, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x18, 0x0c, 0x06, 0x06, 0x06, 0x0c, 0x18, 0x00], // '('
+    [0x06, 0x0c, 0x18, 0x18, 0x18, 0x0c, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3c, 0xff, 0x3c, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0c, 0x0c, 0x3f, 0x0c, 0x0c, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0c, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3e, 0x63, 0x73, 0x7b, 0x6f, 0x67, 0x3e, 0x00], // '0'
+    [0x0c, 0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x3f, 0x00], // '1'
+    [0x1e, 0x33, 0x30, 0x1c, 0x06, 0x33, 0x3f, 0x00], // '2'
+    [0x1e, 0x33, 0x30, 0x1c, 0x30, 0x33, 0x1e, 0x00], // '3'
+    [0x38, 0x3c, 0x36, 0x33, 0x7f, 0x30, 0x78, 0x00], // '4'
+    [0x3f, 0x03, 0x1f, 0x30, 0x30, 0x33, 0x1e, 0x00], // '5'
+    [0x1c, 0x06, 0x03, 0x1f, 0x33, 0x33, 0x1e, 0x00], // '6'
+    [0x3f, 0x33, 0x30, 0x18, 0x0c, 0x0c, 0x0c, 0x00], // '7'
+    [0x1e, 0x33, 0x33, 0x1e, 0x33, 0x33, 0x1e, 0x00], // '8'
+    [0x1e, 0x33, 0x33, 0x3e, 0x30, 0x18, 0x0e, 0x00], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x00, 0x0c, 0x0c, 0x06], // ';'
+    [0x18, 0x0c, 0x06, 0x03, 0x06, 0x0c, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3f, 0x00, 0x00, 0x3f, 0x00, 0x00], // '='
+    [0x06, 0x0c, 0x18, 0x30, 0x18, 0x0c, 0x06, 0x00], // '>'
+    [0x1e, 0x33, 0x30, 0x18, 0x0c, 0x00, 0x0c, 0x00], // '?'
+    [0x3e, 0x63, 0x7b, 0x7b, 0x7b, 0x03, 0x1e, 0x00], // '@'
+    [0x0c, 0x1e, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x00], // 'A'
+    [0x3f, 0x66, 0x66, 0x3e, 0x66, 0x66, 0x3f, 0x00], // 'B'
+    [0x3c, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3c, 0x00], // 'C'
+    [0x1f, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1f, 0x00], // 'D'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x46, 0x7f, 0x00], // 'E'
+    [0x7f, 0x46, 0x16, 0x1e, 0x16, 0x06, 0x0f, 0x00], // 'F'
+    [0x3c, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7c, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3f, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1e, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0f, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7f, 0x00], // 'L'
+    [0x63, 0x77, 0x7f, 0x7f, 0x6b, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6f, 0x7b, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1c, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1c, 0x00], // 'O'
+    [0x3f, 0x66, 0x66, 0x3e, 0x06, 0x06, 0x0f, 0x00], // 'P'
+    [0x1e, 0x33, 0x33, 0x33, 0x3b, 0x1e, 0x38, 0x00], // 'Q'
+    [0x3f, 0x66, 0x66, 0x3e, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1e, 0x33, 0x07, 0x0e, 0x38, 0x33, 0x1e, 0x00], // 'S'
+    [0x3f, 0x2d, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3f, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6b, 0x7f, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1c, 0x1c, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1e, 0x0c, 0x0c, 0x1e, 0x00], // 'Y'
+    [0x7f, 0x63, 0x31, 0x18, 0x4c, 0x66, 0x7f, 0x00], // 'Z'
+    [0x1e, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1e, 0x00], // '['
+    [0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x40, 0x00], // '\\'
+    [0x1e, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1e, 0x00], // ']'
+    [0x08, 0x1c, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff], // '_'
+    [0x0c, 0x0c, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1e, 0x30, 0x3e, 0x33, 0x6e, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3e, 0x66, 0x66, 0x3b, 0x00], // 'b'
+    [0x00, 0x00, 0x1e, 0x33, 0x03, 0x33, 0x1e, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3e, 0x33, 0x33, 0x6e, 0x00], // 'd'
+    [0x00, 0x00, 0x1e, 0x33, 0x3f, 0x03, 0x1e, 0x00], // 'e'
+    [0x1c, 0x36, 0x06, 0x0f, 0x06, 0x06, 0x0f, 0x00], // 'f'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'g'
+    [0x07, 0x06, 0x36, 0x6e, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0c, 0x00, 0x0e, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1e], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1e, 0x36, 0x67, 0x00], // 'k'
+    [0x0e, 0x0c, 0x0c, 0x0c, 0x0c, 0x0c, 0x1e, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7f, 0x7f, 0x6b, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1f, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1e, 0x33, 0x33, 0x33, 0x1e, 0x00], // 'o'
+    [0x00, 0x00, 0x3b, 0x66, 0x66, 0x3e, 0x06, 0x0f], // 'p'
+    [0x00, 0x00, 0x6e, 0x33, 0x33, 0x3e, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3b, 0x6e, 0x66, 0x06, 0x0f, 0x00], // 'r'
+    [0x00, 0x00, 0x3e, 0x03, 0x1e, 0x30, 0x1f, 0x00], // 's'
+    [0x08, 0x0c, 0x3e, 0x0c, 0x0c, 0x2c, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6e, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1e, 0x0c, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6b, 0x7f, 0x7f, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1c, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3e, 0x30, 0x1f], // 'y'
+    [0x00, 0x00, 0x3f, 0x19, 0x0c, 0x26, 0x3f, 0x00], // 'z'
+    [0x38, 0x0c, 0x0c, 0x07, 0x0c, 0x0c, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0c, 0x0c, 0x38, 0x0c, 0x0c, 0x07, 0x00], // '}'
+    [0x6e, 0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
@@ -0,0 +1,126 @@
+//! The kernel log on the framebuffer, with scrollback.
+//!
+//! Once [`init`] ran, everything printed is also drawn on a [`BasicConsole`].
+//! Shift+PageUp switches to a view of older output from the log ring and scrolls back
+//! by half a screen, Shift+PageDown scrolls forward again. While the view is shown, new
+//! output is only kept in the ring. Scrolling forward past the end or pressing any
+//! other key returns to live output, with what was missed redrawn.
+//!
+//! Key presses are queued by the keyboard interrupt and handled by [`process_pending`],
+//! which the idle loop calls, so nothing is redrawn in interrupt context.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use limine::LimineFramebuffer;
+use spin::{Mutex, MutexGuard};
+
+use super::console::BasicConsole;
+use super::theme::Theme;
+use crate::print;
+
+static CONSOLE: Mutex<Option<BasicConsole<&'static LimineFramebuffer>>> = Mutex::new(None);
+/// How many lines before the newest the bottom of the view is, 0 for live output.
+static OFFSET: AtomicUsize = AtomicUsize::new(0);
+/// Set when live output couldn't be drawn because the console was busy.
+static MISSED: AtomicBool = AtomicBool::new(false);
+
+/// Starts drawing the log on `framebuffer`, showing what was printed so far.
+pub fn init(framebuffer: &'static LimineFramebuffer) {
+    #[cfg(feature = "kernel-file")]
+    let theme = Theme::from_cmdline();
+    #[cfg(not(feature = "kernel-file"))]
+    let theme = Theme::default();
+
+    *CONSOLE.lock() = Some(BasicConsole::with_theme(framebuffer, theme));
+    redraw(0);
+    #[cfg(target_arch = "x86_64")]
+    crate::arch::x86_64::keyboard::init();
+}
+
+/// Keeps the console from drawing until the guard is dropped, for reading the screen
+/// without catching a line half drawn. What is printed meanwhile is drawn afterwards.
+pub(crate) fn hold() -> MutexGuard<'static, Option<BasicConsole<&'static LimineFramebuffer>>> {
+    CONSOLE.lock()
+}
+
+/// Draws live output, unless the scrollback view is shown.
+pub(crate) fn write_fmt(args: fmt::Arguments) {
+    if OFFSET.load(Ordering::Relaxed) != 0 {
+        return;
+    }
+    // The console is busy when an interrupt handler prints during a redraw.
+    match CONSOLE.try_lock() {
+        Some(mut console) => {
+            if let Some(console) = console.as_mut() {
+                console.write_fmt(args).ok();
+            }
+        }
+        None => MISSED.store(true, Ordering::Relaxed),
+    }
+}
+
+/// Handles queued key presses and redraws output that was missed.
+pub fn process_pending() {
+    #[cfg(target_arch = "x86_64")]
+    while let Some(event) = crate::arch::x86_64::keyboard::take_event() {
+        use crate::arch::x86_64::keyboard::Key;
+
+        let half_screen = CONSOLE
+            .lock()
+            .as_ref()
+            .map_or(1, |console| (console.rows() as usize / 2).max(1));
+        let offset = OFFSET.load(Ordering::Relaxed);
+        match (event.key, event.shift) {
+            (Key::PageUp, true) => scroll_to(offset + half_screen),
+            (Key::PageDown, true) => scroll_to(offset.saturating_sub(half_screen)),
+            _ if offset != 0 => scroll_to(0),
+            _ => {}
+        }
+    }
+
+    if MISSED.swap(false, Ordering::Relaxed) && OFFSET.load(Ordering::Relaxed) == 0 {
+        redraw(0);
+    }
+}
+
+/// Shows the view ending `offset` lines before the newest, clamped so it starts no
+/// earlier than the oldest line. 0 returns to live output.
+fn scroll_to(offset: usize) {
+    let rows = CONSOLE
+        .lock()
+        .as_ref()
+        .map_or(0, |console| console.rows() as usize);
+    let max_offset = print::log_line_count().saturating_sub(rows);
+    let offset = offset.min(max_offset);
+    if offset != OFFSET.load(Ordering::Relaxed) {
+        OFFSET.store(offset, Ordering::Relaxed);
+        redraw(offset);
+    }
+}
+
+/// Redraws the whole console from the log ring, with the line `offset` lines before the
+/// newest at the bottom. Lines longer than the console are cut off rather than wrapped,
+/// so every line takes exactly one row.
+fn redraw(offset: usize) {
+    let mut console = CONSOLE.lock();
+    let Some(console) = console.as_mut() else {
+        return;
+    };
+    let (rows, columns) = (console.rows() as usize, console.columns() as usize);
+    console.clear();
+
+    let mut first = true;
+    print::with_log_lines(offset, rows, |line| {
+        if !first {
+            console.write_char('\n');
+        }
+        first = false;
+        for byte in line.bytes().take(columns) {
+            console.write_char(match byte {
+                b' '..=b'~' => byte as char,
+                _ => '?',
+            });
+        }
+    });
+}
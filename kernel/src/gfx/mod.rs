@@ -0,0 +1,812 @@
+//! Drawing on Limine framebuffers.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::{fmt, ptr, slice};
+
+use limine::LimineFramebuffer;
+
+use self::font::Font;
+use crate::crypto::fnv;
+
+pub mod console;
+pub mod double_buffer;
+pub mod font;
+#[cfg(feature = "log-console")]
+pub mod log_console;
+pub mod panic;
+pub mod psf;
+pub mod screenshot;
+pub mod surface;
+pub mod theme;
+pub mod widgets;
+
+#[cfg(feature = "framebuffer")]
+pub use self::screenshot::dump_screenshot;
+
+/// How many columns [`LimineFramebufferExt::write_char`] puts between tab stops.
+pub const TAB_COLUMNS: u64 = 8;
+
+/// An RGB color, independent of the framebuffer's pixel layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FramebufferColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl FramebufferColor {
+    pub const BLACK: Self = Self::from_hex(0x000000);
+    pub const WHITE: Self = Self::from_hex(0xffffff);
+    pub const RED: Self = Self::from_hex(0xff0000);
+    pub const GREEN: Self = Self::from_hex(0x00ff00);
+    pub const BLUE: Self = Self::from_hex(0x0000ff);
+    pub const YELLOW: Self = Self::from_hex(0xffff00);
+    pub const MAGENTA: Self = Self::from_hex(0xff00ff);
+    pub const CYAN: Self = Self::from_hex(0x00ffff);
+    pub const GRAY: Self = Self::from_hex(0x808080);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Unpacks `0xRRGGBB`. Bits above the low 24 are ignored.
+    pub const fn from_hex(rgb: u32) -> Self {
+        Self::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    /// Packs the color as `0xRRGGBB`.
+    pub const fn to_hex(self) -> u32 {
+        (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+}
+
+/// How pixels are stored to framebuffer memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Every pixel is a volatile store, so none are merged, reordered or dropped by the
+    /// compiler. The default, as the framebuffer is device memory.
+    Volatile,
+    /// Plain stores, which let [`fill_rect`](LimineFramebufferExt::fill_rect) fill whole
+    /// rows at once. Fine on framebuffers that are ordinary RAM or write-combined.
+    Plain,
+}
+
+/// Whether a framebuffer address can be written through as is, see
+/// [`LimineFramebufferExt::address_type`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramebufferAddressType {
+    /// A physical address, which has to be mapped before the framebuffer is drawn on.
+    Physical,
+    /// An address in the higher half direct map, ready to use.
+    HhdmMapped,
+}
+
+static PLAIN_WRITES: AtomicBool = AtomicBool::new(false);
+
+/// Selects how every framebuffer is written from now on.
+pub fn set_write_mode(mode: WriteMode) {
+    PLAIN_WRITES.store(mode == WriteMode::Plain, Ordering::Relaxed);
+}
+
+pub fn write_mode() -> WriteMode {
+    if PLAIN_WRITES.load(Ordering::Relaxed) {
+        WriteMode::Plain
+    } else {
+        WriteMode::Volatile
+    }
+}
+
+/// Builds a framebuffer of the geometry and pixel format `info`, as the bootloader would
+/// report it, for exercising drawing code on a plain buffer. It has no EDID.
+///
+/// ## Safety
+///
+/// `address` must be null, which makes drawing do nothing, or point to `info.pitch *
+/// info.height` bytes that stay valid and writable for as long as the framebuffer is
+/// used.
+pub const unsafe fn framebuffer_from_parts(
+    address: *mut u8,
+    info: FramebufferInfo,
+) -> LimineFramebuffer {
+    LimineFramebuffer {
+        // SAFETY: `LiminePtr` is a transparent wrapper around a nullable pointer.
+        address: unsafe { core::mem::transmute::<*mut u8, limine::LiminePtr<u8>>(address) },
+        width: info.width,
+        height: info.height,
+        pitch: info.pitch,
+        bpp: info.bpp,
+        // RGB, the only memory model there is.
+        memory_model: 1,
+        red_mask_size: info.red_mask_size,
+        red_mask_shift: info.red_mask_shift,
+        green_mask_size: info.green_mask_size,
+        green_mask_shift: info.green_mask_shift,
+        blue_mask_size: info.blue_mask_size,
+        blue_mask_shift: info.blue_mask_shift,
+        reserved: [0; 7],
+        edid_size: 0,
+        // SAFETY: As above.
+        edid: unsafe { core::mem::transmute::<*mut u8, limine::LiminePtr<u8>>(ptr::null_mut()) },
+    }
+}
+
+/// Scales an 8-bit channel value down to a channel of `size` bits.
+fn scale_channel(value: u8, size: u8) -> u32 {
+    match size {
+        0 => 0,
+        1..=8 => value as u32 >> (8 - size),
+        _ => (value as u32) << (size - 8),
+    }
+}
+
+/// Expands a channel of `size` bits back to an 8-bit value, mapping its maximum to 0xff.
+fn expand_channel(value: u32, size: u8) -> u8 {
+    match size {
+        0 => 0,
+        1..=8 => {
+            let max = (1 << size) - 1;
+            ((value & max) * 0xff / max) as u8
+        }
+        _ => (value >> (size - 8)) as u8,
+    }
+}
+
+/// The geometry and pixel format of a framebuffer, without its address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    pub width: u64,
+    pub height: u64,
+    /// Bytes per scanline.
+    pub pitch: u64,
+    pub bpp: u16,
+    pub red_mask_size: u8,
+    pub red_mask_shift: u8,
+    pub green_mask_size: u8,
+    pub green_mask_shift: u8,
+    pub blue_mask_size: u8,
+    pub blue_mask_shift: u8,
+}
+
+impl FramebufferInfo {
+    /// 32 bpp XRGB without padding between scanlines, the format of most framebuffers,
+    /// for drawing into plain arrays.
+    pub const fn xrgb8888(width: u64, height: u64) -> Self {
+        Self {
+            width,
+            height,
+            pitch: 4 * width,
+            bpp: 32,
+            red_mask_size: 8,
+            red_mask_shift: 16,
+            green_mask_size: 8,
+            green_mask_shift: 8,
+            blue_mask_size: 8,
+            blue_mask_shift: 0,
+        }
+    }
+
+    #[inline]
+    pub fn bytes_per_pixel(&self) -> usize {
+        (self.bpp as usize).div_ceil(8)
+    }
+
+    /// Packs `color` into a raw pixel value according to the channel masks.
+    pub fn encode(&self, color: FramebufferColor) -> u32 {
+        scale_channel(color.r, self.red_mask_size) << self.red_mask_shift
+            | scale_channel(color.g, self.green_mask_size) << self.green_mask_shift
+            | scale_channel(color.b, self.blue_mask_size) << self.blue_mask_shift
+    }
+
+    /// Unpacks a raw pixel value into its RGB components, the inverse of
+    /// [`encode`](Self::encode).
+    pub fn decode(&self, raw: u32) -> FramebufferColor {
+        let channel = |shift: u8, size: u8| {
+            let value = raw.checked_shr(shift as u32).unwrap_or(0);
+            let mask = 1u32
+                .checked_shl(size as u32)
+                .map_or(u32::MAX, |bit| bit - 1);
+            expand_channel(value & mask, size)
+        };
+
+        FramebufferColor::new(
+            channel(self.red_mask_shift, self.red_mask_size),
+            channel(self.green_mask_shift, self.green_mask_size),
+            channel(self.blue_mask_shift, self.blue_mask_size),
+        )
+    }
+
+    /// The offset of the pixel at `(x, y)` from the start of the framebuffer, if it is in
+    /// bounds.
+    fn pixel_offset(&self, x: u64, y: u64) -> Option<usize> {
+        (x < self.width && y < self.height)
+            .then(|| y as usize * self.pitch as usize + x as usize * self.bytes_per_pixel())
+    }
+}
+
+/// Returned when a rectangle doesn't fit in the framebuffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutOfBounds;
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("rectangle out of framebuffer bounds")
+    }
+}
+
+/// Pixel level drawing on a [`LimineFramebuffer`].
+///
+/// Coordinates outside the framebuffer are ignored rather than treated as errors, so
+/// callers can draw partially visible shapes.
+pub trait LimineFramebufferExt {
+    fn bytes_per_pixel(&self) -> usize;
+
+    /// A pointer to the first byte of the pixel at `(x, y)`, or `None` if it lies outside
+    /// the framebuffer or the framebuffer has no address.
+    fn pixel_at(&self, x: u64, y: u64) -> Option<*mut u8>;
+
+    /// Like [`pixel_at`](LimineFramebufferExt::pixel_at), as a pointer to the whole pixel
+    /// of a 32 bits per pixel framebuffer. `None` for other depths as well.
+    fn pixel_word_at_32(&self, x: u64, y: u64) -> Option<*mut u32>;
+
+    /// Copies out the geometry and pixel format.
+    fn info(&self) -> FramebufferInfo;
+
+    /// A surface drawing to this framebuffer that doesn't borrow it.
+    fn raw_surface(&self) -> Option<surface::RawSurface>;
+
+    /// Whether the address lies in the HHDM starting at `hhdm_offset`, or has to be
+    /// mapped first. A missing address counts as physical.
+    fn address_type(&self, hhdm_offset: u64) -> FramebufferAddressType;
+
+    /// The framebuffer's address as an array of 16-bit cells, for early code written
+    /// against the VGA text buffer at `0xb8000`. `None` unless the framebuffer is 16 bits
+    /// per pixel and at least 80 by 25 pixels, the size of that buffer in cells.
+    ///
+    /// The cells are pixels, so what such code writes shows up as colored dots rather
+    /// than text. Rows are [`pitch`](LimineFramebuffer::pitch) bytes apart, not 160.
+    fn as_vga_compat_buffer(&self) -> Option<*mut u16>;
+
+    /// Packs `color` into a raw pixel value according to the framebuffer's channel masks.
+    fn encode(&self, color: FramebufferColor) -> u32;
+
+    /// Unpacks a raw pixel value into its RGB components, the inverse of [`encode`].
+    ///
+    /// [`encode`]: LimineFramebufferExt::encode
+    fn decode(&self, raw: u32) -> FramebufferColor;
+
+    /// Writes the raw pixel value `raw` at `(x, y)`.
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32);
+
+    /// Reads the raw pixel value at `(x, y)`, or `None` if it lies outside the framebuffer.
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32>;
+
+    fn put_pixel(&self, x: u64, y: u64, color: FramebufferColor) {
+        self.put_raw_pixel(x, y, self.encode(color));
+    }
+
+    fn get_pixel(&self, x: u64, y: u64) -> Option<FramebufferColor> {
+        self.get_raw_pixel(x, y).map(|raw| self.decode(raw))
+    }
+
+    /// A [FNV-1a](crate::crypto::fnv) hash of the visible pixels, row by row, leaving out
+    /// the padding between rows. Renders that look the same hash the same.
+    fn checksum(&self) -> u64;
+
+    /// Fills the rectangle of `width` by `height` pixels at `(x, y)` with `color`.
+    fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor);
+
+    /// Draws `c` with its top left corner at `(x, y)` and returns where the next character
+    /// goes. `'\n'` moves to the start of the next line and `'\t'` to the next multiple of
+    /// [`TAB_COLUMNS`] columns, both without drawing. Characters the font doesn't cover are
+    /// drawn as its replacement character.
+    ///
+    /// Returns `None`, drawing nothing, if the glyph doesn't fit in the framebuffer at
+    /// `(x, y)`.
+    fn write_char(
+        &self,
+        c: char,
+        x: u64,
+        y: u64,
+        foreground: FramebufferColor,
+        background: FramebufferColor,
+        font: &Font,
+    ) -> Option<(u64, u64)>;
+
+    /// Blends the color `(r, g, b)` with opacity `a` over the pixel at `(x, y)`.
+    fn alpha_blend_pixel(&self, x: u64, y: u64, r: u8, g: u8, b: u8, a: u8) {
+        let color = match a {
+            0 => return,
+            0xff => FramebufferColor::new(r, g, b),
+            _ => {
+                let Some(dst) = self.get_pixel(x, y) else {
+                    return;
+                };
+                FramebufferColor::new(
+                    blend_channel(r, dst.r, a),
+                    blend_channel(g, dst.g, a),
+                    blend_channel(b, dst.b, a),
+                )
+            }
+        };
+        self.put_pixel(x, y, color);
+    }
+
+    /// Blends `color` with opacity `alpha` over the rectangle of `width` by `height`
+    /// pixels at `(x, y)`, clipped to the framebuffer.
+    fn blend_rect(
+        &self,
+        x: u64,
+        y: u64,
+        width: u64,
+        height: u64,
+        color: FramebufferColor,
+        alpha: u8,
+    );
+
+    /// Copies the rectangle of `width` by `height` pixels at `(src_x, src_y)` to
+    /// `(dst_x, dst_y)`. The rectangles may overlap. Unlike the drawing functions this
+    /// doesn't clip: nothing is copied if either rectangle sticks out of the framebuffer.
+    fn move_region(
+        &self,
+        src_x: u64,
+        src_y: u64,
+        dst_x: u64,
+        dst_y: u64,
+        width: u64,
+        height: u64,
+    ) -> Result<(), OutOfBounds>;
+
+    /// Blends an image of `src_width` by `src_height` pixels over the framebuffer with
+    /// its top left corner at `(x, y)`.
+    ///
+    /// `src` holds 32-bit little-endian ARGB pixels, i.e. the bytes `b, g, r, a`, with
+    /// `src_pitch` bytes between the starts of consecutive rows. Rows or pixels that
+    /// `src` is too short for, or whose position doesn't fit in a `u64`, are skipped.
+    fn alpha_blend_region(
+        &self,
+        x: u64,
+        y: u64,
+        src: &[u8],
+        src_width: u64,
+        src_height: u64,
+        src_pitch: u64,
+    ) {
+        for row in 0..src_height {
+            let Some(line) = row
+                .checked_mul(src_pitch)
+                .and_then(|start| src.get(start as usize..))
+            else {
+                break;
+            };
+            let Some(y) = y.checked_add(row) else {
+                break;
+            };
+            for (column, pixel) in line.chunks_exact(4).take(src_width as usize).enumerate() {
+                let Some(x) = x.checked_add(column as u64) else {
+                    break;
+                };
+                let &[b, g, r, a] = pixel else {
+                    unreachable!();
+                };
+                self.alpha_blend_pixel(x, y, r, g, b, a);
+            }
+        }
+    }
+}
+
+/// Computes `src * a / 255 + dst * (255 - a) / 255`, rounded to nearest.
+fn blend_channel(src: u8, dst: u8, a: u8) -> u8 {
+    let a = a as u32;
+    ((src as u32 * a + dst as u32 * (0xff - a) + 0x7f) / 0xff) as u8
+}
+
+impl LimineFramebufferExt for LimineFramebuffer {
+    #[inline]
+    fn bytes_per_pixel(&self) -> usize {
+        (self.bpp as usize).div_ceil(8)
+    }
+
+    #[inline(always)]
+    fn pixel_at(&self, x: u64, y: u64) -> Option<*mut u8> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let base = self.address.as_ptr()?;
+        let offset = y as usize * self.pitch as usize + x as usize * self.bytes_per_pixel();
+        // SAFETY: The pixel is in bounds, and the bootloader maps the whole framebuffer.
+        Some(unsafe { base.add(offset) })
+    }
+
+    #[inline(always)]
+    fn pixel_word_at_32(&self, x: u64, y: u64) -> Option<*mut u32> {
+        if self.bpp != 32 {
+            return None;
+        }
+        self.pixel_at(x, y).map(|pixel| pixel as *mut u32)
+    }
+
+    fn info(&self) -> FramebufferInfo {
+        FramebufferInfo {
+            width: self.width,
+            height: self.height,
+            pitch: self.pitch,
+            bpp: self.bpp,
+            red_mask_size: self.red_mask_size,
+            red_mask_shift: self.red_mask_shift,
+            green_mask_size: self.green_mask_size,
+            green_mask_shift: self.green_mask_shift,
+            blue_mask_size: self.blue_mask_size,
+            blue_mask_shift: self.blue_mask_shift,
+        }
+    }
+
+    fn raw_surface(&self) -> Option<surface::RawSurface> {
+        let base = self.address.as_ptr()?;
+        // SAFETY: The bootloader maps the whole framebuffer at its address for good.
+        Some(unsafe { surface::RawSurface::new(base, self.info()) })
+    }
+
+    fn address_type(&self, hhdm_offset: u64) -> FramebufferAddressType {
+        match self.address.as_ptr() {
+            Some(address) if address as u64 >= hhdm_offset => FramebufferAddressType::HhdmMapped,
+            _ => FramebufferAddressType::Physical,
+        }
+    }
+
+    fn as_vga_compat_buffer(&self) -> Option<*mut u16> {
+        if self.width < 80 || self.height < 25 || self.bpp != 16 {
+            return None;
+        }
+        self.address.as_ptr().map(|address| address as *mut u16)
+    }
+
+    fn encode(&self, color: FramebufferColor) -> u32 {
+        self.info().encode(color)
+    }
+
+    fn decode(&self, raw: u32) -> FramebufferColor {
+        self.info().decode(raw)
+    }
+
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32) {
+        let Some(pixel) = self.pixel_at(x, y) else {
+            return;
+        };
+        unsafe { store_pixel(pixel, raw, self.bytes_per_pixel()) };
+    }
+
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32> {
+        let pixel = self.pixel_at(x, y)?;
+        Some(unsafe { load_pixel(pixel, self.bytes_per_pixel()) })
+    }
+
+    fn checksum(&self) -> u64 {
+        let Some(base) = self.address.as_ptr() else {
+            return fnv::OFFSET_BASIS;
+        };
+        let row_len = self.width as usize * self.bytes_per_pixel();
+        (0..self.height).fold(fnv::OFFSET_BASIS, |hash, y| {
+            // SAFETY: Each row starts `pitch` bytes after the previous one and holds
+            // `width` pixels.
+            let row =
+                unsafe { slice::from_raw_parts(base.add((y * self.pitch) as usize), row_len) };
+            fnv::fnv1a_update(hash, row)
+        })
+    }
+
+    fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor) {
+        let raw = self.encode(color);
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let word_rows = write_mode() == WriteMode::Plain && self.bytes_per_pixel() == 4;
+
+        for y in y..y_end {
+            match self.pixel_at(x, y) {
+                // Aligned rows of 32-bit pixels are filled a word at a time.
+                Some(row) if word_rows && (row as *mut u32).is_aligned() => unsafe {
+                    slice::from_raw_parts_mut(row as *mut u32, (x_end - x) as usize).fill(raw)
+                },
+                _ => {
+                    for x in x..x_end {
+                        self.put_raw_pixel(x, y, raw);
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_char(
+        &self,
+        c: char,
+        x: u64,
+        y: u64,
+        foreground: FramebufferColor,
+        background: FramebufferColor,
+        font: &Font,
+    ) -> Option<(u64, u64)> {
+        let (width, height) = (font.width(), font.height());
+        // Control characters only move, so they work at the edges too.
+        match c {
+            '\n' => return Some((0, y.checked_add(height)?)),
+            '\t' => {
+                let tab = width * TAB_COLUMNS;
+                return Some(((x / tab + 1) * tab, y));
+            }
+            _ => {}
+        }
+
+        if x.checked_add(width)? > self.width || y.checked_add(height)? > self.height {
+            return None;
+        }
+
+        let raw_foreground = self.encode(foreground);
+        let raw_background = self.encode(background);
+        let glyph = font.glyph(c);
+        for dy in 0..height {
+            for dx in 0..width {
+                let raw = if glyph.pixel(dx, dy) {
+                    raw_foreground
+                } else {
+                    raw_background
+                };
+                self.put_raw_pixel(x + dx, y + dy, raw);
+            }
+        }
+        Some((x + width, y))
+    }
+
+    fn blend_rect(
+        &self,
+        x: u64,
+        y: u64,
+        width: u64,
+        height: u64,
+        color: FramebufferColor,
+        alpha: u8,
+    ) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+
+        for y in y..y_end {
+            for x in x..x_end {
+                self.alpha_blend_pixel(x, y, color.r, color.g, color.b, alpha);
+            }
+        }
+    }
+
+    fn move_region(
+        &self,
+        src_x: u64,
+        src_y: u64,
+        dst_x: u64,
+        dst_y: u64,
+        width: u64,
+        height: u64,
+    ) -> Result<(), OutOfBounds> {
+        let fits = |x: u64, y: u64| {
+            x.checked_add(width).is_some_and(|end| end <= self.width)
+                && y.checked_add(height).is_some_and(|end| end <= self.height)
+        };
+        if !fits(src_x, src_y) || !fits(dst_x, dst_y) {
+            return Err(OutOfBounds);
+        }
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let (Some(src), Some(dst)) = (self.pixel_at(src_x, src_y), self.pixel_at(dst_x, dst_y))
+        else {
+            return Ok(());
+        };
+
+        let pitch = self.pitch as usize;
+        let row_len = width as usize * self.bytes_per_pixel();
+        let copy_row =
+            |row: usize| unsafe { ptr::copy(src.add(row * pitch), dst.add(row * pitch), row_len) };
+        // Rows are copied away from the destination first, so none is overwritten before
+        // it has been moved. Within a row, `ptr::copy` handles the overlap.
+        if dst_y > src_y {
+            (0..height as usize).rev().for_each(copy_row);
+        } else {
+            (0..height as usize).for_each(copy_row);
+        }
+        Ok(())
+    }
+}
+
+/// Stores a pixel the way the current [`WriteMode`] asks for.
+unsafe fn store_pixel(pixel: *mut u8, raw: u32, bytes_per_pixel: usize) {
+    match write_mode() {
+        WriteMode::Volatile => write_pixel_volatile(pixel, raw, bytes_per_pixel),
+        WriteMode::Plain => write_pixel(pixel, raw, bytes_per_pixel),
+    }
+}
+
+/// Reads the `bytes_per_pixel` bytes at `pixel` as a raw pixel value.
+unsafe fn load_pixel(pixel: *const u8, bytes_per_pixel: usize) -> u32 {
+    if bytes_per_pixel == 4 {
+        (pixel as *const u32).read_unaligned()
+    } else {
+        (0..bytes_per_pixel).fold(0, |raw, i| raw | (*pixel.add(i) as u32) << (8 * i))
+    }
+}
+
+/// Stores the low `bytes_per_pixel` bytes of `raw` at `pixel`.
+unsafe fn write_pixel(pixel: *mut u8, raw: u32, bytes_per_pixel: usize) {
+    if bytes_per_pixel == 4 {
+        (pixel as *mut u32).write_unaligned(raw);
+    } else {
+        for i in 0..bytes_per_pixel {
+            *pixel.add(i) = (raw >> (8 * i)) as u8;
+        }
+    }
+}
+
+/// Like [`write_pixel`], but with volatile stores: one word if the pixel is an aligned
+/// 32-bit one, else one per byte.
+unsafe fn write_pixel_volatile(pixel: *mut u8, raw: u32, bytes_per_pixel: usize) {
+    if bytes_per_pixel == 4 && (pixel as *mut u32).is_aligned() {
+        ptr::write_volatile(pixel as *mut u32, raw);
+    } else {
+        for i in 0..bytes_per_pixel {
+            ptr::write_volatile(pixel.add(i), (raw >> (8 * i)) as u8);
+        }
+    }
+}
+
+/// Runs the tests of the framebuffer drawing code on plain buffers.
+pub fn self_test() -> bool {
+    write_modes_self_test() && pixel_read_self_test() && blend_self_test() && checksum_self_test()
+}
+
+/// Draws the same pixels and rectangles in both [`WriteMode`]s, on a 32 bpp and on a
+/// padded 24 bpp framebuffer, and checks that the pixels come out the same.
+fn write_modes_self_test() -> bool {
+    fn draw(framebuffer: &LimineFramebuffer) {
+        framebuffer.fill_rect(1, 1, 3, 2, FramebufferColor::CYAN);
+        // Clipped on the right and at the bottom.
+        framebuffer.fill_rect(4, 2, 10, 10, FramebufferColor::MAGENTA);
+        framebuffer.put_pixel(0, 0, FramebufferColor::RED);
+        framebuffer.put_pixel(5, 3, FramebufferColor::new(0x12, 0x34, 0x56));
+    }
+
+    /// Draws into `volatile` and `plain` in the mode of their name, and leaves the
+    /// write mode as it was.
+    fn draw_both(info: FramebufferInfo, volatile: *mut u8, plain: *mut u8) {
+        let previous = write_mode();
+        for (mode, address) in [(WriteMode::Volatile, volatile), (WriteMode::Plain, plain)] {
+            set_write_mode(mode);
+            // SAFETY: The callers pass buffers of `pitch * height` bytes.
+            draw(&unsafe { framebuffer_from_parts(address, info) });
+        }
+        set_write_mode(previous);
+    }
+
+    let info = FramebufferInfo::xrgb8888(6, 4);
+    let (mut volatile, mut plain) = ([0u32; 24], [0u32; 24]);
+    draw_both(
+        info,
+        volatile.as_mut_ptr().cast(),
+        plain.as_mut_ptr().cast(),
+    );
+    let words_ok = volatile == plain
+        && volatile[0] == 0xff0000
+        && volatile[7] == 0x00ffff
+        && volatile[16] == 0xff00ff
+        && volatile[23] == 0x123456;
+
+    // Two bytes of padding after each row of 6 pixels, which stay untouched.
+    let info = FramebufferInfo {
+        pitch: 20,
+        bpp: 24,
+        ..FramebufferInfo::xrgb8888(6, 4)
+    };
+    let (mut volatile, mut plain) = ([0u8; 80], [0u8; 80]);
+    draw_both(info, volatile.as_mut_ptr(), plain.as_mut_ptr());
+    let bytes_ok = volatile == plain
+        && volatile[..3] == [0x00, 0x00, 0xff]
+        && volatile[75..] == [0x56, 0x34, 0x12, 0, 0]
+        && volatile.chunks(20).all(|row| row[18..] == [0, 0]);
+
+    words_ok && bytes_ok
+}
+
+/// Writes colors with `put_pixel` and reads them back with `get_pixel` on 32, 24 and
+/// 16 bpp framebuffers, and reads outside of them.
+fn pixel_read_self_test() -> bool {
+    let xrgb = FramebufferInfo::xrgb8888(2, 2);
+    let bgr24 = FramebufferInfo {
+        pitch: 8,
+        bpp: 24,
+        red_mask_shift: 0,
+        blue_mask_shift: 16,
+        ..xrgb
+    };
+    let rgb565 = FramebufferInfo {
+        pitch: 4,
+        bpp: 16,
+        red_mask_size: 5,
+        red_mask_shift: 11,
+        green_mask_size: 6,
+        green_mask_shift: 5,
+        blue_mask_size: 5,
+        blue_mask_shift: 0,
+        ..xrgb
+    };
+    // RGB565 only keeps the top bits, so its color has none below them.
+    let cases = [
+        (xrgb, FramebufferColor::new(0x12, 0x34, 0x56)),
+        (bgr24, FramebufferColor::new(0x12, 0x34, 0x56)),
+        (rgb565, FramebufferColor::MAGENTA),
+    ];
+
+    cases.into_iter().all(|(info, color)| {
+        let mut pixels = [0u8; 16];
+        // SAFETY: 16 bytes hold the `pitch * height` bytes of every info above.
+        let framebuffer = unsafe { framebuffer_from_parts(pixels.as_mut_ptr(), info) };
+        framebuffer.put_pixel(1, 1, color);
+        framebuffer.get_pixel(1, 1) == Some(color)
+            && framebuffer.get_raw_pixel(1, 1) == Some(info.encode(color))
+            && framebuffer.get_pixel(0, 1) == Some(FramebufferColor::BLACK)
+            && framebuffer.get_pixel(2, 0).is_none()
+            && framebuffer.get_pixel(0, 2).is_none()
+    })
+}
+
+/// Blends half transparent white over black with `blend_rect` and `alpha_blend_region`,
+/// which has to give midpoint gray, including where they clip or positions overflow.
+fn blend_self_test() -> bool {
+    const GRAY: u32 = 0x80_80_80;
+
+    let mut pixels = [0u32; 8];
+    // SAFETY: The array holds the `pitch * height` bytes of a 4 by 2 framebuffer.
+    let framebuffer = unsafe {
+        framebuffer_from_parts(pixels.as_mut_ptr().cast(), FramebufferInfo::xrgb8888(4, 2))
+    };
+    framebuffer.blend_rect(0, 0, 2, 1, FramebufferColor::WHITE, 0x80);
+    // Clipped to the bottom right pixel.
+    framebuffer.blend_rect(3, 1, 10, 10, FramebufferColor::WHITE, 0x80);
+
+    // Half transparent white, then a fully transparent pixel.
+    let src = [0xff, 0xff, 0xff, 0x80, 0xff, 0xff, 0xff, 0x00];
+    framebuffer.alpha_blend_region(0, 1, &src, 2, 1, 8);
+    // Off screen, and the pixels or rows after the first past `u64::MAX`.
+    framebuffer.alpha_blend_region(u64::MAX, 0, &src, 2, 1, 8);
+    framebuffer.alpha_blend_region(0, u64::MAX, &src, 1, 2, 4);
+
+    pixels == [GRAY, GRAY, 0, 0, GRAY, 0, 0, GRAY]
+}
+
+/// Renders the same picture into two padded buffers whose padding differs, and checks
+/// that their checksums agree until a pixel changes.
+fn checksum_self_test() -> bool {
+    fn render(framebuffer: &LimineFramebuffer) {
+        framebuffer.fill_rect(0, 0, 3, 2, FramebufferColor::BLUE);
+        framebuffer.put_pixel(1, 1, FramebufferColor::YELLOW);
+    }
+
+    // A padding pixel after each row of 3.
+    let info = FramebufferInfo {
+        pitch: 16,
+        ..FramebufferInfo::xrgb8888(3, 2)
+    };
+    let mut first_pixels = [0u32; 8];
+    let mut second_pixels = [0u32; 8];
+    second_pixels[3] = 0xdead_beef;
+    second_pixels[7] = 0x1234_5678;
+    // SAFETY: The arrays hold the `pitch * height` bytes of `info` and outlive the
+    // framebuffers.
+    let (first, second) = unsafe {
+        (
+            framebuffer_from_parts(first_pixels.as_mut_ptr().cast(), info),
+            framebuffer_from_parts(second_pixels.as_mut_ptr().cast(), info),
+        )
+    };
+
+    let blank = first.checksum();
+    render(&first);
+    render(&second);
+    let rendered = first.checksum();
+    let same = rendered != blank && rendered == second.checksum();
+    second.put_pixel(2, 0, FramebufferColor::RED);
+    same && second.checksum() != rendered
+}
@@ -0,0 +1,141 @@
+//! Showing panic messages on a framebuffer.
+//!
+//! The panic handler can't rely on whatever console was in use, which may be the reason
+//! for the panic or be locked by the panicking code, so it draws with a fresh
+//! [`BasicConsole`] onto the framebuffer registered here.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+use limine::LimineFramebuffer;
+
+use super::console::BasicConsole;
+use super::font::Font;
+use super::surface::Surface;
+use super::{set_write_mode, FramebufferColor, WriteMode};
+
+static FRAMEBUFFER: AtomicPtr<LimineFramebuffer> = AtomicPtr::new(ptr::null_mut());
+
+/// The clear color as `0x01rrggbb`, or 0 to keep the screen contents.
+static CLEAR_COLOR: AtomicU32 = AtomicU32::new(0);
+const CLEAR_ENABLED: u32 = 1 << 24;
+
+/// Makes panic messages show up on `framebuffer`.
+pub fn register_framebuffer(framebuffer: &'static LimineFramebuffer) {
+    FRAMEBUFFER.store(framebuffer as *const _ as *mut _, Ordering::Release);
+}
+
+/// Fills the screen with `color` before a panic message is drawn, so it isn't lost in
+/// whatever was on screen.
+pub fn set_panic_clear(color: FramebufferColor) {
+    CLEAR_COLOR.store(CLEAR_ENABLED | color.to_hex(), Ordering::Relaxed);
+}
+
+/// Draws panic messages over the current screen contents again.
+pub fn disable_panic_clear() {
+    CLEAR_COLOR.store(0, Ordering::Relaxed);
+}
+
+fn panic_clear() -> Option<FramebufferColor> {
+    let value = CLEAR_COLOR.load(Ordering::Relaxed);
+    (value & CLEAR_ENABLED != 0).then(|| FramebufferColor::from_hex(value))
+}
+
+/// Draws `info` on the registered framebuffer, if there is one.
+pub fn show(info: &PanicInfo) {
+    let framebuffer = FRAMEBUFFER.load(Ordering::Acquire);
+    // SAFETY: Only `&'static` framebuffers are registered.
+    let Some(framebuffer) = (unsafe { framebuffer.as_ref() }) else {
+        return;
+    };
+
+    // Nothing is drawn after this, so the whole-row fill is worth more than the ordering.
+    set_write_mode(WriteMode::Plain);
+    draw(framebuffer, panic_clear(), info);
+}
+
+/// Draws `message` in white onto `surface`, first filling all of it with `clear` if
+/// given.
+///
+/// The text is in the [embedded font](Font::EMBEDDED), as loading the boot font parses
+/// the command line and a module, and reports failures by printing.
+pub fn draw<S: Surface>(surface: S, clear: Option<FramebufferColor>, message: impl fmt::Display) {
+    let background = clear.unwrap_or(FramebufferColor::BLACK);
+    let mut console = BasicConsole::with_font_and_colors(
+        surface,
+        Font::EMBEDDED,
+        FramebufferColor::WHITE,
+        background,
+    );
+    if clear.is_some() {
+        console.clear();
+    }
+    let _ = write!(console, "{}", message);
+}
+
+/// Draws onto a surface that keeps the order of the writes, and checks that the clear
+/// color covers the whole surface before the first text pixel, and that nothing is
+/// cleared without a clear color.
+pub fn self_test() -> bool {
+    use core::cell::Cell;
+
+    const WIDTH: u64 = 64;
+    const HEIGHT: u64 = 16;
+
+    #[derive(Default)]
+    struct Recorder {
+        writes: Cell<usize>,
+        /// The write count when the whole surface was filled with the clear color.
+        cleared_at: Cell<Option<usize>>,
+        /// The write count at the first white pixel.
+        text_at: Cell<Option<usize>>,
+    }
+
+    impl Surface for Recorder {
+        fn width(&self) -> u64 {
+            WIDTH
+        }
+
+        fn height(&self) -> u64 {
+            HEIGHT
+        }
+
+        fn encode(&self, color: FramebufferColor) -> u32 {
+            color.to_hex()
+        }
+
+        fn put_raw_pixel(&self, _x: u64, _y: u64, raw: u32) {
+            if raw == FramebufferColor::WHITE.to_hex() && self.text_at.get().is_none() {
+                self.text_at.set(Some(self.writes.get()));
+            }
+            self.writes.set(self.writes.get() + 1);
+        }
+
+        fn get_raw_pixel(&self, _x: u64, _y: u64) -> Option<u32> {
+            None
+        }
+
+        fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor) {
+            let whole = x == 0 && y == 0 && width >= WIDTH && height >= HEIGHT;
+            if whole && color == FramebufferColor::RED {
+                self.cleared_at.set(Some(self.writes.get()));
+            }
+            self.writes.set(self.writes.get() + 1);
+        }
+    }
+
+    let cleared = Recorder::default();
+    draw(&cleared, Some(FramebufferColor::RED), "panicked");
+    let cleared_first = matches!(
+        (cleared.cleared_at.get(), cleared.text_at.get()),
+        (Some(clear), Some(text)) if clear < text
+    );
+
+    let kept = Recorder::default();
+    draw(&kept, None, "panicked");
+    let not_cleared = kept.cleared_at.get().is_none() && kept.text_at.get().is_some();
+
+    cleared_first && not_cleared
+}
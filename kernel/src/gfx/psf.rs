@@ -0,0 +1,344 @@
+//! Parsing of PC Screen Fonts, the console font format of Linux.
+//!
+//! PSF1 fonts are always 8 pixels wide and hold 256 or 512 glyphs. PSF2 fonts can have
+//! any glyph size. Both can carry a unicode table mapping code points to glyphs; fonts
+//! without one are indexed by code point.
+
+use core::fmt;
+
+use super::font::{Font, Mapping};
+#[cfg(all(feature = "kernel-file", feature = "modules"))]
+use crate::boot::cmdline;
+#[cfg(all(feature = "kernel-file", feature = "modules"))]
+use crate::boot::module::{LimineFileExt, LimineModuleResponseExt};
+#[cfg(all(feature = "kernel-file", feature = "modules"))]
+use crate::boot::request::LimineRequest;
+#[cfg(all(feature = "kernel-file", feature = "modules"))]
+use crate::boot::requests::MODULES;
+#[cfg(all(feature = "kernel-file", feature = "modules"))]
+use crate::kprintln;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TAB: u8 = 0x02;
+const PSF1_MODE_SEQ: u8 = 0x04;
+const PSF1_HEADER_SIZE: usize = 4;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_HEADER_SIZE: usize = 32;
+
+/// Why a font couldn't be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PsfError {
+    /// The data starts with neither the PSF1 nor the PSF2 magic.
+    BadMagic,
+    /// The header describes more data than there is.
+    Truncated,
+    /// The header fields contradict each other or describe an empty font.
+    InvalidHeader,
+}
+
+impl fmt::Display for PsfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::BadMagic => "not a PSF font",
+            Self::Truncated => "font data is truncated",
+            Self::InvalidHeader => "invalid PSF header",
+        })
+    }
+}
+
+fn le32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Parses a PSF1 or PSF2 font, checking every size in its header against `bytes`.
+pub fn parse(bytes: &'static [u8]) -> Result<Font, PsfError> {
+    if bytes.starts_with(&PSF2_MAGIC) {
+        parse_psf2(bytes)
+    } else if bytes.starts_with(&PSF1_MAGIC) {
+        parse_psf1(bytes)
+    } else {
+        Err(PsfError::BadMagic)
+    }
+}
+
+fn parse_psf1(bytes: &'static [u8]) -> Result<Font, PsfError> {
+    if bytes.len() < PSF1_HEADER_SIZE {
+        return Err(PsfError::Truncated);
+    }
+    let mode = bytes[2];
+    let height = bytes[3] as usize;
+    if height == 0 {
+        return Err(PsfError::InvalidHeader);
+    }
+
+    let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+    let glyphs_end = PSF1_HEADER_SIZE + glyph_count * height;
+    let glyphs = bytes
+        .get(PSF1_HEADER_SIZE..glyphs_end)
+        .ok_or(PsfError::Truncated)?;
+
+    let mapping = if mode & (PSF1_MODE_HAS_TAB | PSF1_MODE_SEQ) != 0 {
+        Mapping::Psf1(&bytes[glyphs_end..])
+    } else {
+        Mapping::Identity
+    };
+
+    Ok(Font {
+        width: 8,
+        height: height as u64,
+        glyph_count,
+        glyphs,
+        mapping,
+    })
+}
+
+fn parse_psf2(bytes: &'static [u8]) -> Result<Font, PsfError> {
+    if bytes.len() < PSF2_HEADER_SIZE {
+        return Err(PsfError::Truncated);
+    }
+    let header_size = le32(bytes, 8) as usize;
+    let flags = le32(bytes, 12);
+    let glyph_count = le32(bytes, 16) as usize;
+    let glyph_size = le32(bytes, 20) as usize;
+    let height = le32(bytes, 24) as usize;
+    let width = le32(bytes, 28) as usize;
+
+    if header_size < PSF2_HEADER_SIZE
+        || glyph_count == 0
+        || width == 0
+        || height == 0
+        || Some(glyph_size) != width.div_ceil(8).checked_mul(height)
+    {
+        return Err(PsfError::InvalidHeader);
+    }
+
+    let glyphs_end = glyph_count
+        .checked_mul(glyph_size)
+        .and_then(|size| size.checked_add(header_size))
+        .ok_or(PsfError::InvalidHeader)?;
+    let glyphs = bytes
+        .get(header_size..glyphs_end)
+        .ok_or(PsfError::Truncated)?;
+
+    let mapping = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+        Mapping::Psf2(&bytes[glyphs_end..])
+    } else {
+        Mapping::Identity
+    };
+
+    Ok(Font {
+        width: width as u64,
+        height: height as u64,
+        glyph_count,
+        glyphs,
+        mapping,
+    })
+}
+
+/// Loads the font named by the `font=<tag>` command line option from the module whose
+/// cmdline is `<tag>`.
+///
+/// A missing or malformed font is reported and `None` returned, so the caller can fall
+/// back to the embedded one.
+#[cfg(all(feature = "kernel-file", feature = "modules"))]
+pub fn load_from_cmdline() -> Option<Font> {
+    let tag = cmdline::value("font")?;
+    let Some(module) = MODULES
+        .response()
+        .and_then(|modules| modules.find_by_cmdline(tag))
+    else {
+        kprintln!("font: no module tagged `{}`", tag);
+        return None;
+    };
+
+    match parse(module.data()?) {
+        Ok(font) => Some(font),
+        Err(err) => {
+            kprintln!("font: module `{}`: {}", tag, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leak(bytes: Vec<u8>) -> &'static [u8] {
+        bytes.leak()
+    }
+
+    /// The first byte of the glyph `font` draws for `c`, which the fixtures set to the
+    /// glyph's index.
+    fn glyph_index(font: &Font, c: char) -> u8 {
+        let glyph = font.glyph(c);
+        (0..8).fold(0, |byte, x| byte << 1 | glyph.pixel(x, 0) as u8)
+    }
+
+    /// A PSF1 font of 256 glyphs of 2 rows each, followed by `table` in UCS-2.
+    fn psf1(mode: u8, table: &[u16]) -> Vec<u8> {
+        let mut bytes = vec![PSF1_MAGIC[0], PSF1_MAGIC[1], mode, 2];
+        for index in 0..=255 {
+            bytes.extend_from_slice(&[index, 0]);
+        }
+        bytes.extend(table.iter().flat_map(|unit| unit.to_le_bytes()));
+        bytes
+    }
+
+    fn psf2_header(
+        header_size: u32,
+        flags: u32,
+        glyph_count: u32,
+        glyph_size: u32,
+        height: u32,
+        width: u32,
+    ) -> Vec<u8> {
+        let mut bytes = PSF2_MAGIC.to_vec();
+        for field in [
+            0,
+            header_size,
+            flags,
+            glyph_count,
+            glyph_size,
+            height,
+            width,
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.resize((header_size as usize).max(PSF2_HEADER_SIZE), 0);
+        bytes
+    }
+
+    /// A PSF2 font of 4 glyphs of 12 by 2 pixels, followed by `table`.
+    fn psf2(flags: u32, table: &[u8]) -> Vec<u8> {
+        let mut bytes = psf2_header(32, flags, 4, 4, 2, 12);
+        for index in 0..4 {
+            bytes.extend_from_slice(&[index, 0, 0, 0]);
+        }
+        bytes.extend_from_slice(table);
+        bytes
+    }
+
+    /// `?`, `A`, then `é` along with the combining sequence `e` U+0301.
+    const PSF1_TABLE: [u16; 9] = [
+        0x3f, 0xffff, 0x41, 0xffff, 0xe9, 0xfffe, 0x65, 0x301, 0xffff,
+    ];
+    /// The same in UTF-8, then `█`.
+    const PSF2_TABLE: &[u8] = b"?\xffA\xff\xc3\xa9\xfee\xcc\x81\xff\xe2\x96\x88\xff";
+
+    #[test]
+    fn bad_magic() {
+        assert_eq!(parse(b"\x36\x05\x00\x08").err(), Some(PsfError::BadMagic));
+        assert_eq!(parse(b"").err(), Some(PsfError::BadMagic));
+    }
+
+    #[test]
+    fn psf1_header() {
+        assert_eq!(parse(&PSF1_MAGIC).err(), Some(PsfError::Truncated));
+        let mut zero_height = psf1(0, &[]);
+        zero_height[3] = 0;
+        assert_eq!(
+            parse(leak(zero_height)).err(),
+            Some(PsfError::InvalidHeader)
+        );
+
+        let font = parse(leak(psf1(0, &[]))).unwrap();
+        assert_eq!((font.width(), font.height()), (8, 2));
+        assert_eq!(font.glyph_count, 256);
+        assert!(matches!(font.mapping, Mapping::Identity));
+        // 512 glyphs need twice the data.
+        assert_eq!(
+            parse(leak(psf1(PSF1_MODE_512, &[]))).err(),
+            Some(PsfError::Truncated)
+        );
+    }
+
+    #[test]
+    fn psf1_truncated_glyphs() {
+        let mut bytes = psf1(0, &[]);
+        bytes.pop();
+        assert_eq!(parse(leak(bytes)).err(), Some(PsfError::Truncated));
+    }
+
+    #[test]
+    fn psf2_header_fields() {
+        let short = psf2_header(32, 0, 4, 4, 2, 12);
+        assert_eq!(
+            parse(leak(short[..31].to_vec())).err(),
+            Some(PsfError::Truncated)
+        );
+
+        for header in [
+            psf2_header(28, 0, 4, 4, 2, 12),
+            psf2_header(32, 0, 0, 4, 2, 12),
+            psf2_header(32, 0, 4, 4, 2, 0),
+            psf2_header(32, 0, 4, 4, 0, 12),
+            // 12 pixels take 2 bytes per row.
+            psf2_header(32, 0, 4, 2, 2, 12),
+        ] {
+            assert_eq!(parse(leak(header)).err(), Some(PsfError::InvalidHeader));
+        }
+
+        // Glyphs start where the header says, past any padding.
+        let mut padded = psf2_header(40, 0, 1, 4, 2, 12);
+        padded.extend_from_slice(&[0x80, 0, 0, 0]);
+        let font = parse(leak(padded)).unwrap();
+        assert_eq!((font.width(), font.height()), (12, 2));
+        assert!(font.glyph('\0').pixel(0, 0));
+    }
+
+    #[test]
+    fn psf2_truncated_glyphs() {
+        let mut bytes = psf2(0, &[]);
+        bytes.pop();
+        assert_eq!(parse(leak(bytes)).err(), Some(PsfError::Truncated));
+    }
+
+    #[test]
+    fn identity_without_table() {
+        let font = parse(leak(psf2(0, &[]))).unwrap();
+        assert_eq!(glyph_index(&font, '\u{2}'), 2);
+        // Past the last glyph, and nothing to fall back to but the first.
+        assert_eq!(glyph_index(&font, 'A'), 0);
+    }
+
+    #[test]
+    fn psf1_unicode_table() {
+        let font = parse(leak(psf1(PSF1_MODE_HAS_TAB, &PSF1_TABLE))).unwrap();
+        assert_eq!(glyph_index(&font, 'A'), 1);
+        assert_eq!(glyph_index(&font, 'é'), 2);
+        // Only part of a combining sequence, so it falls back to `?`.
+        assert_eq!(glyph_index(&font, 'e'), 0);
+        // Outside of UCS-2.
+        assert_eq!(glyph_index(&font, '🦀'), 0);
+    }
+
+    #[test]
+    fn psf2_unicode_table() {
+        let font = parse(leak(psf2(PSF2_HAS_UNICODE_TABLE, PSF2_TABLE))).unwrap();
+        assert_eq!(glyph_index(&font, 'A'), 1);
+        assert_eq!(glyph_index(&font, 'é'), 2);
+        assert_eq!(glyph_index(&font, '█'), 3);
+        assert_eq!(glyph_index(&font, 'e'), 0);
+        assert_eq!(glyph_index(&font, 'B'), 0);
+    }
+
+    #[test]
+    fn truncated_unicode_table() {
+        // Cut in the middle of the UCS-2 unit for `é`.
+        let mut psf1_bytes = psf1(PSF1_MODE_HAS_TAB, &PSF1_TABLE[..5]);
+        psf1_bytes.pop();
+        let font = parse(leak(psf1_bytes)).unwrap();
+        assert_eq!(glyph_index(&font, 'A'), 1);
+        assert_eq!(glyph_index(&font, 'é'), 0);
+
+        // Cut in the middle of the UTF-8 encoding of `█`.
+        let table = &PSF2_TABLE[..PSF2_TABLE.len() - 2];
+        let font = parse(leak(psf2(PSF2_HAS_UNICODE_TABLE, table))).unwrap();
+        assert_eq!(glyph_index(&font, 'é'), 2);
+        assert_eq!(glyph_index(&font, '█'), 0);
+    }
+}
@@ -0,0 +1,251 @@
+//! Screenshots of a framebuffer as PPM images, to see exactly what is on the screen of
+//! a machine that can only be reached over serial.
+//!
+//! [`write_ppm`] produces a binary PPM (P6) image, with every pixel taken through
+//! [`FramebufferInfo::decode`] so any pixel format comes out as 8-bit RGB.
+//! [`write_ppm_base64`] wraps it in base64 lines between markers, with progress comments
+//! in between, which survives a text console. On the host, the image is recovered from
+//! a serial log with:
+//!
+//! ```text
+//! sed -n '/^-----BEGIN PPM/,/^-----END PPM/p' serial.log | grep -v '^[#-]' | base64 -d > screen.ppm
+//! ```
+//!
+//! [`FramebufferInfo::decode`]: super::FramebufferInfo::decode
+
+use core::fmt::{self, Write};
+
+use super::surface::{RawSurface, Surface};
+use crate::array_vec::ArrayVec;
+
+/// Pixels copied out of the framebuffer at a time, so the screen is only held still for
+/// a few rows.
+const BATCH_PIXELS: usize = 1024;
+/// Rows between progress comments in base64 output.
+const PROGRESS_ROWS: u64 = 64;
+/// Image bytes per base64 line, which makes lines of 76 characters.
+const LINE_BYTES: usize = 57;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Where [`write_ppm`] puts the image.
+pub trait PpmSink {
+    fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result;
+
+    /// Called every [`PROGRESS_ROWS`] rows, with how many of `height` rows are written.
+    fn progress(&mut self, _rows: u64, _height: u64) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// Writes the header through `fmt`.
+struct Header<'a>(&'a mut dyn PpmSink);
+
+impl Write for Header<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_bytes(s.as_bytes())
+    }
+}
+
+/// Streams `surface` to `sink` as a binary PPM image.
+///
+/// The pixels are read in batches of [`BATCH_PIXELS`] and converted and written between
+/// batches. With the `log-console` feature the log console is kept from drawing while a
+/// batch is read, so glyphs aren't caught half drawn, but never while `sink` runs.
+pub fn write_ppm(surface: &RawSurface, sink: &mut dyn PpmSink) -> fmt::Result {
+    let info = *surface.info();
+    let (width, height) = (info.width, info.height);
+    write!(Header(sink), "P6\n{} {}\n255\n", width, height)?;
+    if width == 0 {
+        return Ok(());
+    }
+
+    let mut raws = [0u32; BATCH_PIXELS];
+    let mut rgb = [0u8; 3 * BATCH_PIXELS];
+    let (mut x, mut y) = (0, 0);
+    while y < height {
+        let first_row = y;
+        let mut count = 0;
+        {
+            #[cfg(feature = "log-console")]
+            let _console = super::log_console::hold();
+            while count < BATCH_PIXELS && y < height {
+                raws[count] = surface.get_raw_pixel(x, y).unwrap_or(0);
+                count += 1;
+                x += 1;
+                if x == width {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+
+        for (&raw, pixel) in raws[..count].iter().zip(rgb.chunks_exact_mut(3)) {
+            let color = info.decode(raw);
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+        sink.write_bytes(&rgb[..3 * count])?;
+        if y / PROGRESS_ROWS > first_row / PROGRESS_ROWS && y < height {
+            sink.progress(y, height)?;
+        }
+    }
+    Ok(())
+}
+
+/// Base64 encodes into lines of [`LINE_BYTES`] bytes, with progress comments between
+/// lines.
+struct Base64Lines<'a, W: Write + ?Sized> {
+    out: &'a mut W,
+    pending: ArrayVec<u8, LINE_BYTES>,
+}
+
+impl<W: Write + ?Sized> Base64Lines<'_, W> {
+    fn flush_line(&mut self) -> fmt::Result {
+        for group in self.pending.chunks(3) {
+            let bits = group.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+                bits | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                let c = if i <= group.len() {
+                    BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize]
+                } else {
+                    b'='
+                };
+                self.out.write_char(c as char)?;
+            }
+        }
+        self.pending.clear();
+        self.out.write_char('\n')
+    }
+}
+
+impl<W: Write + ?Sized> PpmSink for Base64Lines<'_, W> {
+    fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+        for &byte in bytes {
+            if self.pending.push(byte).is_err() {
+                self.flush_line()?;
+                let _ = self.pending.push(byte);
+            }
+        }
+        Ok(())
+    }
+
+    fn progress(&mut self, rows: u64, height: u64) -> fmt::Result {
+        writeln!(self.out, "# {}/{} rows", rows, height)
+    }
+}
+
+/// Streams `surface` as a base64 encoded PPM image, between `-----BEGIN PPM-----` and
+/// `-----END PPM-----` lines, with `#` progress comments.
+pub fn write_ppm_base64<W: Write + ?Sized>(out: &mut W, surface: &RawSurface) -> fmt::Result {
+    let info = surface.info();
+    writeln!(out, "-----BEGIN PPM {}x{}-----", info.width, info.height)?;
+    let mut lines = Base64Lines {
+        out: &mut *out,
+        pending: ArrayVec::new(),
+    };
+    write_ppm(surface, &mut lines)?;
+    if !lines.pending.is_empty() {
+        lines.flush_line()?;
+    }
+    writeln!(out, "-----END PPM-----")
+}
+
+/// Streams the first framebuffer to `out` with [`write_ppm_base64`].
+#[cfg(feature = "framebuffer")]
+pub fn dump_screenshot<W: Write + ?Sized>(out: &mut W) -> fmt::Result {
+    use super::LimineFramebufferExt;
+    use crate::boot::request::LimineRequest;
+
+    let surface = crate::boot::requests::FRAMEBUFFER
+        .response()
+        .and_then(|response| response.framebuffers().first())
+        .and_then(|framebuffer| framebuffer.raw_surface());
+    match surface {
+        Some(surface) => write_ppm_base64(out, &surface),
+        None => writeln!(out, "there is no framebuffer to take a screenshot of"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::FramebufferInfo;
+    use super::*;
+
+    impl PpmSink for Vec<u8> {
+        fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+            self.extend_from_slice(bytes);
+            Ok(())
+        }
+    }
+
+    fn rgb565(width: u64, height: u64) -> FramebufferInfo {
+        FramebufferInfo {
+            width,
+            height,
+            pitch: 2 * width,
+            bpp: 16,
+            red_mask_size: 5,
+            red_mask_shift: 11,
+            green_mask_size: 6,
+            green_mask_shift: 5,
+            blue_mask_size: 5,
+            blue_mask_shift: 0,
+        }
+    }
+
+    /// Red, green, blue and white.
+    const PIXELS: [u16; 4] = [0xf800, 0x07e0, 0x001f, 0xffff];
+
+    #[test]
+    fn binary() {
+        let mut pixels = PIXELS;
+        // SAFETY: The array holds the `pitch * height` bytes the info describes.
+        let surface = unsafe { RawSurface::new(pixels.as_mut_ptr().cast(), rgb565(2, 2)) };
+        let mut binary = Vec::new();
+        write_ppm(&surface, &mut binary).unwrap();
+        assert_eq!(
+            binary,
+            b"P6\n2 2\n255\n\xff\x00\x00\x00\xff\x00\x00\x00\xff\xff\xff\xff"
+        );
+    }
+
+    #[test]
+    fn base64() {
+        let mut pixels = PIXELS;
+        // SAFETY: The array holds the `pitch * height` bytes the info describes.
+        let surface = unsafe { RawSurface::new(pixels.as_mut_ptr().cast(), rgb565(2, 2)) };
+        let mut text = String::new();
+        write_ppm_base64(&mut text, &surface).unwrap();
+        assert_eq!(
+            text,
+            "-----BEGIN PPM 2x2-----\nUDYKMiAyCjI1NQr/AAAA/wAAAP////8=\n-----END PPM-----\n"
+        );
+    }
+
+    #[test]
+    fn base64_lines_and_progress() {
+        // 64 rows of 16 pixels fill a batch, so progress is reported after 64 and 128 rows.
+        let mut pixels = vec![0u16; 16 * 130];
+        // SAFETY: The vector holds the `pitch * height` bytes the info describes.
+        let surface = unsafe { RawSurface::new(pixels.as_mut_ptr().cast(), rgb565(16, 130)) };
+        let mut text = String::new();
+        write_ppm_base64(&mut text, &surface).unwrap();
+
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.first(), Some(&"-----BEGIN PPM 16x130-----"));
+        assert_eq!(lines.last(), Some(&"-----END PPM-----"));
+        let body = &lines[1..lines.len() - 1];
+        let progress: Vec<_> = body.iter().filter(|line| line.starts_with('#')).collect();
+        assert_eq!(progress, [&"# 64/130 rows", &"# 128/130 rows"]);
+
+        let data: Vec<_> = body.iter().filter(|line| !line.starts_with('#')).collect();
+        let (last, full) = data.split_last().unwrap();
+        assert!(full.iter().all(|line| line.len() == 76));
+        let image_bytes = "P6\n16 130\n255\n".len() + 3 * 16 * 130;
+        assert_eq!(
+            full.len() * LINE_BYTES + last.len() / 4 * 3,
+            image_bytes.next_multiple_of(3)
+        );
+    }
+}
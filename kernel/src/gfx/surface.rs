@@ -0,0 +1,455 @@
+//! Something pixels can be drawn on.
+//!
+//! [`Surface`] is what drawing code like [`BasicConsole`](super::console::BasicConsole)
+//! is written against, so it can render to a framebuffer directly or through a wrapper
+//! such as [`RotatedSurface`].
+
+use core::ptr;
+
+use limine::LimineFramebuffer;
+
+use super::{load_pixel, store_pixel, FramebufferColor, FramebufferInfo, LimineFramebufferExt};
+
+/// A rectangle of pixels addressed by `(x, y)`, with `(0, 0)` at the top left.
+///
+/// Like [`LimineFramebufferExt`], coordinates outside the surface are ignored.
+pub trait Surface {
+    fn width(&self) -> u64;
+
+    fn height(&self) -> u64;
+
+    /// Packs `color` into the surface's raw pixel format.
+    fn encode(&self, color: FramebufferColor) -> u32;
+
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32);
+
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32>;
+
+    fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor) {
+        let raw = self.encode(color);
+        let x_end = x.saturating_add(width).min(self.width());
+        let y_end = y.saturating_add(height).min(self.height());
+
+        for y in y..y_end {
+            for x in x..x_end {
+                self.put_raw_pixel(x, y, raw);
+            }
+        }
+    }
+
+    /// Copies `pixels`, rows of `width` raw pixels one after another, to the rectangle
+    /// with its top left corner at `(x, y)`.
+    fn blit(&self, x: u64, y: u64, width: u64, pixels: &[u32]) {
+        if width == 0 {
+            return;
+        }
+        for (row, raws) in pixels.chunks(width as usize).enumerate() {
+            for (column, &raw) in raws.iter().enumerate() {
+                self.put_raw_pixel(x + column as u64, y + row as u64, raw);
+            }
+        }
+    }
+
+    /// Moves everything below the first `lines` rows up by `lines` rows. The bottom
+    /// `lines` rows keep their old contents.
+    fn scroll_up(&self, lines: u64) {
+        scroll_pixelwise(self, lines);
+    }
+}
+
+/// The generic [`Surface::scroll_up`], one pixel at a time.
+fn scroll_pixelwise<S: Surface + ?Sized>(surface: &S, lines: u64) {
+    for y in lines..surface.height() {
+        for x in 0..surface.width() {
+            if let Some(raw) = surface.get_raw_pixel(x, y) {
+                surface.put_raw_pixel(x, y - lines, raw);
+            }
+        }
+    }
+}
+
+impl<S: Surface + ?Sized> Surface for &S {
+    fn width(&self) -> u64 {
+        (**self).width()
+    }
+
+    fn height(&self) -> u64 {
+        (**self).height()
+    }
+
+    fn encode(&self, color: FramebufferColor) -> u32 {
+        (**self).encode(color)
+    }
+
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32) {
+        (**self).put_raw_pixel(x, y, raw)
+    }
+
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32> {
+        (**self).get_raw_pixel(x, y)
+    }
+
+    fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor) {
+        (**self).fill_rect(x, y, width, height, color)
+    }
+
+    fn blit(&self, x: u64, y: u64, width: u64, pixels: &[u32]) {
+        (**self).blit(x, y, width, pixels)
+    }
+
+    fn scroll_up(&self, lines: u64) {
+        (**self).scroll_up(lines)
+    }
+}
+
+impl Surface for LimineFramebuffer {
+    fn width(&self) -> u64 {
+        self.width
+    }
+
+    fn height(&self) -> u64 {
+        self.height
+    }
+
+    fn encode(&self, color: FramebufferColor) -> u32 {
+        LimineFramebufferExt::encode(self, color)
+    }
+
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32) {
+        LimineFramebufferExt::put_raw_pixel(self, x, y, raw)
+    }
+
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32> {
+        LimineFramebufferExt::get_raw_pixel(self, x, y)
+    }
+
+    fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor) {
+        LimineFramebufferExt::fill_rect(self, x, y, width, height, color)
+    }
+
+    /// Scanlines are contiguous, so this is a single memmove.
+    fn scroll_up(&self, lines: u64) {
+        let Some(base) = self.address.as_ptr() else {
+            return;
+        };
+        if lines >= self.height {
+            return;
+        }
+
+        let offset = (lines * self.pitch) as usize;
+        let len = ((self.height - lines) * self.pitch) as usize;
+        unsafe { ptr::copy(base.add(offset), base, len) };
+    }
+}
+
+/// A framebuffer described by its base address and [`FramebufferInfo`], for code that
+/// shouldn't hold on to the [`LimineFramebuffer`], or that draws into a plain buffer.
+#[derive(Clone, Copy, Debug)]
+pub struct RawSurface {
+    base: *mut u8,
+    info: FramebufferInfo,
+}
+
+impl RawSurface {
+    /// ## Safety
+    ///
+    /// `base` must point to `info.pitch * info.height` bytes that stay valid and writable
+    /// for as long as the surface is used.
+    pub unsafe fn new(base: *mut u8, info: FramebufferInfo) -> Self {
+        Self { base, info }
+    }
+
+    pub fn info(&self) -> &FramebufferInfo {
+        &self.info
+    }
+
+    fn pixel_ptr(&self, x: u64, y: u64) -> Option<*mut u8> {
+        let offset = self.info.pixel_offset(x, y)?;
+        // SAFETY: `new`'s contract covers every in bounds pixel.
+        Some(unsafe { self.base.add(offset) })
+    }
+}
+
+impl Surface for RawSurface {
+    fn width(&self) -> u64 {
+        self.info.width
+    }
+
+    fn height(&self) -> u64 {
+        self.info.height
+    }
+
+    fn encode(&self, color: FramebufferColor) -> u32 {
+        self.info.encode(color)
+    }
+
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32) {
+        if let Some(pixel) = self.pixel_ptr(x, y) {
+            unsafe { store_pixel(pixel, raw, self.info.bytes_per_pixel()) };
+        }
+    }
+
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32> {
+        let pixel = self.pixel_ptr(x, y)?;
+        Some(unsafe { load_pixel(pixel, self.info.bytes_per_pixel()) })
+    }
+
+    fn scroll_up(&self, lines: u64) {
+        if lines >= self.info.height {
+            return;
+        }
+
+        let offset = (lines * self.info.pitch) as usize;
+        let len = ((self.info.height - lines) * self.info.pitch) as usize;
+        unsafe { ptr::copy(self.base.add(offset), self.base, len) };
+    }
+}
+
+/// How far a [`RotatedSurface`] turns the picture clockwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    /// Parses a clockwise angle of 0, 90, 180 or 270 degrees.
+    pub fn from_degrees(degrees: &str) -> Option<Self> {
+        match degrees {
+            "0" => Some(Self::None),
+            "90" => Some(Self::Clockwise90),
+            "180" => Some(Self::Clockwise180),
+            "270" => Some(Self::Clockwise270),
+            _ => None,
+        }
+    }
+
+    /// The rotation the `fbrotate` option asks for, such as `fbrotate=90` for a panel
+    /// mounted with its top edge on the left.
+    #[cfg(feature = "kernel-file")]
+    pub fn from_cmdline() -> Self {
+        let Some(degrees) = crate::boot::cmdline::value("fbrotate") else {
+            return Self::None;
+        };
+        Self::from_degrees(degrees).unwrap_or_else(|| {
+            crate::kwarn!("unsupported fbrotate={}, not rotating", degrees);
+            Self::None
+        })
+    }
+
+    /// Whether width and height trade places.
+    pub fn is_transposed(self) -> bool {
+        matches!(self, Self::Clockwise90 | Self::Clockwise270)
+    }
+}
+
+/// Presents a rotated view of another surface, so content drawn upright on it shows
+/// up upright on a panel mounted sideways or upside down.
+///
+/// Rotating by 90 or 270 degrees swaps [`width`](Surface::width) and
+/// [`height`](Surface::height).
+pub struct RotatedSurface<S: Surface> {
+    inner: S,
+    rotation: Rotation,
+}
+
+impl<S: Surface> RotatedSurface<S> {
+    pub fn new(inner: S, rotation: Rotation) -> Self {
+        Self { inner, rotation }
+    }
+
+    pub fn rotation(&self) -> Rotation {
+        self.rotation
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Maps logical coordinates to the inner surface's, or `None` if they are out of
+    /// bounds.
+    pub fn to_physical(&self, x: u64, y: u64) -> Option<(u64, u64)> {
+        if x >= self.width() || y >= self.height() {
+            return None;
+        }
+        let width = self.inner.width();
+        let height = self.inner.height();
+
+        Some(match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Clockwise90 => (width - 1 - y, x),
+            Rotation::Clockwise180 => (width - 1 - x, height - 1 - y),
+            Rotation::Clockwise270 => (y, height - 1 - x),
+        })
+    }
+
+    fn copy_inner_pixel(&self, (from_x, from_y): (u64, u64), (to_x, to_y): (u64, u64)) {
+        if let Some(raw) = self.inner.get_raw_pixel(from_x, from_y) {
+            self.inner.put_raw_pixel(to_x, to_y, raw);
+        }
+    }
+}
+
+impl<S: Surface> Surface for RotatedSurface<S> {
+    fn width(&self) -> u64 {
+        if self.rotation.is_transposed() {
+            self.inner.height()
+        } else {
+            self.inner.width()
+        }
+    }
+
+    fn height(&self) -> u64 {
+        if self.rotation.is_transposed() {
+            self.inner.width()
+        } else {
+            self.inner.height()
+        }
+    }
+
+    fn encode(&self, color: FramebufferColor) -> u32 {
+        self.inner.encode(color)
+    }
+
+    fn put_raw_pixel(&self, x: u64, y: u64, raw: u32) {
+        if let Some((x, y)) = self.to_physical(x, y) {
+            self.inner.put_raw_pixel(x, y, raw);
+        }
+    }
+
+    fn get_raw_pixel(&self, x: u64, y: u64) -> Option<u32> {
+        let (x, y) = self.to_physical(x, y)?;
+        self.inner.get_raw_pixel(x, y)
+    }
+
+    /// Fills the matching rectangle of the inner surface, so its own fill can be used.
+    fn fill_rect(&self, x: u64, y: u64, width: u64, height: u64, color: FramebufferColor) {
+        let x_end = x.saturating_add(width).min(self.width());
+        let y_end = y.saturating_add(height).min(self.height());
+        if x >= x_end || y >= y_end {
+            return;
+        }
+        let (Some((x0, y0)), Some((x1, y1))) = (
+            self.to_physical(x, y),
+            self.to_physical(x_end - 1, y_end - 1),
+        ) else {
+            return;
+        };
+
+        let (left, right) = (x0.min(x1), x0.max(x1));
+        let (top, bottom) = (y0.min(y1), y0.max(y1));
+        self.inner
+            .fill_rect(left, top, right - left + 1, bottom - top + 1, color);
+    }
+
+    fn scroll_up(&self, lines: u64) {
+        // Logical rows are physical rows only without rotation, and the inner surface's
+        // own scroll is usually much faster. Otherwise the move happens on the inner
+        // surface in its own row order, which keeps the accesses sequential.
+        let width = self.inner.width();
+        let height = self.inner.height();
+        match self.rotation {
+            Rotation::None => self.inner.scroll_up(lines),
+            // Logical rows are physical rows counted from the bottom.
+            Rotation::Clockwise180 => {
+                for y in (0..height.saturating_sub(lines)).rev() {
+                    for x in 0..width {
+                        self.copy_inner_pixel((x, y), (x, y + lines));
+                    }
+                }
+            }
+            // Logical rows are physical columns counted from the right.
+            Rotation::Clockwise90 => {
+                for y in 0..height {
+                    for x in (0..width.saturating_sub(lines)).rev() {
+                        self.copy_inner_pixel((x, y), (x + lines, y));
+                    }
+                }
+            }
+            // Logical rows are physical columns counted from the left.
+            Rotation::Clockwise270 => {
+                for y in 0..height {
+                    for x in lines..width {
+                        self.copy_inner_pixel((x, y), (x - lines, y));
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn self_test() -> bool {
+    raw_surface_self_test() && rotation_self_test()
+}
+
+/// Draws through the [`RawSurface`] of a framebuffer over a buffer with a padding pixel
+/// after each row, and checks every pixel, the padding included.
+fn raw_surface_self_test() -> bool {
+    const W: u32 = 0xffffff;
+
+    let info = FramebufferInfo {
+        pitch: 16,
+        ..FramebufferInfo::xrgb8888(3, 3)
+    };
+    let mut pixels = [0u32; 12];
+    // SAFETY: The array holds the `pitch * height` bytes of `info` and outlives the
+    // framebuffer.
+    let framebuffer = unsafe { super::framebuffer_from_parts(pixels.as_mut_ptr().cast(), info) };
+    // SAFETY: Without an address, nothing is drawn.
+    let missing = unsafe { super::framebuffer_from_parts(ptr::null_mut(), info) };
+    let Some(surface) = framebuffer.raw_surface() else {
+        return false;
+    };
+
+    surface.fill_rect(0, 0, 2, 2, FramebufferColor::WHITE);
+    surface.blit(1, 2, 2, &[1, 2]);
+    // Where the padding is, which is outside of the surface.
+    surface.put_raw_pixel(3, 0, 7);
+    let read = surface.get_raw_pixel(2, 2) == Some(2) && surface.get_raw_pixel(3, 0).is_none();
+    surface.scroll_up(1);
+
+    missing.raw_surface().is_none() && read && pixels == [W, W, 0, 0, 0, 1, 2, 0, 0, 1, 2, 0]
+}
+
+/// Draws at two logical pixels through a [`RotatedSurface`] over a 4x2 buffer for every
+/// rotation, and checks where they land and the swapped dimensions.
+fn rotation_self_test() -> bool {
+    const WIDTH: u64 = 4;
+    const HEIGHT: u64 = 2;
+
+    let cases = [
+        (Rotation::None, (0, 0), (1, 0)),
+        (Rotation::Clockwise90, (3, 0), (3, 1)),
+        (Rotation::Clockwise180, (3, 1), (2, 1)),
+        (Rotation::Clockwise270, (0, 1), (0, 0)),
+    ];
+    cases.into_iter().all(|(rotation, origin, next)| {
+        let mut pixels = [0u32; (WIDTH * HEIGHT) as usize];
+        // SAFETY: The array holds the `pitch * height` bytes the info describes and
+        // outlives the surface.
+        let raw = unsafe {
+            RawSurface::new(
+                pixels.as_mut_ptr().cast(),
+                FramebufferInfo::xrgb8888(WIDTH, HEIGHT),
+            )
+        };
+        let surface = RotatedSurface::new(raw, rotation);
+        let dimensions = if rotation.is_transposed() {
+            (HEIGHT, WIDTH)
+        } else {
+            (WIDTH, HEIGHT)
+        };
+        let sized = (surface.width(), surface.height()) == dimensions;
+
+        surface.put_raw_pixel(0, 0, 1);
+        surface.put_raw_pixel(1, 0, 2);
+        let at = |(x, y): (u64, u64)| pixels[(y * WIDTH + x) as usize];
+        let placed =
+            at(origin) == 1 && at(next) == 2 && pixels.iter().filter(|&&p| p != 0).count() == 2;
+
+        sized && placed
+    })
+}
@@ -0,0 +1,123 @@
+//! Console color schemes.
+//!
+//! A [`Theme`] gives a console its default colors and the 16 ANSI colors, which are
+//! picked by index: 0 to 7 are black, red, green, yellow, blue, magenta, cyan and white,
+//! and 8 to 15 their bright variants. The `theme=dark` or `theme=light` option selects
+//! one of the built-in themes.
+
+use super::FramebufferColor;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Theme {
+    pub foreground: FramebufferColor,
+    pub background: FramebufferColor,
+    pub palette: [FramebufferColor; 16],
+}
+
+const fn palette(colors: [u32; 16]) -> [FramebufferColor; 16] {
+    let mut palette = [FramebufferColor::BLACK; 16];
+    let mut i = 0;
+    while i < palette.len() {
+        palette[i] = FramebufferColor::from_hex(colors[i]);
+        i += 1;
+    }
+    palette
+}
+
+impl Theme {
+    /// Light gray on black, with the classic VGA palette.
+    pub const DARK: Self = Self {
+        foreground: FramebufferColor::from_hex(0xaaaaaa),
+        background: FramebufferColor::BLACK,
+        palette: palette([
+            0x000000, 0xaa0000, 0x00aa00, 0xaa5500, 0x0000aa, 0xaa00aa, 0x00aaaa, 0xaaaaaa,
+            0x555555, 0xff5555, 0x55ff55, 0xffff55, 0x5555ff, 0xff55ff, 0x55ffff, 0xffffff,
+        ]),
+    };
+
+    /// Near black on off-white, with colors dark enough to read on it.
+    pub const LIGHT: Self = Self {
+        foreground: FramebufferColor::from_hex(0x202020),
+        background: FramebufferColor::from_hex(0xf5f5f5),
+        palette: palette([
+            0x202020, 0xb01010, 0x107010, 0x806000, 0x1030b0, 0x901090, 0x107070, 0xc0c0c0,
+            0x606060, 0xe03030, 0x30a030, 0xa08000, 0x3060e0, 0xc030c0, 0x30a0a0, 0xffffff,
+        ]),
+    };
+
+    /// Looks up a built-in theme by name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::DARK),
+            "light" => Some(Self::LIGHT),
+            _ => None,
+        }
+    }
+
+    /// The theme the `theme` option asks for, [`DARK`](Self::DARK) by default.
+    #[cfg(feature = "kernel-file")]
+    pub fn from_cmdline() -> Self {
+        let Some(name) = crate::boot::cmdline::value("theme") else {
+            return Self::DARK;
+        };
+        Self::from_name(name).unwrap_or_else(|| {
+            crate::kwarn!("unknown theme={}, using dark", name);
+            Self::DARK
+        })
+    }
+
+    /// The ANSI color `index`, or `None` if it is 16 or above.
+    pub fn ansi(&self, index: u8) -> Option<FramebufferColor> {
+        self.palette.get(index as usize).copied()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DARK
+    }
+}
+
+/// Converts colors to and from XRGB8888, BGR and RGB565 pixels, and looks up themes and
+/// their ANSI colors.
+pub fn self_test() -> bool {
+    use super::FramebufferInfo;
+
+    let orange = FramebufferColor::from_hex(0xff8000);
+    let hex_ok = orange == FramebufferColor::new(0xff, 0x80, 0x00)
+        && FramebufferColor::from_hex(0xab12_3456).to_hex() == 0x12_3456;
+
+    let xrgb = FramebufferInfo::xrgb8888(1, 1);
+    let bgr = FramebufferInfo {
+        red_mask_shift: 0,
+        blue_mask_shift: 16,
+        ..xrgb
+    };
+    let rgb565 = FramebufferInfo {
+        pitch: 2,
+        bpp: 16,
+        red_mask_size: 5,
+        red_mask_shift: 11,
+        green_mask_size: 6,
+        green_mask_shift: 5,
+        blue_mask_size: 5,
+        blue_mask_shift: 0,
+        ..xrgb
+    };
+    let formats_ok = xrgb.encode(orange) == 0xff8000
+        && xrgb.decode(0xff8000) == orange
+        && bgr.encode(orange) == 0x0080ff
+        && rgb565.encode(orange) == 0xfc00
+        // Channels come back scaled to 8 bits, so 0x80 in 6 bits comes back as 0x81.
+        && rgb565.decode(0xfc00) == FramebufferColor::new(0xff, 0x81, 0x00);
+
+    let themes_ok = Theme::from_name("dark") == Some(Theme::DARK)
+        && Theme::from_name("light") == Some(Theme::LIGHT)
+        && Theme::from_name("Dark").is_none()
+        && Theme::default() == Theme::DARK
+        && Theme::DARK.ansi(1) == Some(FramebufferColor::from_hex(0xaa0000))
+        && Theme::LIGHT.ansi(15) == Some(FramebufferColor::WHITE)
+        && Theme::DARK.ansi(16).is_none();
+
+    hex_ok && formats_ok && themes_ok
+}
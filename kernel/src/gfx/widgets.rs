@@ -0,0 +1,105 @@
+//! Simple widgets drawn straight to a framebuffer, for boot screens.
+
+use limine::LimineFramebuffer;
+
+use super::{FramebufferColor, LimineFramebufferExt};
+
+/// A horizontal bar filled from the left in proportion to the progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgressBar {
+    pub x: u64,
+    pub y: u64,
+    pub width: u64,
+    pub height: u64,
+    /// The color of the filled part.
+    pub foreground: FramebufferColor,
+    /// The color of the rest.
+    pub background: FramebufferColor,
+}
+
+impl ProgressBar {
+    pub const fn new(
+        x: u64,
+        y: u64,
+        width: u64,
+        height: u64,
+        foreground: FramebufferColor,
+        background: FramebufferColor,
+    ) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            foreground,
+            background,
+        }
+    }
+
+    /// How many pixels of the bar are filled at a progress of `numerator / denominator`.
+    /// Fractions above 1 fill the whole bar, a zero denominator none of it.
+    pub fn filled_width(&self, numerator: u32, denominator: u32) -> u64 {
+        if denominator == 0 {
+            return 0;
+        }
+        let numerator = numerator.min(denominator);
+        (self.width as u128 * numerator as u128 / denominator as u128) as u64
+    }
+
+    /// Redraws the bar at a progress of `fraction_num / fraction_den`. The unfilled part
+    /// is redrawn too, so progress may also go down.
+    pub fn set_progress(&self, fb: &LimineFramebuffer, fraction_num: u32, fraction_den: u32) {
+        let filled = self.filled_width(fraction_num, fraction_den);
+        fb.fill_rect(self.x, self.y, filled, self.height, self.foreground);
+        fb.fill_rect(
+            self.x + filled,
+            self.y,
+            self.width - filled,
+            self.height,
+            self.background,
+        );
+    }
+}
+
+/// Draws a bar at several progress values into a buffer and checks how much of it is
+/// filled, and that nothing outside of it is drawn.
+pub fn self_test() -> bool {
+    use super::{framebuffer_from_parts, FramebufferInfo};
+
+    const WIDTH: usize = 10;
+    const FILLED: u32 = 0xffffff;
+    const EMPTY: u32 = 0x808080;
+
+    let bar = ProgressBar::new(1, 0, 8, 1, FramebufferColor::WHITE, FramebufferColor::GRAY);
+    let mut pixels = [0u32; 2 * WIDTH];
+    // SAFETY: The array holds the `pitch * height` bytes of the info and outlives the
+    // framebuffer.
+    let fb = unsafe {
+        framebuffer_from_parts(
+            pixels.as_mut_ptr().cast(),
+            FramebufferInfo::xrgb8888(WIDTH as u64, 2),
+        )
+    };
+
+    // Progress, and the filled width it has to give. The last one goes back down.
+    let cases = [
+        (0, 4, 0),
+        (1, 2, 4),
+        (4, 4, 8),
+        (9, 4, 8),
+        (3, 0, 0),
+        (1, 4, 2),
+    ];
+    cases.into_iter().all(|(numerator, denominator, filled)| {
+        bar.set_progress(&fb, numerator, denominator);
+        let row = &pixels[..WIDTH];
+        bar.filled_width(numerator, denominator) == filled as u64
+            && row[1..1 + filled].iter().all(|&pixel| pixel == FILLED)
+            && row[1 + filled..WIDTH - 1]
+                .iter()
+                .all(|&pixel| pixel == EMPTY)
+            && row[0] == 0
+            && row[WIDTH - 1] == 0
+            && pixels[WIDTH..].iter().all(|&pixel| pixel == 0)
+    })
+}
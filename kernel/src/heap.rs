@@ -0,0 +1,422 @@
+//! A global allocator over the usable memory in the Limine memory map.
+//!
+//! Free memory is kept in a singly linked list of blocks sorted by address, whose nodes
+//! live in the free memory itself, reached through the HHDM. Allocation is first fit;
+//! freed blocks are merged with their neighbours.
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: LimineHeap = LimineHeap::new();
+//!
+//! unsafe { ALLOCATOR.init(MEMORY_MAP.response().unwrap(), &[]) };
+//! ```
+//!
+//! Every heap counts its allocations into the statistics [`stats`] returns. With the
+//! `heap-leaks` feature, live allocations are also recorded along with their caller, for
+//! [`report_leaks`].
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::mem::{align_of, size_of};
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use limine::{LimineMemmapResponse, LimineMemoryMapEntryType};
+use spin::Mutex;
+
+use crate::boot::memmap::PhysAddrRange;
+use crate::boot::ptr::ArrayPtrExt;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+
+/// Header of a free block.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Every block is at least this large and aligned, so it can hold its header once freed.
+const MIN_BLOCK: usize = size_of::<FreeBlock>();
+const BLOCK_ALIGN: usize = align_of::<FreeBlock>();
+
+struct FreeList {
+    head: *mut FreeBlock,
+}
+
+// The blocks are only reached through the list, which the mutex guards.
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    /// Inserts the free memory at `addr`, merging it with adjacent blocks.
+    ///
+    /// ## Safety
+    ///
+    /// `addr..addr + size` must be unused, writable memory that is `BLOCK_ALIGN` aligned
+    /// and at least `MIN_BLOCK` bytes, and not already on the list.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    /// Takes the first block that fits `size` bytes at `align`, returning its address.
+    unsafe fn allocate(&mut self, size: usize, align: usize) -> Option<usize> {
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut block = self.head;
+
+        while !block.is_null() {
+            let start = block as usize;
+            let end = start + (*block).size;
+            let next = (*block).next;
+
+            let mut addr = start.next_multiple_of(align);
+            // A gap in front of the allocation must be able to stay a free block.
+            if addr != start && addr - start < MIN_BLOCK {
+                addr = (start + MIN_BLOCK).next_multiple_of(align);
+            }
+
+            if addr
+                .checked_add(size)
+                .is_some_and(|alloc_end| alloc_end <= end)
+            {
+                let alloc_end = addr + size;
+                if prev.is_null() {
+                    self.head = next;
+                } else {
+                    (*prev).next = next;
+                }
+
+                if addr != start {
+                    self.insert(start, addr - start);
+                }
+                if end - alloc_end >= MIN_BLOCK {
+                    self.insert(alloc_end, end - alloc_end);
+                }
+                return Some(addr);
+            }
+
+            prev = block;
+            block = next;
+        }
+        None
+    }
+}
+
+/// A linked list allocator fed from the memory map. See the [module docs](self).
+pub struct LimineHeap {
+    free: Mutex<FreeList>,
+}
+
+impl LimineHeap {
+    /// Creates an empty heap. Every allocation fails until [`init`](Self::init).
+    pub const fn new() -> Self {
+        Self {
+            free: Mutex::new(FreeList {
+                head: ptr::null_mut(),
+            }),
+        }
+    }
+
+    /// Adds every usable region of `memmap` to the heap, except for the parts covered by
+    /// `exclude`.
+    ///
+    /// ## Safety
+    ///
+    /// Must be called at most once, and nothing else may use the usable memory outside
+    /// `exclude`: it is written to as soon as it is added.
+    pub unsafe fn init(&self, memmap: &LimineMemmapResponse, exclude: &[PhysAddrRange]) {
+        let entries = memmap.entries.iter(memmap.entry_count as usize);
+        for entry in entries.filter(|entry| entry.typ == LimineMemoryMapEntryType::Usable) {
+            self.add_excluding(entry.base..entry.base + entry.len, exclude);
+        }
+    }
+
+    unsafe fn add_excluding(&self, region: PhysAddrRange, exclude: &[PhysAddrRange]) {
+        let overlap = exclude
+            .iter()
+            .position(|range| range.start < region.end && region.start < range.end);
+        let Some(index) = overlap else {
+            self.add_region(region);
+            return;
+        };
+
+        let excluded = &exclude[index];
+        let rest = &exclude[index + 1..];
+        if region.start < excluded.start {
+            self.add_excluding(region.start..excluded.start, rest);
+        }
+        if excluded.end < region.end {
+            self.add_excluding(excluded.end..region.end, rest);
+        }
+    }
+
+    /// Adds the physical memory in `region` to the heap, for example memory reclaimed
+    /// after boot. Parts too small to hold a block are dropped.
+    ///
+    /// ## Safety
+    ///
+    /// The memory must be unused, and not already part of the heap.
+    pub unsafe fn add_region(&self, region: PhysAddrRange) {
+        let Some(hhdm) = HHDM.response() else {
+            return;
+        };
+
+        let start = (region.start + hhdm.offset) as usize;
+        let end = (region.end + hhdm.offset) as usize;
+        let start = start.next_multiple_of(BLOCK_ALIGN);
+        let end = end & !(BLOCK_ALIGN - 1);
+        if end > start && end - start >= MIN_BLOCK {
+            self.free.lock().insert(start, end - start);
+        }
+    }
+}
+
+impl Default for LimineHeap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The size and alignment a `layout` is actually served with.
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let align = layout.align().max(BLOCK_ALIGN);
+    let size = layout.size().max(MIN_BLOCK).next_multiple_of(BLOCK_ALIGN);
+    (size, align)
+}
+
+unsafe impl GlobalAlloc for LimineHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = block_layout(layout);
+        let Some(addr) = self.free.lock().allocate(size, align) else {
+            STATS.failed_allocations.fetch_add(1, Ordering::Relaxed);
+            return ptr::null_mut();
+        };
+
+        STATS.record_alloc(size);
+        #[cfg(feature = "heap-leaks")]
+        leaks::record(addr, size);
+        addr as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = block_layout(layout);
+        self.free.lock().insert(ptr as usize, size);
+
+        STATS.record_dealloc(size);
+        #[cfg(feature = "heap-leaks")]
+        leaks::forget(ptr as usize);
+    }
+}
+
+/// How many size classes [`HeapStats::size_classes`] has. Class `i` counts allocations
+/// of at most `16 << i` bytes, the last one everything larger.
+pub const SIZE_CLASSES: usize = 10;
+
+fn size_class(size: usize) -> usize {
+    let class = size.max(16).next_power_of_two().trailing_zeros() as usize - 4;
+    class.min(SIZE_CLASSES - 1)
+}
+
+/// A snapshot of the heap accounting. Sizes are those of the blocks handed out, which
+/// are rounded up from the requested ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStats {
+    pub live_bytes: usize,
+    pub live_allocations: usize,
+    pub peak_bytes: usize,
+    pub total_allocations: usize,
+    pub failed_allocations: usize,
+    /// How many allocations there have been of each size class.
+    pub size_classes: [usize; SIZE_CLASSES],
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "heap: {} bytes in {} allocations, peak {} bytes, {} allocations, {} failed",
+            self.live_bytes,
+            self.live_allocations,
+            self.peak_bytes,
+            self.total_allocations,
+            self.failed_allocations
+        )?;
+        for (class, &count) in self.size_classes.iter().enumerate() {
+            if class == SIZE_CLASSES - 1 {
+                write!(f, "  >{:<6} {}", 16 << (class - 1), count)?;
+            } else {
+                writeln!(f, "  <={:<5} {}", 16 << class, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lock free, so it is safe to update from interrupt handlers and never allocates.
+struct Accounting {
+    live_bytes: AtomicUsize,
+    live_allocations: AtomicUsize,
+    peak_bytes: AtomicUsize,
+    total_allocations: AtomicUsize,
+    failed_allocations: AtomicUsize,
+    size_classes: [AtomicUsize; SIZE_CLASSES],
+}
+
+impl Accounting {
+    fn record_alloc(&self, size: usize) {
+        let live = self.live_bytes.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak_bytes.fetch_max(live, Ordering::Relaxed);
+        self.live_allocations.fetch_add(1, Ordering::Relaxed);
+        self.total_allocations.fetch_add(1, Ordering::Relaxed);
+        self.size_classes[size_class(size)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(&self, size: usize) {
+        self.live_bytes.fetch_sub(size, Ordering::Relaxed);
+        self.live_allocations.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+static STATS: Accounting = Accounting {
+    live_bytes: AtomicUsize::new(0),
+    live_allocations: AtomicUsize::new(0),
+    peak_bytes: AtomicUsize::new(0),
+    total_allocations: AtomicUsize::new(0),
+    failed_allocations: AtomicUsize::new(0),
+    size_classes: [const { AtomicUsize::new(0) }; SIZE_CLASSES],
+};
+
+/// Returns the current heap statistics. The counters are read one by one, so a snapshot
+/// taken while other CPUs allocate can be slightly inconsistent.
+pub fn stats() -> HeapStats {
+    HeapStats {
+        live_bytes: STATS.live_bytes.load(Ordering::Relaxed),
+        live_allocations: STATS.live_allocations.load(Ordering::Relaxed),
+        peak_bytes: STATS.peak_bytes.load(Ordering::Relaxed),
+        total_allocations: STATS.total_allocations.load(Ordering::Relaxed),
+        failed_allocations: STATS.failed_allocations.load(Ordering::Relaxed),
+        size_classes: core::array::from_fn(|class| {
+            STATS.size_classes[class].load(Ordering::Relaxed)
+        }),
+    }
+}
+
+/// Prints every live allocation the leak table knows of, with its size and caller.
+#[cfg(feature = "heap-leaks")]
+pub fn report_leaks() {
+    leaks::report();
+}
+
+#[cfg(feature = "heap-leaks")]
+mod leaks {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::kprintln;
+
+    /// How many live allocations are tracked. Allocations made while the table is full
+    /// are only counted.
+    const SLOTS: usize = 256;
+
+    struct Slot {
+        /// The allocation's address, 0 if the slot is free.
+        addr: AtomicUsize,
+        size: AtomicUsize,
+        caller: AtomicUsize,
+    }
+
+    static TABLE: [Slot; SLOTS] = [const {
+        Slot {
+            addr: AtomicUsize::new(0),
+            size: AtomicUsize::new(0),
+            caller: AtomicUsize::new(0),
+        }
+    }; SLOTS];
+    static UNTRACKED: AtomicUsize = AtomicUsize::new(0);
+
+    // Kept out of line so `caller` knows which frame it is in.
+    #[inline(never)]
+    pub(super) fn record(addr: usize, size: usize) {
+        let caller = caller();
+        let slot = TABLE.iter().find(|slot| {
+            slot.addr
+                .compare_exchange(0, addr, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        });
+        match slot {
+            Some(slot) => {
+                slot.size.store(size, Ordering::Relaxed);
+                slot.caller.store(caller, Ordering::Release);
+            }
+            None => {
+                UNTRACKED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(super) fn forget(addr: usize) {
+        if let Some(slot) = TABLE
+            .iter()
+            .find(|slot| slot.addr.load(Ordering::Relaxed) == addr)
+        {
+            slot.addr.store(0, Ordering::Release);
+        }
+    }
+
+    pub(super) fn report() {
+        for slot in &TABLE {
+            let addr = slot.addr.load(Ordering::Acquire);
+            if addr != 0 {
+                kprintln!(
+                    "{} bytes at {:#x} from {:#x}",
+                    slot.size.load(Ordering::Relaxed),
+                    addr,
+                    slot.caller.load(Ordering::Acquire)
+                );
+            }
+        }
+        let untracked = UNTRACKED.load(Ordering::Relaxed);
+        if untracked != 0 {
+            kprintln!("{} allocations weren't tracked", untracked);
+        }
+    }
+
+    /// The address the allocator returns to, `__rust_alloc`'s or, where that and the
+    /// allocator were inlined, the allocating code's. 0 where frame pointers can't be
+    /// followed.
+    #[inline(always)]
+    fn caller() -> usize {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use crate::arch::x86_64::extable;
+
+            let rbp: u64;
+            // SAFETY: Only reads the frame pointer.
+            unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp) };
+            extable::read_u64(rbp)
+                .and_then(|caller_rbp| extable::read_u64(caller_rbp + 8))
+                .unwrap_or(0) as usize
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        0
+    }
+}
@@ -0,0 +1,77 @@
+//! Translation between link-time and load-time kernel addresses.
+//!
+//! The kernel is linked at [`LINK_BASE`] but Limine may load it anywhere in the top 2 GiB,
+//! sliding every address by the same amount. Symbol tables and the linker script speak
+//! link-time addresses, while instruction pointers and page tables see load-time ones;
+//! everything converting between the two goes through here.
+
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::KERNEL_ADDRESS;
+
+/// The address the linker script places the kernel at.
+pub const LINK_BASE: u64 = 0xffff_ffff_8000_0000;
+
+extern "C" {
+    static __kernel_start: u8;
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+    static __data_start: u8;
+    static __kernel_end: u8;
+}
+
+/// The load-time address of a linker script symbol. Position independent code computes
+/// it relative to the instruction pointer, so it already includes the slide.
+macro_rules! symbol {
+    ($name:ident) => {
+        (&raw const $name) as u64
+    };
+}
+
+/// How far the kernel was moved from where it was linked.
+///
+/// Taken from the kernel address response, or from where the kernel's first byte ended
+/// up if the bootloader didn't answer.
+pub fn slide() -> u64 {
+    let base = KERNEL_ADDRESS
+        .response()
+        .map_or(symbol!(__kernel_start), |response| response.virtual_base);
+    base.wrapping_sub(LINK_BASE)
+}
+
+pub fn link_to_runtime(addr: u64) -> u64 {
+    addr.wrapping_add(slide())
+}
+
+pub fn runtime_to_link(addr: u64) -> u64 {
+    addr.wrapping_sub(slide())
+}
+
+/// Whether the load-time address `addr` is in the kernel's code.
+pub fn is_kernel_text(addr: u64) -> bool {
+    (symbol!(__text_start)..symbol!(__text_end)).contains(&addr)
+}
+
+/// Whether the load-time address `addr` is in the kernel's read-only data.
+pub fn is_kernel_rodata(addr: u64) -> bool {
+    (symbol!(__rodata_start)..symbol!(__rodata_end)).contains(&addr)
+}
+
+/// Whether the load-time address `addr` is in the kernel's writable data or bss.
+pub fn is_kernel_data(addr: u64) -> bool {
+    (symbol!(__data_start)..symbol!(__kernel_end)).contains(&addr)
+}
+
+/// Checks that the slide the bootloader reported matches where the kernel actually runs,
+/// and that translating an address of this very function round-trips back into the
+/// kernel's code.
+pub fn self_check() -> bool {
+    let here = self_check as fn() -> bool as usize as u64;
+    let link = runtime_to_link(here);
+
+    runtime_to_link(symbol!(__kernel_start)) == LINK_BASE
+        && is_kernel_text(here)
+        && link >= LINK_BASE
+        && link_to_runtime(link) == here
+}
@@ -0,0 +1,76 @@
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(target_arch = "x86_64", feature(abi_x86_interrupt))]
+
+pub mod arch;
+pub mod array_vec;
+pub mod boot;
+#[cfg(feature = "memory-map")]
+pub mod bootalloc;
+pub mod boottrace;
+pub mod crypto;
+#[cfg(feature = "dma")]
+pub mod dma;
+#[cfg(all(feature = "efi", target_arch = "x86_64"))]
+pub mod efi;
+pub mod elf;
+pub mod fmt;
+#[cfg(feature = "memory-map")]
+pub mod frame;
+pub mod gfx;
+#[cfg(feature = "global-allocator")]
+pub mod heap;
+#[cfg(feature = "kernel-address")]
+pub mod kaslr;
+pub mod log;
+#[cfg(target_arch = "x86_64")]
+pub mod mem;
+#[cfg(all(feature = "monitor", target_arch = "x86_64"))]
+pub mod monitor;
+pub mod pci;
+pub mod print;
+#[cfg(feature = "pstore")]
+pub mod pstore;
+pub mod rng;
+#[cfg(target_arch = "x86_64")]
+pub mod serial;
+#[cfg(all(feature = "shell", target_arch = "x86_64"))]
+pub mod shell;
+pub mod smp;
+#[cfg(all(feature = "stack-protector", target_arch = "x86_64"))]
+pub mod stack_protector;
+#[cfg(all(feature = "tasks", target_arch = "x86_64"))]
+pub mod task;
+pub mod time;
+#[cfg(all(feature = "usermode", target_arch = "x86_64"))]
+pub mod usermode;
+#[cfg(all(feature = "virtio", target_arch = "x86_64"))]
+pub mod virtio;
+#[cfg(all(feature = "watchdog", target_arch = "x86_64"))]
+pub mod watchdog;
+
+/// The kernel's idle loop: reports deferred events, handles shell input and lets the
+/// ready tasks run, then halts until the next interrupt.
+pub fn idle() -> ! {
+    loop {
+        #[cfg(target_arch = "x86_64")]
+        arch::x86_64::nmi::report_pending();
+        #[cfg(all(feature = "watchdog", target_arch = "x86_64"))]
+        watchdog::pet();
+        #[cfg(all(feature = "shell", target_arch = "x86_64"))]
+        shell::poll();
+        #[cfg(feature = "log-console")]
+        gfx::log_console::process_pending();
+        #[cfg(all(feature = "tasks", target_arch = "x86_64"))]
+        task::schedule();
+
+        arch::wait_for_interrupt();
+    }
+}
+
+/// Halt and catch fire: disable interrupts and halt the CPU forever.
+pub fn hcf() -> ! {
+    arch::disable_interrupts();
+    loop {
+        arch::wait_for_interrupt();
+    }
+}
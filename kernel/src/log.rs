@@ -0,0 +1,296 @@
+//! Leveled logging on top of [`kprintln!`](crate::kprintln), filtered per module.
+//!
+//! Every record has a level and a target, the module path of the code logging it. A
+//! record is printed if its level is at most the one the longest matching module prefix
+//! allows, or the global maximum if no prefix matches. Both come from the command line:
+//!
+//! ```text
+//! loglevel=debug logfilter=kernel::pci=warn,kernel::acpi=trace
+//! ```
+//!
+//! Until [`init`] runs, everything up to [`Level::Info`] is printed.
+
+use core::fmt;
+
+use spin::Once;
+
+use crate::array_vec::ArrayVec;
+
+/// How many `logfilter` directives are kept. Further ones are reported and skipped.
+pub const MAX_DIRECTIVES: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The most verbose level let through, or `Off`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LevelFilter {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    /// Parses a level name as used on the command line, ignoring case.
+    pub fn parse(name: &str) -> Option<Self> {
+        const NAMES: [(&str, LevelFilter); 6] = [
+            ("off", LevelFilter::Off),
+            ("error", LevelFilter::Error),
+            ("warn", LevelFilter::Warn),
+            ("info", LevelFilter::Info),
+            ("debug", LevelFilter::Debug),
+            ("trace", LevelFilter::Trace),
+        ];
+        NAMES
+            .iter()
+            .find(|(level, _)| level.eq_ignore_ascii_case(name))
+            .map(|&(_, filter)| filter)
+    }
+
+    pub fn allows(self, level: Level) -> bool {
+        level as u8 <= self as u8
+    }
+}
+
+/// Why a `logfilter` directive was skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirectiveError<'a> {
+    /// The directive isn't of the form `module=level`.
+    Malformed(&'a str),
+    /// The level isn't one [`LevelFilter::parse`] knows.
+    UnknownLevel(&'a str),
+    /// There were more than [`MAX_DIRECTIVES`].
+    TooMany(&'a str),
+}
+
+impl fmt::Display for DirectiveError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(directive) => write!(f, "malformed log directive `{}`", directive),
+            Self::UnknownLevel(level) => write!(f, "unknown log level `{}`", level),
+            Self::TooMany(directive) => write!(
+                f,
+                "more than {} log directives, skipping `{}`",
+                MAX_DIRECTIVES, directive
+            ),
+        }
+    }
+}
+
+/// A global maximum level and per-module overrides.
+#[derive(Debug)]
+pub struct Filter<'a> {
+    max: LevelFilter,
+    directives: ArrayVec<(&'a str, LevelFilter), MAX_DIRECTIVES>,
+}
+
+impl<'a> Filter<'a> {
+    pub const fn new(max: LevelFilter) -> Self {
+        Self {
+            max,
+            directives: ArrayVec::new(),
+        }
+    }
+
+    /// Adds the comma separated `module=level` directives in `spec`. Directives that
+    /// can't be used are passed to `report` and skipped, the rest still apply.
+    pub fn parse_directives(&mut self, spec: &'a str, mut report: impl FnMut(DirectiveError<'a>)) {
+        for directive in spec.split(',').filter(|directive| !directive.is_empty()) {
+            let Some((module, level)) = directive
+                .split_once('=')
+                .filter(|(module, _)| !module.is_empty())
+            else {
+                report(DirectiveError::Malformed(directive));
+                continue;
+            };
+            let Some(level) = LevelFilter::parse(level) else {
+                report(DirectiveError::UnknownLevel(level));
+                continue;
+            };
+            if self.directives.push((module, level)).is_err() {
+                report(DirectiveError::TooMany(directive));
+            }
+        }
+    }
+
+    /// The filter for `target`: that of the longest directive whose module is `target` or
+    /// one of its parents, else the global maximum.
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(module, _)| is_module_prefix(module, target))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.max, |&(_, level)| level)
+    }
+
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        self.level_for(target).allows(level)
+    }
+}
+
+/// Whether `module` names `target` or a module containing it. `kernel::pci` matches
+/// `kernel::pci::msi` but not `kernel::pcie`.
+fn is_module_prefix(module: &str, target: &str) -> bool {
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+static FILTER: Once<Filter<'static>> = Once::new();
+
+static DEFAULT_FILTER: Filter<'static> = Filter::new(LevelFilter::Info);
+
+/// Installs the filter described by the `loglevel` and `logfilter` options. Only the
+/// first call has an effect.
+#[cfg(feature = "kernel-file")]
+pub fn init() {
+    use crate::boot::cmdline;
+
+    FILTER.call_once(|| {
+        let max = match cmdline::value("loglevel") {
+            Some(name) => LevelFilter::parse(name).unwrap_or_else(|| {
+                crate::kprintln!("log: {}", DirectiveError::UnknownLevel(name));
+                DEFAULT_FILTER.max
+            }),
+            None => DEFAULT_FILTER.max,
+        };
+
+        let mut filter = Filter::new(max);
+        if let Some(spec) = cmdline::value("logfilter") {
+            filter.parse_directives(spec, |err| crate::kprintln!("log: {}", err));
+        }
+        filter
+    });
+}
+
+/// Installs `filter`, unless one was installed already.
+pub fn set_filter(filter: Filter<'static>) {
+    FILTER.call_once(|| filter);
+}
+
+/// Whether a record of `level` from `target` would be printed.
+pub fn enabled(level: Level, target: &str) -> bool {
+    FILTER
+        .get()
+        .unwrap_or(&DEFAULT_FILTER)
+        .enabled(level, target)
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, target: &str, args: fmt::Arguments) {
+    if enabled(level, target) {
+        crate::kprintln!("[{} {}] {}", level, target, args);
+    }
+}
+
+/// Logs at the given level, with the calling module as the target.
+#[macro_export]
+macro_rules! klog {
+    ($level:expr, $($arg:tt)*) => {
+        $crate::log::_log($level, module_path!(), format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! kerror {
+    ($($arg:tt)*) => ($crate::klog!($crate::log::Level::Error, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! kwarn {
+    ($($arg:tt)*) => ($crate::klog!($crate::log::Level::Warn, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! kinfo {
+    ($($arg:tt)*) => ($crate::klog!($crate::log::Level::Info, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! kdebug {
+    ($($arg:tt)*) => ($crate::klog!($crate::log::Level::Debug, $($arg)*));
+}
+
+#[macro_export]
+macro_rules! ktrace {
+    ($($arg:tt)*) => ($crate::klog!($crate::log::Level::Trace, $($arg)*));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_prefix() {
+        assert!(is_module_prefix("kernel::pci", "kernel::pci"));
+        assert!(is_module_prefix("kernel::pci", "kernel::pci::msi"));
+        assert!(is_module_prefix("kernel", "kernel::pci"));
+        assert!(!is_module_prefix("kernel::pci", "kernel::pcie"));
+        assert!(!is_module_prefix("kernel::pci::msi", "kernel::pci"));
+    }
+
+    #[test]
+    fn directives() {
+        let mut filter = Filter::new(LevelFilter::Info);
+        let mut errors = Vec::new();
+        filter.parse_directives(
+            "kernel::pci=warn,,kernel::pci::msi=TRACE,kernel::acpi,=debug,kernel::fs=loud",
+            |err| errors.push(err),
+        );
+        assert_eq!(
+            errors,
+            [
+                DirectiveError::Malformed("kernel::acpi"),
+                DirectiveError::Malformed("=debug"),
+                DirectiveError::UnknownLevel("loud"),
+            ]
+        );
+        assert_eq!(filter.level_for("kernel::pci"), LevelFilter::Warn);
+        assert_eq!(
+            filter.level_for("kernel::pci::msi::vector"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.level_for("kernel::pcie"), LevelFilter::Info);
+        assert_eq!(filter.level_for("kernel::fs"), LevelFilter::Info);
+        assert!(filter.enabled(Level::Warn, "kernel::pci"));
+        assert!(!filter.enabled(Level::Info, "kernel::pci"));
+    }
+
+    #[test]
+    fn too_many_directives() {
+        let mut full = Filter::new(LevelFilter::Off);
+        let mut errors = Vec::new();
+        for _ in 0..=MAX_DIRECTIVES {
+            full.parse_directives("kernel=error", |err| errors.push(err));
+        }
+        assert_eq!(errors, [DirectiveError::TooMany("kernel=error")]);
+        assert!(!full.enabled(Level::Info, "other"));
+    }
+}
@@ -1,22 +1,157 @@
 #![no_std]
 #![no_main]
 
-use core::arch::asm;
-
-use limine::LimineFramebufferRequest;
-
-static FRAMEBUFFER_REQUEST: LimineFramebufferRequest = LimineFramebufferRequest::new(0);
+#[cfg(any(
+    feature = "framebuffer",
+    feature = "pstore",
+    feature = "smbios",
+    all(feature = "monitor", target_arch = "x86_64")
+))]
+use kernel::boot::request::LimineRequest;
+#[cfg(feature = "framebuffer")]
+use kernel::boot::requests::FRAMEBUFFER;
+use kernel::{boottrace, hcf, kprintln};
 
 #[no_mangle]
 unsafe extern "C" fn _start() -> ! {
+    kernel::boot::limine_init();
+    // Before anything stores a canary with the built-in guard and returns.
+    #[cfg(all(feature = "stack-protector", target_arch = "x86_64"))]
+    kernel::stack_protector::init();
+    kernel::boot::ptr::set_null_response_handler(|name| {
+        kprintln!("the bootloader provided no {}", name)
+    });
+    #[cfg(feature = "legacy-terminal")]
+    kernel::boot::terminal::init();
+    {
+        let _span = boottrace::span("arch_init");
+        kernel::arch::init();
+    }
+    {
+        let _span = boottrace::span("timers");
+        #[cfg(all(feature = "acpi", target_arch = "x86_64"))]
+        {
+            let _span = boottrace::span("acpi_hpet");
+            if let Err(err) = kernel::time::register_acpi_hpet() {
+                kprintln!("not using the HPET: {}", err);
+            }
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            let _span = boottrace::span("tsc_calibration");
+            kernel::time::init();
+        }
+    }
+    #[cfg(feature = "kernel-file")]
+    {
+        let _span = boottrace::span("log_init");
+        kernel::log::init();
+    }
+    #[cfg(feature = "pstore")]
+    if let Some(memmap) = kernel::boot::requests::MEMORY_MAP.response() {
+        let _span = boottrace::span("pstore_init");
+        kernel::pstore::init(memmap);
+    }
+    #[cfg(all(feature = "watchdog", target_arch = "x86_64"))]
+    kernel::watchdog::init();
+    kernel::arch::enable_interrupts();
+    #[cfg(target_arch = "x86_64")]
+    kernel::time::enable_sleeping();
+
+    #[cfg(feature = "self-test")]
+    {
+        let _span = boottrace::span("self_tests");
+        run_self_tests();
+    }
+
+    #[cfg(all(feature = "acpi", target_arch = "x86_64"))]
+    if kernel::pci::self_check() == Some(false) {
+        kprintln!("ECAM and port PCI configuration access disagree");
+    }
+
+    #[cfg(all(feature = "stack-protector-test", target_arch = "x86_64"))]
+    kernel::stack_protector::smash();
+
+    #[cfg(feature = "kernel-address")]
+    if !kernel::kaslr::self_check() {
+        kprintln!("kernel address translation self check failed");
+    }
+
+    #[cfg(feature = "smbios")]
+    if let Some(response) = kernel::boot::requests::SMBIOS.response() {
+        match kernel::boot::smbios::Smbios::from_response(response) {
+            Ok(smbios) => {
+                if let Some(system) = smbios.system_info() {
+                    kprintln!(
+                        "Running on {} {}",
+                        system.manufacturer().unwrap_or("unknown"),
+                        system.product().unwrap_or("machine")
+                    );
+                }
+            }
+            Err(err) => kprintln!("{}", err),
+        }
+    }
+
+    #[cfg(all(feature = "usermode", target_arch = "x86_64"))]
+    match kernel::usermode::run() {
+        Ok(exit) => kprintln!("user task finished: {:?}", exit),
+        Err(err) => kprintln!("failed to run the user task: {:?}", err),
+    }
+
+    #[cfg(all(feature = "virtio", target_arch = "x86_64"))]
+    {
+        let _span = boottrace::span("virtio_disk");
+        dump_first_sector();
+    }
+
+    // Without a screen, the monitor on COM1 is the only way to look around.
+    #[cfg(all(feature = "monitor", target_arch = "x86_64"))]
+    {
+        #[cfg(feature = "framebuffer")]
+        let has_screen = FRAMEBUFFER
+            .response()
+            .is_some_and(|response| response.framebuffer_count > 0);
+        #[cfg(not(feature = "framebuffer"))]
+        let has_screen = false;
+
+        if !has_screen {
+            if let Some(memmap) = kernel::boot::requests::MEMORY_MAP.response() {
+                kernel::monitor::run(&mut kernel::serial::COM1.lock(), memmap);
+            }
+        }
+    }
+
     // Ensure we got a framebuffer.
-    if let Some(framebuffer_response) = FRAMEBUFFER_REQUEST.get_response().get() {
+    #[cfg(feature = "framebuffer")]
+    if let Some(framebuffer_response) = FRAMEBUFFER.response() {
         if framebuffer_response.framebuffer_count < 1 {
             hcf();
         }
 
         // Get the first framebuffer's information.
         let framebuffer = &framebuffer_response.framebuffers()[0];
+        kernel::gfx::panic::register_framebuffer(framebuffer);
+
+        let console_init = boottrace::span("console_init");
+        // The log console takes over the screen, the banner goes to the log instead.
+        #[cfg(feature = "log-console")]
+        {
+            kernel::gfx::log_console::init(framebuffer);
+            kprintln!("limine-rust-barebones");
+        }
+        #[cfg(all(feature = "kernel-file", not(feature = "log-console")))]
+        {
+            use kernel::gfx::console::BasicConsole;
+            use kernel::gfx::surface::{RotatedSurface, Rotation};
+            use kernel::gfx::theme::Theme;
+
+            let surface = RotatedSurface::new(&**framebuffer, Rotation::from_cmdline());
+            let mut console = BasicConsole::with_theme(surface, Theme::from_cmdline());
+            console.clear();
+            console.write_str("limine-rust-barebones\n");
+        }
+        drop(console_init);
 
         for i in 0..100_usize {
             // Calculate the pixel offset using the framebuffer information we obtained above.
@@ -27,24 +162,265 @@ unsafe extern "C" fn _start() -> ! {
             // We can safely unwrap the result of `as_ptr()` because the framebuffer address is
             // guaranteed to be provided by the bootloader.
             unsafe {
-                *(framebuffer.address.as_ptr().unwrap().offset(pixel_offset as isize) as *mut u32) = 0xFFFFFFFF;
+                *(framebuffer.address.as_ptr().unwrap().add(pixel_offset) as *mut u32) = 0xFFFFFFFF;
             }
         }
     }
 
-    hcf();
+    #[cfg(all(feature = "tasks", target_arch = "x86_64"))]
+    if let Err(err) = demo_tasks::spawn() {
+        kprintln!("failed to start the demo tasks: {}", err);
+    }
+
+    #[cfg(all(feature = "shell", target_arch = "x86_64"))]
+    kernel::shell::init();
+
+    boottrace::report();
+
+    kernel::idle();
 }
 
-#[panic_handler]
-fn rust_panic(_info: &core::panic::PanicInfo) -> ! {
-    hcf();
+/// Runs the modules' boot-time self tests and logs the ones that fail. Some briefly take
+/// over live state, like the debug registers, the framebuffer write mode or a deliberate
+/// page fault, so they only run in builds with the `self-test` feature.
+#[cfg(feature = "self-test")]
+fn run_self_tests() {
+    #[cfg(target_arch = "x86_64")]
+    if !kernel::arch::x86_64::debug::self_test() {
+        kprintln!("hardware watchpoint self test failed");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    if !kernel::mem::self_test() {
+        kprintln!("fault-safe memory access self test failed");
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    if !kernel::serial::self_test() {
+        kprintln!("serial input self test failed");
+    }
+
+    #[cfg(all(feature = "monitor", target_arch = "x86_64"))]
+    if !kernel::monitor::self_test() {
+        kprintln!("monitor parser self test failed");
+    }
+
+    if !kernel::boot::ptr::self_test() {
+        kprintln!("ArrayPtr iteration self test failed");
+    }
+
+    if !kernel::boot::request::self_test() {
+        kprintln!("request response self test failed");
+    }
+
+    if !kernel::boot::compat::self_test() {
+        kprintln!("revision gate self test failed");
+    }
+
+    #[cfg(any(feature = "framebuffer", feature = "memory-map"))]
+    if !kernel::boot::info::self_test() {
+        kprintln!("boot info collection self test failed");
+    }
+
+    #[cfg(feature = "framebuffer")]
+    if !kernel::boot::framebuffer::self_test() {
+        kprintln!("framebuffer response self test failed");
+    }
+
+    #[cfg(feature = "memory-map")]
+    if !kernel::boot::memmap::self_test() {
+        kprintln!("memory map self test failed");
+    }
+
+    #[cfg(feature = "modules")]
+    if !kernel::boot::module::self_test() {
+        kprintln!("module verification self test failed");
+    }
+
+    if !kernel::crypto::crc32::self_test() {
+        kprintln!("CRC-32 self test failed");
+    }
+
+    if !kernel::crypto::sha256::self_test() {
+        kprintln!("SHA-256 self test failed");
+    }
+
+    #[cfg(feature = "memory-map")]
+    if !kernel::bootalloc::self_test() {
+        kprintln!("boot allocation self test failed");
+    }
+
+    #[cfg(feature = "memory-map")]
+    if !kernel::frame::self_test() {
+        kprintln!("frame allocator self test failed");
+    }
+
+    if !kernel::time::self_test() {
+        kprintln!("cycle counter self test failed");
+    }
+
+    if !kernel::rng::self_test() {
+        kprintln!("random number generator self test failed");
+    }
+
+    if !kernel::elf::self_test() {
+        kprintln!("ELF symbol lookup self test failed");
+    }
+
+    if !kernel::gfx::self_test() {
+        kprintln!("framebuffer drawing self test failed");
+    }
+
+    if !kernel::gfx::theme::self_test() {
+        kprintln!("color and theme self test failed");
+    }
+
+    if !kernel::gfx::surface::self_test() {
+        kprintln!("surface self test failed");
+    }
+
+    if !kernel::gfx::console::self_test() {
+        kprintln!("console self test failed");
+    }
+
+    if !kernel::gfx::widgets::self_test() {
+        kprintln!("progress bar self test failed");
+    }
+
+    if !kernel::gfx::panic::self_test() {
+        kprintln!("panic screen self test failed");
+    }
+
+    #[cfg(feature = "acpi")]
+    if !kernel::boot::acpi::self_test() {
+        kprintln!("ACPI HPET table self test failed");
+    }
+
+    #[cfg(feature = "dtb")]
+    if !kernel::boot::dtb::self_test() {
+        kprintln!("device tree size self test failed");
+    }
+
+    #[cfg(feature = "firmware-type")]
+    if !kernel::boot::firmware::self_test() {
+        kprintln!("firmware type self test failed");
+    }
+
+    #[cfg(all(feature = "paging-mode", target_arch = "x86_64"))]
+    if !kernel::boot::paging_mode::self_test() {
+        kprintln!("paging mode response self test failed");
+    }
 }
 
-fn hcf() -> ! {
-    unsafe {
-        asm!("cli");
+/// Prints the first sector of the first virtio disk, as `make run-virtio` attaches one.
+#[cfg(all(feature = "virtio", target_arch = "x86_64"))]
+fn dump_first_sector() {
+    use kernel::fmt::hexdump;
+    use kernel::print::LogWriter;
+    use kernel::virtio::blk::{Block, SECTOR_SIZE};
+
+    /// Plenty for the queue, a request page and the bounce buffer.
+    const DMA_POOL_SIZE: u64 = 64 * 1024;
+
+    if let Err(err) = kernel::dma::reserve_pool(DMA_POOL_SIZE, kernel::dma::DMA32_LIMIT) {
+        kprintln!("no DMA memory for virtio: {}", err);
+        return;
+    }
+    let mut disk = match Block::probe(kernel::pci::access()) {
+        Some(Ok(disk)) => disk,
+        Some(Err(err)) => {
+            kprintln!("virtio disk: {}", err);
+            return;
+        }
+        None => return,
+    };
+
+    let mut sector = [0; SECTOR_SIZE];
+    if let Err(err) = disk.read(0, &mut sector) {
+        kprintln!("virtio disk: {}", err);
+        return;
+    }
+    kprintln!("virtio disk, {} sectors, sector 0:", disk.capacity());
+    let _ = hexdump(&mut LogWriter, 0, &sector);
+}
+
+/// Two tasks sharing the CPU: one blinks a cursor block under the banner, the other
+/// reports PS/2 keyboard scancodes.
+#[cfg(all(feature = "tasks", target_arch = "x86_64"))]
+mod demo_tasks {
+    #[cfg(feature = "framebuffer")]
+    use kernel::arch::x86_64::pit;
+    use kernel::arch::x86_64::port::inb;
+    use kernel::task::{self, TaskError};
+
+    /// Timer ticks the cursor stays on or off for.
+    #[cfg(feature = "framebuffer")]
+    const BLINK_TICKS: u64 = 50;
+
+    const PS2_DATA: u16 = 0x60;
+    const PS2_STATUS: u16 = 0x64;
+    /// Set while the output buffer holds a byte for us.
+    const PS2_OUTPUT_FULL: u8 = 1 << 0;
+
+    pub fn spawn() -> Result<(), TaskError> {
+        task::init()?;
+        #[cfg(feature = "framebuffer")]
+        task::spawn(blink_cursor)?;
+        task::spawn(poll_keyboard)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "framebuffer")]
+    fn blink_cursor() {
+        use kernel::boot::request::LimineRequest;
+        use kernel::gfx::font::Font;
+        use kernel::gfx::theme::Theme;
+        use kernel::gfx::LimineFramebufferExt;
+
+        let Some(response) = super::FRAMEBUFFER.response() else {
+            return;
+        };
+        let Some(framebuffer) = response.framebuffers().first() else {
+            return;
+        };
+        let font = Font::boot_font();
+        let theme = Theme::DARK;
+
+        let mut shown = None;
         loop {
-            asm!("hlt");
+            let visible = (pit::ticks() / BLINK_TICKS).is_multiple_of(2);
+            if shown != Some(visible) {
+                let color = if visible {
+                    theme.foreground
+                } else {
+                    theme.background
+                };
+                framebuffer.fill_rect(0, font.height(), font.width(), font.height(), color);
+                shown = Some(visible);
+            }
+            task::yield_now();
         }
     }
+
+    fn poll_keyboard() {
+        loop {
+            // SAFETY: Reading the PS/2 controller's ports has no side effects beyond
+            // taking the byte we were handed.
+            unsafe {
+                if inb(PS2_STATUS) & PS2_OUTPUT_FULL != 0 {
+                    kernel::kprintln!("scancode {:#04x}", inb(PS2_DATA));
+                }
+            }
+            task::yield_now();
+        }
+    }
+}
+
+#[panic_handler]
+fn rust_panic(info: &core::panic::PanicInfo) -> ! {
+    kprintln!("{}", info);
+    #[cfg(feature = "pstore")]
+    kernel::pstore::record_panic(info);
+    kernel::gfx::panic::show(info);
+    hcf();
 }
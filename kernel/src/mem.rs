@@ -0,0 +1,79 @@
+//! Memory accesses that survive faults.
+//!
+//! The copies go through the [exception table](crate::arch::x86_64::extable), so reading
+//! an unmapped or non-canonical address returns a [`Fault`] instead of taking the kernel
+//! down. This is what debuggers and the shell need to poke at addresses they were given.
+
+use core::fmt;
+use core::mem::{size_of, MaybeUninit};
+
+use crate::arch::x86_64::extable;
+
+/// An access that faulted, after `copied` bytes went through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub copied: usize,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory access faulted after {} bytes", self.copied)
+    }
+}
+
+/// Types for which every bit pattern is a valid value, so they can be read from
+/// arbitrary memory.
+///
+/// ## Safety
+///
+/// Implementors must have no padding, invalid values or pointers that are dereferenced
+/// without checks.
+pub unsafe trait Plain: Copy {}
+
+macro_rules! impl_plain {
+    ($($ty:ty),*) => {
+        $(unsafe impl Plain for $ty {})*
+    };
+}
+
+impl_plain!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// Copies `len` bytes from `src` to `dst`, failing at the first byte that can't be read
+/// or written. The bytes before it are copied either way.
+///
+/// ## Safety
+///
+/// `dst` must not overlap `src`, and writing to it must not break anything the kernel
+/// relies on.
+pub unsafe fn try_copy(dst: *mut u8, src: *const u8, len: usize) -> Result<(), Fault> {
+    extable::copy(dst, src, len).map_err(|copied| Fault { copied })
+}
+
+/// Reads a `T` from `addr`, which doesn't need to be aligned or even mapped.
+pub fn try_read<T: Plain>(addr: u64) -> Result<T, Fault> {
+    let mut value = MaybeUninit::<T>::uninit();
+    // SAFETY: The destination is a local of the right size, and `T` is valid for
+    // whatever bytes were read.
+    unsafe {
+        try_copy(value.as_mut_ptr().cast(), addr as *const u8, size_of::<T>())?;
+        Ok(value.assume_init())
+    }
+}
+
+/// Reads a static through [`try_read`] and checks that a non-canonical address, which
+/// always raises a general protection fault, comes back as an error.
+pub fn self_test() -> bool {
+    static VALUE: u64 = 0x0123_4567_89ab_cdef;
+    const NON_CANONICAL: u64 = 0x8000_0000_0000_0000;
+
+    let read = try_read::<u64>(&raw const VALUE as u64);
+    let faulted = try_read::<u64>(NON_CANONICAL);
+    // Straddling the end of the canonical lower half faults partway through.
+    let straddling = try_read::<[u8; 8]>(0x0000_7fff_ffff_fffc);
+
+    read == Ok(VALUE)
+        && faulted == Err(Fault { copied: 0 })
+        && straddling.is_err_and(|fault| fault.copied <= 4)
+}
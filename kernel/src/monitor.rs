@@ -0,0 +1,231 @@
+//! A minimal debugger on a serial port, for when there is no screen to look at.
+//!
+//! Unlike the [shell](crate::shell), the monitor polls the port itself and blocks until
+//! it is left with `exit`, so it works without interrupts or a working idle loop. Input
+//! is echoed and collected a line at a time; Backspace erases and Ctrl-C discards it.
+//!
+//! The port's input interrupt must not be enabled while the monitor runs, or its
+//! handler takes the bytes first.
+
+use core::fmt::{self, Write};
+
+use limine::{LimineMemmapResponse, LimineMemoryMapEntryType};
+
+use crate::arch::x86_64::exception::ControlRegisters;
+use crate::arch::x86_64::extable;
+use crate::array_vec::ArrayVec;
+use crate::boot::memmap::LimineMemmapResponseExt;
+use crate::boot::ptr::ArrayPtrExt;
+use crate::serial::SerialPort;
+
+/// The longest line the monitor takes. Further characters are refused with a bell.
+pub const MAX_LINE: usize = 80;
+/// How many bytes `peek` dumps.
+pub const PEEK_LEN: u64 = 16;
+
+const PROMPT: &str = "monitor> ";
+
+const HELP: &str = "commands: mem, peek <addr>, regs, exit";
+
+/// A command line, split into the command and its argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Empty,
+    Mem,
+    Peek(&'a str),
+    Regs,
+    Exit,
+    /// Any other command, or a known one with the wrong number of arguments.
+    Unknown(&'a str),
+}
+
+impl<'a> Line<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (None, ..) => Self::Empty,
+            (Some("mem"), None, _) => Self::Mem,
+            (Some("peek"), Some(address), None) => Self::Peek(address),
+            (Some("regs"), None, _) => Self::Regs,
+            (Some("exit"), None, _) => Self::Exit,
+            (Some(command), ..) => Self::Unknown(command),
+        }
+    }
+}
+
+/// Why an address given to `peek` was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressError {
+    Empty,
+    /// A character that isn't a hexadecimal digit.
+    InvalidDigit,
+    /// More than 16 digits.
+    Overflow,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("no address given"),
+            Self::InvalidDigit => f.write_str("addresses are hexadecimal"),
+            Self::Overflow => f.write_str("address is wider than 64 bits"),
+        }
+    }
+}
+
+/// Parses a hexadecimal address, with or without a `0x` prefix. Underscores may group
+/// the digits, as in `ffff_8000_0000_1000`.
+pub fn parse_address(s: &str) -> Result<u64, AddressError> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    let mut address: u64 = 0;
+    let mut any = false;
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c.to_digit(16).ok_or(AddressError::InvalidDigit)?;
+        address = address.checked_mul(16).ok_or(AddressError::Overflow)? | digit as u64;
+        any = true;
+    }
+    if any {
+        Ok(address)
+    } else {
+        Err(AddressError::Empty)
+    }
+}
+
+/// Runs the monitor on `serial` until it is left with `exit`.
+pub fn run(serial: &mut SerialPort, mm: &LimineMemmapResponse) {
+    writeln!(serial, "\nkernel monitor, {}", HELP).ok();
+    let mut line = ArrayVec::<u8, MAX_LINE>::new();
+
+    loop {
+        serial.write_str(PROMPT).ok();
+        line.clear();
+        if !read_line(serial, &mut line) {
+            serial.write_str("^C\n").ok();
+            continue;
+        }
+
+        // The editor only takes printable ASCII, so the line is valid UTF-8.
+        let text = core::str::from_utf8(&line).unwrap_or("");
+        let result = match Line::parse(text) {
+            Line::Empty => Ok(()),
+            Line::Mem => print_memory_map(serial, mm),
+            Line::Peek(address) => match parse_address(address) {
+                Ok(address) => peek(serial, address),
+                Err(err) => writeln!(serial, "peek: {}", err),
+            },
+            Line::Regs => print_registers(serial),
+            Line::Exit => return,
+            Line::Unknown(_) => writeln!(serial, "{}", HELP),
+        };
+        result.ok();
+    }
+}
+
+/// Reads a line into `line`, echoing it. Returns `false` if it was discarded with
+/// Ctrl-C.
+fn read_line(serial: &mut SerialPort, line: &mut ArrayVec<u8, MAX_LINE>) -> bool {
+    loop {
+        match serial.read_byte_blocking() {
+            b'\r' | b'\n' => {
+                serial.write_bytes(b"\n");
+                return true;
+            }
+            // Ctrl-C.
+            0x03 => return false,
+            // Backspace and delete, which erase nothing on an empty line.
+            0x08 | 0x7f if line.pop().is_some() => serial.write_bytes(b"\x08 \x08"),
+            byte @ b' '..=b'~' => match line.push(byte) {
+                Ok(()) => serial.write_byte(byte),
+                Err(_) => serial.write_byte(0x07),
+            },
+            _ => {}
+        }
+    }
+}
+
+fn print_memory_map(out: &mut dyn Write, mm: &LimineMemmapResponse) -> fmt::Result {
+    mm.print_map(out)?;
+    // SAFETY: The count comes from the bootloader along with the array.
+    let usable: u64 = unsafe { mm.entries.iter(mm.entry_count as usize) }
+        .filter(|entry| entry.typ == LimineMemoryMapEntryType::Usable)
+        .map(|entry| entry.len)
+        .sum();
+    writeln!(out, "{} KiB usable", usable / 1024)
+}
+
+fn peek(out: &mut dyn Write, address: u64) -> fmt::Result {
+    write!(out, "{:016x}:", address)?;
+    for offset in 0..PEEK_LEN {
+        match extable::read_byte(address.wrapping_add(offset)) {
+            Some(byte) => write!(out, " {:02x}", byte)?,
+            None => out.write_str(" ??")?,
+        }
+    }
+    writeln!(out)
+}
+
+fn print_registers(out: &mut dyn Write) -> fmt::Result {
+    let (rsp, rbp, rflags): (u64, u64, u64);
+    unsafe {
+        core::arch::asm!(
+            "mov {}, rsp",
+            "mov {}, rbp",
+            "pushfq",
+            "pop {}",
+            out(reg) rsp,
+            out(reg) rbp,
+            out(reg) rflags,
+        );
+    }
+    let control = ControlRegisters::read();
+
+    writeln!(
+        out,
+        "rsp={:#018x} rbp={:#018x} rflags={:#x}",
+        rsp, rbp, rflags
+    )?;
+    writeln!(
+        out,
+        "cr0={:#018x} cr2={:#018x} cr3={:#018x} cr4={:#018x}",
+        control.cr0, control.cr2, control.cr3, control.cr4
+    )
+}
+
+/// Checks the command line parser and the address parser, bad input included.
+pub fn self_test() -> bool {
+    let lines_ok = [
+        ("", Line::Empty),
+        ("   ", Line::Empty),
+        ("mem", Line::Mem),
+        ("  peek   0x1000 ", Line::Peek("0x1000")),
+        ("regs", Line::Regs),
+        ("exit", Line::Exit),
+        ("peek", Line::Unknown("peek")),
+        ("peek 1 2", Line::Unknown("peek")),
+        ("mem all", Line::Unknown("mem")),
+        ("help", Line::Unknown("help")),
+    ]
+    .into_iter()
+    .all(|(line, parsed)| Line::parse(line) == parsed);
+
+    let addresses_ok = [
+        ("0x1000", Ok(0x1000)),
+        ("0XfF", Ok(0xff)),
+        ("ffff_8000_0000_1000", Ok(0xffff_8000_0000_1000)),
+        ("ffffffffffffffff", Ok(u64::MAX)),
+        ("", Err(AddressError::Empty)),
+        ("0x", Err(AddressError::Empty)),
+        ("0x_", Err(AddressError::Empty)),
+        ("0x12g4", Err(AddressError::InvalidDigit)),
+        ("-1", Err(AddressError::InvalidDigit)),
+        ("1_0000_0000_0000_0000", Err(AddressError::Overflow)),
+    ]
+    .into_iter()
+    .all(|(s, parsed)| parse_address(s) == parsed);
+
+    lines_ok && addresses_ok
+}
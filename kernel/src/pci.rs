@@ -0,0 +1,563 @@
+//! PCI configuration space access and bus enumeration.
+//!
+//! Configuration space is reached through a [`ConfigAccess`] backend. On x86_64,
+//! [`PortAccess`] uses the legacy `0xcf8`/`0xcfc` mechanism, which reaches the first
+//! 256 bytes of every function on segment 0. With ACPI, [`EcamAccess`] uses the memory
+//! mapped windows the MCFG table lists, which reach all 4096 bytes, and [`access`] picks
+//! it whenever there is one.
+
+use core::{fmt, iter};
+
+use crate::boot::PhysAddr;
+
+#[cfg(all(feature = "msi", target_arch = "x86_64"))]
+pub mod msi;
+#[cfg(all(feature = "msi", target_arch = "x86_64"))]
+pub use msi::{enable_msi, enable_msix, MsiError};
+
+pub const VENDOR_ID: u16 = 0x00;
+pub const DEVICE_ID: u16 = 0x02;
+pub const COMMAND: u16 = 0x04;
+pub const STATUS: u16 = 0x06;
+pub const REVISION_ID: u16 = 0x08;
+pub const PROG_IF: u16 = 0x09;
+pub const SUBCLASS: u16 = 0x0a;
+pub const CLASS: u16 = 0x0b;
+pub const HEADER_TYPE: u16 = 0x0e;
+pub const BAR0: u16 = 0x10;
+pub const CAPABILITIES_POINTER: u16 = 0x34;
+/// Where the extended capabilities start, past the 256 bytes of the legacy space.
+pub const EXTENDED_CAPABILITIES: u16 = 0x100;
+
+pub const CAP_MSI: u8 = 0x05;
+pub const CAP_PCI_EXPRESS: u8 = 0x10;
+pub const CAP_MSI_X: u8 = 0x11;
+
+pub const EXT_CAP_AER: u16 = 0x0001;
+pub const EXT_CAP_SRIOV: u16 = 0x0010;
+
+/// The command register bit keeping the function from asserting its INTx line.
+pub const COMMAND_INTX_DISABLE: u16 = 1 << 10;
+
+/// The header type bit marking a device with more than one function.
+const MULTIFUNCTION: u8 = 1 << 7;
+/// How many BARs a type 0 header has.
+const BAR_COUNT: u8 = 6;
+/// The status bit telling that the capabilities pointer is valid.
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+/// The most capabilities that fit in either space, which bounds walks of lists that
+/// loop.
+const MAX_CAPABILITIES: usize = 48;
+const MAX_EXTENDED_CAPABILITIES: usize = 960;
+
+/// Where a function sits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PciAddress {
+    pub segment: u16,
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    pub const fn new(segment: u16, bus: u8, device: u8, function: u8) -> Self {
+        Self {
+            segment,
+            bus,
+            device,
+            function,
+        }
+    }
+}
+
+impl fmt::Display for PciAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:02x}:{:02x}.{}",
+            self.segment, self.bus, self.device, self.function
+        )
+    }
+}
+
+/// A way of reaching configuration space.
+pub trait ConfigAccess {
+    /// Reads the dword at `offset`, rounded down to a multiple of 4. Functions and
+    /// offsets the backend can't reach read as all ones, like absent functions do.
+    fn read_u32(&self, address: PciAddress, offset: u16) -> u32;
+
+    /// Writes the dword at `offset`, rounded down to a multiple of 4. Writes the backend
+    /// can't perform are dropped.
+    fn write_u32(&self, address: PciAddress, offset: u16, value: u32);
+
+    fn read_u16(&self, address: PciAddress, offset: u16) -> u16 {
+        (self.read_u32(address, offset) >> ((offset & 2) * 8)) as u16
+    }
+
+    fn read_u8(&self, address: PciAddress, offset: u16) -> u8 {
+        (self.read_u32(address, offset) >> ((offset & 3) * 8)) as u8
+    }
+}
+
+/// The legacy configuration mechanism through I/O ports `0xcf8` and `0xcfc`.
+#[cfg(target_arch = "x86_64")]
+pub struct PortAccess;
+
+#[cfg(target_arch = "x86_64")]
+mod port_access {
+    use spin::Mutex;
+
+    use super::{ConfigAccess, PciAddress, PortAccess};
+    use crate::arch::x86_64::port::{inl, outl};
+
+    const CONFIG_ADDRESS: u16 = 0xcf8;
+    const CONFIG_DATA: u16 = 0xcfc;
+    const ENABLE: u32 = 1 << 31;
+
+    /// Accesses take two port operations that mustn't interleave with another's.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    fn config_address(address: PciAddress, offset: u16) -> Option<u32> {
+        let reachable =
+            address.segment == 0 && offset < 0x100 && address.device < 32 && address.function < 8;
+        reachable.then_some(
+            ENABLE
+                | (address.bus as u32) << 16
+                | (address.device as u32) << 11
+                | (address.function as u32) << 8
+                | (offset & 0xfc) as u32,
+        )
+    }
+
+    impl ConfigAccess for PortAccess {
+        fn read_u32(&self, address: PciAddress, offset: u16) -> u32 {
+            let Some(config_address) = config_address(address, offset) else {
+                return u32::MAX;
+            };
+            let _guard = LOCK.lock();
+            unsafe {
+                outl(CONFIG_ADDRESS, config_address);
+                inl(CONFIG_DATA)
+            }
+        }
+
+        fn write_u32(&self, address: PciAddress, offset: u16, value: u32) {
+            let Some(config_address) = config_address(address, offset) else {
+                return;
+            };
+            let _guard = LOCK.lock();
+            unsafe {
+                outl(CONFIG_ADDRESS, config_address);
+                outl(CONFIG_DATA, value);
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "acpi", target_arch = "x86_64"))]
+pub use ecam::{EcamAccess, EcamWindow, MAX_ECAM_WINDOWS};
+
+#[cfg(all(feature = "acpi", target_arch = "x86_64"))]
+mod ecam {
+    use core::ptr;
+
+    use super::{ConfigAccess, PciAddress};
+    use crate::arch::x86_64::paging::Mapper;
+    use crate::array_vec::ArrayVec;
+    use crate::boot::acpi::{Acpi, AcpiError, McfgEntry};
+    use crate::kwarn;
+
+    /// How many MCFG entries are used. Further ones are reported and skipped.
+    pub const MAX_ECAM_WINDOWS: usize = 8;
+
+    /// The configuration space of buses `start_bus..=end_bus` on `segment`, reached
+    /// through the HHDM.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct EcamWindow {
+        pub segment: u16,
+        pub start_bus: u8,
+        pub end_bus: u8,
+        /// The HHDM address of bus 0's configuration space.
+        base: u64,
+    }
+
+    impl EcamWindow {
+        fn address(&self, address: PciAddress, offset: u16) -> Option<*mut u32> {
+            let reachable = address.segment == self.segment
+                && (self.start_bus..=self.end_bus).contains(&address.bus)
+                && address.device < 32
+                && address.function < 8
+                && offset < 0x1000;
+            reachable.then(|| {
+                (self.base
+                    + ((address.bus as u64) << 20
+                        | (address.device as u64) << 15
+                        | (address.function as u64) << 12
+                        | (offset & 0xffc) as u64)) as *mut u32
+            })
+        }
+    }
+
+    /// Memory mapped configuration access through the MCFG table's ECAM windows.
+    #[derive(Debug)]
+    pub struct EcamAccess {
+        windows: ArrayVec<EcamWindow, MAX_ECAM_WINDOWS>,
+    }
+
+    impl EcamAccess {
+        /// Uses the windows of the MCFG table the HHDM covers. Limine only maps MMIO
+        /// below 4 GiB into the HHDM, windows elsewhere are reported and skipped.
+        pub fn from_acpi(acpi: &Acpi) -> Result<Self, AcpiError> {
+            let hhdm = acpi.hhdm_offset();
+            // SAFETY: The mapper only translates.
+            let mapper = unsafe { Mapper::new(hhdm) };
+
+            let mut windows = ArrayVec::new();
+            for entry in acpi.find_mcfg()? {
+                let McfgEntry {
+                    base_address,
+                    segment,
+                    start_bus,
+                    end_bus,
+                } = entry;
+                if start_bus > end_bus {
+                    kwarn!("skipping backwards ECAM window {:?}", entry);
+                    continue;
+                }
+
+                let start = base_address + ((start_bus as u64) << 20);
+                let end = base_address + ((end_bus as u64 + 1) << 20);
+                if !mapper.hhdm_covers(start..end) {
+                    kwarn!("skipping ECAM window {:?} outside the HHDM", entry);
+                    continue;
+                }
+
+                let window = EcamWindow {
+                    segment,
+                    start_bus,
+                    end_bus,
+                    base: base_address + hhdm,
+                };
+                if windows.push(window).is_err() {
+                    kwarn!(
+                        "more than {} ECAM windows, skipping {:?}",
+                        MAX_ECAM_WINDOWS,
+                        entry
+                    );
+                }
+            }
+            Ok(Self { windows })
+        }
+
+        pub fn windows(&self) -> &[EcamWindow] {
+            &self.windows
+        }
+
+        fn register(&self, address: PciAddress, offset: u16) -> Option<*mut u32> {
+            self.windows
+                .iter()
+                .find_map(|window| window.address(address, offset))
+        }
+    }
+
+    impl ConfigAccess for EcamAccess {
+        fn read_u32(&self, address: PciAddress, offset: u16) -> u32 {
+            match self.register(address, offset) {
+                // SAFETY: The register lies in a window the HHDM maps.
+                Some(register) => unsafe { ptr::read_volatile(register) },
+                None => u32::MAX,
+            }
+        }
+
+        fn write_u32(&self, address: PciAddress, offset: u16, value: u32) {
+            if let Some(register) = self.register(address, offset) {
+                // SAFETY: The register lies in a window the HHDM maps.
+                unsafe { ptr::write_volatile(register, value) };
+            }
+        }
+    }
+}
+
+/// The configuration mechanism [`access`] picked.
+#[cfg(target_arch = "x86_64")]
+pub enum Access {
+    Port(PortAccess),
+    #[cfg(feature = "acpi")]
+    Ecam(EcamAccess),
+}
+
+#[cfg(target_arch = "x86_64")]
+impl ConfigAccess for Access {
+    fn read_u32(&self, address: PciAddress, offset: u16) -> u32 {
+        match self {
+            Self::Port(port) => port.read_u32(address, offset),
+            #[cfg(feature = "acpi")]
+            Self::Ecam(ecam) => ecam.read_u32(address, offset),
+        }
+    }
+
+    fn write_u32(&self, address: PciAddress, offset: u16, value: u32) {
+        match self {
+            Self::Port(port) => port.write_u32(address, offset, value),
+            #[cfg(feature = "acpi")]
+            Self::Ecam(ecam) => ecam.write_u32(address, offset, value),
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+static ACCESS: spin::Once<Access> = spin::Once::new();
+
+/// The best configuration mechanism: ECAM if the MCFG table lists a usable window,
+/// else the I/O ports. Chosen on the first call.
+#[cfg(target_arch = "x86_64")]
+pub fn access() -> &'static Access {
+    ACCESS.call_once(|| {
+        #[cfg(feature = "acpi")]
+        match crate::boot::acpi::built_in().and_then(|acpi| EcamAccess::from_acpi(&acpi)) {
+            Ok(ecam) if !ecam.windows().is_empty() => return Access::Ecam(ecam),
+            Ok(_) => {}
+            Err(err) => crate::kdebug!("not using ECAM: {}", err),
+        }
+        Access::Port(PortAccess)
+    })
+}
+
+/// Checks that ECAM and the I/O ports agree on the legacy configuration space of every
+/// function on segment 0. The command and status dword is left out, as status bits can
+/// change between the reads. Returns `None` if there is no ECAM to compare with.
+#[cfg(all(feature = "acpi", target_arch = "x86_64"))]
+pub fn self_check() -> Option<bool> {
+    let Access::Ecam(ecam) = access() else {
+        return None;
+    };
+    Some(enumerate(&PortAccess, 0).all(|device| {
+        (0..0x100)
+            .step_by(4)
+            .filter(|&offset| offset != COMMAND)
+            .all(|offset| {
+                PortAccess.read_u32(device.address, offset) == ecam.read_u32(device.address, offset)
+            })
+    }))
+}
+
+/// Where a base address register points.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bar {
+    Memory {
+        address: PhysAddr,
+        prefetchable: bool,
+        /// Whether the BAR takes up the next register for the upper half of the address.
+        wide: bool,
+    },
+    Io {
+        port: u16,
+    },
+}
+
+/// An entry of a function's capability list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capability {
+    pub id: u8,
+    /// Where the capability's registers start, with the ID and next pointer.
+    pub offset: u16,
+}
+
+/// An entry of a PCI Express function's extended capability list.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtendedCapability {
+    pub id: u16,
+    pub version: u8,
+    /// Where the capability's registers start, with its header.
+    pub offset: u16,
+}
+
+/// The identifying fields of a function's configuration header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PciDevice {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+}
+
+impl PciDevice {
+    /// Reads the function at `address`, or returns `None` if there is none.
+    pub fn read(access: &impl ConfigAccess, address: PciAddress) -> Option<Self> {
+        let vendor_id = access.read_u16(address, VENDOR_ID);
+        if vendor_id == u16::MAX {
+            return None;
+        }
+        Some(Self {
+            address,
+            vendor_id,
+            device_id: access.read_u16(address, DEVICE_ID),
+            class: access.read_u8(address, CLASS),
+            subclass: access.read_u8(address, SUBCLASS),
+            prog_if: access.read_u8(address, PROG_IF),
+            revision: access.read_u8(address, REVISION_ID),
+            header_type: access.read_u8(address, HEADER_TYPE),
+        })
+    }
+
+    /// Whether the device has functions besides function 0.
+    pub fn is_multifunction(&self) -> bool {
+        self.header_type & MULTIFUNCTION != 0
+    }
+
+    /// Decodes BAR `index` of a type 0 header, or returns `None` if it is out of range,
+    /// unimplemented or the upper half of a 64-bit BAR.
+    pub fn bar(&self, access: &impl ConfigAccess, index: u8) -> Option<Bar> {
+        if index >= BAR_COUNT || self.header_type & !MULTIFUNCTION != 0 {
+            return None;
+        }
+        let offset = BAR0 + index as u16 * 4;
+        let low = access.read_u32(self.address, offset);
+        if low == 0 {
+            return None;
+        }
+        if low & 1 != 0 {
+            return Some(Bar::Io {
+                port: (low & 0xfffc) as u16,
+            });
+        }
+
+        let wide = low >> 1 & 0b11 == 0b10;
+        let high = if wide {
+            if index + 1 >= BAR_COUNT {
+                return None;
+            }
+            access.read_u32(self.address, offset + 4)
+        } else {
+            0
+        };
+        Some(Bar::Memory {
+            address: (high as u64) << 32 | (low & !0xf) as u64,
+            prefetchable: low & (1 << 3) != 0,
+            wide,
+        })
+    }
+
+    /// Sets or clears [`COMMAND_INTX_DISABLE`]. The status half of the dword is written
+    /// as zeros, which leaves its write-one-to-clear bits alone.
+    pub fn set_intx_disabled(&self, access: &impl ConfigAccess, disabled: bool) {
+        let command = access.read_u16(self.address, COMMAND);
+        let command = if disabled {
+            command | COMMAND_INTX_DISABLE
+        } else {
+            command & !COMMAND_INTX_DISABLE
+        };
+        access.write_u32(self.address, COMMAND, command as u32);
+    }
+
+    /// Walks the capability list in the legacy configuration space.
+    pub fn capabilities<'a>(
+        &self,
+        access: &'a impl ConfigAccess,
+    ) -> impl Iterator<Item = Capability> + 'a {
+        let address = self.address;
+        let mut next = if access.read_u16(address, STATUS) & STATUS_CAPABILITIES != 0 {
+            access.read_u8(address, CAPABILITIES_POINTER)
+        } else {
+            0
+        };
+        iter::from_fn(move || {
+            // The bottom two bits are reserved, and pointers into the header are invalid.
+            let offset = (next & 0xfc) as u16;
+            if offset < 0x40 {
+                return None;
+            }
+            let header = access.read_u16(address, offset);
+            next = (header >> 8) as u8;
+            Some(Capability {
+                id: header as u8,
+                offset,
+            })
+        })
+        .take(MAX_CAPABILITIES)
+    }
+
+    /// Walks the extended capability list, which starts at [`EXTENDED_CAPABILITIES`].
+    /// It is empty unless the device is PCI Express and the backend reaches past the
+    /// first 256 bytes.
+    pub fn extended_capabilities<'a>(
+        &self,
+        access: &'a impl ConfigAccess,
+    ) -> impl Iterator<Item = ExtendedCapability> + 'a {
+        let address = self.address;
+        let mut next = EXTENDED_CAPABILITIES;
+        iter::from_fn(move || {
+            let offset = next & 0xffc;
+            if offset < EXTENDED_CAPABILITIES {
+                return None;
+            }
+            // A header of all zeros means there are none, all ones that nothing answered.
+            let header = access.read_u32(address, offset);
+            if header == 0 || header == u32::MAX {
+                return None;
+            }
+            next = (header >> 20) as u16;
+            Some(ExtendedCapability {
+                id: header as u16,
+                version: (header >> 16 & 0xf) as u8,
+                offset,
+            })
+        })
+        .take(MAX_EXTENDED_CAPABILITIES)
+    }
+
+    pub fn find_capability(&self, access: &impl ConfigAccess, id: u8) -> Option<Capability> {
+        self.capabilities(access)
+            .find(|capability| capability.id == id)
+    }
+
+    pub fn find_extended_capability(
+        &self,
+        access: &impl ConfigAccess,
+        id: u16,
+    ) -> Option<ExtendedCapability> {
+        self.extended_capabilities(access)
+            .find(|capability| capability.id == id)
+    }
+}
+
+impl fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:04x}:{:04x} class {:02x}.{:02x}.{:02x} rev {:02x}",
+            self.address,
+            self.vendor_id,
+            self.device_id,
+            self.class,
+            self.subclass,
+            self.prog_if,
+            self.revision
+        )
+    }
+}
+
+/// Iterates over every function on every bus of `segment`, probing all of them rather
+/// than following bridges.
+pub fn enumerate<A: ConfigAccess>(
+    access: &A,
+    segment: u16,
+) -> impl Iterator<Item = PciDevice> + '_ {
+    (0..=255u8).flat_map(move |bus| {
+        (0..32u8).flat_map(move |device| {
+            let functions = match PciDevice::read(access, PciAddress::new(segment, bus, device, 0))
+            {
+                Some(first) if first.is_multifunction() => 8,
+                Some(_) => 1,
+                None => 0,
+            };
+            (0..functions).filter_map(move |function| {
+                PciDevice::read(access, PciAddress::new(segment, bus, device, function))
+            })
+        })
+    })
+}
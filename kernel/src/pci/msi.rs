@@ -0,0 +1,249 @@
+//! Message signalled interrupts, MSI and MSI-X.
+//!
+//! Both have the function write a message to an address in the local APIC's range
+//! instead of asserting an INTx line, which the PIC would have to route. The address
+//! names the target CPU by its local APIC ID and the data holds the vector, here one
+//! from [`alloc_vector`](crate::arch::x86_64::interrupts::alloc_vector), sent with fixed
+//! delivery and edge triggering. MSI keeps the message in its capability; MSI-X has a
+//! table of them in the memory of one of the function's BARs.
+
+use core::fmt;
+use core::ptr;
+
+use super::{Bar, ConfigAccess, PciDevice, CAP_MSI, CAP_MSI_X, COMMAND};
+use crate::arch::x86_64::lapic;
+use crate::arch::x86_64::paging::Mapper;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+
+/// Where messages go, with the destination APIC ID in bits 12 to 19.
+const MESSAGE_ADDRESS: u32 = 0xfee0_0000;
+
+const MSI_ENABLE: u16 = 1 << 0;
+/// The multiple message enable field, left at one vector.
+const MSI_MULTIPLE_MESSAGES: u16 = 0b111 << 4;
+const MSI_64_BIT: u16 = 1 << 7;
+const MSI_PER_VECTOR_MASKING: u16 = 1 << 8;
+
+const MSIX_TABLE_SIZE: u16 = 0x7ff;
+const MSIX_FUNCTION_MASK: u16 = 1 << 14;
+const MSIX_ENABLE: u16 = 1 << 15;
+const MSIX_ENTRY_LEN: u64 = 16;
+const MSIX_VECTOR_MASKED: u32 = 1 << 0;
+
+const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+
+/// Why message signalled interrupts couldn't be set up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsiError {
+    /// The function has no capability of the kind asked for.
+    NoCapability,
+    /// The local APIC the message would go to isn't initialized.
+    NoLapic,
+    /// The MSI-X table has no entry of that index.
+    NoSuchEntry { entry: u16, table_size: u16 },
+    /// The BAR holding the MSI-X table isn't a memory BAR.
+    BadBar(u8),
+    /// The MSI-X table isn't reachable through the HHDM.
+    NotMapped,
+}
+
+impl fmt::Display for MsiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoCapability => f.write_str("the function can't signal that kind of message"),
+            Self::NoLapic => f.write_str("the local APIC is not initialized"),
+            Self::NoSuchEntry { entry, table_size } => write!(
+                f,
+                "MSI-X entry {} is past the table of {}",
+                entry, table_size
+            ),
+            Self::BadBar(bar) => write!(f, "MSI-X table is in BAR {}, which isn't memory", bar),
+            Self::NotMapped => f.write_str("MSI-X table is outside the HHDM"),
+        }
+    }
+}
+
+fn message_address() -> Result<u32, MsiError> {
+    if !lapic::is_initialized() {
+        return Err(MsiError::NoLapic);
+    }
+    Ok(MESSAGE_ADDRESS | (lapic::id() as u32) << 12)
+}
+
+/// Replaces the message control register, the upper half of a capability's first dword.
+fn write_control(access: &impl ConfigAccess, device: &PciDevice, capability: u16, control: u16) {
+    let header = access.read_u32(device.address, capability) & 0xffff;
+    access.write_u32(device.address, capability, header | (control as u32) << 16);
+}
+
+fn read_control(access: &impl ConfigAccess, device: &PciDevice, capability: u16) -> u16 {
+    access.read_u16(device.address, capability + 2)
+}
+
+/// Makes `device` signal `vector` on the calling CPU through its MSI capability, with
+/// MSI-X and INTx disabled.
+pub fn enable_msi(
+    access: &impl ConfigAccess,
+    device: &PciDevice,
+    vector: u8,
+) -> Result<(), MsiError> {
+    let capability = device
+        .find_capability(access, CAP_MSI)
+        .ok_or(MsiError::NoCapability)?
+        .offset;
+    let address = message_address()?;
+    let control = read_control(access, device, capability);
+
+    disable_msix(access, device);
+    write_control(access, device, capability, control & !MSI_ENABLE);
+
+    access.write_u32(device.address, capability + 4, address);
+    let data = if control & MSI_64_BIT != 0 {
+        access.write_u32(device.address, capability + 8, 0);
+        capability + 0x0c
+    } else {
+        capability + 0x08
+    };
+    access.write_u32(device.address, data, vector as u32);
+    if control & MSI_PER_VECTOR_MASKING != 0 {
+        // The mask bits follow the data register.
+        access.write_u32(device.address, data + 4, 0);
+    }
+
+    write_control(
+        access,
+        device,
+        capability,
+        (control & !MSI_MULTIPLE_MESSAGES) | MSI_ENABLE,
+    );
+    device.set_intx_disabled(access, true);
+    Ok(())
+}
+
+/// Masks or unmasks the MSI vector, if the function supports per-vector masking.
+/// Returns whether it does.
+pub fn set_msi_masked(access: &impl ConfigAccess, device: &PciDevice, masked: bool) -> bool {
+    let Some(capability) = device.find_capability(access, CAP_MSI) else {
+        return false;
+    };
+    let control = read_control(access, device, capability.offset);
+    if control & MSI_PER_VECTOR_MASKING == 0 {
+        return false;
+    }
+    let mask = capability.offset
+        + if control & MSI_64_BIT != 0 {
+            0x10
+        } else {
+            0x0c
+        };
+    access.write_u32(device.address, mask, masked as u32);
+    true
+}
+
+fn disable_msix(access: &impl ConfigAccess, device: &PciDevice) {
+    if let Some(capability) = device.find_capability(access, CAP_MSI_X) {
+        let control = read_control(access, device, capability.offset);
+        write_control(access, device, capability.offset, control & !MSIX_ENABLE);
+    }
+}
+
+fn disable_msi(access: &impl ConfigAccess, device: &PciDevice) {
+    if let Some(capability) = device.find_capability(access, CAP_MSI) {
+        let control = read_control(access, device, capability.offset);
+        write_control(access, device, capability.offset, control & !MSI_ENABLE);
+    }
+}
+
+/// The HHDM address of MSI-X table entry `entry`, and the capability's offset.
+fn msix_entry(
+    access: &impl ConfigAccess,
+    device: &PciDevice,
+    entry: u16,
+) -> Result<(*mut u32, u16), MsiError> {
+    let capability = device
+        .find_capability(access, CAP_MSI_X)
+        .ok_or(MsiError::NoCapability)?
+        .offset;
+    let table_size = (read_control(access, device, capability) & MSIX_TABLE_SIZE) + 1;
+    if entry >= table_size {
+        return Err(MsiError::NoSuchEntry { entry, table_size });
+    }
+
+    let table = access.read_u32(device.address, capability + 4);
+    let bir = (table & 0b111) as u8;
+    let Some(Bar::Memory { address, .. }) = device.bar(access, bir) else {
+        return Err(MsiError::BadBar(bir));
+    };
+    let phys = address + (table & !0b111) as u64 + entry as u64 * MSIX_ENTRY_LEN;
+
+    let hhdm = HHDM.response().ok_or(MsiError::NotMapped)?.offset;
+    // SAFETY: The mapper only translates.
+    let mapper = unsafe { Mapper::new(hhdm) };
+    if !mapper.hhdm_covers(phys..phys + MSIX_ENTRY_LEN) {
+        return Err(MsiError::NotMapped);
+    }
+    Ok(((phys + hhdm) as *mut u32, capability))
+}
+
+/// Makes MSI-X table entry `entry` of `device` signal `vector` on the calling CPU, and
+/// enables MSI-X with MSI and INTx disabled. Other entries keep their messages and
+/// masks.
+pub fn enable_msix(
+    access: &impl ConfigAccess,
+    device: &PciDevice,
+    entry: u16,
+    vector: u8,
+) -> Result<(), MsiError> {
+    let (message, capability) = msix_entry(access, device, entry)?;
+    let address = message_address()?;
+
+    disable_msi(access, device);
+    let command = access.read_u16(device.address, COMMAND);
+    // The status half is written as zeros, see `PciDevice::set_intx_disabled`.
+    access.write_u32(
+        device.address,
+        COMMAND,
+        (command | COMMAND_MEMORY_SPACE) as u32,
+    );
+
+    // The function mask holds back every entry while this one is rewritten.
+    let control = read_control(access, device, capability);
+    write_control(
+        access,
+        device,
+        capability,
+        control | MSIX_ENABLE | MSIX_FUNCTION_MASK,
+    );
+    // SAFETY: `msix_entry` checked that the HHDM maps the entry's four dwords.
+    unsafe {
+        ptr::write_volatile(message.add(3), MSIX_VECTOR_MASKED);
+        ptr::write_volatile(message, address);
+        ptr::write_volatile(message.add(1), 0);
+        ptr::write_volatile(message.add(2), vector as u32);
+        ptr::write_volatile(message.add(3), 0);
+    }
+    write_control(
+        access,
+        device,
+        capability,
+        (control | MSIX_ENABLE) & !MSIX_FUNCTION_MASK,
+    );
+
+    device.set_intx_disabled(access, true);
+    Ok(())
+}
+
+/// Masks or unmasks MSI-X table entry `entry` of `device`.
+pub fn set_msix_masked(
+    access: &impl ConfigAccess,
+    device: &PciDevice,
+    entry: u16,
+    masked: bool,
+) -> Result<(), MsiError> {
+    let (message, _) = msix_entry(access, device, entry)?;
+    let control = if masked { MSIX_VECTOR_MASKED } else { 0 };
+    // SAFETY: `msix_entry` checked that the HHDM maps the entry's four dwords.
+    unsafe { ptr::write_volatile(message.add(3), control) };
+    Ok(())
+}
@@ -0,0 +1,185 @@
+//! Kernel print macros.
+//!
+//! Everything printed is also kept in a ring of the last [`LOG_RING_SIZE`] bytes, which
+//! [`replay_log`] hands out again, for example to a shell's `dmesg`, and
+//! [`with_log_lines`] line by line, for example to redraw a console.
+
+use core::fmt::{self, Write};
+use core::ops::Range;
+
+use spin::Mutex;
+
+use crate::array_vec::ArrayVec;
+
+/// How many bytes of output the log ring keeps.
+pub const LOG_RING_SIZE: usize = 16 * 1024;
+/// The most lines [`with_log_lines`] hands out at once.
+pub const MAX_LOG_LINES: usize = 256;
+
+struct LogRing {
+    bytes: [u8; LOG_RING_SIZE],
+    /// Where the oldest byte is.
+    start: usize,
+    len: usize,
+}
+
+impl fmt::Write for LogRing {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.bytes[(self.start + self.len) % LOG_RING_SIZE] = byte;
+            if self.len == LOG_RING_SIZE {
+                self.start = (self.start + 1) % LOG_RING_SIZE;
+            } else {
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl LogRing {
+    /// The byte `index` bytes after the oldest one.
+    fn byte(&self, index: usize) -> u8 {
+        self.bytes[(self.start + index) % LOG_RING_SIZE]
+    }
+
+    /// The bytes in `range`, counted from the oldest one.
+    fn line(&self, range: Range<usize>) -> LogLine<'_> {
+        let start = (self.start + range.start) % LOG_RING_SIZE;
+        let len = range.end - range.start;
+        if start + len <= LOG_RING_SIZE {
+            LogLine {
+                head: &self.bytes[start..start + len],
+                tail: &[],
+            }
+        } else {
+            LogLine {
+                head: &self.bytes[start..],
+                tail: &self.bytes[..start + len - LOG_RING_SIZE],
+            }
+        }
+    }
+
+    /// The lines are what the contents split at newlines gives, so there is one more
+    /// than there are newlines, the last being empty right after a newline.
+    fn line_count(&self) -> usize {
+        1 + (0..self.len)
+            .filter(|&index| self.byte(index) == b'\n')
+            .count()
+    }
+
+    /// The ranges of up to `count` lines, oldest first, of which the newest is `skip`
+    /// lines before the newest line in the ring.
+    fn line_ranges(&self, skip: usize, count: usize) -> ArrayVec<Range<usize>, MAX_LOG_LINES> {
+        let mut ranges = ArrayVec::new();
+        let mut end = self.len;
+        let mut line = 0;
+        while ranges.len() < count.min(MAX_LOG_LINES) {
+            let newline = (0..end).rev().find(|&index| self.byte(index) == b'\n');
+            let start = newline.map_or(0, |newline| newline + 1);
+            if line >= skip {
+                let _ = ranges.push(start..end);
+            }
+            match newline {
+                Some(newline) => end = newline,
+                None => break,
+            }
+            line += 1;
+        }
+        ranges.reverse();
+        ranges
+    }
+}
+
+/// A line of the log ring without its newline, in two pieces if it wraps around the
+/// ring's end.
+#[derive(Clone, Copy, Debug)]
+pub struct LogLine<'a> {
+    pub head: &'a [u8],
+    pub tail: &'a [u8],
+}
+
+impl LogLine<'_> {
+    pub fn len(&self) -> usize {
+        self.head.len() + self.tail.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.head.iter().chain(self.tail).copied()
+    }
+}
+
+static LOG_RING: Mutex<LogRing> = Mutex::new(LogRing {
+    bytes: [0; LOG_RING_SIZE],
+    start: 0,
+    len: 0,
+});
+
+/// Passes the contents of the log ring to `f`, oldest first, in at most two pieces.
+/// Whatever is printed meanwhile, like from `f` itself, doesn't make it into the ring.
+pub fn replay_log(mut f: impl FnMut(&[u8])) {
+    let ring = LOG_RING.lock();
+    let end = ring.start + ring.len;
+    if end <= LOG_RING_SIZE {
+        f(&ring.bytes[ring.start..end]);
+    } else {
+        f(&ring.bytes[ring.start..]);
+        f(&ring.bytes[..end - LOG_RING_SIZE]);
+    }
+}
+
+/// How many lines the log ring holds, counted as [`with_log_lines`] does.
+pub fn log_line_count() -> usize {
+    LOG_RING.lock().line_count()
+}
+
+/// Passes up to `count` lines of the log ring to `f`, oldest first, ending `skip` lines
+/// before the newest one. The newest line is the one still being written, which is
+/// empty after a newline. The oldest line may have lost its start to newer output.
+pub fn with_log_lines(skip: usize, count: usize, mut f: impl FnMut(LogLine<'_>)) {
+    let ring = LOG_RING.lock();
+    for range in ring.line_ranges(skip, count).iter() {
+        f(ring.line(range.clone()));
+    }
+}
+
+/// The kernel log as a writer, for code that writes to any [`fmt::Write`], like
+/// [`hexdump`](crate::fmt::hexdump).
+pub struct LogWriter;
+
+impl fmt::Write for LogWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        _print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    crate::arch::write_log(args);
+    #[cfg(feature = "legacy-terminal")]
+    crate::boot::terminal::write_fmt(args);
+    // An interrupt handler printing while the ring is locked mustn't deadlock.
+    if let Some(mut ring) = LOG_RING.try_lock() {
+        ring.write_fmt(args).ok();
+    }
+    #[cfg(feature = "log-console")]
+    crate::gfx::log_console::write_fmt(args);
+}
+
+/// Prints to the kernel log.
+#[macro_export]
+macro_rules! kprint {
+    ($($arg:tt)*) => ($crate::print::_print(format_args!($($arg)*)));
+}
+
+/// Prints to the kernel log, with a newline.
+#[macro_export]
+macro_rules! kprintln {
+    () => ($crate::kprint!("\n"));
+    ($($arg:tt)*) => ($crate::print::_print(format_args!("{}\n", format_args!($($arg)*))));
+}
@@ -0,0 +1,267 @@
+//! A panic message that survives a reboot.
+//!
+//! A frame of RAM holds a small record: a magic number, what boot it was written by and
+//! the message of the last panic, protected by a CRC-32. [`init`] reports a record left
+//! by the previous boot and starts a new one; [`record_panic`] fills it in. RAM keeps its
+//! contents across a warm reboot, such as a triple fault in QEMU, but not a power cycle.
+//!
+//! The frame is the last one of the highest usable memory map entry below 4 GiB, which
+//! stays put as long as the machine and boot configuration do, or the one given with
+//! `pstore=<physical address>`. It has to be kept out of every allocator, see [`init`].
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+use limine::{LimineMemmapResponse, LimineMemoryMapEntryType};
+
+use crate::boot::ptr::ArrayPtrExt;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+use crate::boot::PhysAddrRange;
+use crate::bootalloc;
+use crate::crypto::crc32::crc32_update;
+use crate::kprintln;
+
+/// The size of the persistent region.
+pub const PSTORE_SIZE: u64 = 4096;
+
+const MAGIC: u64 = u64::from_le_bytes(*b"PSTORE01");
+
+#[repr(C)]
+struct Record {
+    magic: u64,
+    /// Counts up on every boot that finds a valid record.
+    boot_count: u32,
+    /// How many bytes of `message` are used, 0 if the boot didn't panic.
+    len: u32,
+    /// The CRC-32 of `boot_count`, `len` and the used part of `message`.
+    crc: u32,
+    _reserved: u32,
+    message: [u8; PSTORE_SIZE as usize - 24],
+}
+
+impl Record {
+    fn checksum(&self) -> u32 {
+        let crc = crc32_update(0, &self.boot_count.to_le_bytes());
+        let crc = crc32_update(crc, &self.len.to_le_bytes());
+        crc32_update(crc, &self.message[..self.len as usize])
+    }
+
+    fn is_valid(&self) -> bool {
+        self.magic == MAGIC
+            && self.len as usize <= self.message.len()
+            && self.crc == self.checksum()
+    }
+
+    fn message(&self) -> &str {
+        let message = &self.message[..self.len as usize];
+        // Truncation can cut a character in half.
+        match core::str::from_utf8(message) {
+            Ok(message) => message,
+            Err(err) => core::str::from_utf8(&message[..err.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+static RECORD: AtomicPtr<Record> = AtomicPtr::new(ptr::null_mut());
+
+/// Finds the persistent region, reports the panic the previous boot recorded there, if
+/// any, and prepares it for this boot. The region is recorded with
+/// [`bootalloc::reserve`], which keeps [`bootalloc::take`] and the allocators set up
+/// after it away from it. Returns the physical range of the region, for allocators that
+/// work from a memory map of their own (see
+/// [`MemoryMap::reserve`](crate::boot::memmap::MemoryMap::reserve)), or `None` if there
+/// is no suitable memory, no HHDM or no room to record the region.
+pub fn init(memmap: &LimineMemmapResponse) -> Option<PhysAddrRange> {
+    let region = find_region(memmap)?;
+    let hhdm = HHDM.response()?;
+    if !bootalloc::reserve(region.clone()) {
+        kprintln!("pstore: too many carve-outs to reserve {:#x?}", region);
+        return None;
+    }
+    let record_ptr = (hhdm.offset + region.start) as *mut Record;
+
+    // SAFETY: The region is usable RAM the kernel owns, mapped in the HHDM, and nothing
+    // else touches it as long as the caller keeps it out of the allocators as documented.
+    let record = unsafe { &mut *record_ptr };
+    let boot_count = if record.is_valid() {
+        if record.len != 0 {
+            kprintln!(
+                "Previous boot (#{}) panicked: {}",
+                record.boot_count,
+                record.message()
+            );
+        }
+        record.boot_count.wrapping_add(1)
+    } else {
+        0
+    };
+
+    record.magic = MAGIC;
+    record.boot_count = boot_count;
+    record.len = 0;
+    record._reserved = 0;
+    record.crc = record.checksum();
+
+    RECORD.store(record_ptr, Ordering::Release);
+    Some(region)
+}
+
+fn find_region(memmap: &LimineMemmapResponse) -> Option<PhysAddrRange> {
+    let usable = || {
+        // SAFETY: The count comes from the bootloader along with the array.
+        unsafe { memmap.entries.iter(memmap.entry_count as usize) }
+            .filter(|entry| entry.typ == LimineMemoryMapEntryType::Usable)
+    };
+
+    #[cfg(feature = "kernel-file")]
+    if let Some(value) = crate::boot::cmdline::value("pstore") {
+        let region = u64::from_str_radix(value.trim_start_matches("0x"), 16)
+            .ok()
+            .filter(|base| base.is_multiple_of(PSTORE_SIZE))
+            .and_then(|base| Some(base..base.checked_add(PSTORE_SIZE)?));
+        let Some(region) = region else {
+            kprintln!("pstore: invalid address {}", value);
+            return None;
+        };
+        let base = region.start;
+        if !usable().any(|entry| {
+            entry.base <= region.start
+                && entry
+                    .base
+                    .checked_add(entry.len)
+                    .is_some_and(|end| region.end <= end)
+        }) {
+            kprintln!("pstore: {:#x} isn't usable memory", base);
+            return None;
+        }
+        return Some(region);
+    }
+
+    usable()
+        .filter_map(|entry| {
+            let end = entry.base.saturating_add(entry.len).min(1 << 32) & !(PSTORE_SIZE - 1);
+            let start = end.checked_sub(PSTORE_SIZE)?;
+            (start >= entry.base).then_some(start..end)
+        })
+        .max_by_key(|region| region.start)
+}
+
+/// Writes into the message buffer, dropping whatever doesn't fit.
+struct MessageWriter<'a> {
+    message: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = self.message.len() - self.len;
+        let count = s.len().min(available);
+        self.message[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+/// Stores `info`, and a backtrace where one is available, for the next boot to report.
+/// Does nothing before [`init`].
+pub fn record_panic(info: &PanicInfo) {
+    // SAFETY: `init` stored a pointer to the region it set up.
+    let Some(record) = (unsafe { RECORD.load(Ordering::Acquire).as_mut() }) else {
+        return;
+    };
+
+    let mut writer = MessageWriter {
+        message: &mut record.message,
+        len: 0,
+    };
+    let _ = write!(writer, "{}", info);
+    #[cfg(target_arch = "x86_64")]
+    {
+        let (rip, rbp): (u64, u64);
+        // SAFETY: Only reads the instruction and frame pointers.
+        unsafe {
+            core::arch::asm!("lea {}, [rip]", "mov {}, rbp", out(reg) rip, out(reg) rbp);
+        }
+        let backtrace = crate::arch::x86_64::exception::Backtrace { rip, rbp };
+        let _ = write!(writer, "\n{}", backtrace);
+    }
+
+    record.len = writer.len as u32;
+    record.crc = record.checksum();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A record of this boot, with `message` written the way [`record_panic`] does.
+    fn record(message: &str) -> Record {
+        // SAFETY: Every field is an integer or an array of them.
+        let mut record: Record = unsafe { core::mem::zeroed() };
+        record.magic = MAGIC;
+        record.boot_count = 3;
+        let mut writer = MessageWriter {
+            message: &mut record.message,
+            len: 0,
+        };
+        let _ = writer.write_str(message);
+        record.len = writer.len as u32;
+        record.crc = record.checksum();
+        record
+    }
+
+    #[test]
+    fn valid_record() {
+        let record = record("panicked at src/main.rs:1:1");
+        assert!(record.is_valid());
+        assert_eq!(record.message(), "panicked at src/main.rs:1:1");
+
+        let mut wrong_magic = record;
+        wrong_magic.magic ^= 1;
+        assert!(!wrong_magic.is_valid());
+    }
+
+    #[test]
+    fn corruption_is_rejected() {
+        let mut corrupted_crc = record("oops");
+        corrupted_crc.crc ^= 1;
+        assert!(!corrupted_crc.is_valid());
+
+        let mut corrupted_message = record("oops");
+        corrupted_message.message[0] = b'O';
+        assert!(!corrupted_message.is_valid());
+
+        let mut corrupted_count = record("oops");
+        corrupted_count.boot_count += 1;
+        assert!(!corrupted_count.is_valid());
+    }
+
+    #[test]
+    fn len_past_the_message_is_rejected() {
+        let mut record = record("oops");
+        record.len = record.message.len() as u32 + 1;
+        assert!(!record.is_valid());
+        record.len = u32::MAX;
+        assert!(!record.is_valid());
+    }
+
+    #[test]
+    fn overlong_message_is_truncated() {
+        let capacity = PSTORE_SIZE as usize - 24;
+        let long = "x".repeat(capacity + 100);
+        let truncated = record(&long);
+        assert!(truncated.is_valid());
+        assert_eq!(truncated.len as usize, capacity);
+        assert_eq!(truncated.message(), &long[..capacity]);
+
+        // Only the first of the two bytes of `é` fits.
+        let cut = "x".repeat(capacity - 1) + "é";
+        let truncated = record(&cut);
+        assert!(truncated.is_valid());
+        assert_eq!(truncated.len as usize, capacity);
+        assert_eq!(truncated.message(), &cut[..capacity - 1]);
+    }
+}
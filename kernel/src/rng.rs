@@ -0,0 +1,134 @@
+//! A non-cryptographic random number generator available from early boot.
+//!
+//! [`Rng`] is xoshiro256**. The global generator behind [`next_u64`] and [`fill_bytes`] is
+//! seeded by mixing the boot timestamp, the RSDP address and the CPU's cycle counter, so
+//! it differs between boots without needing a hardware RNG. None of these are secret, so
+//! the output must not be used where unpredictability matters.
+
+use spin::{Lazy, Mutex};
+
+use crate::arch::timestamp;
+#[cfg(any(feature = "boot-time", feature = "acpi"))]
+use crate::boot::request::LimineRequest;
+
+/// xoshiro256**.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: [u64; 4],
+}
+
+/// Advances a splitmix64 state and returns the next output, used to expand seeds.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl Rng {
+    /// Expands `seed` into a full state. Equal seeds give equal sequences.
+    pub fn from_seed(seed: u64) -> Self {
+        let mut seed = seed;
+        Self::from_state([
+            splitmix64(&mut seed),
+            splitmix64(&mut seed),
+            splitmix64(&mut seed),
+            splitmix64(&mut seed),
+        ])
+    }
+
+    /// Uses `state` directly, except that the all-zero state, which xoshiro can never
+    /// leave, is replaced by a fixed non-zero one.
+    pub fn from_state(state: [u64; 4]) -> Self {
+        if state == [0; 4] {
+            return Self::from_seed(0);
+        }
+        Self { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s1.wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = *s1 << 17;
+
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+
+        result
+    }
+
+    pub fn fill_bytes(&mut self, bytes: &mut [u8]) {
+        let mut chunks = bytes.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+        let rest = chunks.into_remainder();
+        if !rest.is_empty() {
+            let len = rest.len();
+            rest.copy_from_slice(&self.next_u64().to_le_bytes()[..len]);
+        }
+    }
+}
+
+/// Mixes together everything that varies from boot to boot.
+fn boot_seed() -> u64 {
+    [timestamp(), boot_time(), rsdp_address()]
+        .into_iter()
+        .fold(0, |seed, source| splitmix64(&mut (seed ^ source)))
+}
+
+fn boot_time() -> u64 {
+    #[cfg(feature = "boot-time")]
+    if let Some(response) = crate::boot::requests::BOOT_TIME.response() {
+        return response.boot_time as u64;
+    }
+    0
+}
+
+fn rsdp_address() -> u64 {
+    #[cfg(feature = "acpi")]
+    if let Some(response) = crate::boot::requests::RSDP.response() {
+        return response.address.as_ptr().map_or(0, |ptr| ptr as u64);
+    }
+    0
+}
+
+static RNG: Lazy<Mutex<Rng>> = Lazy::new(|| Mutex::new(Rng::from_seed(boot_seed())));
+
+/// Returns the next value of the global generator.
+pub fn next_u64() -> u64 {
+    RNG.lock().next_u64()
+}
+
+/// Fills `bytes` from the global generator.
+pub fn fill_bytes(bytes: &mut [u8]) {
+    RNG.lock().fill_bytes(bytes);
+}
+
+/// Checks the generator against the reference outputs of xoshiro256** for the state
+/// `[1, 2, 3, 4]`, that equal seeds give equal sequences, and that the all-zero state is
+/// replaced rather than kept.
+pub fn self_test() -> bool {
+    let mut reference = Rng::from_state([1, 2, 3, 4]);
+    let outputs = [11520, 0, 1509978240, 1215971899390074240];
+    let matches_reference = outputs.iter().all(|&output| reference.next_u64() == output);
+
+    let (mut a, mut b) = (Rng::from_seed(42), Rng::from_seed(42));
+    let deterministic = (0..16).all(|_| a.next_u64() == b.next_u64());
+
+    let mut bytes = [0; 11];
+    Rng::from_seed(42).fill_bytes(&mut bytes);
+    let mut words = Rng::from_seed(42);
+    let (first, second) = (words.next_u64(), words.next_u64());
+    let filled = bytes[..8] == first.to_le_bytes() && bytes[8..] == second.to_le_bytes()[..3];
+
+    let zero = Rng::from_state([0; 4]);
+    let not_stuck = zero.state != [0; 4] && zero.state == Rng::from_seed(0).state;
+
+    matches_reference && deterministic && filled && not_stuck
+}
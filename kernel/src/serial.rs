@@ -0,0 +1,198 @@
+//! 16550 UART serial port driver.
+
+use core::fmt;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use spin::{Lazy, Mutex};
+
+use crate::arch::x86_64::pic::{self, COM1_IRQ};
+use crate::arch::x86_64::port::{inb, outb};
+
+const COM1_BASE: u16 = 0x3f8;
+const COM2_BASE: u16 = 0x2f8;
+
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+const INTERRUPT_ENABLE_RECEIVED: u8 = 1 << 0;
+
+/// How many received bytes COM1's interrupt handler buffers until they are taken.
+pub const INPUT_BUFFER_SIZE: usize = 256;
+
+/// The first serial port, initialised on first use.
+pub static COM1: Lazy<Mutex<SerialPort>> = Lazy::new(|| {
+    let mut port = unsafe { SerialPort::new(COM1_BASE) };
+    port.init();
+    Mutex::new(port)
+});
+
+/// The second serial port, used by the GDB stub so its packets don't mix with the log.
+pub static COM2: Lazy<Mutex<SerialPort>> = Lazy::new(|| {
+    let mut port = unsafe { SerialPort::new(COM2_BASE) };
+    port.init();
+    Mutex::new(port)
+});
+
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    /// ## Safety
+    ///
+    /// `base` must be the I/O port base of a 16550 compatible UART that nothing else uses.
+    pub const unsafe fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    /// Configures the port for 38400 baud, 8N1 with FIFOs enabled and interrupts disabled.
+    pub fn init(&mut self) {
+        unsafe {
+            outb(self.base + INTERRUPT_ENABLE, 0x00);
+            // Set the divisor latch access bit to program the baud rate divisor.
+            outb(self.base + LINE_CONTROL, 0x80);
+            outb(self.base + DATA, 0x03);
+            outb(self.base + INTERRUPT_ENABLE, 0x00);
+            outb(self.base + LINE_CONTROL, 0x03);
+            outb(self.base + FIFO_CONTROL, 0xc7);
+            outb(self.base + MODEM_CONTROL, 0x0b);
+        }
+    }
+
+    /// Writes a byte, waiting for the transmit buffer to drain first.
+    pub fn write_byte(&mut self, byte: u8) {
+        unsafe {
+            while inb(self.base + LINE_STATUS) & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            outb(self.base + DATA, byte);
+        }
+    }
+
+    /// Reads a byte if one has been received, without waiting. Once COM1's input
+    /// interrupt is enabled, its handler takes the bytes first, see [`take_input`].
+    pub fn read_byte(&self) -> Option<u8> {
+        receive(|register| unsafe { inb(self.base + register) })
+    }
+
+    /// Reads a byte, waiting until one has been received.
+    pub fn read_byte_blocking(&mut self) -> u8 {
+        loop {
+            if let Some(byte) = self.read_byte() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Writes raw bytes, translating `\n` into `\r\n`.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+    }
+}
+
+/// Reads the data register if the line status says a byte was received, with `read`
+/// reading the register at the given offset from the base.
+fn receive(mut read: impl FnMut(u16) -> u8) -> Option<u8> {
+    (read(LINE_STATUS) & LINE_STATUS_DATA_READY != 0).then(|| read(DATA))
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Bytes COM1 received, written only by its interrupt handler and read only by
+/// [`take_input`]. One slot stays empty to tell a full buffer from an empty one.
+static INPUT: [AtomicU8; INPUT_BUFFER_SIZE] = [const { AtomicU8::new(0) }; INPUT_BUFFER_SIZE];
+static INPUT_HEAD: AtomicUsize = AtomicUsize::new(0);
+static INPUT_TAIL: AtomicUsize = AtomicUsize::new(0);
+
+/// Makes COM1 raise an interrupt for every byte it receives, which [`take_input`] then
+/// returns.
+pub fn enable_input_interrupt() {
+    let port = COM1.lock();
+    unsafe { outb(port.base + INTERRUPT_ENABLE, INTERRUPT_ENABLE_RECEIVED) };
+    pic::unmask(COM1_IRQ);
+}
+
+/// Returns the oldest byte COM1 received that wasn't taken yet. Bytes received while
+/// [`INPUT_BUFFER_SIZE`] minus one are waiting are dropped.
+pub fn take_input() -> Option<u8> {
+    let tail = INPUT_TAIL.load(Ordering::Relaxed);
+    if tail == INPUT_HEAD.load(Ordering::Acquire) {
+        return None;
+    }
+    let byte = INPUT[tail].load(Ordering::Relaxed);
+    INPUT_TAIL.store((tail + 1) % INPUT_BUFFER_SIZE, Ordering::Release);
+    Some(byte)
+}
+
+/// Moves everything COM1 received into the input buffer.
+///
+/// The registers are accessed without locking [`COM1`], which the interrupted code
+/// may hold. Reading the data register doesn't disturb a transmission in progress.
+pub(crate) fn handle_com1_interrupt() {
+    while let Some(byte) = receive(|register| unsafe { inb(COM1_BASE + register) }) {
+        let head = INPUT_HEAD.load(Ordering::Relaxed);
+        let next = (head + 1) % INPUT_BUFFER_SIZE;
+        if next != INPUT_TAIL.load(Ordering::Acquire) {
+            INPUT[head].store(byte, Ordering::Relaxed);
+            INPUT_HEAD.store(next, Ordering::Release);
+        }
+    }
+    pic::end_of_interrupt(COM1_IRQ);
+}
+
+/// Receives from a mocked UART with a few bytes queued, checking they come out in
+/// order, and that an empty one yields nothing without its data register being read.
+pub fn self_test() -> bool {
+    struct MockUart {
+        queued: &'static [u8],
+        data_reads: usize,
+    }
+
+    impl MockUart {
+        fn read(&mut self, register: u16) -> u8 {
+            match register {
+                LINE_STATUS if self.queued.is_empty() => LINE_STATUS_TRANSMIT_EMPTY,
+                LINE_STATUS => LINE_STATUS_TRANSMIT_EMPTY | LINE_STATUS_DATA_READY,
+                DATA => {
+                    self.data_reads += 1;
+                    let (&byte, rest) = self.queued.split_first().unwrap_or((&0, &[]));
+                    self.queued = rest;
+                    byte
+                }
+                _ => 0,
+            }
+        }
+    }
+
+    let mut uart = MockUart {
+        queued: b"hi\n",
+        data_reads: 0,
+    };
+    let mut received = [None; 4];
+    for byte in &mut received {
+        *byte = receive(|register| uart.read(register));
+    }
+    let empty = receive(|register| uart.read(register));
+
+    received == [Some(b'h'), Some(b'i'), Some(b'\n'), None]
+        && empty.is_none()
+        && uart.data_reads == 3
+}
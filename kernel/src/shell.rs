@@ -0,0 +1,415 @@
+//! An interactive shell on COM1 with diagnostic commands.
+//!
+//! [`init`] makes COM1 interrupt on input, and the idle loop calls [`poll`], which feeds
+//! the received bytes to a line editor and runs every line completed with Enter.
+//! Backspace erases the last character and Ctrl-C discards the line.
+//!
+//! A line is split into arguments at whitespace. Double quotes group words into one
+//! argument, so `peek "0x1000" 32` and `peek 0x1000 32` are the same.
+
+use core::fmt::{self, Write};
+
+use spin::Mutex;
+
+use crate::array_vec::ArrayVec;
+use crate::serial::{self, COM1};
+
+/// The longest line the editor takes. Further characters are refused with a bell.
+pub const MAX_LINE: usize = 128;
+/// The most arguments a line can have, counting the command name.
+pub const MAX_ARGS: usize = 8;
+/// The most bytes `peek` dumps at once.
+pub const MAX_PEEK: u64 = 4096;
+
+const PROMPT: &str = "> ";
+
+/// Why a line couldn't be split into arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    UnterminatedQuote,
+    /// There were more than [`MAX_ARGS`].
+    TooManyArguments,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnterminatedQuote => f.write_str("unterminated quote"),
+            Self::TooManyArguments => write!(f, "more than {} arguments", MAX_ARGS),
+        }
+    }
+}
+
+/// Splits `line` at whitespace, keeping what double quotes enclose together. A quote
+/// only groups, it may start or end in the middle of an argument but isn't part of it.
+pub fn split_args(line: &str) -> Result<ArrayVec<&str, MAX_ARGS>, ParseError> {
+    let mut args = ArrayVec::new();
+    let mut rest = line;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(args);
+        }
+
+        let arg = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').ok_or(ParseError::UnterminatedQuote)?;
+            rest = &quoted[end + 1..];
+            &quoted[..end]
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == '"')
+                .unwrap_or(rest.len());
+            let arg = &rest[..end];
+            rest = &rest[end..];
+            arg
+        };
+        args.push(arg).map_err(|_| ParseError::TooManyArguments)?;
+    }
+}
+
+/// Parses a `0x` prefixed hexadecimal or a decimal number.
+pub fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Writes to COM1, locking it for every piece so commands can still print.
+struct Output;
+
+impl fmt::Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        COM1.lock().write_str(s)
+    }
+}
+
+/// A built-in command, which gets the arguments after its name.
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+    run: fn(&mut dyn Write, &[&str]) -> fmt::Result,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "",
+        help: "list the commands",
+        run: help,
+    },
+    #[cfg(feature = "memory-map")]
+    Command {
+        name: "memmap",
+        usage: "",
+        help: "print the memory map",
+        run: memmap,
+    },
+    Command {
+        name: "lspci",
+        usage: "",
+        help: "list the PCI functions on segment 0",
+        run: lspci,
+    },
+    #[cfg(feature = "acpi")]
+    Command {
+        name: "acpi",
+        usage: "",
+        help: "list the ACPI table signatures",
+        run: acpi,
+    },
+    Command {
+        name: "dmesg",
+        usage: "",
+        help: "replay the kernel log",
+        run: dmesg,
+    },
+    #[cfg(feature = "global-allocator")]
+    Command {
+        name: "memstat",
+        usage: "",
+        help: "print heap statistics",
+        run: memstat,
+    },
+    Command {
+        name: "uptime",
+        usage: "",
+        help: "print the time since boot",
+        run: uptime,
+    },
+    Command {
+        name: "reboot",
+        usage: "",
+        help: "reset the machine",
+        run: reboot,
+    },
+    #[cfg(feature = "framebuffer")]
+    Command {
+        name: "screenshot",
+        usage: "[raw]",
+        help: "dump the screen as a PPM image",
+        run: screenshot,
+    },
+    Command {
+        name: "peek",
+        usage: "<addr> [len]",
+        help: "hex dump memory, faults show as ??",
+        run: peek,
+    },
+];
+
+struct Editor {
+    line: ArrayVec<u8, MAX_LINE>,
+    /// Whether the last byte was a carriage return, whose line feed is then skipped.
+    after_cr: bool,
+}
+
+static EDITOR: Mutex<Editor> = Mutex::new(Editor {
+    line: ArrayVec::new(),
+    after_cr: false,
+});
+
+/// Enables COM1's input interrupt and prints the first prompt.
+pub fn init() {
+    serial::enable_input_interrupt();
+    Output.write_str(PROMPT).ok();
+}
+
+/// Handles the input received since the last call, running every completed line.
+pub fn poll() {
+    while let Some(byte) = serial::take_input() {
+        let mut editor = EDITOR.lock();
+        let after_cr = core::mem::replace(&mut editor.after_cr, byte == b'\r');
+        match byte {
+            b'\n' if after_cr => {}
+            b'\r' | b'\n' => {
+                let mut line = [0; MAX_LINE];
+                let len = editor.line.len();
+                line[..len].copy_from_slice(&editor.line);
+                editor.line.clear();
+                drop(editor);
+
+                Output.write_str("\n").ok();
+                // Only printable ASCII makes it into the line.
+                run_line(core::str::from_utf8(&line[..len]).unwrap(), &mut Output).ok();
+                Output.write_str(PROMPT).ok();
+            }
+            // Backspace and delete.
+            0x08 | 0x7f => {
+                if editor.line.pop().is_some() {
+                    Output.write_str("\x08 \x08").ok();
+                }
+            }
+            // Ctrl-C.
+            0x03 => {
+                editor.line.clear();
+                write!(Output, "^C\n{}", PROMPT).ok();
+            }
+            b' '..=b'~' => match editor.line.push(byte) {
+                Ok(()) => COM1.lock().write_byte(byte),
+                Err(_) => COM1.lock().write_byte(0x07),
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Splits `line` and runs the command it names.
+fn run_line(line: &str, out: &mut dyn Write) -> fmt::Result {
+    let args = match split_args(line) {
+        Ok(args) => args,
+        Err(err) => return writeln!(out, "error: {}", err),
+    };
+    let Some((&name, args)) = args.split_first() else {
+        return Ok(());
+    };
+
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => (command.run)(out, args),
+        None => {
+            write!(out, "unknown command `{}`", name)?;
+            let mut similar = COMMANDS
+                .iter()
+                .filter(|command| command.name.starts_with(name) || name.starts_with(command.name));
+            if let Some(command) = similar.next() {
+                write!(out, ", did you mean `{}`?", command.name)?;
+            }
+            writeln!(out, " Type `help` for a list of commands.")
+        }
+    }
+}
+
+fn help(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    for command in COMMANDS {
+        let usage_len = command.name.len() + 1 + command.usage.len();
+        writeln!(
+            out,
+            "  {} {}{:pad$}  {}",
+            command.name,
+            command.usage,
+            "",
+            command.help,
+            pad = 20usize.saturating_sub(usage_len)
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "memory-map")]
+fn memmap(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    use limine::LimineMemoryMapEntryType;
+
+    use crate::boot::memmap::LimineMemmapResponseExt;
+    use crate::boot::ptr::ArrayPtrExt;
+    use crate::boot::request::LimineRequest;
+
+    let Some(memmap) = crate::boot::requests::MEMORY_MAP.response() else {
+        return writeln!(out, "the bootloader provided no memory map");
+    };
+    memmap.print_map(out)?;
+    // SAFETY: The count comes from the bootloader along with the array.
+    let usable: u64 = unsafe { memmap.entries.iter(memmap.entry_count as usize) }
+        .filter(|entry| entry.typ == LimineMemoryMapEntryType::Usable)
+        .map(|entry| entry.len)
+        .sum();
+    writeln!(out, "{} KiB usable", usable / 1024)
+}
+
+fn lspci(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    use crate::pci;
+
+    for device in pci::enumerate(pci::access(), 0) {
+        writeln!(out, "{}", device)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "acpi")]
+fn acpi(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    use crate::boot::acpi::{Acpi, AcpiError};
+    use crate::boot::request::LimineRequest;
+
+    let acpi = crate::boot::requests::RSDP
+        .response()
+        .ok_or(AcpiError::NoRsdp)
+        .and_then(Acpi::from_response);
+    match acpi {
+        Ok(acpi) => {
+            for signature in acpi.signatures() {
+                writeln!(out, "{}", signature.escape_ascii())?;
+            }
+            Ok(())
+        }
+        Err(err) => writeln!(out, "{}", err),
+    }
+}
+
+fn dmesg(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    let mut result = Ok(());
+    crate::print::replay_log(|bytes| {
+        for chunk in bytes.utf8_chunks() {
+            if result.is_ok() {
+                result = out.write_str(chunk.valid());
+            }
+        }
+    });
+    result
+}
+
+#[cfg(feature = "global-allocator")]
+fn memstat(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    writeln!(out, "{}", crate::heap::stats())
+}
+
+fn uptime(out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    use crate::arch::x86_64::pit::{self, TIMER_HZ};
+
+    let ticks = pit::ticks();
+    let millis = ticks * 1000 / TIMER_HZ as u64;
+    writeln!(out, "up {}.{:03} s", millis / 1000, millis % 1000)
+}
+
+fn reboot(_out: &mut dyn Write, _args: &[&str]) -> fmt::Result {
+    crate::arch::x86_64::reboot();
+}
+
+/// Prints the screen in base64, or with `raw` sends the binary image, for a terminal
+/// program capturing to a file.
+#[cfg(feature = "framebuffer")]
+fn screenshot(out: &mut dyn Write, args: &[&str]) -> fmt::Result {
+    use crate::boot::request::LimineRequest;
+    use crate::gfx::screenshot::{self, PpmSink};
+    use crate::gfx::LimineFramebufferExt;
+
+    /// Sends the image straight to COM1, locking it for every batch. Not through
+    /// `write_bytes`, whose line ending translation would corrupt it.
+    struct Serial;
+
+    impl PpmSink for Serial {
+        fn write_bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+            let mut port = COM1.lock();
+            for &byte in bytes {
+                port.write_byte(byte);
+            }
+            Ok(())
+        }
+    }
+
+    match *args {
+        [] => crate::gfx::dump_screenshot(out),
+        ["raw"] => {
+            let surface = crate::boot::requests::FRAMEBUFFER
+                .response()
+                .and_then(|response| response.framebuffers().first())
+                .and_then(|framebuffer| framebuffer.raw_surface());
+            match surface {
+                Some(surface) => screenshot::write_ppm(&surface, &mut Serial),
+                None => writeln!(out, "there is no framebuffer to take a screenshot of"),
+            }
+        }
+        _ => writeln!(out, "usage: screenshot [raw]"),
+    }
+}
+
+fn peek(out: &mut dyn Write, args: &[&str]) -> fmt::Result {
+    use crate::arch::x86_64::extable;
+
+    let (address, len) = match *args {
+        [address] => (parse_number(address), Some(16)),
+        [address, len] => (parse_number(address), parse_number(len)),
+        _ => return writeln!(out, "usage: peek <addr> [len]"),
+    };
+    let (Some(address), Some(len)) = (address, len) else {
+        return writeln!(out, "peek: expected numbers, like 0xffff800000001000 or 64");
+    };
+    let len = len.min(MAX_PEEK);
+
+    for line in (0..len).step_by(16) {
+        let mut bytes = [None; 16];
+        for (offset, byte) in bytes.iter_mut().enumerate().take((len - line) as usize) {
+            *byte = Some(extable::read_byte(
+                address.wrapping_add(line + offset as u64),
+            ));
+        }
+
+        write!(out, "{:016x}:", address.wrapping_add(line))?;
+        for byte in bytes {
+            match byte {
+                Some(Some(byte)) => write!(out, " {:02x}", byte)?,
+                Some(None) => out.write_str(" ??")?,
+                None => out.write_str("   ")?,
+            }
+        }
+        out.write_str("  |")?;
+        for byte in bytes.into_iter().flatten() {
+            let c = match byte {
+                Some(byte @ b' '..=b'~') => byte as char,
+                _ => '.',
+            };
+            out.write_char(c)?;
+        }
+        out.write_str("|\n")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+//! Per-CPU data.
+//!
+//! Each CPU points its per-CPU base register at its own [`PerCpuData`]. On x86_64 that is
+//! the `fs` base, which can't be read back cheaply, so the structure's first field holds
+//! its own address: reading `fs:0` yields a normal pointer to the current CPU's data
+//! without knowing which CPU we are running on. Elsewhere the base register (`TPIDR_EL1`
+//! or `tp`) is read directly.
+
+use core::ptr;
+
+use crate::arch::{per_cpu_base, set_per_cpu_base};
+
+pub mod sync;
+
+#[repr(C)]
+pub struct PerCpuData<T> {
+    /// The address of this structure, read back through `fs:0` on x86_64.
+    this: *const PerCpuData<T>,
+    data: T,
+}
+
+// The self pointer is only ever used to find `data` again.
+unsafe impl<T: Sync> Sync for PerCpuData<T> {}
+
+impl<T> PerCpuData<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            this: ptr::null(),
+            data,
+        }
+    }
+
+    /// Makes this the calling CPU's per-CPU data.
+    ///
+    /// ## Safety
+    ///
+    /// Every [`read_per_cpu`] on this CPU from now on has to ask for the same `T`.
+    pub unsafe fn install(&'static mut self) {
+        self.this = self;
+        set_per_cpu_base(self.this as u64);
+    }
+
+    pub fn get(&self) -> &T {
+        &self.data
+    }
+}
+
+/// Returns the calling CPU's per-CPU data.
+///
+/// ## Safety
+///
+/// A `PerCpuData<T>` of exactly this `T` must have been installed on the calling CPU.
+pub unsafe fn read_per_cpu<T>() -> &'static T {
+    let this = per_cpu_base() as *const PerCpuData<T>;
+    &(*this).data
+}
@@ -0,0 +1,30 @@
+//! Rendezvous between the BSP and the APs it starts.
+//!
+//! Each AP bumps a shared counter once it reaches the point the BSP waits for:
+//!
+//! ```ignore
+//! static READY: AtomicU64 = AtomicU64::new(0);
+//!
+//! // On each AP, after its setup:
+//! smp::sync::ap_signal_ready(&READY);
+//! // On the BSP, after starting them:
+//! smp::sync::wait_all_ready(ap_count, &READY);
+//! ```
+//!
+//! The orderings pair up: what an AP wrote before signalling is visible to the BSP once
+//! the wait returns.
+
+use core::hint;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Spins until `ready_counter` reaches `count`.
+pub fn wait_all_ready(count: u64, ready_counter: &AtomicU64) {
+    while ready_counter.load(Ordering::Acquire) != count {
+        hint::spin_loop();
+    }
+}
+
+/// Counts the calling AP as ready.
+pub fn ap_signal_ready(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Release);
+}
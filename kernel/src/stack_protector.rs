@@ -0,0 +1,78 @@
+//! Runtime support for building with `-Z stack-protector`.
+//!
+//! Protected functions put a copy of [`__stack_chk_guard`] between their locals and the
+//! return address and compare it before returning, calling [`__stack_chk_fail`] if an
+//! overrun changed it. On `x86_64-unknown-none` the compiler reads the guard from this
+//! global rather than from thread-local storage, so all CPUs share one guard and it
+//! can't be changed per CPU: frames that are live on other CPUs would fail their check.
+//!
+//! `make STACK_PROTECTOR=1` builds with the flag and this feature.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::arch::x86_64::exception::Backtrace;
+use crate::arch::x86_64::rdtsc;
+use crate::{kprintln, rng};
+
+/// The guard before [`init`] runs. Its low byte is zero, like the random guard's, so an
+/// overrun by a string copy stops at it.
+const INITIAL_GUARD: usize = 0x5f3c_a9d1_e2b7_4800;
+
+#[no_mangle]
+pub static mut __stack_chk_guard: usize = INITIAL_GUARD;
+
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Replaces the built-in guard with a random one.
+///
+/// Every protected function that is running when the guard changes fails its check on
+/// return, so this has to be called early from a function that never returns, before
+/// anything it calls stores a canary that outlives the call.
+pub fn init() {
+    let mut guard = rng::next_u64() as usize;
+    if guard >> 8 == 0 {
+        // Fairly unlikely, but a guard of zero is the first thing an overrun is likely to
+        // write.
+        guard = INITIAL_GUARD ^ rdtsc() as usize;
+    }
+    // SAFETY: The guard is only written here, with no protected caller left to notice.
+    unsafe { (&raw mut __stack_chk_guard).write_volatile(guard & !0xff) };
+}
+
+/// Called by a protected function that found its canary overwritten.
+///
+/// The stack and the guard can't be trusted anymore, so this reports once and halts.
+/// Printing may itself run into a corrupted guard; a second failure halts silently
+/// instead of recursing.
+#[no_mangle]
+pub extern "C" fn __stack_chk_fail() -> ! {
+    if !FAILED.swap(true, Ordering::SeqCst) {
+        let (rip, rbp): (u64, u64);
+        unsafe {
+            core::arch::asm!(
+                "lea {}, [rip]",
+                "mov {}, rbp",
+                out(reg) rip,
+                out(reg) rbp,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        kprintln!("*** STACK SMASHING DETECTED ***");
+        kprintln!("{}", Backtrace { rip, rbp });
+    }
+    crate::hcf()
+}
+
+/// Overruns a local buffer, which has to end in [`__stack_chk_fail`] when the kernel is
+/// built with stack protectors.
+#[cfg(feature = "stack-protector-test")]
+#[inline(never)]
+pub fn smash() {
+    let mut buffer = [0u8; 16];
+    let start = buffer.as_mut_ptr();
+    for offset in 0..buffer.len() + 16 {
+        // SAFETY: None, clobbering the canary past the buffer is the point.
+        unsafe { start.add(offset).write_volatile(0x41) };
+    }
+    core::hint::black_box(&buffer);
+}
@@ -0,0 +1,308 @@
+//! Cooperative kernel tasks on the bootstrap processor.
+//!
+//! Each task runs on its own stack until it calls [`yield_now`] or [`schedule`], which
+//! switch to the next ready task round robin by saving the callee-saved registers and
+//! the stack pointer. The boot context is the first task and runs the idle loop, which
+//! calls [`schedule`] before halting, so every task gets a turn. The timer interrupt
+//! never switches by itself: it only requests a reschedule, which the next
+//! [`yield_now`] honours.
+//!
+//! Stacks are mapped at fixed addresses above an unmapped guard page, from frames
+//! reserved by [`init`]. When a task's function returns, its stack goes back to them.
+//!
+//! ```ignore
+//! task::init()?;
+//! task::spawn(blink_cursor)?;
+//! ```
+
+use core::arch::naked_asm;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::x86_64::paging::{Mapper, PAGE_SIZE, WRITABLE};
+use crate::boot::memmap::FreeRegionList;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+use crate::bootalloc;
+
+/// How many tasks can exist at once, counting the boot context.
+pub const MAX_TASKS: usize = 8;
+/// The size of every task's stack, in pages.
+pub const STACK_PAGES: u64 = 4;
+
+const STACK_SIZE: u64 = STACK_PAGES * PAGE_SIZE;
+/// Every slot is a guard page followed by the stack.
+const SLOT_SIZE: u64 = STACK_SIZE + PAGE_SIZE;
+/// Where the stack slots are mapped, well clear of the HHDM and the kernel image.
+const STACK_AREA: u64 = 0xffff_c000_0000_0000;
+/// Frames set aside for the page tables of the stack area. All slots fit one table.
+const TABLE_FRAMES: u64 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Free,
+    Ready,
+    /// Finished, with the stack still mapped until another task reaps it.
+    Exited,
+}
+
+struct Table {
+    states: [State; MAX_TASKS],
+    current: usize,
+}
+
+static TABLE: Mutex<Table> = Mutex::new(Table {
+    states: {
+        let mut states = [State::Free; MAX_TASKS];
+        states[0] = State::Ready;
+        states
+    },
+    current: 0,
+});
+/// The stack pointer of every task that isn't running.
+static SAVED_RSP: [AtomicU64; MAX_TASKS] = [const { AtomicU64::new(0) }; MAX_TASKS];
+static RESCHEDULE: AtomicBool = AtomicBool::new(false);
+static FRAMES: Mutex<FreeRegionList<4>> = Mutex::new(FreeRegionList::new());
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Identifies a task by its slot. Slots are reused once a task exits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// The boot context.
+    pub const BOOT: Self = Self(0);
+
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskError {
+    /// [`init`] didn't run or failed.
+    NotInitialized,
+    /// The bootloader provided no HHDM to reach the page tables through.
+    NoHhdm,
+    /// All [`MAX_TASKS`] slots are taken.
+    TooManyTasks,
+    OutOfMemory,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotInitialized => "tasks aren't initialized",
+            Self::NoHhdm => "no HHDM to reach the page tables through",
+            Self::TooManyTasks => "too many tasks",
+            Self::OutOfMemory => "out of memory for task stacks",
+        })
+    }
+}
+
+/// Reserves the frames for every stack and the page tables mapping them. Only the
+/// first successful call has an effect.
+pub fn init() -> Result<(), TaskError> {
+    if INITIALIZED.load(Ordering::Acquire) {
+        return Ok(());
+    }
+    HHDM.response().ok_or(TaskError::NoHhdm)?;
+
+    let size = (MAX_TASKS as u64 - 1) * STACK_SIZE + TABLE_FRAMES * PAGE_SIZE;
+    let base = bootalloc::take(size, PAGE_SIZE, None).ok_or(TaskError::OutOfMemory)?;
+    FRAMES.lock().free_region(base..base + size);
+    INITIALIZED.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Starts a task running `entry`. It first runs when the current task yields.
+pub fn spawn(entry: fn()) -> Result<TaskId, TaskError> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return Err(TaskError::NotInitialized);
+    }
+    reap();
+
+    let mut table = TABLE.lock();
+    let slot = (1..MAX_TASKS)
+        .find(|&slot| table.states[slot] == State::Free)
+        .ok_or(TaskError::TooManyTasks)?;
+    let top = map_stack(slot)?;
+
+    // The initial frame `switch_stacks` pops: r15 to r12, then rbx holding the entry
+    // point, rbp, and `task_trampoline` as the return address. It is placed so the
+    // trampoline starts with the stack 16 byte aligned.
+    let rsp = top - 72;
+    let frame = rsp as *mut u64;
+    // SAFETY: The stack was just mapped, and the frame lies in its top.
+    unsafe {
+        frame.write_bytes(0, 7);
+        frame.add(4).write(entry as usize as u64);
+        frame.add(6).write(task_trampoline as *const () as u64);
+    }
+    SAVED_RSP[slot].store(rsp, Ordering::Relaxed);
+    table.states[slot] = State::Ready;
+    Ok(TaskId(slot))
+}
+
+/// The running task.
+pub fn current() -> TaskId {
+    TaskId(TABLE.lock().current)
+}
+
+/// Asks the next [`yield_now`] to switch tasks. Called from the timer interrupt.
+pub fn request_reschedule() {
+    RESCHEDULE.store(true, Ordering::Relaxed);
+}
+
+/// Switches to the next ready task if a reschedule was requested since the last switch,
+/// else returns right away. Long running tasks should call this regularly.
+pub fn yield_now() {
+    if RESCHEDULE.load(Ordering::Relaxed) {
+        schedule();
+    }
+}
+
+/// Switches to the next ready task, if there is one besides the current task.
+pub fn schedule() {
+    RESCHEDULE.store(false, Ordering::Relaxed);
+    let (save, load) = {
+        let mut table = TABLE.lock();
+        let current = table.current;
+        let Some(next) = (1..MAX_TASKS)
+            .map(|offset| (current + offset) % MAX_TASKS)
+            .find(|&slot| table.states[slot] == State::Ready)
+        else {
+            return;
+        };
+        table.current = next;
+        (
+            SAVED_RSP[current].as_ptr(),
+            SAVED_RSP[next].load(Ordering::Relaxed),
+        )
+    };
+
+    // SAFETY: `load` was saved by the last switch away from `next`, or set up by
+    // `spawn`, and `next`'s stack stays mapped while it isn't exited.
+    unsafe { switch_stacks(save, load) };
+    reap();
+}
+
+/// Unmaps the stacks of exited tasks, except the one running on it.
+fn reap() {
+    let mut table = TABLE.lock();
+    for slot in 1..MAX_TASKS {
+        if table.states[slot] == State::Exited && slot != table.current {
+            unmap_stack(slot);
+            table.states[slot] = State::Free;
+        }
+    }
+}
+
+fn slot_stack_base(slot: usize) -> u64 {
+    STACK_AREA + slot as u64 * SLOT_SIZE + PAGE_SIZE
+}
+
+/// Maps the stack of `slot`, leaving its guard page unmapped, and returns its top.
+fn map_stack(slot: usize) -> Result<u64, TaskError> {
+    let hhdm = HHDM.response().ok_or(TaskError::NoHhdm)?.offset;
+    let mut frames = FRAMES.lock();
+    // SAFETY: The offset comes from the bootloader, and only the task table, whose lock
+    // is held, edits the stack area.
+    let mut mapper = unsafe { Mapper::new(hhdm) };
+    let base = slot_stack_base(slot);
+
+    for page in 0..STACK_PAGES {
+        let mapped = frames.alloc_region(PAGE_SIZE, PAGE_SIZE).and_then(|frame| {
+            let mut alloc_table = || {
+                let table = frames.alloc_region(PAGE_SIZE, PAGE_SIZE)?.start;
+                // SAFETY: The frame was reserved for us and the HHDM covers it.
+                unsafe { ((table + hhdm) as *mut u8).write_bytes(0, PAGE_SIZE as usize) };
+                Some(table)
+            };
+            mapper
+                .map(
+                    base + page * PAGE_SIZE,
+                    frame.start,
+                    WRITABLE,
+                    &mut alloc_table,
+                )
+                .ok()
+        });
+        if mapped.is_none() {
+            drop(frames);
+            unmap_stack(slot);
+            return Err(TaskError::OutOfMemory);
+        }
+    }
+    Ok(base + STACK_SIZE)
+}
+
+/// Unmaps whatever part of the stack of `slot` is mapped and frees its frames.
+fn unmap_stack(slot: usize) {
+    let Some(hhdm) = HHDM.response() else {
+        return;
+    };
+    let mut frames = FRAMES.lock();
+    // SAFETY: As in `map_stack`.
+    let mut mapper = unsafe { Mapper::new(hhdm.offset) };
+    let base = slot_stack_base(slot);
+
+    for page in 0..STACK_PAGES {
+        if let Some(frame) = mapper.unmap(base + page * PAGE_SIZE) {
+            frames.free_region(frame..frame + PAGE_SIZE);
+        }
+    }
+}
+
+/// Saves the callee-saved registers on the current stack and its pointer to `save`,
+/// then switches to the stack at `load` and restores the registers saved there.
+#[unsafe(naked)]
+unsafe extern "C" fn switch_stacks(save: *mut u64, load: u64) {
+    naked_asm!(
+        "push rbp",
+        "push rbx",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "mov [rdi], rsp",
+        "mov rsp, rsi",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbx",
+        "pop rbp",
+        "ret",
+    );
+}
+
+/// Where a new task starts, with its entry point in rbx.
+#[unsafe(naked)]
+unsafe extern "C" fn task_trampoline() {
+    naked_asm!(
+        "mov rdi, rbx",
+        "call {run}",
+        "ud2",
+        run = sym run_task,
+    );
+}
+
+extern "C" fn run_task(entry: *const ()) -> ! {
+    // SAFETY: `spawn` stored a `fn()` for the trampoline to pass on.
+    let entry = unsafe { core::mem::transmute::<*const (), fn()>(entry) };
+    reap();
+    entry();
+
+    {
+        let mut table = TABLE.lock();
+        let current = table.current;
+        table.states[current] = State::Exited;
+    }
+    // The boot context is always ready, so this never comes back.
+    loop {
+        schedule();
+    }
+}
@@ -0,0 +1,440 @@
+//! Early timekeeping on top of the CPU's cycle counter.
+//!
+//! The counter runs before any timer is set up, which makes it the time base for short
+//! spin waits during boot, like UART timeouts or waiting for application processors.
+//!
+//! On x86_64, [`init`] also picks the source [`delay_ns`] and friends measure time with:
+//! the TSC if it is invariant, else the HPET if one was registered, else the PIT's count.
+
+use crate::arch;
+
+/// Reads the cycle counter: the TSC on x86_64, the counter [`arch::timestamp`] exposes
+/// elsewhere.
+#[inline]
+pub fn tsc_now() -> u64 {
+    arch::timestamp()
+}
+
+/// The cycles from `start` to `now`, correct across a counter wrap.
+#[inline]
+pub fn cycles_between(start: u64, now: u64) -> u64 {
+    now.wrapping_sub(start)
+}
+
+/// Spins until the counter has advanced by `cycles`.
+pub fn busy_wait_cycles(cycles: u64) {
+    wait_cycles_on(cycles, tsc_now);
+}
+
+/// [`busy_wait_cycles`] on the counter `read`.
+fn wait_cycles_on(cycles: u64, mut read: impl FnMut() -> u64) {
+    let start = read();
+    while cycles_between(start, read()) < cycles {
+        core::hint::spin_loop();
+    }
+}
+
+/// Measures the TSC frequency over `pit_ticks` timer interrupts. Returns `None` if
+/// interrupts are disabled, as the timer can't advance then.
+///
+/// A window of a few ticks gives a rough value, good to within about a percent.
+#[cfg(target_arch = "x86_64")]
+pub fn calibrate_tsc_hz(pit_ticks: u64) -> Option<u64> {
+    use arch::x86_64::pit::{self, TIMER_HZ};
+
+    if pit_ticks == 0 || !arch::x86_64::interrupts_enabled() {
+        return None;
+    }
+
+    // Start on a tick boundary so the window is a whole number of ticks.
+    let first = pit::ticks();
+    while pit::ticks() == first {
+        core::hint::spin_loop();
+    }
+    let start_tick = pit::ticks();
+    let start = tsc_now();
+    while pit::ticks() - start_tick < pit_ticks {
+        core::hint::spin_loop();
+    }
+    let cycles = cycles_between(start, tsc_now());
+
+    Some(cycles * TIMER_HZ as u64 / pit_ticks)
+}
+
+#[cfg(target_arch = "x86_64")]
+pub use delay::*;
+
+#[cfg(target_arch = "x86_64")]
+mod delay {
+    #[cfg(feature = "acpi")]
+    use core::fmt;
+    use core::ptr;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::time::Duration;
+
+    use spin::Once;
+
+    use super::{cycles_between, tsc_now};
+    use crate::arch::x86_64::pit::{self, BASE_FREQUENCY, DIVISOR, TIMER_HZ};
+    #[cfg(feature = "acpi")]
+    use crate::boot::acpi::{self, AcpiError};
+    #[cfg(feature = "acpi")]
+    use crate::boot::request::LimineRequest;
+
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    const FEMTOS_PER_NANO: u128 = 1_000_000;
+
+    /// Offset of the capabilities register, with the counter period in the top half.
+    #[cfg(feature = "acpi")]
+    const HPET_CAPABILITIES: usize = 0x00;
+    #[cfg(feature = "acpi")]
+    const HPET_CONFIGURATION: usize = 0x10;
+    /// Offset of the main counter in the HPET's registers.
+    const HPET_MAIN_COUNTER: usize = 0xf0;
+    /// The configuration bit that starts the main counter.
+    #[cfg(feature = "acpi")]
+    const HPET_ENABLE: u64 = 1 << 0;
+    /// The longest counter period the specification allows, 100 ns.
+    #[cfg(feature = "acpi")]
+    const HPET_MAX_PERIOD_FS: u32 = 100_000_000;
+
+    /// An HPET main counter, mapped and enabled.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct HpetCounter {
+        /// The virtual address of the HPET's registers.
+        pub base: usize,
+        /// The counter period in femtoseconds, from the capabilities register.
+        pub period_fs: u32,
+        /// Whether the counter is 64 bits wide rather than 32.
+        pub wide: bool,
+    }
+
+    impl HpetCounter {
+        fn read(&self) -> u64 {
+            let counter = (self.base + HPET_MAIN_COUNTER) as *const u64;
+            // SAFETY: `register_hpet` requires the registers to be mapped.
+            let value = unsafe { ptr::read_volatile(counter) };
+            if self.wide {
+                value
+            } else {
+                value & u32::MAX as u64
+            }
+        }
+
+        /// The ticks from `start` to `now`, across one wrap of a 32-bit counter.
+        fn ticks_between(&self, start: u64, now: u64) -> u64 {
+            let ticks = now.wrapping_sub(start);
+            if self.wide {
+                ticks
+            } else {
+                ticks & u32::MAX as u64
+            }
+        }
+    }
+
+    /// What delays are measured with.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DelaySource {
+        /// The invariant TSC, at the calibrated frequency.
+        Tsc {
+            hz: u64,
+        },
+        Hpet(HpetCounter),
+        /// Polling the count of PIT channel 0.
+        Pit,
+    }
+
+    static SOURCE: Once<DelaySource> = Once::new();
+    static HPET: Once<HpetCounter> = Once::new();
+    /// Whether the timer interrupt drives the PIT tick, so [`sleep`] may halt.
+    static SLEEPING_ENABLED: AtomicBool = AtomicBool::new(false);
+
+    /// Offers `counter` to [`init`]. Only the first call has an effect.
+    ///
+    /// ## Safety
+    ///
+    /// The HPET's registers must stay mapped at `counter.base`, with the main counter
+    /// running.
+    pub unsafe fn register_hpet(counter: HpetCounter) {
+        HPET.call_once(|| counter);
+    }
+
+    /// Why [`register_acpi_hpet`] didn't register an HPET.
+    #[cfg(feature = "acpi")]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum HpetError {
+        Acpi(AcpiError),
+        /// The capabilities register reports a period the specification doesn't allow.
+        BadPeriod(u32),
+    }
+
+    #[cfg(feature = "acpi")]
+    impl fmt::Display for HpetError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Self::Acpi(err) => err.fmt(f),
+                Self::BadPeriod(period) => write!(f, "HPET reports a period of {} fs", period),
+            }
+        }
+    }
+
+    /// Starts the main counter of the HPET the ACPI tables describe and offers it to
+    /// [`init`], which has to run afterwards to pick it.
+    #[cfg(feature = "acpi")]
+    pub fn register_acpi_hpet() -> Result<HpetCounter, HpetError> {
+        let info = acpi::find_hpet().map_err(HpetError::Acpi)?;
+        let hhdm = crate::boot::requests::HHDM
+            .response()
+            .ok_or(HpetError::Acpi(AcpiError::NoHhdm))?;
+        let base = (info.base_address + hhdm.offset) as usize;
+
+        // SAFETY: Before base revision 3 the HHDM covers the first 4 GiB, where firmware
+        // places the HPET's registers.
+        unsafe {
+            let capabilities = ptr::read_volatile((base + HPET_CAPABILITIES) as *const u64);
+            let period_fs = (capabilities >> 32) as u32;
+            if period_fs == 0 || period_fs > HPET_MAX_PERIOD_FS {
+                return Err(HpetError::BadPeriod(period_fs));
+            }
+
+            let configuration = (base + HPET_CONFIGURATION) as *mut u64;
+            ptr::write_volatile(
+                configuration,
+                ptr::read_volatile(configuration) | HPET_ENABLE,
+            );
+
+            let counter = HpetCounter {
+                base,
+                period_fs,
+                wide: info.counter_size,
+            };
+            register_hpet(counter);
+            Ok(counter)
+        }
+    }
+
+    /// Whether the TSC runs at a constant rate in every power state.
+    pub fn has_invariant_tsc() -> bool {
+        use core::arch::x86_64::__cpuid;
+
+        __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+    }
+
+    /// Picks the delay source, calibrating the TSC against the PIT if it is used. Runs
+    /// once; later calls return the source picked the first time.
+    pub fn init() -> DelaySource {
+        *SOURCE.call_once(|| {
+            select_source(
+                has_invariant_tsc(),
+                HPET.get().copied(),
+                calibrate_tsc_with_pit,
+            )
+        })
+    }
+
+    /// The source [`init`] picks: the TSC if it is invariant, at the frequency
+    /// `calibrate_tsc` measures, else `hpet` if there is one, else the PIT.
+    fn select_source(
+        invariant_tsc: bool,
+        hpet: Option<HpetCounter>,
+        calibrate_tsc: impl FnOnce() -> u64,
+    ) -> DelaySource {
+        match (invariant_tsc, hpet) {
+            (true, _) => DelaySource::Tsc {
+                hz: calibrate_tsc(),
+            },
+            (false, Some(hpet)) => DelaySource::Hpet(hpet),
+            (false, None) => DelaySource::Pit,
+        }
+    }
+
+    /// The source picked by [`init`], or `None` before it ran.
+    pub fn delay_source() -> Option<DelaySource> {
+        SOURCE.get().copied()
+    }
+
+    /// Measures the TSC against 5 PIT periods of polling, about 50 ms.
+    fn calibrate_tsc_with_pit() -> u64 {
+        const PERIODS: u64 = 5;
+        let pit_ticks = PERIODS * DIVISOR as u64;
+
+        let start = tsc_now();
+        wait_pit(pit_ticks);
+        let cycles = cycles_between(start, tsc_now());
+        (cycles as u128 * BASE_FREQUENCY as u128 / pit_ticks as u128) as u64
+    }
+
+    /// Polls the PIT until `ticks` of its input clock have passed.
+    fn wait_pit(ticks: u64) {
+        let mut last = pit::read_count();
+        let mut elapsed = 0;
+        while elapsed < ticks {
+            let now = pit::read_count();
+            // The count runs down and reloads from `DIVISOR`, so passing the reload
+            // looks like going up.
+            elapsed += if now <= last {
+                (last - now) as u64
+            } else {
+                (last as u64 + DIVISOR as u64) - now as u64
+            };
+            last = now;
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Polls the HPET until `ticks` have passed. A 32-bit counter may wrap any number of
+    /// times, as every poll accounts for the ticks since the previous one.
+    fn wait_hpet(hpet: &HpetCounter, ticks: u64) {
+        wait_hpet_on(hpet, ticks, || hpet.read());
+    }
+
+    /// [`wait_hpet`] with the main counter read by `read`.
+    fn wait_hpet_on(hpet: &HpetCounter, ticks: u64, mut read: impl FnMut() -> u64) {
+        let mut last = read();
+        let mut elapsed: u64 = 0;
+        while elapsed < ticks {
+            let now = read();
+            elapsed = elapsed.saturating_add(hpet.ticks_between(last, now));
+            last = now;
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Spins for at least `ns` nanoseconds. Calls [`init`] if nothing did yet.
+    pub fn delay_ns(ns: u64) {
+        match init() {
+            DelaySource::Tsc { hz } => {
+                let cycles = (ns as u128 * hz as u128).div_ceil(NANOS_PER_SEC);
+                super::busy_wait_cycles(cycles.min(u64::MAX as u128) as u64);
+            }
+            DelaySource::Hpet(hpet) => {
+                let ticks = (ns as u128 * FEMTOS_PER_NANO).div_ceil(hpet.period_fs.max(1) as u128);
+                wait_hpet(&hpet, ticks.min(u64::MAX as u128) as u64);
+            }
+            DelaySource::Pit => {
+                let ticks = (ns as u128 * BASE_FREQUENCY as u128).div_ceil(NANOS_PER_SEC);
+                wait_pit(ticks.min(u64::MAX as u128) as u64);
+            }
+        }
+    }
+
+    pub fn delay_us(us: u64) {
+        delay_ns(us.saturating_mul(1_000));
+    }
+
+    pub fn delay_ms(ms: u64) {
+        delay_ns(ms.saturating_mul(1_000_000));
+    }
+
+    /// Lets [`sleep`] halt until timer interrupts instead of spinning, once the PIT tick
+    /// runs and interrupts are enabled.
+    pub fn enable_sleeping() {
+        SLEEPING_ENABLED.store(true, Ordering::Release);
+    }
+
+    /// Waits for at least `duration`. Whole timer periods are spent halted when
+    /// [`enable_sleeping`] was called and interrupts are enabled, the rest spinning.
+    pub fn sleep(duration: Duration) {
+        let period = Duration::from_secs(1) / TIMER_HZ;
+        let can_halt =
+            SLEEPING_ENABLED.load(Ordering::Acquire) && crate::arch::x86_64::interrupts_enabled();
+        if !can_halt || duration < period * 2 {
+            delay_ns(duration.as_nanos().min(u64::MAX as u128) as u64);
+            return;
+        }
+
+        // The first tick can come at any point of the current period, so only the
+        // periods after it count.
+        let periods = (duration.as_nanos() / period.as_nanos()) as u64;
+        let target = pit::ticks() + periods + 1;
+        while pit::ticks() < target {
+            crate::arch::wait_for_interrupt();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const NARROW: HpetCounter = HpetCounter {
+            base: 0,
+            period_fs: 10_000_000,
+            wide: false,
+        };
+        const WIDE: HpetCounter = HpetCounter {
+            wide: true,
+            ..NARROW
+        };
+
+        #[test]
+        fn source_selection() {
+            let hz = || 3_000_000_000;
+            assert_eq!(
+                select_source(true, Some(NARROW), hz),
+                DelaySource::Tsc { hz: 3_000_000_000 }
+            );
+            assert_eq!(
+                select_source(true, None, hz),
+                DelaySource::Tsc { hz: 3_000_000_000 }
+            );
+            let unused = || panic!("calibrated a TSC that isn't used");
+            assert_eq!(
+                select_source(false, Some(NARROW), unused),
+                DelaySource::Hpet(NARROW)
+            );
+            assert_eq!(select_source(false, None, unused), DelaySource::Pit);
+        }
+
+        #[test]
+        fn ticks_across_a_wrap() {
+            assert_eq!(NARROW.ticks_between(0x10, 0x30), 0x20);
+            assert_eq!(NARROW.ticks_between(0xffff_fff0, 0x10), 0x20);
+            assert_eq!(NARROW.ticks_between(7, 7), 0);
+            assert_eq!(WIDE.ticks_between(0xffff_fff0, 0x10), 0xffff_ffff_0000_0020);
+            assert_eq!(WIDE.ticks_between(u64::MAX - 0xf, 0x10), 0x20);
+        }
+
+        #[test]
+        fn wait_across_several_wraps() {
+            // Three quarters of the 32-bit range pass between reads, so the counter
+            // wraps on most of them.
+            let mut now: u64 = 0xffff_0000;
+            let mut reads = 0;
+            wait_hpet_on(&NARROW, 3 << 32, || {
+                reads += 1;
+                now = (now + 0xc000_0000) & u32::MAX as u64;
+                now
+            });
+            // The first read is the start, and the 5th the first one 3 * 2^32 ticks
+            // past it.
+            assert_eq!(reads, 5);
+        }
+    }
+}
+
+/// Checks the cycle arithmetic across a counter wrap, and waits on a mocked counter that
+/// counts its reads.
+pub fn self_test() -> bool {
+    let arithmetic_ok = cycles_between(10, 25) == 15
+        && cycles_between(u64::MAX - 4, 5) == 10
+        && cycles_between(7, 7) == 0;
+
+    // Starts 100 cycles before the wrap and advances 30 per read.
+    let mut now = u64::MAX - 99;
+    let mut reads = 0;
+    wait_cycles_on(200, || {
+        reads += 1;
+        now = now.wrapping_add(30);
+        now
+    });
+    // The first read is the start, and the 8th the first one 200 cycles past it.
+    let wait_ok = reads == 8;
+
+    let mut reads = 0;
+    wait_cycles_on(0, || {
+        reads += 1;
+        0
+    });
+
+    arithmetic_ok && wait_ok && reads == 2
+}
@@ -0,0 +1,278 @@
+//! A minimal ring 3 demonstration.
+//!
+//! [`run`] maps a page of user code and a page of user stack, drops to ring 3 with
+//! `iretq` and returns once the user task exits through the `exit` syscall or faults.
+//! The task is a tiny embedded flat binary that writes a greeting with the `write`
+//! syscall and exits.
+
+use core::arch::{global_asm, naked_asm};
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Mutex;
+
+use crate::arch::x86_64::exception::{has_error_code, ControlRegisters};
+use crate::arch::x86_64::gdt::{USER_CODE_SELECTOR, USER_DATA_SELECTOR};
+use crate::arch::x86_64::idt::PAGE_FAULT;
+use crate::arch::x86_64::msr::{self, EFER_NXE, IA32_EFER};
+use crate::arch::x86_64::paging::{
+    MapError, Mapper, PageTable, NO_EXECUTE, PAGE_SIZE, USER, WRITABLE,
+};
+use crate::arch::x86_64::trap::TrapFrame;
+use crate::arch::x86_64::with_user_access;
+use crate::boot::request::LimineRequest;
+use crate::boot::requests::HHDM;
+use crate::serial::COM1;
+
+pub const SYS_EXIT: u64 = 0;
+pub const SYS_WRITE: u64 = 1;
+
+/// The user code lives in the second PML4 slot, which the bootloader leaves unused.
+pub const USER_CODE_BASE: u64 = 0x0000_0080_0000_0000;
+pub const USER_STACK_BASE: u64 = USER_CODE_BASE + 0x10_0000;
+
+/// IF set, plus the always-one reserved bit.
+const USER_RFLAGS: u64 = 0x202;
+
+/// Returned from a syscall the kernel does not know.
+const ENOSYS: u64 = -38i64 as u64;
+/// Returned when a syscall is passed memory the task has no access to.
+const EFAULT: u64 = -14i64 as u64;
+
+global_asm!(
+    ".pushsection .rodata.usermode, \"a\"",
+    ".global __usermode_program_start",
+    ".global __usermode_program_end",
+    "__usermode_program_start:",
+    "lea rsi, [rip + 2f]",
+    "lea rdx, [rip + 3f]",
+    "sub rdx, rsi",
+    "mov edi, 1",
+    "mov eax, {write}",
+    "syscall",
+    "xor edi, edi",
+    "mov eax, {exit}",
+    "syscall",
+    "ud2",
+    "2:",
+    ".ascii \"Hello from ring 3!\\n\"",
+    "3:",
+    "__usermode_program_end:",
+    ".popsection",
+    write = const SYS_WRITE,
+    exit = const SYS_EXIT,
+);
+
+extern "C" {
+    static __usermode_program_start: u8;
+    static __usermode_program_end: u8;
+}
+
+/// How the user task came back to the kernel.
+#[derive(Clone, Copy, Debug)]
+pub enum UserExit {
+    /// The task invoked the `exit` syscall with this status.
+    Exited(u64),
+    /// The task raised an exception and was terminated.
+    Fault {
+        vector: u8,
+        rip: u64,
+        error_code: Option<u64>,
+        /// The faulting address, for page faults.
+        address: Option<u64>,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Error {
+    /// The bootloader did not answer the HHDM request.
+    NoHhdm,
+    /// The user task has already been run.
+    AlreadyRan,
+    /// A kernel page backing the user task could not be translated.
+    Untranslatable,
+    Map(MapError),
+}
+
+impl From<MapError> for Error {
+    fn from(err: MapError) -> Self {
+        Self::Map(err)
+    }
+}
+
+const PDPT: usize = 0;
+const PD: usize = 1;
+const PT: usize = 2;
+const CODE: usize = 3;
+const STACK: usize = 4;
+
+/// The page tables and frames backing the user task.
+struct UserPages(UnsafeCell<[PageTable; 5]>);
+
+unsafe impl Sync for UserPages {}
+
+static USER_PAGES: UserPages = UserPages(UnsafeCell::new([const { PageTable([0; 512]) }; 5]));
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+static EXIT: Mutex<Option<UserExit>> = Mutex::new(None);
+static mut KERNEL_RSP: u64 = 0;
+
+/// Maps the user task, runs it to completion and reports how it ended.
+pub fn run() -> Result<UserExit, Error> {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return Err(Error::AlreadyRan);
+    }
+
+    let hhdm = HHDM.response().ok_or(Error::NoHhdm)?;
+    let mut mapper = unsafe { Mapper::new(hhdm.offset) };
+
+    let pages = USER_PAGES.0.get() as *mut PageTable;
+    let mut frames = [0; 5];
+    for (index, frame) in frames.iter_mut().enumerate() {
+        let virt = unsafe { pages.add(index) } as u64;
+        *frame = mapper.translate(virt).ok_or(Error::Untranslatable)?;
+    }
+
+    unsafe {
+        let start = &raw const __usermode_program_start;
+        let len = (&raw const __usermode_program_end).offset_from(start) as usize;
+        assert!(len <= size_of::<PageTable>());
+        ptr::copy_nonoverlapping(start, pages.add(CODE) as *mut u8, len);
+    }
+
+    let stack_flags = if unsafe { msr::rdmsr(IA32_EFER) } & EFER_NXE != 0 {
+        USER | WRITABLE | NO_EXECUTE
+    } else {
+        USER | WRITABLE
+    };
+
+    let mut tables = [frames[PDPT], frames[PD], frames[PT]].into_iter();
+    let mut alloc_frame = || tables.next();
+    mapper.map(USER_CODE_BASE, frames[CODE], USER, &mut alloc_frame)?;
+    mapper.map(
+        USER_STACK_BASE,
+        frames[STACK],
+        stack_flags,
+        &mut alloc_frame,
+    )?;
+
+    unsafe { enter_user(USER_CODE_BASE, USER_STACK_BASE + PAGE_SIZE) };
+
+    Ok(EXIT
+        .lock()
+        .take()
+        .expect("user task returned without an exit reason"))
+}
+
+/// Terminates the user task after it raised an exception and resumes the kernel.
+///
+/// Called by the exception handlers when the interrupted code ran in ring 3.
+pub fn terminate(frame: &TrapFrame, control: &ControlRegisters) -> ! {
+    let vector = frame.vector as u8;
+    exit(UserExit::Fault {
+        vector,
+        rip: frame.rip,
+        error_code: has_error_code(vector).then_some(frame.error_code),
+        address: (vector == PAGE_FAULT).then_some(control.cr2),
+    });
+}
+
+fn exit(reason: UserExit) -> ! {
+    *EXIT.lock() = Some(reason);
+    unsafe { exit_to_kernel() }
+}
+
+/// Whether `[addr, addr + len)` lies within the pages mapped for the user task.
+fn user_range_is_mapped(addr: u64, len: u64) -> bool {
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+
+    [USER_CODE_BASE, USER_STACK_BASE]
+        .into_iter()
+        .any(|base| addr >= base && end <= base + PAGE_SIZE)
+}
+
+/// Called by the `syscall` entry stub.
+pub(crate) extern "C" fn handle_syscall(number: u64, arg0: u64, arg1: u64, arg2: u64) -> u64 {
+    match number {
+        SYS_EXIT => exit(UserExit::Exited(arg0)),
+        SYS_WRITE => {
+            // Only a single output stream exists, so the file descriptor is ignored.
+            let _ = arg0;
+            let (buf, len) = (arg1, arg2);
+            if !user_range_is_mapped(buf, len) {
+                return EFAULT;
+            }
+
+            with_user_access(|| {
+                let bytes = unsafe { core::slice::from_raw_parts(buf as *const u8, len as usize) };
+                COM1.lock().write_bytes(bytes);
+            });
+            len
+        }
+        _ => ENOSYS,
+    }
+}
+
+/// Saves the kernel's callee-saved registers, flags and stack pointer, then `iretq`s to `entry`
+/// in ring 3. Returns when [`exit_to_kernel`] is called.
+#[unsafe(naked)]
+unsafe extern "C" fn enter_user(entry: u64, stack: u64) {
+    naked_asm!(
+        "push rbx",
+        "push rbp",
+        "push r12",
+        "push r13",
+        "push r14",
+        "push r15",
+        "pushfq",
+        "mov [rip + {kernel_rsp}], rsp",
+        "push {ss}",
+        "push rsi",
+        "push {rflags}",
+        "push {cs}",
+        "push rdi",
+        // Don't leak kernel register contents into ring 3.
+        "xor eax, eax",
+        "xor ebx, ebx",
+        "xor ecx, ecx",
+        "xor edx, edx",
+        "xor esi, esi",
+        "xor edi, edi",
+        "xor ebp, ebp",
+        "xor r8d, r8d",
+        "xor r9d, r9d",
+        "xor r10d, r10d",
+        "xor r11d, r11d",
+        "xor r12d, r12d",
+        "xor r13d, r13d",
+        "xor r14d, r14d",
+        "xor r15d, r15d",
+        "iretq",
+        kernel_rsp = sym KERNEL_RSP,
+        ss = const USER_DATA_SELECTOR,
+        rflags = const USER_RFLAGS,
+        cs = const USER_CODE_SELECTOR,
+    );
+}
+
+/// Discards the current stack and returns from [`enter_user`].
+#[unsafe(naked)]
+unsafe extern "C" fn exit_to_kernel() -> ! {
+    naked_asm!(
+        "mov rsp, [rip + {kernel_rsp}]",
+        // Back to the flags the kernel entered user mode with.
+        "popfq",
+        "pop r15",
+        "pop r14",
+        "pop r13",
+        "pop r12",
+        "pop rbp",
+        "pop rbx",
+        "ret",
+        kernel_rsp = sym KERNEL_RSP,
+    );
+}
@@ -0,0 +1,437 @@
+//! Virtio devices on PCI, through the legacy interface.
+//!
+//! Legacy (virtio 0.9.5) devices are what the transitional devices QEMU offers by
+//! default look like to an old driver: their registers are in an I/O BAR, and each
+//! queue is one physically contiguous area whose page number is handed to the device.
+//! Modern devices would need their vendor capabilities walked and memory BARs mapped
+//! first, which the legacy interface spares.
+//!
+//! Completions are polled. Queues tell the device not to interrupt, so nothing has to be
+//! routed to the driver, and drivers wait for the used ring instead.
+
+use core::fmt;
+use core::ops::RangeInclusive;
+use core::ptr;
+
+use crate::arch::x86_64::port::{inb, inl, inw, outb, outl, outw};
+use crate::boot::PhysAddr;
+use crate::dma::{DmaBuffer, DmaConstraints, DmaError};
+use crate::pci::{Bar, ConfigAccess, PciDevice, COMMAND};
+
+pub mod blk;
+
+pub const VENDOR_ID: u16 = 0x1af4;
+/// The device IDs of transitional devices, which have the legacy interface. Modern-only
+/// devices start at `0x1040`.
+pub const LEGACY_DEVICE_IDS: RangeInclusive<u16> = 0x1000..=0x103f;
+
+const DEVICE_FEATURES: u16 = 0x00;
+const GUEST_FEATURES: u16 = 0x04;
+const QUEUE_ADDRESS: u16 = 0x08;
+const QUEUE_SIZE: u16 = 0x0c;
+const QUEUE_SELECT: u16 = 0x0e;
+const QUEUE_NOTIFY: u16 = 0x10;
+const DEVICE_STATUS: u16 = 0x12;
+/// Where the device specific configuration starts while MSI-X is off, as it is here.
+const DEVICE_CONFIG: u16 = 0x14;
+
+pub const STATUS_ACKNOWLEDGE: u8 = 1 << 0;
+pub const STATUS_DRIVER: u8 = 1 << 1;
+pub const STATUS_DRIVER_OK: u8 = 1 << 2;
+pub const STATUS_FAILED: u8 = 1 << 7;
+
+const COMMAND_IO_SPACE: u16 = 1 << 0;
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+/// The unit of legacy queue addresses, and the alignment of the used ring.
+const QUEUE_ALIGN: u64 = 4096;
+/// Queue addresses are 32-bit page numbers.
+const QUEUE_LIMIT: u64 = QUEUE_ALIGN << 32;
+
+const DESCRIPTOR_NEXT: u16 = 1 << 0;
+const DESCRIPTOR_WRITE: u16 = 1 << 1;
+const AVAIL_NO_INTERRUPT: u16 = 1 << 0;
+const USED_NO_NOTIFY: u16 = 1 << 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VirtioError {
+    /// The function isn't a transitional virtio device.
+    NotLegacy,
+    /// BAR 0 isn't an I/O BAR.
+    NoIoBar,
+    /// The device has no queue of that index.
+    NoQueue(u16),
+    Dma(DmaError),
+    /// Fewer descriptors are free than the chain needs.
+    QueueFull,
+    /// A chain without buffers, or a longer one than the queue.
+    InvalidChain,
+}
+
+impl fmt::Display for VirtioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotLegacy => f.write_str("not a legacy virtio device"),
+            Self::NoIoBar => f.write_str("virtio device has no I/O BAR"),
+            Self::NoQueue(index) => write!(f, "virtio device has no queue {}", index),
+            Self::Dma(err) => err.fmt(f),
+            Self::QueueFull => f.write_str("virtqueue is full"),
+            Self::InvalidChain => f.write_str("invalid virtqueue descriptor chain"),
+        }
+    }
+}
+
+impl From<DmaError> for VirtioError {
+    fn from(err: DmaError) -> Self {
+        Self::Dma(err)
+    }
+}
+
+/// The legacy register block of a device, in its I/O BAR.
+#[derive(Debug)]
+pub struct LegacyTransport {
+    base: u16,
+}
+
+impl LegacyTransport {
+    /// Finds the registers of `device` and enables its I/O space and bus mastering.
+    pub fn new(access: &impl ConfigAccess, device: &PciDevice) -> Result<Self, VirtioError> {
+        if device.vendor_id != VENDOR_ID || !LEGACY_DEVICE_IDS.contains(&device.device_id) {
+            return Err(VirtioError::NotLegacy);
+        }
+        let Some(Bar::Io { port }) = device.bar(access, 0) else {
+            return Err(VirtioError::NoIoBar);
+        };
+
+        let command = access.read_u16(device.address, COMMAND);
+        // The status half is written as zeros, see `PciDevice::set_intx_disabled`.
+        access.write_u32(
+            device.address,
+            COMMAND,
+            (command | COMMAND_IO_SPACE | COMMAND_BUS_MASTER) as u32,
+        );
+        Ok(Self { base: port })
+    }
+
+    fn read_u8(&self, offset: u16) -> u8 {
+        // SAFETY: The port is one of the device's registers.
+        unsafe { inb(self.base + offset) }
+    }
+
+    fn read_u16(&self, offset: u16) -> u16 {
+        // SAFETY: As for `read_u8`.
+        unsafe { inw(self.base + offset) }
+    }
+
+    fn read_u32(&self, offset: u16) -> u32 {
+        // SAFETY: As for `read_u8`.
+        unsafe { inl(self.base + offset) }
+    }
+
+    fn write_u8(&self, offset: u16, value: u8) {
+        // SAFETY: As for `read_u8`.
+        unsafe { outb(self.base + offset, value) }
+    }
+
+    fn write_u16(&self, offset: u16, value: u16) {
+        // SAFETY: As for `read_u8`.
+        unsafe { outw(self.base + offset, value) }
+    }
+
+    fn write_u32(&self, offset: u16, value: u32) {
+        // SAFETY: As for `read_u8`.
+        unsafe { outl(self.base + offset, value) }
+    }
+
+    /// Stops the device and makes it forget its queues and features.
+    pub fn reset(&self) {
+        self.write_u8(DEVICE_STATUS, 0);
+    }
+
+    pub fn status(&self) -> u8 {
+        self.read_u8(DEVICE_STATUS)
+    }
+
+    /// Sets `bits` in the device status, which only ever gains bits until a reset.
+    pub fn add_status(&self, bits: u8) {
+        self.write_u8(DEVICE_STATUS, self.status() | bits);
+    }
+
+    pub fn device_features(&self) -> u32 {
+        self.read_u32(DEVICE_FEATURES)
+    }
+
+    /// Acknowledges `features`, which must be a subset of [`device_features`].
+    ///
+    /// [`device_features`]: LegacyTransport::device_features
+    pub fn set_guest_features(&self, features: u32) {
+        self.write_u32(GUEST_FEATURES, features);
+    }
+
+    /// Reads the dword at `offset` in the device specific configuration.
+    pub fn config_u32(&self, offset: u16) -> u32 {
+        self.read_u32(DEVICE_CONFIG + offset)
+    }
+
+    /// Reads the qword at `offset` in the device specific configuration. The halves are
+    /// read separately, so the value may tear if the device changes it meanwhile.
+    pub fn config_u64(&self, offset: u16) -> u64 {
+        self.config_u32(offset) as u64 | (self.config_u32(offset + 4) as u64) << 32
+    }
+
+    fn notify(&self, queue: u16) {
+        self.write_u16(QUEUE_NOTIFY, queue);
+    }
+}
+
+/// A buffer of a descriptor chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Buffer {
+    pub addr: PhysAddr,
+    pub len: u32,
+    /// Whether the device writes the buffer rather than reads it. Writable buffers have
+    /// to come after all readable ones.
+    pub writable: bool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UsedElement {
+    id: u32,
+    len: u32,
+}
+
+/// Offsets of the rings in a queue of `size` entries, laid out as the legacy interface
+/// fixes it: descriptors, then the available ring, then the used ring on the next page.
+#[derive(Clone, Copy)]
+struct Layout {
+    avail: usize,
+    used: usize,
+    len: usize,
+}
+
+impl Layout {
+    fn new(size: u16) -> Self {
+        let size = size as usize;
+        let avail = size * size_of::<Descriptor>();
+        // Flags, index, ring and the used event the driver doesn't use.
+        let used = (avail + 6 + 2 * size).next_multiple_of(QUEUE_ALIGN as usize);
+        // Flags, index, ring and the available event.
+        let len = used + 6 + size * size_of::<UsedElement>();
+        Self { avail, used, len }
+    }
+}
+
+/// A split virtqueue in DMA memory.
+///
+/// The driver owns the descriptor table and the available ring, the device the used
+/// ring. Each side only reads the other's index with volatile loads, and the fences
+/// order the rings' contents before the index that publishes them.
+pub struct Virtqueue {
+    memory: DmaBuffer,
+    layout: Layout,
+    index: u16,
+    size: u16,
+    /// The first descriptor of the free list, linked through `next`.
+    free_head: u16,
+    free_count: u16,
+    /// The available ring index the next chain goes to, the device's copy of which is
+    /// only updated when the chain is published.
+    next_avail: u16,
+    /// How many used ring entries have been taken. Like the device's index, it wraps at
+    /// 2^16 rather than at the queue size.
+    last_used: u16,
+}
+
+impl Virtqueue {
+    /// Sets up queue `index` of the device behind `transport`, in the size the device
+    /// asks for.
+    pub fn new(transport: &LegacyTransport, index: u16) -> Result<Self, VirtioError> {
+        transport.write_u16(QUEUE_SELECT, index);
+        let size = transport.read_u16(QUEUE_SIZE);
+        if size == 0 {
+            return Err(VirtioError::NoQueue(index));
+        }
+        let layout = Layout::new(size);
+        let memory = DmaBuffer::alloc(
+            layout.len,
+            DmaConstraints {
+                limit: QUEUE_LIMIT,
+                align: QUEUE_ALIGN,
+                ..DmaConstraints::ANY
+            },
+        )?;
+
+        let queue = Self {
+            memory,
+            layout,
+            index,
+            size,
+            free_head: 0,
+            free_count: size,
+            next_avail: 0,
+            last_used: 0,
+        };
+        for i in 0..size {
+            // SAFETY: The index is in the table, and the device doesn't have it yet.
+            unsafe { (*queue.descriptor(i)).next = i.wrapping_add(1) };
+        }
+        // SAFETY: As above.
+        unsafe { ptr::write_volatile(queue.avail_flags(), AVAIL_NO_INTERRUPT) };
+
+        queue.memory.sync_for_device();
+        transport.write_u32(
+            QUEUE_ADDRESS,
+            (queue.memory.phys_addr() / QUEUE_ALIGN) as u32,
+        );
+        Ok(queue)
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// How many more descriptors chains can take.
+    pub fn free_count(&self) -> u16 {
+        self.free_count
+    }
+
+    fn at<T>(&self, offset: usize) -> *mut T {
+        // SAFETY: The layout keeps every ring inside the buffer.
+        unsafe { self.memory.as_ptr().add(offset).cast() }
+    }
+
+    fn descriptor(&self, i: u16) -> *mut Descriptor {
+        self.at::<Descriptor>(0).wrapping_add(i as usize)
+    }
+
+    fn avail_flags(&self) -> *mut u16 {
+        self.at(self.layout.avail)
+    }
+
+    fn avail_index(&self) -> *mut u16 {
+        self.at(self.layout.avail + 2)
+    }
+
+    fn avail_slot(&self, index: u16) -> *mut u16 {
+        self.at::<u16>(self.layout.avail + 4)
+            .wrapping_add((index % self.size) as usize)
+    }
+
+    fn used_flags(&self) -> *const u16 {
+        self.at(self.layout.used)
+    }
+
+    fn used_index(&self) -> *const u16 {
+        self.at(self.layout.used + 2)
+    }
+
+    fn used_slot(&self, index: u16) -> *const UsedElement {
+        self.at::<UsedElement>(self.layout.used + 4)
+            .wrapping_add((index % self.size) as usize)
+    }
+
+    /// Hands the chain of `buffers` to the device and notifies it, unless it asked not
+    /// to be. Returns the ID [`pop_used`](Virtqueue::pop_used) reports the chain with.
+    ///
+    /// Fails with [`VirtioError::QueueFull`] if there aren't enough free descriptors,
+    /// which frees up as used chains are popped.
+    ///
+    /// ## Safety
+    ///
+    /// The buffers must stay valid, and the writable ones unused by anything else, until
+    /// the chain is popped.
+    pub unsafe fn submit(
+        &mut self,
+        transport: &LegacyTransport,
+        buffers: &[Buffer],
+    ) -> Result<u16, VirtioError> {
+        if buffers.is_empty() || buffers.len() > self.size as usize {
+            return Err(VirtioError::InvalidChain);
+        }
+        if buffers.len() > self.free_count as usize {
+            return Err(VirtioError::QueueFull);
+        }
+
+        let head = self.free_head;
+        let mut last = head;
+        for (i, buffer) in buffers.iter().enumerate() {
+            let descriptor = self.descriptor(last);
+            let next = (*descriptor).next;
+            let mut flags = if buffer.writable { DESCRIPTOR_WRITE } else { 0 };
+            if i + 1 < buffers.len() {
+                flags |= DESCRIPTOR_NEXT;
+            }
+            ptr::write_volatile(
+                descriptor,
+                Descriptor {
+                    addr: buffer.addr,
+                    len: buffer.len,
+                    flags,
+                    next,
+                },
+            );
+            if i + 1 < buffers.len() {
+                last = next;
+            } else {
+                self.free_head = next;
+            }
+        }
+        self.free_count -= buffers.len() as u16;
+
+        ptr::write_volatile(self.avail_slot(self.next_avail), head);
+        // The device may look at the slot as soon as the index covers it.
+        self.memory.sync_for_device();
+        self.next_avail = self.next_avail.wrapping_add(1);
+        ptr::write_volatile(self.avail_index(), self.next_avail);
+        // And it has to see the new index before it is told to look, or before its flag
+        // saying it is looking anyway is read.
+        self.memory.sync_for_device();
+        if ptr::read_volatile(self.used_flags()) & USED_NO_NOTIFY == 0 {
+            transport.notify(self.index);
+        }
+        Ok(head)
+    }
+
+    /// Takes the next chain the device is done with and frees its descriptors. Returns
+    /// its ID and how many bytes the device wrote to it.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        // SAFETY: The index is in the buffer, and the device only ever writes it whole.
+        let used = unsafe { ptr::read_volatile(self.used_index()) };
+        if used == self.last_used {
+            return None;
+        }
+        // The element has to be read after the index that published it.
+        self.memory.sync_for_cpu();
+        // SAFETY: The device wrote the element before moving the index past it.
+        let element = unsafe { ptr::read_volatile(self.used_slot(self.last_used)) };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let head = element.id as u16;
+        let mut last = head;
+        let mut count = 1;
+        loop {
+            // SAFETY: The chain is the driver's again, and its links stay in the table.
+            let descriptor = unsafe { ptr::read_volatile(self.descriptor(last)) };
+            if descriptor.flags & DESCRIPTOR_NEXT == 0 || count == self.size {
+                break;
+            }
+            last = descriptor.next % self.size;
+            count += 1;
+        }
+        // SAFETY: As above.
+        unsafe { (*self.descriptor(last)).next = self.free_head };
+        self.free_head = head;
+        self.free_count += count;
+        Some((head, element.len))
+    }
+}
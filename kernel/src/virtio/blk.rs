@@ -0,0 +1,242 @@
+//! The virtio block device, as QEMU attaches a `-drive` with `if=virtio`.
+//!
+//! Reads go through one request at a time: a header and status byte in one DMA page
+//! and a bounce buffer for the data, so callers can read into any memory.
+
+use core::fmt;
+
+use super::{Buffer, LegacyTransport, VirtioError, Virtqueue, VENDOR_ID};
+use super::{STATUS_ACKNOWLEDGE, STATUS_DRIVER, STATUS_DRIVER_OK, STATUS_FAILED};
+use crate::dma::{DmaBuffer, DmaConstraints};
+use crate::frame::FRAME_SIZE;
+use crate::pci::{self, ConfigAccess, PciDevice};
+use crate::time;
+
+/// The legacy device ID of block devices.
+pub const DEVICE_ID: u16 = 0x1001;
+pub const SECTOR_SIZE: usize = 512;
+
+/// The device refuses writes.
+const F_RO: u32 = 1 << 5;
+
+/// The capacity in sectors, the first field of the configuration.
+const CONFIG_CAPACITY: u16 = 0x00;
+
+const REQUEST_IN: u32 = 0;
+
+const STATUS_OK: u8 = 0;
+const STATUS_IO_ERROR: u8 = 1;
+const STATUS_UNSUPPORTED: u8 = 2;
+
+/// How many bytes one request moves at most, the size of the bounce buffer.
+const BOUNCE_LEN: usize = 16 * SECTOR_SIZE;
+/// Where the status byte goes in the request page, after the header.
+const STATUS_OFFSET: usize = 16;
+
+/// How long a request may take before the device is given up on.
+const TIMEOUT_US: u64 = 1_000_000;
+const POLL_INTERVAL_US: u64 = 10;
+
+#[repr(C)]
+struct RequestHeader {
+    typ: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockError {
+    Virtio(VirtioError),
+    /// The buffer isn't a whole number of sectors.
+    Unaligned,
+    /// The read goes past the last sector.
+    OutOfRange,
+    /// The device reported an I/O error.
+    Io,
+    /// The device doesn't support the request.
+    Unsupported,
+    /// The device didn't complete the request in time. It may still write to the
+    /// driver's buffers, so the device is not used again.
+    Timeout,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Virtio(err) => err.fmt(f),
+            Self::Unaligned => write!(f, "reads are in whole {} byte sectors", SECTOR_SIZE),
+            Self::OutOfRange => f.write_str("read past the end of the disk"),
+            Self::Io => f.write_str("the disk reported an I/O error"),
+            Self::Unsupported => f.write_str("the disk doesn't support the request"),
+            Self::Timeout => f.write_str("the disk didn't answer in time"),
+        }
+    }
+}
+
+impl From<VirtioError> for BlockError {
+    fn from(err: VirtioError) -> Self {
+        Self::Virtio(err)
+    }
+}
+
+/// A legacy virtio block device, with its single request queue.
+pub struct Block {
+    transport: LegacyTransport,
+    queue: Virtqueue,
+    /// The request header, then the status byte.
+    request: DmaBuffer,
+    bounce: DmaBuffer,
+    capacity: u64,
+    read_only: bool,
+    /// A request timed out and may still be in flight.
+    stuck: bool,
+}
+
+impl Block {
+    /// Sets up the first block device on segment 0, or returns `None` if there is none.
+    pub fn probe(access: &impl ConfigAccess) -> Option<Result<Self, VirtioError>> {
+        pci::enumerate(access, 0)
+            .find(|device| device.vendor_id == VENDOR_ID && device.device_id == DEVICE_ID)
+            .map(|device| Self::new(access, &device))
+    }
+
+    /// Resets `device` and brings it up again with this driver.
+    pub fn new(access: &impl ConfigAccess, device: &PciDevice) -> Result<Self, VirtioError> {
+        let transport = LegacyTransport::new(access, device)?;
+        transport.reset();
+        transport.add_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let features = transport.device_features();
+        // Nothing else changes how the queue or the requests look.
+        transport.set_guest_features(features & F_RO);
+
+        let setup = || -> Result<_, VirtioError> {
+            let queue = Virtqueue::new(&transport, 0)?;
+            let request = DmaBuffer::alloc(FRAME_SIZE as usize, DmaConstraints::ANY)?;
+            let bounce = DmaBuffer::alloc(BOUNCE_LEN, DmaConstraints::ANY)?;
+            Ok((queue, request, bounce))
+        };
+        let (queue, request, bounce) = setup().inspect_err(|_| {
+            transport.add_status(STATUS_FAILED);
+        })?;
+        transport.add_status(STATUS_DRIVER_OK);
+
+        Ok(Self {
+            capacity: transport.config_u64(CONFIG_CAPACITY),
+            read_only: features & F_RO != 0,
+            stuck: false,
+            transport,
+            queue,
+            request,
+            bounce,
+        })
+    }
+
+    /// The size of the disk in sectors.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Reads `buf.len() / SECTOR_SIZE` sectors starting at sector `lba` into `buf`.
+    pub fn read(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        if self.stuck {
+            return Err(BlockError::Timeout);
+        }
+        if !buf.len().is_multiple_of(SECTOR_SIZE) {
+            return Err(BlockError::Unaligned);
+        }
+        let sectors = (buf.len() / SECTOR_SIZE) as u64;
+        if lba
+            .checked_add(sectors)
+            .is_none_or(|end| end > self.capacity)
+        {
+            return Err(BlockError::OutOfRange);
+        }
+
+        for (i, chunk) in buf.chunks_mut(BOUNCE_LEN).enumerate() {
+            let sector = lba + (i * BOUNCE_LEN / SECTOR_SIZE) as u64;
+            self.read_chunk(sector, chunk.len())?;
+            chunk.copy_from_slice(&self.bounce.as_slice()[..chunk.len()]);
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes, at most [`BOUNCE_LEN`], from `sector` on into the bounce
+    /// buffer.
+    fn read_chunk(&mut self, sector: u64, len: usize) -> Result<(), BlockError> {
+        let request = self.request.as_mut_slice();
+        let header = RequestHeader {
+            typ: REQUEST_IN,
+            reserved: 0,
+            sector,
+        };
+        // SAFETY: The header fits in the page, which nothing else uses.
+        unsafe { request.as_mut_ptr().cast::<RequestHeader>().write(header) };
+        request[STATUS_OFFSET] = u8::MAX;
+
+        let base = self.request.phys_addr();
+        let buffers = [
+            Buffer {
+                addr: base,
+                len: size_of::<RequestHeader>() as u32,
+                writable: false,
+            },
+            Buffer {
+                addr: self.bounce.phys_addr(),
+                len: len as u32,
+                writable: true,
+            },
+            Buffer {
+                addr: base + STATUS_OFFSET as u64,
+                len: 1,
+                writable: true,
+            },
+        ];
+
+        let mut waited = 0;
+        let head = loop {
+            self.request.sync_for_device();
+            // SAFETY: Both buffers belong to the driver and are left alone until the
+            // chain comes back below.
+            match unsafe { self.queue.submit(&self.transport, &buffers) } {
+                Ok(head) => break head,
+                // Only a queue shorter than the chain stays full, as nothing else is in
+                // flight. Whatever is comes back before the timeout or not at all.
+                Err(VirtioError::QueueFull) => {
+                    self.queue.pop_used();
+                    self.wait(&mut waited)?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+        loop {
+            match self.queue.pop_used() {
+                Some((id, _)) if id == head => break,
+                Some(_) => {}
+                None => self.wait(&mut waited)?,
+            }
+        }
+
+        self.request.sync_for_cpu();
+        match self.request.as_slice()[STATUS_OFFSET] {
+            STATUS_OK => Ok(()),
+            STATUS_IO_ERROR => Err(BlockError::Io),
+            STATUS_UNSUPPORTED => Err(BlockError::Unsupported),
+            _ => Err(BlockError::Io),
+        }
+    }
+
+    fn wait(&mut self, waited: &mut u64) -> Result<(), BlockError> {
+        if *waited >= TIMEOUT_US {
+            self.stuck = true;
+            return Err(BlockError::Timeout);
+        }
+        time::delay_us(POLL_INTERVAL_US);
+        *waited += POLL_INTERVAL_US;
+        Ok(())
+    }
+}
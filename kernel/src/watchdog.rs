@@ -0,0 +1,165 @@
+//! Soft-lockup watchdog.
+//!
+//! Every timer interrupt bumps the interrupted CPU's heartbeat, and the idle loop or any
+//! long-running loop resets the CPU's deadline by calling [`pet`]. A CPU that keeps taking
+//! timer interrupts without petting the watchdog for longer than the threshold is
+//! reported as stuck, with the RIP and a backtrace of the interrupted code. Whichever
+//! CPU takes a timer interrupt also looks at the other CPUs' heartbeats, which catches
+//! CPUs that stopped taking interrupts altogether.
+//!
+//! Booting with `nowatchdog` disables the checks, which is needed when stepping through
+//! code under a debugger. `watchdog_thresh=<seconds>` changes the threshold.
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::arch::x86_64::cpu_id;
+use crate::arch::x86_64::exception::Backtrace;
+use crate::arch::x86_64::pit::{self, TIMER_HZ};
+use crate::arch::x86_64::trap::TrapFrame;
+use crate::boot::cmdline;
+use crate::serial::COM1;
+
+/// The highest CPU ID plus one that the watchdog keeps track of.
+pub const MAX_CPUS: usize = 64;
+
+/// How long a CPU may go without petting the watchdog by default.
+pub const DEFAULT_THRESHOLD_SECS: u64 = 10;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static THRESHOLD_TICKS: AtomicU64 = AtomicU64::new(DEFAULT_THRESHOLD_SECS * TIMER_HZ as u64);
+
+struct CpuState {
+    /// Whether the CPU has taken a timer interrupt or petted the watchdog yet.
+    online: AtomicBool,
+    /// Timer interrupts this CPU has taken.
+    heartbeat: AtomicU64,
+    /// The heartbeat at the last [`pet`].
+    petted_at: AtomicU64,
+    /// The RIP interrupted by the last timer interrupt.
+    last_rip: AtomicU64,
+    /// Whether the current stall has already been reported.
+    reported: AtomicBool,
+    /// The heartbeat another CPU last saw this one at, and the PIT tick it saw it at.
+    seen_heartbeat: AtomicU64,
+    seen_at: AtomicU64,
+}
+
+impl CpuState {
+    const fn new() -> Self {
+        Self {
+            online: AtomicBool::new(false),
+            heartbeat: AtomicU64::new(0),
+            petted_at: AtomicU64::new(0),
+            last_rip: AtomicU64::new(0),
+            reported: AtomicBool::new(false),
+            seen_heartbeat: AtomicU64::new(0),
+            seen_at: AtomicU64::new(0),
+        }
+    }
+}
+
+static CPUS: [CpuState; MAX_CPUS] = [const { CpuState::new() }; MAX_CPUS];
+
+fn current() -> Option<(usize, &'static CpuState)> {
+    let cpu = cpu_id() as usize;
+    CPUS.get(cpu).map(|state| (cpu, state))
+}
+
+/// Applies the `nowatchdog` and `watchdog_thresh=` command line options.
+pub fn init() {
+    if cmdline::has_flag("nowatchdog") {
+        ENABLED.store(false, Ordering::Relaxed);
+    }
+    if let Some(secs) = cmdline::value("watchdog_thresh").and_then(|secs| secs.parse().ok()) {
+        set_threshold(secs);
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Sets how many seconds a CPU may go without calling [`pet`] before it is reported.
+pub fn set_threshold(secs: u64) {
+    THRESHOLD_TICKS.store(secs.saturating_mul(TIMER_HZ as u64), Ordering::Relaxed);
+}
+
+/// Tells the watchdog the calling CPU is making progress.
+pub fn pet() {
+    let Some((_, state)) = current() else {
+        return;
+    };
+    state.online.store(true, Ordering::Relaxed);
+    state
+        .petted_at
+        .store(state.heartbeat.load(Ordering::Relaxed), Ordering::Relaxed);
+    state.reported.store(false, Ordering::Relaxed);
+}
+
+/// Called on every timer interrupt with the interrupted context.
+pub(crate) fn tick(frame: &TrapFrame) {
+    let Some((cpu, state)) = current() else {
+        return;
+    };
+    state.online.store(true, Ordering::Relaxed);
+    state.last_rip.store(frame.rip, Ordering::Relaxed);
+    let heartbeat = state.heartbeat.fetch_add(1, Ordering::Relaxed) + 1;
+
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let threshold = THRESHOLD_TICKS.load(Ordering::Relaxed);
+
+    let petted_at = state.petted_at.load(Ordering::Relaxed);
+    if heartbeat - petted_at > threshold && !state.reported.swap(true, Ordering::Relaxed) {
+        let backtrace = Backtrace {
+            rip: frame.rip,
+            rbp: frame.rbp,
+        };
+        report(format_args!(
+            "CPU {} appears stuck at RIP={:#018x}\n{}",
+            cpu, frame.rip, backtrace
+        ));
+    }
+
+    let now = pit::ticks();
+    for (other, other_state) in CPUS.iter().enumerate() {
+        if other == cpu || !other_state.online.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        // A CPU whose heartbeat stands still isn't even taking timer interrupts anymore.
+        let other_heartbeat = other_state.heartbeat.load(Ordering::Relaxed);
+        if other_state
+            .seen_heartbeat
+            .swap(other_heartbeat, Ordering::Relaxed)
+            != other_heartbeat
+        {
+            other_state.seen_at.store(now, Ordering::Relaxed);
+        } else if now - other_state.seen_at.load(Ordering::Relaxed) > threshold
+            && !other_state.reported.swap(true, Ordering::Relaxed)
+        {
+            report(format_args!(
+                "CPU {} appears stuck at RIP={:#018x}",
+                other,
+                other_state.last_rip.load(Ordering::Relaxed)
+            ));
+        }
+    }
+}
+
+/// Prints a report from interrupt context.
+///
+/// The stuck code may well have been interrupted while holding the serial port lock, in
+/// which case the lock is broken: interleaved output beats no report at all.
+fn report(args: fmt::Arguments) {
+    let mut port = match COM1.try_lock() {
+        Some(port) => port,
+        None => {
+            unsafe { COM1.force_unlock() };
+            COM1.lock()
+        }
+    };
+    port.write_fmt(format_args!("{}\n", args)).ok();
+}